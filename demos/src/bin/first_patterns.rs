@@ -1,12 +1,12 @@
 use ray_tracer_challenge::camera::Camera;
 use ray_tracer_challenge::color::Color;
 use ray_tracer_challenge::constants::white;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
 use ray_tracer_challenge::light::point_light::PointLight;
 use ray_tracer_challenge::material::Material;
 use ray_tracer_challenge::pattern::pattern::Pattern;
 use ray_tracer_challenge::pattern::sine_2d::Sine2D;
 use ray_tracer_challenge::pattern::stripes::Stripes;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::plane::Plane;
 use ray_tracer_challenge::shape::sphere::Sphere;
 use ray_tracer_challenge::transformations::rotation_z;
@@ -18,6 +18,7 @@ use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
 use ray_tracer_challenge::{color, point, vector};
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 // To render larger, be sure to use an optimized (release) build and give it several minutes to finish
 // const CANVAS_WIDTH: u32 = 1000;
@@ -31,7 +32,7 @@ fn main() {
     let mut sine2d = Sine2D::new(color!(0.1, 1, 0.5), color!(0.9, 0.2, 0.6));
     sine2d.set_transformation(scaling(0.005, 1.0, 0.005) * translation(-5.0, 1.0, 0.5));
     let room_material = Material::builder()
-        .pattern(Box::new(sine2d))
+        .pattern(Arc::new(sine2d))
         .specular(0.)
         .build();
     // The floor is a plane
@@ -39,7 +40,7 @@ fn main() {
 
     // The large sphere in the middle is a unit sphere, translated upward slightly and colored green.
     let middle_sphere_material = Material::builder()
-        .pattern(Box::new(stripes.clone()))
+        .pattern(Arc::new(stripes.clone()))
         .diffuse(0.7)
         .specular(0.3)
         .build();
@@ -47,7 +48,7 @@ fn main() {
 
     // The smaller green sphere on the right is scaled in half
     let right_sphere_material = Material::builder()
-        .pattern(Box::new(stripes.clone()))
+        .pattern(Arc::new(stripes.clone()))
         .diffuse(0.7)
         .specular(0.3)
         .build();
@@ -60,7 +61,7 @@ fn main() {
 
     // The smallest sphere is scaled by a third before being translated
     let left_sphere_material = Material::builder()
-        .pattern(Box::new(stripes))
+        .pattern(Arc::new(stripes))
         .diffuse(0.7)
         .specular(0.3)
         .build();
@@ -69,7 +70,7 @@ fn main() {
         left_sphere_material,
     );
 
-    let world = World {
+    let mut world = World {
         objects: vec![
             Box::new(floor),
             Box::new(left),
@@ -78,6 +79,7 @@ fn main() {
         ],
         // The light source is white, shining from above and to the left
         light: Some(Box::new(PointLight::new(point!(-10, 10, -10), white()))),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -87,6 +89,6 @@ fn main() {
         view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
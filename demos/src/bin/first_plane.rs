@@ -1,9 +1,9 @@
 use ray_tracer_challenge::camera::Camera;
 use ray_tracer_challenge::color::Color;
 use ray_tracer_challenge::constants::white;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
 use ray_tracer_challenge::light::point_light::PointLight;
 use ray_tracer_challenge::material::Material;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::plane::Plane;
 use ray_tracer_challenge::shape::sphere::Sphere;
 use ray_tracer_challenge::transformations::scaling;
@@ -63,7 +63,7 @@ fn main() {
         left_sphere_material,
     );
 
-    let world = World {
+    let mut world = World {
         objects: vec![
             Box::new(floor),
             Box::new(left),
@@ -72,6 +72,7 @@ fn main() {
         ],
         // The light source is white, shining from above and to the left
         light: Some(Box::new(PointLight::new(point!(-10, 10, -10), white()))),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -81,6 +82,6 @@ fn main() {
         view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
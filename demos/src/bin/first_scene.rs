@@ -1,20 +1,7 @@
-use ray_tracer_challenge::camera::Camera;
-use ray_tracer_challenge::color::Color;
-use ray_tracer_challenge::constants::white;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
-use ray_tracer_challenge::light::point_light::PointLight;
-use ray_tracer_challenge::material::Material;
-use ray_tracer_challenge::shape::sphere::Sphere;
-use ray_tracer_challenge::transformations::rotation_x;
-use ray_tracer_challenge::transformations::rotation_y;
-use ray_tracer_challenge::transformations::scaling;
-use ray_tracer_challenge::transformations::shearing;
-use ray_tracer_challenge::transformations::translation;
-use ray_tracer_challenge::transformations::view_transform;
-use ray_tracer_challenge::tuple::Tuple;
-use ray_tracer_challenge::world::World;
-use ray_tracer_challenge::{color, point, vector};
+use ray_tracer_challenge::post::overlay::text_size;
+use ray_tracer_challenge::prelude::*;
 use std::f32::consts::PI;
+use std::time::Instant;
 
 // To render larger, be sure to use an optimized (release) build and give it several minutes to finish
 const CANVAS_WIDTH: u32 = 1000;
@@ -82,26 +69,38 @@ fn main() {
         left_sphere_material,
     );
 
-    let world = World {
-        objects: vec![
-            Box::new(floor),
-            Box::new(left_wall),
-            Box::new(right_wall),
-            Box::new(left),
-            Box::new(middle),
-            Box::new(right),
-        ],
+    let mut world = World::builder()
+        .add(floor)
+        .add(left_wall)
+        .add(right_wall)
+        .add(left)
+        .add(middle)
+        .add(right)
         // The light source is white, shining from above and to the left
-        light: Some(Box::new(PointLight::new(point!(-10, 10, -10), white()))),
-    };
+        .light(PointLight::new(point!(-10, 10, -10), white()))
+        .build();
 
-    let camera = Camera::new(
-        CANVAS_WIDTH,
-        CANVAS_HEIGHT,
-        PI / 3.0,
-        view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
-    );
+    let camera = Camera::builder()
+        .size(CANVAS_WIDTH, CANVAS_HEIGHT)
+        .fov(PI / 3.0)
+        .look_from(point!(0, 1.5, -5))
+        .look_at(point!(0, 1, 0))
+        .up(vector!(0, 1, 0))
+        .build();
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let settings = RenderSettings::default();
+    let start = Instant::now();
+    let mut canvas = camera.render(&mut world, settings);
+
+    // burn the render's details into the bottom left corner
+    let caption = format!(
+        "FIRST SCENE\n{} SAMPLES/PIXEL\n{:.1} S",
+        settings.samples_per_pixel,
+        start.elapsed().as_secs_f32()
+    );
+    let (width, height) = text_size(&caption);
+    let top = (canvas.height - height - 4) as isize;
+    canvas.fill_rect(0, top, width + 4, height + 4, color!(0, 0, 0));
+    canvas.draw_text(2, top + 2, &caption, white());
     println!("{}", canvas.to_ppm());
 }
@@ -5,7 +5,6 @@
 use ray_tracer_challenge::camera::Camera;
 use ray_tracer_challenge::canvas::canvas_from_ppm;
 use ray_tracer_challenge::color::Color;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
 use ray_tracer_challenge::constants::{black, white};
 use ray_tracer_challenge::light::light::Light;
 use ray_tracer_challenge::light::rectangle_light::RectangleLight;
@@ -14,6 +13,7 @@ use ray_tracer_challenge::pattern::uv::get_align_check_cubic_map_pattern;
 use ray_tracer_challenge::pattern::uv::{
     CylindricalMap, PlanarMap, SphericalMap, TextureMap, UVCheckers, UVImage,
 };
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cube::Cube;
 use ray_tracer_challenge::shape::cylinder::Cylinder;
 use ray_tracer_challenge::shape::group::GroupShape;
@@ -26,6 +26,7 @@ use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
 use ray_tracer_challenge::{color, point, vector};
 use std::f32::consts::PI;
+use std::sync::Arc;
 use std::{env, fs::File, path::Path};
 
 const CANVAS_WIDTH: u32 = 1000;
@@ -38,7 +39,7 @@ fn main() {
     let floor = {
         let material = Material::builder()
             .specular(0.)
-            .pattern(Box::new(TextureMap::new(
+            .pattern(Arc::new(TextureMap::new(
                 Box::new(UVCheckers::new(16., 8., black(), white())),
                 Box::new(PlanarMap),
             )))
@@ -48,7 +49,7 @@ fn main() {
 
     let sphere = {
         let material = Material::builder()
-            .pattern(Box::new(TextureMap::new(
+            .pattern(Arc::new(TextureMap::new(
                 Box::new(UVCheckers::new(16., 8., black(), white())),
                 Box::new(SphericalMap),
             )))
@@ -63,8 +64,8 @@ fn main() {
         let canvas = canvas_from_ppm(file).unwrap();
 
         let material = Material::builder()
-            .pattern(Box::new(TextureMap::new(
-                Box::new(UVImage::new(canvas)),
+            .pattern(Arc::new(TextureMap::new(
+                Box::new(UVImage::new(canvas).with_mipmaps()),
                 Box::new(SphericalMap),
             )))
             .diffuse(0.9)
@@ -92,7 +93,7 @@ fn main() {
             .specular(0.6)
             .shininess(15.)
             .diffuse(0.8)
-            .pattern(Box::new(TextureMap::new(
+            .pattern(Arc::new(TextureMap::new(
                 Box::new(UVCheckers::new(16., 16., color!(0, 0.5, 0), white())),
                 Box::new(CylindricalMap),
             )))
@@ -107,7 +108,7 @@ fn main() {
 
     let cube = {
         let material = Material::builder()
-            .pattern(Box::new(get_align_check_cubic_map_pattern()))
+            .pattern(Arc::new(get_align_check_cubic_map_pattern()))
             .build();
 
         let mut c = Cube::new();
@@ -117,7 +118,7 @@ fn main() {
         c
     };
 
-    let world = World {
+    let mut world = World {
         objects: vec![
             Box::new(floor),
             Box::new(sphere),
@@ -127,6 +128,7 @@ fn main() {
         ],
         // The light source is white, shining from above and to the left
         light: Some(get_light()),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -136,7 +138,7 @@ fn main() {
         view_transform(point!(0, 1.5, -10), point!(2, 2.8, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
 
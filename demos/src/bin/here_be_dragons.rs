@@ -6,12 +6,16 @@ use ray_tracer_challenge::camera::Camera;
 use ray_tracer_challenge::color::Color;
 use ray_tracer_challenge::light::point_light::PointLight;
 use ray_tracer_challenge::material::Material;
-use ray_tracer_challenge::matrix::Matrix;
-use ray_tracer_challenge::obj_parser::parse_obj;
+use ray_tracer_challenge::matrix::{identity_4x4, Matrix};
+use ray_tracer_challenge::mesh_cache::parse_obj_file;
+use ray_tracer_challenge::obj_parser::ParseOptions;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cube::Cube;
 use ray_tracer_challenge::shape::cylinder::Cylinder;
 use ray_tracer_challenge::shape::group::GroupShape;
+use ray_tracer_challenge::shape::instance::Instance;
 use ray_tracer_challenge::shape::shape::Shape;
+use ray_tracer_challenge::stats;
 use ray_tracer_challenge::transformations::rotation_y;
 use ray_tracer_challenge::transformations::scaling;
 use ray_tracer_challenge::transformations::translation;
@@ -20,8 +24,9 @@ use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
 use ray_tracer_challenge::{color, point, vector};
 use std::f32::consts::PI;
+use std::sync::Arc;
 use std::time::Instant;
-use std::{env, fs::File, path::Path};
+use std::{env, path::Path};
 
 // To render larger, be sure to use an optimized (release) build and give it up to a minute to finish
 const CANVAS_WIDTH: u32 = 1000;
@@ -36,6 +41,12 @@ const CANVAS_HEIGHT: u32 = 400;
 fn main() {
     let args: Vec<String> = env::args().collect();
     let dragon_file_path = Path::new(&args[1]);
+    // an optional triangle count to simplify the dragon to, for quick previews
+    let max_triangles = args.get(2).map(|count| count.parse().unwrap());
+    // set RTC_STATS to print a breakdown of where the time went after rendering
+    if env::var_os("RTC_STATS").is_some() {
+        stats::enable();
+    }
 
     let light = get_light();
 
@@ -137,36 +148,36 @@ fn main() {
             .build(),
     );
 
-    // load the dragon once and clone it to save time
-    let dragon = get_dragon(dragon_file_path);
+    // load and divide the dragon once; every scene element shares it through an instance
+    let dragon = get_dragon(dragon_file_path, max_triangles);
 
     let mut element_data = vec![
         (
-            dragon.clone(),
+            Arc::clone(&dragon),
             center_front_transform,
             center_front_dragon_material,
             center_front_case_material,
         ),
         (
-            dragon.clone(),
+            Arc::clone(&dragon),
             center_back_transform,
             center_back_dragon_material,
             center_back_case_material,
         ),
         (
-            dragon.clone(),
+            Arc::clone(&dragon),
             center_left_transform,
             center_left_dragon_material,
             center_left_case_material,
         ),
         (
-            dragon.clone(),
+            Arc::clone(&dragon),
             left_transform,
             left_dragon_material,
             left_case_material,
         ),
         (
-            dragon.clone(),
+            Arc::clone(&dragon),
             center_right_transform,
             center_right_dragon_material,
             center_right_case_material,
@@ -195,9 +206,10 @@ fn main() {
         duration
     );
 
-    let world = World {
+    let mut world = World {
         objects,
         light: Some(Box::new(light)),
+        ..World::new()
     };
 
     // - add: camera
@@ -215,7 +227,7 @@ fn main() {
         view_transform(point!(0, 2.5, -10), point!(0, 1, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, 5);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
 
@@ -280,9 +292,14 @@ fn get_pedestal() -> Cylinder {
     c
 }
 
-fn get_dragon(dragon_file_path: &Path) -> GroupShape {
-    let file = File::open(dragon_file_path).unwrap();
-    let mut parse_results = parse_obj(file).unwrap();
+fn get_dragon(dragon_file_path: &Path, max_triangles: Option<usize>) -> Arc<dyn Shape> {
+    let options = ParseOptions {
+        weld_tolerance: max_triangles.map(|_| 0.),
+        max_triangles,
+        ..ParseOptions::default()
+    };
+    // parsed once and cached next to the OBJ file after that
+    let mut parse_results = parse_obj_file(dragon_file_path, &options).unwrap();
     let mut dragon = parse_results.take_all_as_group().unwrap();
     // lift dragon so that it sits on the pedestal
     // raw normalized OBJ bounds were:
@@ -292,11 +309,15 @@ fn get_dragon(dragon_file_path: &Path) -> GroupShape {
 
     eprintln!("Finished parsing dragon");
 
-    dragon
+    eprintln!("Dividing dragon...");
+    dragon.divide(4);
+    eprintln!("Finished dividing dragon");
+
+    Arc::new(dragon)
 }
 
 fn get_scene_element(
-    mut dragon: GroupShape,
+    dragon: Arc<dyn Shape>,
     element_transform: Matrix,
     dragon_material: Material,
     display_case_material: Option<Material>,
@@ -304,8 +325,7 @@ fn get_scene_element(
     let mut element = GroupShape::new();
     element.set_transformation(element_transform);
 
-    eprintln!("Setting dragon material...");
-    dragon.set_material(dragon_material);
+    let dragon = Instance::new(dragon, identity_4x4(), Some(dragon_material));
 
     let dragon_box = {
         match display_case_material {
@@ -323,7 +343,7 @@ fn get_scene_element(
 
                 dragon_box
             }
-            None => dragon,
+            None => GroupShape::with_children(vec![Box::new(dragon)]),
         }
     };
 
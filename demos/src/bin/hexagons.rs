@@ -2,10 +2,10 @@ use ray_tracer_challenge::camera::Camera;
 use ray_tracer_challenge::color::Color;
 use ray_tracer_challenge::constants::glass;
 use ray_tracer_challenge::constants::white;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
 use ray_tracer_challenge::light::point_light::PointLight;
 use ray_tracer_challenge::material::Material;
 use ray_tracer_challenge::pattern::checkers::Checkers;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cylinder::Cylinder;
 use ray_tracer_challenge::shape::group::GroupShape;
 use ray_tracer_challenge::shape::plane::Plane;
@@ -19,9 +19,9 @@ use ray_tracer_challenge::transformations::translation;
 use ray_tracer_challenge::transformations::view_transform;
 use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
-use ray_tracer_challenge::{point, vector};
+use ray_tracer_challenge::{color, point, vector};
 use std::f32::consts::PI;
-use std::str::FromStr;
+use std::sync::Arc;
 
 // To render larger, be sure to use an optimized (release) build and give it several minutes to finish
 const CANVAS_WIDTH: u32 = 1000;
@@ -35,22 +35,20 @@ fn main() {
         plane.set_transformation(translation(0.0, 0.0, 5.0) * rotation_x(PI / 2.0));
 
         // TODO: looks like checkers doesn't work right. Creates square rings. Which are also cool!
-        let checkers = Checkers::new(
-            Color::from_str("#C5D86D").unwrap(),
-            Color::from_str("#261C15").unwrap(),
-        );
+        let checkers = Checkers::new(color!("#C5D86D"), color!("#261C15"));
         // checkers.set_transformation(rotation_x(PI / 2.0));
-        let m = Material::builder().pattern(Box::new(checkers)).build();
+        let m = Material::builder().pattern(Arc::new(checkers)).build();
 
         plane.set_material(m);
         Box::new(plane)
     };
     let mut hex1 = hexagon(&glass());
     hex1.set_transformation(translation(0.0, 0.75, 0.0) * rotation_x(PI / 2.0));
-    let world = World {
+    let mut world = World {
         objects: vec![floor, Box::new(hex1)],
         // The light source is white, shining from above and to the left
         light: Some(Box::new(PointLight::new(point!(-10, 10, -10), white()))),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -60,7 +58,7 @@ fn main() {
         view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
 
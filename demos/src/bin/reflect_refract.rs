@@ -8,6 +8,7 @@ use ray_tracer_challenge::pattern::pattern::Pattern;
 use ray_tracer_challenge::pattern::rings::Rings;
 use ray_tracer_challenge::pattern::sine_2d::Sine2D;
 use ray_tracer_challenge::pattern::stripes::Stripes;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cone::Cone;
 use ray_tracer_challenge::shape::csg::{CSGOperator, CSG};
 use ray_tracer_challenge::shape::cylinder::Cylinder;
@@ -23,6 +24,7 @@ use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
 use ray_tracer_challenge::{color, point, vector};
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 // To render larger, be sure to use an optimized (release) build and give it several seconds to finish
 const CANVAS_WIDTH: u32 = 1000;
@@ -38,7 +40,7 @@ fn main() {
     let mut sine2d = Sine2D::new(color!(0.1, 1, 0.5), color!(0.9, 0.2, 0.6));
     sine2d.set_transformation(scaling(0.05, 1., 0.05) * translation(-5., 1., 0.5));
     let room_material = Material::builder()
-        .pattern(Box::new(sine2d))
+        .pattern(Arc::new(sine2d))
         .specular(0.)
         .reflective(0.5)
         .build();
@@ -52,14 +54,14 @@ fn main() {
     // The smaller green sphere on the right is scaled in half
 
     let right_sphere_material = Material::builder()
-        .pattern(Box::new(stripes.clone()))
+        .pattern(Arc::new(stripes.clone()))
         .diffuse(0.7)
         .specular(0.3)
         .build();
     let mut metal_rings = metal();
     let mut ring_pattern = Rings::new(yellow() / 2., white() / 2.);
     ring_pattern.set_transformation(scaling(0.1, 0.1, 0.1));
-    metal_rings.pattern = Some(Box::new(ring_pattern));
+    metal_rings.pattern = Some(Arc::new(ring_pattern));
     let right = Sphere::build(
         shearing(0., 1., 0., 0., 0., 1.) * translation(1.5, 0.5, -0.5) * scaling(0.5, 0.5, 0.5),
         metal_rings,
@@ -72,7 +74,7 @@ fn main() {
     stripes2.a = stripes2.a / 4.;
     stripes2.b = stripes2.b / 4.;
     let left_sphere_material = Material::builder()
-        .pattern(Box::new(stripes2))
+        .pattern(Arc::new(stripes2))
         .diffuse(0.7)
         .specular(1.)
         .reflective(0.8)
@@ -101,7 +103,7 @@ fn main() {
         c
     };
 
-    let world = World {
+    let mut world = World {
         objects: vec![
             Box::new(floor),
             Box::new(left),
@@ -113,6 +115,7 @@ fn main() {
         ],
         // The light source is white, shining from above and to the left
         light: Some(Box::new(PointLight::new(point!(-10, 10, -10), white()))),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -122,7 +125,7 @@ fn main() {
         view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, 5);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
 
@@ -3,14 +3,14 @@
 // Each image should be converted to PPM format. This can be done withe ImageMagick:
 // convert x.jpg -compress none x.ppm
 use ray_tracer_challenge::camera::Camera;
-use ray_tracer_challenge::canvas::canvas_from_ppm;
+use ray_tracer_challenge::canvas::canvas_from_image;
 use ray_tracer_challenge::color::Color;
-use ray_tracer_challenge::constants::DEFAULT_RAY_RECURSION_DEPTH;
 use ray_tracer_challenge::light::light::Light;
 use ray_tracer_challenge::light::point_light::PointLight;
 use ray_tracer_challenge::material::Material;
 use ray_tracer_challenge::pattern::uv::CubicMap;
 use ray_tracer_challenge::pattern::uv::UVImage;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cube::Cube;
 use ray_tracer_challenge::shape::sphere::Sphere;
 use ray_tracer_challenge::transformations::view_transform;
@@ -18,12 +18,13 @@ use ray_tracer_challenge::transformations::{scaling, translation};
 use ray_tracer_challenge::tuple::Tuple;
 use ray_tracer_challenge::world::World;
 use ray_tracer_challenge::{color, point, vector};
+use std::sync::Arc;
 use std::{env, fs::File, path::Path};
 
 const CANVAS_WIDTH: u32 = 800;
 const CANVAS_HEIGHT: u32 = 400;
 
-fn main() {
+fn main() -> ray_tracer_challenge::Result<()> {
     let args: Vec<String> = env::args().collect();
     let skybox_image_directory = Path::new(&args[1]);
 
@@ -80,31 +81,32 @@ fn main() {
     //     ambient: 1
     let skybox = {
         eprintln!("Loading front...");
-        let front = get_uv_from_path(&skybox_image_directory.join("posz.ppm"));
+        let front = get_uv_from_path(&skybox_image_directory.join("posz.ppm"))?;
         eprintln!("Loading back...");
-        let back = get_uv_from_path(&skybox_image_directory.join("negz.ppm"));
+        let back = get_uv_from_path(&skybox_image_directory.join("negz.ppm"))?;
         eprintln!("Loading left...");
-        let left = get_uv_from_path(&skybox_image_directory.join("posx.ppm"));
+        let left = get_uv_from_path(&skybox_image_directory.join("posx.ppm"))?;
         eprintln!("Loading right...");
-        let right = get_uv_from_path(&skybox_image_directory.join("negx.ppm"));
+        let right = get_uv_from_path(&skybox_image_directory.join("negx.ppm"))?;
         eprintln!("Loading up...");
-        let up = get_uv_from_path(&skybox_image_directory.join("posy.ppm"));
+        let up = get_uv_from_path(&skybox_image_directory.join("posy.ppm"))?;
         eprintln!("Loading down...");
-        let down = get_uv_from_path(&skybox_image_directory.join("negy.ppm"));
+        let down = get_uv_from_path(&skybox_image_directory.join("negy.ppm"))?;
 
         let material = Material::builder()
             .diffuse(0.)
             .specular(0.)
             .ambient(1.)
-            .pattern(Box::new(CubicMap::new(front, back, left, right, up, down)))
+            .pattern(Arc::new(CubicMap::new(front, back, left, right, up, down)))
             .build();
 
         Cube::build(scaling(1000., 1000., 1000.), material)
     };
 
-    let world = World {
+    let mut world = World {
         objects: vec![Box::new(sphere), Box::new(skybox)],
         light: Some(get_light()),
+        ..World::new()
     };
 
     let camera = Camera::new(
@@ -114,16 +116,17 @@ fn main() {
         view_transform(point!(0, 0, 0), point!(0, 0, 5), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, DEFAULT_RAY_RECURSION_DEPTH);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
+    Ok(())
 }
 
 fn get_light() -> Box<dyn Light> {
     Box::new(PointLight::new(point!(0, 100, 0), color!(1, 1, 1)))
 }
 
-fn get_uv_from_path(path: &Path) -> Box<UVImage> {
-    let file = File::open(path).unwrap();
-    let canvas = canvas_from_ppm(file).unwrap();
-    Box::new(UVImage::new(canvas))
+fn get_uv_from_path(path: &Path) -> ray_tracer_challenge::Result<Box<UVImage>> {
+    let file = File::open(path)?;
+    let canvas = canvas_from_image(file)?;
+    Ok(Box::new(UVImage::new(canvas)))
 }
@@ -8,6 +8,7 @@ use ray_tracer_challenge::constants::white;
 use ray_tracer_challenge::light::rectangle_light::RectangleLight;
 use ray_tracer_challenge::material::Material;
 use ray_tracer_challenge::matrix::identity_4x4;
+use ray_tracer_challenge::render_settings::RenderSettings;
 use ray_tracer_challenge::shape::cube::Cube;
 use ray_tracer_challenge::shape::plane::Plane;
 use ray_tracer_challenge::shape::shape::Shape;
@@ -33,7 +34,7 @@ const CANVAS_HEIGHT: u32 = 400;
 fn main() {
     let light = get_light();
 
-    let world = World {
+    let mut world = World {
         objects: vec![
             Box::new(get_lampshade()),
             Box::new(get_floor()),
@@ -41,6 +42,7 @@ fn main() {
             Box::new(get_sphere_2()),
         ],
         light: Some(Box::new(light)),
+        ..World::new()
     };
 
     //     - add: camera
@@ -57,7 +59,7 @@ fn main() {
         view_transform(point!(-3, 1, 2.5), point!(0, 0.5, 0), vector!(0, 1, 0)),
     );
 
-    let canvas = camera.render(world, 5);
+    let canvas = camera.render(&mut world, RenderSettings::default());
     println!("{}", canvas.to_ppm());
 }
 
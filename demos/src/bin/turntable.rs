@@ -0,0 +1,68 @@
+use ray_tracer_challenge::animation::{turntable, Animation};
+use ray_tracer_challenge::camera::Camera;
+use ray_tracer_challenge::color::Color;
+use ray_tracer_challenge::constants::white;
+use ray_tracer_challenge::light::point_light::PointLight;
+use ray_tracer_challenge::material::Material;
+use ray_tracer_challenge::pattern::checkers::Checkers;
+use ray_tracer_challenge::pattern::pattern::Pattern;
+use ray_tracer_challenge::shape::cube::Cube;
+use ray_tracer_challenge::shape::plane::Plane;
+use ray_tracer_challenge::transformations::{scaling, translation, view_transform};
+use ray_tracer_challenge::tuple::Tuple;
+use ray_tracer_challenge::{color, point, vector};
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+const CANVAS_WIDTH: u32 = 200;
+const CANVAS_HEIGHT: u32 = 100;
+const FRAMES: usize = 48;
+const FPS: f32 = 24.;
+
+// Renders a cube spinning on a checkered floor into turntable_frames/; assemble with e.g.
+// `ffmpeg -framerate 24 -i turntable_frames/frame_%04d.ppm turntable.mp4`
+fn main() {
+    let mut animation = Animation::new(
+        |_| {
+            Camera::new(
+                CANVAS_WIDTH,
+                CANVAS_HEIGHT,
+                PI / 3.,
+                view_transform(point!(0, 2.5, -6), point!(0, 1, 0), vector!(0, 1, 0)),
+            )
+        },
+        // the light slowly dims over the course of the animation
+        |t| {
+            Box::new(PointLight::new(
+                point!(-10, 10, -10),
+                white() * (1. - t / 4.),
+            ))
+        },
+    );
+
+    let mut checkers = Checkers::new(color!(0.9, 0.9, 0.9), color!(0.2, 0.2, 0.3));
+    checkers.set_transformation(scaling(0.5, 0.5, 0.5));
+    let floor_material = Material::builder()
+        .pattern(Arc::new(checkers))
+        .specular(0.)
+        .build();
+    animation.add_object(Box::new(Plane::build(
+        translation(0., 0., 0.),
+        floor_material,
+    )));
+
+    let cube_material = Material::builder()
+        .color(color!(0.8, 0.3, 0.2))
+        .reflective(0.2)
+        .build();
+    animation.add_animated_object(
+        Box::new(Cube::build(translation(0., 1., 0.), cube_material)),
+        turntable(FRAMES as f32 / FPS, translation(0., 1., 0.)),
+    );
+
+    let paths = animation
+        .render_sequence(FRAMES, FPS, Path::new("turntable_frames"))
+        .expect("Could not write frames");
+    eprintln!("Wrote {} frames", paths.len());
+}
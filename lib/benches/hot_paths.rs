@@ -0,0 +1,154 @@
+// Benchmarks for the code paths that dominate render time. Run with `cargo bench`; compare
+// against a saved baseline with `cargo bench -- --save-baseline before` followed by
+// `cargo bench -- --baseline before`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ray_tracer_challenge::camera::Camera;
+use ray_tracer_challenge::matrix::Matrix;
+use ray_tracer_challenge::ray::Ray;
+use ray_tracer_challenge::render_settings::RenderSettings;
+use ray_tracer_challenge::scenes::generate::mesh_forest;
+use ray_tracer_challenge::shape::cube::Cube;
+use ray_tracer_challenge::shape::plane::Plane;
+use ray_tracer_challenge::shape::shape::{Shape, ShapeBuilder};
+use ray_tracer_challenge::shape::shape_enum::ShapeEnum;
+use ray_tracer_challenge::shape::sphere::Sphere;
+use ray_tracer_challenge::shape::triangle::Triangle;
+use ray_tracer_challenge::transformations::{rotation_x, scaling, translation, view_transform};
+use ray_tracer_challenge::tuple::Tuple;
+use ray_tracer_challenge::world::World;
+use ray_tracer_challenge::{point, vector};
+use std::f32::consts::PI;
+
+fn ray_sphere(c: &mut Criterion) {
+    let sphere = Sphere::build(
+        translation(0., 0., 1.) * scaling(2., 2., 2.),
+        Default::default(),
+    );
+    let ray = Ray::new(point!(0.5, 0.5, -5), vector!(0, 0, 1));
+    c.bench_function("ray-sphere intersection", |b| {
+        b.iter(|| black_box(&sphere).intersect(black_box(ray)).len())
+    });
+}
+
+fn ray_triangle(c: &mut Criterion) {
+    let triangle = Triangle::new(point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0));
+    let ray = Ray::new(point!(0, 0.5, -2), vector!(0, 0, 1));
+    c.bench_function("ray-triangle intersection", |b| {
+        b.iter(|| black_box(&triangle).intersect(black_box(ray)).len())
+    });
+}
+
+fn bvh_traversal(c: &mut Criterion) {
+    let world = mesh_forest(200, 1);
+    let rays: Vec<Ray> = (0..64)
+        .map(|i| {
+            let x = -10. + 20. * (i as f32 / 64.);
+            Ray::new(
+                point!(x, 10, -20),
+                (point!(x, 0, 0) - point!(x, 10, -20)).norm(),
+            )
+        })
+        .collect();
+    c.bench_function("BVH traversal, 64 rays through 200 trees", |b| {
+        b.iter(|| {
+            rays.iter()
+                .map(|r| world.intersect(*r).len())
+                .sum::<usize>()
+        })
+    });
+}
+
+// The same 96 shapes intersected through a vtable and through ShapeEnum's match
+fn shape_dispatch(c: &mut Criterion) {
+    let enum_shapes: Vec<ShapeEnum> = (0..96)
+        .map(|i| {
+            let x = (i % 8) as f32 - 4.;
+            let z = (i / 8) as f32 + 2.;
+            let shape: ShapeEnum = match i % 3 {
+                0 => Sphere::new().into(),
+                1 => Cube::new().into(),
+                _ => Triangle::new(point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0)).into(),
+            };
+            shape.with_transform(translation(x, 0., z) * scaling(0.4, 0.4, 0.4))
+        })
+        .chain(std::iter::once(
+            Plane::new().with_transform(translation(0., -1., 0.)).into(),
+        ))
+        .collect();
+    let dyn_shapes: Vec<Box<dyn Shape>> = enum_shapes
+        .iter()
+        .map(|s| dyn_clone::clone_box(s.as_shape()))
+        .collect();
+    let ray = Ray::new(point!(0.1, 0.1, -5), vector!(0.01, 0, 1).norm());
+    let mut group = c.benchmark_group("shape dispatch");
+    group.bench_function("dyn Shape", |b| {
+        b.iter(|| {
+            black_box(&dyn_shapes)
+                .iter()
+                .map(|s| s.intersect(black_box(ray)).len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("ShapeEnum", |b| {
+        b.iter(|| {
+            black_box(&enum_shapes)
+                .iter()
+                .map(|s| s.intersect(black_box(ray)).len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+fn matrix_multiply(c: &mut Criterion) {
+    let a: Matrix = rotation_x(PI / 5.) * translation(1., 2., 3.);
+    let b: Matrix = scaling(2., 3., 4.) * rotation_x(PI / 7.);
+    c.bench_function("4x4 matrix multiply", |bench| {
+        bench.iter(|| black_box(&a) * black_box(&b))
+    });
+    c.bench_function("4x4 matrix inverse", |bench| {
+        bench.iter(|| black_box(&a).inverse())
+    });
+}
+
+fn ray_transform(c: &mut Criterion) {
+    let m: Matrix = rotation_x(PI / 5.) * translation(1., 2., 3.) * scaling(2., 3., 4.);
+    let ray = Ray::new(point!(0.5, 0.5, -5), vector!(0, 0, 1));
+    c.bench_function("ray transform", |b| {
+        b.iter(|| black_box(ray).transform(black_box(&m)))
+    });
+    c.bench_function("ray transform in place", |b| {
+        b.iter(|| {
+            let mut r = black_box(ray);
+            r.transform_mut(black_box(&m));
+            r
+        })
+    });
+}
+
+fn default_world_render(c: &mut Criterion) {
+    let camera = Camera::new(
+        100,
+        100,
+        PI / 2.,
+        view_transform(point!(0, 0, -5), point!(0, 0, 0), vector!(0, 1, 0)),
+    );
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("100x100 default world", |b| {
+        b.iter(|| camera.render(&mut World::default(), RenderSettings::default()))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    ray_sphere,
+    ray_triangle,
+    bvh_traversal,
+    shape_dispatch,
+    matrix_multiply,
+    ray_transform,
+    default_world_render
+);
+criterion_main!(benches);
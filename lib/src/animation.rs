@@ -0,0 +1,625 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::light::light::Light;
+use crate::matrix::Matrix;
+use crate::quaternion::Quaternion;
+use crate::render_settings::RenderSettings;
+use crate::shape::shape::Shape;
+use crate::transformations::{rotation_y, scaling, translation, view_transform};
+use crate::tuple::Tuple;
+use crate::world::World;
+use std::f32::consts::PI;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A scene parameter expressed as a function of time, in seconds since the start of the animation
+pub type Timeline<T> = Box<dyn Fn(f32) -> T>;
+
+struct AnimatedObject {
+    shape: Box<dyn Shape>,
+    // overrides the shape's own transformation when present
+    transform: Option<Timeline<Matrix>>,
+}
+
+// A scene whose objects, camera and light may change over time. Each frame is rendered from a
+// fresh World built for that frame's time, so the original objects are never modified.
+pub struct Animation {
+    objects: Vec<AnimatedObject>,
+    camera: Timeline<Camera>,
+    light: Timeline<Box<dyn Light>>,
+    pub render_settings: RenderSettings,
+}
+
+impl Animation {
+    pub fn new<C, L>(camera: C, light: L) -> Animation
+    where
+        C: Fn(f32) -> Camera + 'static,
+        L: Fn(f32) -> Box<dyn Light> + 'static,
+    {
+        Animation {
+            objects: vec![],
+            camera: Box::new(camera),
+            light: Box::new(light),
+            render_settings: RenderSettings::default(),
+        }
+    }
+
+    // Add an object that does not move
+    pub fn add_object(&mut self, shape: Box<dyn Shape>) {
+        self.objects.push(AnimatedObject {
+            shape,
+            transform: None,
+        });
+    }
+
+    // Add an object whose transformation at time t is given by transform(t)
+    pub fn add_animated_object<F>(&mut self, shape: Box<dyn Shape>, transform: F)
+    where
+        F: Fn(f32) -> Matrix + 'static,
+    {
+        self.objects.push(AnimatedObject {
+            shape,
+            transform: Some(Box::new(transform)),
+        });
+    }
+
+    // Add an object whose transformation is interpolated between the keyframes of track
+    pub fn add_keyframed_object(&mut self, shape: Box<dyn Shape>, track: KeyframeTrack) {
+        self.add_animated_object(shape, move |time| track.transform_at(time));
+    }
+
+    pub fn world_at(&self, time: f32) -> World {
+        let mut world = World::new();
+        for object in &self.objects {
+            let mut shape = object.shape.clone();
+            if let Some(transform) = &object.transform {
+                shape.set_transformation(transform(time));
+            }
+            world.objects.push(shape);
+        }
+        world.light = Some((self.light)(time));
+        world
+    }
+
+    pub fn camera_at(&self, time: f32) -> Camera {
+        (self.camera)(time)
+    }
+
+    pub fn render_frame(&self, time: f32) -> Canvas {
+        self.camera_at(time)
+            .render(&mut self.world_at(time), self.render_settings)
+    }
+
+    // Render frames at evenly spaced times and write them to out_dir as frame_0000.ppm,
+    // frame_0001.ppm, etc., ready to be assembled into a video. Returns the paths written.
+    pub fn render_sequence(
+        &self,
+        frames: usize,
+        fps: f32,
+        out_dir: &Path,
+    ) -> io::Result<Vec<PathBuf>> {
+        let times: Vec<f32> = (0..frames).map(|frame| frame as f32 / fps).collect();
+        self.render_times(&times, out_dir)
+    }
+
+    // Like render_sequence, but spreads the frames evenly from time 0 to time duration, so that
+    // the first and last frames show the start and end of e.g. a CameraPath's timeline
+    pub fn render_span(
+        &self,
+        frames: usize,
+        duration: f32,
+        out_dir: &Path,
+    ) -> io::Result<Vec<PathBuf>> {
+        let step = duration / (frames.max(2) - 1) as f32;
+        let times: Vec<f32> = (0..frames).map(|frame| frame as f32 * step).collect();
+        self.render_times(&times, out_dir)
+    }
+
+    fn render_times(&self, times: &[f32], out_dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(out_dir)?;
+        let frames = times.len();
+        let mut paths = vec![];
+        for (frame, &time) in times.iter().enumerate() {
+            eprintln!("Rendering frame {}/{} (t={}s)", frame + 1, frames, time);
+            let canvas = self.render_frame(time);
+            let path = out_dir.join(format!("frame_{:04}.ppm", frame));
+            fs::write(&path, canvas.to_ppm())?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+// Transformation for spinning an object once around the y axis every period seconds, on top of
+// its resting transformation
+pub fn turntable(period: f32, rest: Matrix) -> impl Fn(f32) -> Matrix {
+    move |time| rotation_y(2. * PI * time / period) * &rest
+}
+
+// How the interpolation parameter progresses between two keyframes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    // starts and ends slowly (smoothstep)
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+// Pose of an object at a point in time. The easing is used for the segment between this
+// keyframe and the next one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Tuple,
+    pub rotation: Quaternion,
+    pub scale: Tuple,
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, translation: Tuple, rotation: Quaternion, scale: Tuple) -> Keyframe {
+        Keyframe {
+            time,
+            translation,
+            rotation,
+            scale,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Keyframe {
+        self.easing = easing;
+        self
+    }
+
+    fn transformation(&self) -> Matrix {
+        translation(self.translation.x, self.translation.y, self.translation.z)
+            * self.rotation.to_matrix()
+            * scaling(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+// Sequence of keyframes for a single object. Translation and scale are interpolated linearly
+// and rotation spherically; before the first and after the last keyframe the pose is held.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InvalidKeyframeTrackError {
+    NoKeyframes,
+    // keyframe times must be finite so that they can be put in order
+    NonFiniteTime(f32),
+}
+
+impl Display for InvalidKeyframeTrackError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InvalidKeyframeTrackError::NoKeyframes => {
+                write!(f, "Track needs at least one keyframe")
+            }
+            InvalidKeyframeTrackError::NonFiniteTime(time) => {
+                write!(f, "Keyframe time must be finite; was {}", time)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidKeyframeTrackError {}
+
+#[derive(Clone, Debug)]
+pub struct KeyframeTrack {
+    keyframes: Vec<Keyframe>,
+}
+
+impl KeyframeTrack {
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Result<KeyframeTrack, InvalidKeyframeTrackError> {
+        if keyframes.is_empty() {
+            return Err(InvalidKeyframeTrackError::NoKeyframes);
+        }
+        if let Some(k) = keyframes.iter().find(|k| !k.time.is_finite()) {
+            return Err(InvalidKeyframeTrackError::NonFiniteTime(k.time));
+        }
+        keyframes.sort_by(|k1, k2| k1.time.total_cmp(&k2.time));
+        Ok(KeyframeTrack { keyframes })
+    }
+
+    pub fn transform_at(&self, time: f32) -> Matrix {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time {
+            return first.transformation();
+        }
+        if time >= last.time {
+            return last.transformation();
+        }
+        // index of the first keyframe after time; guaranteed to be > 0 by the checks above
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let from = &self.keyframes[next - 1];
+        let to = &self.keyframes[next];
+        let t = from
+            .easing
+            .apply((time - from.time) / (to.time - from.time));
+        Keyframe::new(
+            time,
+            from.translation + (to.translation - from.translation) * t,
+            from.rotation.slerp(&to.rotation, t),
+            from.scale + (to.scale - from.scale) * t,
+        )
+        .transformation()
+    }
+}
+
+// A place the camera passes through, and the point it looks at when it gets there
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Waypoint {
+    pub position: Tuple,
+    pub look_at: Tuple,
+}
+
+impl Waypoint {
+    pub fn new(position: Tuple, look_at: Tuple) -> Waypoint {
+        Waypoint { position, look_at }
+    }
+}
+
+// A smooth camera flight through a list of waypoints. The camera's position and the point it
+// looks at both follow Catmull-Rom splines, which pass through every waypoint without corners.
+// Each stretch between two waypoints takes the same amount of time.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    waypoints: Vec<Waypoint>,
+    // whether the path returns from the last waypoint to the first, e.g. for orbits
+    closed: bool,
+    up: Tuple,
+}
+
+impl CameraPath {
+    pub fn new(waypoints: Vec<Waypoint>) -> CameraPath {
+        assert!(waypoints.len() >= 2, "Path needs at least two waypoints");
+        CameraPath {
+            waypoints,
+            closed: false,
+            up: vector!(0, 1, 0),
+        }
+    }
+
+    pub fn closed(mut self) -> CameraPath {
+        self.closed = true;
+        self
+    }
+
+    pub fn with_up(mut self, up: Tuple) -> CameraPath {
+        self.up = up;
+        self
+    }
+
+    fn segments(&self) -> usize {
+        if self.closed {
+            self.waypoints.len()
+        } else {
+            self.waypoints.len() - 1
+        }
+    }
+
+    // The waypoint with the given index, which may be one past either end. Closed paths wrap
+    // around, and open ones continue in a straight line so that they don't curve at the ends.
+    fn waypoint(&self, index: isize) -> Waypoint {
+        let count = self.waypoints.len() as isize;
+        if self.closed {
+            return self.waypoints[index.rem_euclid(count) as usize];
+        }
+        let extend = |edge: Waypoint, inner: Waypoint| Waypoint {
+            position: edge.position + (edge.position - inner.position),
+            look_at: edge.look_at + (edge.look_at - inner.look_at),
+        };
+        if index < 0 {
+            extend(self.waypoints[0], self.waypoints[1])
+        } else if index >= count {
+            extend(
+                self.waypoints[count as usize - 1],
+                self.waypoints[count as usize - 2],
+            )
+        } else {
+            self.waypoints[index as usize]
+        }
+    }
+
+    // The camera's position and the point it looks at the given fraction of the way along the
+    // path, from 0 at the first waypoint to 1 at the end
+    pub fn at(&self, fraction: f32) -> Waypoint {
+        let segments = self.segments();
+        let scaled = fraction.clamp(0., 1.) * segments as f32;
+        let segment = (scaled.floor() as usize).min(segments - 1);
+        let t = scaled - segment as f32;
+        let i = segment as isize;
+        let (p0, p1, p2, p3) = (
+            self.waypoint(i - 1),
+            self.waypoint(i),
+            self.waypoint(i + 1),
+            self.waypoint(i + 2),
+        );
+        Waypoint {
+            position: catmull_rom(p0.position, p1.position, p2.position, p3.position, t),
+            look_at: catmull_rom(p0.look_at, p1.look_at, p2.look_at, p3.look_at, t),
+        }
+    }
+
+    pub fn view_transform_at(&self, fraction: f32) -> Matrix {
+        let waypoint = self.at(fraction);
+        view_transform(waypoint.position, waypoint.look_at, self.up)
+    }
+
+    // A camera timeline for Animation that flies along the whole path in duration seconds
+    pub fn camera_timeline(
+        self,
+        width: u32,
+        height: u32,
+        field_of_view: f32,
+        duration: f32,
+    ) -> impl Fn(f32) -> Camera {
+        move |time| {
+            Camera::new(
+                width,
+                height,
+                field_of_view,
+                self.view_transform_at(time / duration),
+            )
+        }
+    }
+}
+
+// The point t of the way from p1 to p2 on the Catmull-Rom spline through p0, p1, p2 and p3
+fn catmull_rom(p0: Tuple, p1: Tuple, p2: Tuple, p3: Tuple, t: f32) -> Tuple {
+    // points can't be scaled, so work with the vectors from p1 to the others
+    let (d0, d2, d3) = (p0 - p1, p2 - p1, p3 - p1);
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p1 + ((d2 - d0) * t + (d0 * 2. + d2 * 4. - d3) * t2 + (d3 - d0 - d2 * 3.) * t3) * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::constants::white;
+    use crate::light::point_light::PointLight;
+    use crate::matrix::identity_4x4;
+    use crate::shape::sphere::Sphere;
+    use crate::transformations::view_transform;
+
+    fn test_animation() -> Animation {
+        Animation::new(
+            |_| {
+                Camera::new(
+                    5,
+                    5,
+                    PI / 2.,
+                    view_transform(point!(0, 0, -5), point!(0, 0, 0), vector!(0, 1, 0)),
+                )
+            },
+            |t| Box::new(PointLight::new(point!(-10, 10, -10), white() * (1. - t))),
+        )
+    }
+
+    #[test]
+    fn animated_object_transform_follows_timeline() {
+        let mut animation = test_animation();
+        animation.add_object(Box::new(Sphere::new()));
+        animation.add_animated_object(Box::new(Sphere::new()), |t| translation(t, 0., 0.));
+
+        let world = animation.world_at(2.);
+        assert_eq!(world.objects.len(), 2);
+        assert_eq!(*world.objects[0].transformation(), identity_4x4());
+        assert_eq!(*world.objects[1].transformation(), translation(2., 0., 0.));
+    }
+
+    #[test]
+    fn light_follows_timeline() {
+        let animation = test_animation();
+        let world = animation.world_at(0.25);
+        assert_eq!(world.light.unwrap().intensity(), color!(0.75, 0.75, 0.75));
+    }
+
+    #[test]
+    fn turntable_makes_full_rotation_each_period() {
+        let spin = turntable(4., translation(1., 0., 0.));
+        assert_abs_diff_eq!(spin(0.), translation(1., 0., 0.));
+        assert_abs_diff_eq!(spin(1.), rotation_y(PI / 2.) * translation(1., 0., 0.));
+        assert_abs_diff_eq!(spin(4.), translation(1., 0., 0.), epsilon = 1e-5);
+    }
+
+    fn two_keyframe_track(easing: Easing) -> KeyframeTrack {
+        KeyframeTrack::new(vec![
+            Keyframe::new(
+                1.,
+                vector!(0, 0, 0),
+                Quaternion::identity(),
+                vector!(1, 1, 1),
+            )
+            .with_easing(easing),
+            Keyframe::new(
+                3.,
+                vector!(4, 0, 0),
+                Quaternion::from_axis_angle(vector!(0, 1, 0), PI / 2.),
+                vector!(3, 3, 3),
+            ),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn keyframe_track_rejects_empty_tracks_and_non_finite_times() {
+        let keyframe = |time| {
+            Keyframe::new(
+                time,
+                vector!(0, 0, 0),
+                Quaternion::identity(),
+                vector!(1, 1, 1),
+            )
+        };
+        assert_eq!(
+            KeyframeTrack::new(vec![]).unwrap_err(),
+            InvalidKeyframeTrackError::NoKeyframes
+        );
+        assert!(matches!(
+            KeyframeTrack::new(vec![keyframe(1.), keyframe(f32::NAN)]),
+            Err(InvalidKeyframeTrackError::NonFiniteTime(t)) if t.is_nan()
+        ));
+        assert_eq!(
+            KeyframeTrack::new(vec![keyframe(f32::INFINITY)]).unwrap_err(),
+            InvalidKeyframeTrackError::NonFiniteTime(f32::INFINITY)
+        );
+    }
+
+    #[test]
+    fn keyframe_track_holds_pose_outside_of_keyframes() {
+        let track = two_keyframe_track(Easing::Linear);
+        assert_abs_diff_eq!(track.transform_at(0.), identity_4x4());
+        assert_abs_diff_eq!(
+            track.transform_at(5.),
+            translation(4., 0., 0.) * rotation_y(PI / 2.) * scaling(3., 3., 3.),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn keyframe_track_interpolates_linearly() {
+        let track = two_keyframe_track(Easing::Linear);
+        assert_abs_diff_eq!(
+            track.transform_at(1.5),
+            translation(1., 0., 0.) * rotation_y(PI / 8.) * scaling(1.5, 1.5, 1.5),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn keyframe_track_eases_in_and_out() {
+        let track = two_keyframe_track(Easing::EaseInOut);
+        // a quarter of the way through the segment, smoothstep gives 0.15625
+        let t = 0.156_25;
+        assert_abs_diff_eq!(
+            track.transform_at(1.5),
+            translation(4. * t, 0., 0.)
+                * rotation_y(PI / 2. * t)
+                * scaling(1. + 2. * t, 1. + 2. * t, 1. + 2. * t),
+            epsilon = 1e-6
+        );
+        // easing is symmetric, so the midpoint is unchanged
+        assert_abs_diff_eq!(
+            track.transform_at(2.),
+            two_keyframe_track(Easing::Linear).transform_at(2.),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn keyframed_object_follows_track() {
+        let mut animation = test_animation();
+        animation.add_keyframed_object(Box::new(Sphere::new()), two_keyframe_track(Easing::Linear));
+        let world = animation.world_at(3.);
+        assert_abs_diff_eq!(
+            *world.objects[0].transformation(),
+            translation(4., 0., 0.) * rotation_y(PI / 2.) * scaling(3., 3., 3.),
+            epsilon = 1e-6
+        );
+    }
+
+    fn square_path() -> CameraPath {
+        CameraPath::new(vec![
+            Waypoint::new(point!(0, 0, 0), point!(0, 0, 1)),
+            Waypoint::new(point!(2, 0, 0), point!(2, 0, 1)),
+            Waypoint::new(point!(2, 0, 2), point!(2, 0, 3)),
+            Waypoint::new(point!(0, 0, 2), point!(0, 0, 3)),
+        ])
+    }
+
+    #[test]
+    fn camera_path_passes_through_waypoints() {
+        let test_data = vec![
+            ("open", square_path(), 3.),
+            ("closed", square_path().closed(), 4.),
+        ];
+        for (name, path, segments) in test_data {
+            for (i, waypoint) in path.waypoints.iter().enumerate() {
+                let at = path.at(i as f32 / segments);
+                assert_abs_diff_eq!(at.position, waypoint.position, epsilon = 1e-5);
+                assert_abs_diff_eq!(at.look_at, waypoint.look_at, epsilon = 1e-5);
+                assert_eq!(at.position.w, 1., "Case {}", name);
+            }
+        }
+        // a closed path ends where it started
+        assert_abs_diff_eq!(
+            square_path().closed().at(1.).position,
+            point!(0, 0, 0),
+            epsilon = 1e-5
+        );
+        assert_abs_diff_eq!(square_path().at(1.).position, point!(0, 0, 2));
+    }
+
+    #[test]
+    fn camera_path_curves_smoothly_between_waypoints() {
+        let path = square_path();
+        // halfway along the first stretch; the path bulges away from the next corner
+        let middle = path.at(1. / 6.);
+        assert_abs_diff_eq!(middle.position, point!(1.125, 0, -0.125), epsilon = 1e-5);
+        // no kink at the waypoints: steps just before and after them match
+        let step = 1e-3;
+        let before = path.at(1. / 3.).position - path.at(1. / 3. - step).position;
+        let after = path.at(1. / 3. + step).position - path.at(1. / 3.).position;
+        assert_abs_diff_eq!(before, after, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn camera_timeline_follows_path() {
+        let timeline = square_path().camera_timeline(5, 5, PI / 2., 6.);
+        let camera = timeline(2.);
+        assert_abs_diff_eq!(
+            *camera.transformation(),
+            view_transform(point!(2, 0, 0), point!(2, 0, 1), vector!(0, 1, 0)),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn render_span_includes_both_ends() {
+        let animation = Animation::new(square_path().camera_timeline(3, 3, PI / 2., 2.), |_| {
+            Box::new(PointLight::new(point!(-10, 10, -10), white()))
+        });
+        let out_dir = std::env::temp_dir().join(format!(
+            "ray_tracer_challenge_flythrough_{}",
+            std::process::id()
+        ));
+
+        let paths = animation.render_span(4, 2., &out_dir).unwrap();
+
+        assert_eq!(paths.len(), 4);
+        assert_eq!(paths[3], out_dir.join("frame_0003.ppm"));
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn render_sequence_writes_numbered_frames() {
+        let mut animation = test_animation();
+        animation.add_animated_object(Box::new(Sphere::new()), |t| translation(0., t, 0.));
+        let out_dir = std::env::temp_dir().join(format!(
+            "ray_tracer_challenge_animation_{}",
+            std::process::id()
+        ));
+
+        let paths = animation.render_sequence(3, 24., &out_dir).unwrap();
+
+        assert_eq!(paths.len(), 3);
+        assert_eq!(paths[2], out_dir.join("frame_0002.ppm"));
+        for path in &paths {
+            assert!(fs::read_to_string(path).unwrap().starts_with("P3\n5 5\n"));
+        }
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use crate::matrix::Matrix;
 use crate::ray::Ray;
 use crate::shape::cube::aabb_intersection;
+use crate::stats::{self, Counter};
 use crate::tuple::Tuple;
 use std::f32;
 
@@ -54,6 +55,13 @@ impl BoundingBox {
         self.add_point(other.max);
     }
 
+    // false for empty boxes as well as boxes that extend infinitely along some axis
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+    }
+
     pub fn contains_point(&self, p: Tuple) -> bool {
         p.x.between_inclusive(self.min.x, self.max.x)
             && p.y.between_inclusive(self.min.y, self.max.y)
@@ -64,6 +72,16 @@ impl BoundingBox {
         self.contains_point(other.min) && self.contains_point(other.max)
     }
 
+    // whether the boxes share any points, including just a face or corner
+    pub fn overlaps(&self, other: BoundingBox) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+            && self.min.z <= other.max.z
+            && other.min.z <= self.max.z
+    }
+
     pub fn transform(&self, m: &Matrix) -> BoundingBox {
         let mut new_box = BoundingBox::empty();
         // transform all 8 corners of self and add them to the new bounding box
@@ -77,13 +95,41 @@ impl BoundingBox {
         let p8 = self.max;
 
         for p in vec![p1, p2, p3, p4, p5, p6, p7, p8] {
-            new_box.add_point(m * &p);
+            new_box.add_transformed_corner(m, p);
         }
 
         new_box
     }
 
+    // Adds m * p, where p may have infinite coordinates, as for an unbounded cone or a plane.
+    // Entries of m that are 0 ignore the corresponding coordinate instead of multiplying it to get
+    // NaN. An axis that still comes out NaN (infinity minus infinity, e.g. from a rotation) could
+    // be anything, so the box is made infinite along it.
+    fn add_transformed_corner(&mut self, m: &Matrix, p: Tuple) {
+        let coordinates = [p.x, p.y, p.z, 1.];
+        let mut transformed = [0.; 3];
+        for (row, value) in transformed.iter_mut().enumerate() {
+            *value = m
+                .row(row)
+                .iter()
+                .zip(coordinates.iter())
+                .filter(|(entry, _)| **entry != 0.)
+                .map(|(entry, coordinate)| entry * coordinate)
+                .sum();
+        }
+        let (mut low, mut high) = (transformed, transformed);
+        for axis in 0..3 {
+            if transformed[axis].is_nan() {
+                low[axis] = f32::NEG_INFINITY;
+                high[axis] = f32::INFINITY;
+            }
+        }
+        self.add_point(point!(low[0], low[1], low[2]));
+        self.add_point(point!(high[0], high[1], high[2]));
+    }
+
     pub fn intersects(&self, r: Ray) -> bool {
+        stats::count(Counter::RayAabbTests);
         aabb_intersection(r, self.min, self.max).is_some()
     }
 
@@ -125,11 +171,86 @@ impl BoundingBox {
     }
 }
 
+// Number of boxes in a BoundingBoxPacket
+pub const BOX_PACKET_SIZE: usize = 4;
+
+// Up to BOX_PACKET_SIZE boxes stored component-wise, so that a ray can be tested against all of
+// them in one pass. The per-lane loops are simple enough for the compiler to turn into SIMD
+// instructions.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BoundingBoxPacket {
+    // indexed by axis, then by lane
+    min: [[f32; BOX_PACKET_SIZE]; 3],
+    max: [[f32; BOX_PACKET_SIZE]; 3],
+    len: usize,
+}
+
+impl BoundingBoxPacket {
+    // Packs boxes into packets of BOX_PACKET_SIZE; the last one may be partially filled
+    pub fn pack(boxes: &[BoundingBox]) -> Vec<BoundingBoxPacket> {
+        boxes
+            .chunks(BOX_PACKET_SIZE)
+            .map(|chunk| {
+                let mut packet = BoundingBoxPacket {
+                    len: chunk.len(),
+                    ..Default::default()
+                };
+                for (lane, b) in chunk.iter().enumerate() {
+                    let (min, max) = ([b.min.x, b.min.y, b.min.z], [b.max.x, b.max.y, b.max.z]);
+                    for axis in 0..3 {
+                        packet.min[axis][lane] = min[axis];
+                        packet.max[axis][lane] = max[axis];
+                    }
+                }
+                packet
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Whether r intersects each box; same algorithm as aabb_intersection, one lane per box.
+    // Lanes past len() are always false.
+    pub fn intersects(&self, r: Ray) -> [bool; BOX_PACKET_SIZE] {
+        stats::count_n(Counter::RayAabbTests, self.len as u64);
+        let origin = [r.origin.x, r.origin.y, r.origin.z];
+        let direction_inverses = [
+            r.direction_inverses.x,
+            r.direction_inverses.y,
+            r.direction_inverses.z,
+        ];
+        let mut min_distance = [f32::NEG_INFINITY; BOX_PACKET_SIZE];
+        let mut max_distance = [f32::INFINITY; BOX_PACKET_SIZE];
+        for axis in 0..3 {
+            for lane in 0..BOX_PACKET_SIZE {
+                let d1 = (self.min[axis][lane] - origin[axis]) * direction_inverses[axis];
+                let d2 = (self.max[axis][lane] - origin[axis]) * direction_inverses[axis];
+                min_distance[lane] = min_distance[lane].max(d1.min(d2));
+                max_distance[lane] = max_distance[lane].min(d1.max(d2));
+            }
+        }
+        let mut hits = [false; BOX_PACKET_SIZE];
+        for lane in 0..self.len {
+            hits[lane] = max_distance[lane] >= 0f32.max(min_distance[lane]);
+        }
+        hits
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test::strategies;
     use crate::transformations::rotation_x;
     use crate::transformations::rotation_y;
+    use crate::transformations::{scaling, translation};
+    use proptest::prelude::*;
     use std::f32::consts::PI;
 
     #[test]
@@ -186,6 +307,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_if_bounding_boxes_overlap() {
+        let box1 = BoundingBox::with_bounds(point!(5, -2, 0), point!(11, 4, 7));
+        let test_data = vec![
+            ("inside", point!(6, -1, 1), point!(10, 3, 6), true),
+            ("around", point!(0, -5, -5), point!(20, 5, 10), true),
+            ("partly", point!(10, 3, 6), point!(12, 5, 8), true),
+            ("touching", point!(11, 4, 7), point!(12, 5, 8), true),
+            ("beside", point!(12, -2, 0), point!(14, 4, 7), false),
+            ("diagonal", point!(0, 5, 0), point!(4, 6, 7), false),
+        ];
+        for (name, min, max, expected) in test_data {
+            let box2 = BoundingBox::with_bounds(min, max);
+            assert_eq!(box1.overlaps(box2), expected, "Case {}", name);
+            assert_eq!(box2.overlaps(box1), expected, "Case {}", name);
+        }
+        assert!(!box1.overlaps(BoundingBox::empty()));
+    }
+
     #[test]
     fn transform_bounding_box() {
         let box1 = BoundingBox::with_bounds(point!(-1, -1, -1), point!(1, 1, 1));
@@ -195,6 +335,33 @@ mod tests {
         assert_abs_diff_eq!(box2.max, point!(1.4142135, 1.7071067, 1.7071067));
     }
 
+    #[test]
+    fn transform_infinite_bounding_box() {
+        let infinite = BoundingBox::with_bounds(
+            point!(f32::NEG_INFINITY, -1, f32::NEG_INFINITY),
+            point!(f32::INFINITY, 1, f32::INFINITY),
+        );
+        // moving and scaling keep the finite axis finite
+        let moved = infinite.transform(&(translation(1., 2., 3.) * scaling(2., 2., 2.)));
+        assert_eq!(
+            moved,
+            BoundingBox::with_bounds(
+                point!(f32::NEG_INFINITY, 0, f32::NEG_INFINITY),
+                point!(f32::INFINITY, 4, f32::INFINITY)
+            )
+        );
+        // tilting spreads the infinite axes into the finite one
+        let tilted = infinite.transform(&rotation_x(PI / 4.));
+        assert_eq!(
+            tilted.min,
+            point!(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        );
+        assert_eq!(
+            tilted.max,
+            point!(f32::INFINITY, f32::INFINITY, f32::INFINITY)
+        );
+    }
+
     #[test]
     fn intersecting_ray_with_bounding_box_at_origin() {
         let b = BoundingBox::with_bounds(point!(-1, -1, -1), point!(1, 1, 1));
@@ -285,4 +452,74 @@ mod tests {
         assert_eq!(right.min, point!(-1, -2, 2));
         assert_eq!(right.max, point!(5, 3, 7));
     }
+
+    proptest! {
+        #[test]
+        fn transformed_box_contains_transformed_points(
+            p1 in strategies::point(),
+            p2 in strategies::point(),
+            t in strategies::transform(),
+            fractions in (0f32..1., 0f32..1., 0f32..1.),
+        ) {
+            let mut b = BoundingBox::empty();
+            b.add_point(p1);
+            b.add_point(p2);
+            let transformed = b.transform(&t);
+
+            // the corners are exactly contained, since they define the new box
+            let corners = [
+                b.min,
+                b.max,
+                point!(b.min.x, b.max.y, b.min.z),
+                point!(b.max.x, b.min.y, b.max.z),
+            ];
+            for corner in &corners {
+                assert!(transformed.contains_point(&t * corner));
+            }
+
+            // interior points are contained up to floating point error
+            let (fx, fy, fz) = fractions;
+            let inside = point!(
+                b.min.x + fx * (b.max.x - b.min.x),
+                b.min.y + fy * (b.max.y - b.min.y),
+                b.min.z + fz * (b.max.z - b.min.z)
+            );
+            let p = &t * inside;
+            let epsilon = 1e-3;
+            assert!(p.x >= transformed.min.x - epsilon && p.x <= transformed.max.x + epsilon);
+            assert!(p.y >= transformed.min.y - epsilon && p.y <= transformed.max.y + epsilon);
+            assert!(p.z >= transformed.min.z - epsilon && p.z <= transformed.max.z + epsilon);
+        }
+    }
+
+    #[test]
+    fn packet_intersections_match_single_box_intersections() {
+        let boxes = vec![
+            BoundingBox::with_bounds(point!(-1, -1, -1), point!(1, 1, 1)),
+            BoundingBox::with_bounds(point!(5, -1, -1), point!(6, 1, 1)),
+            BoundingBox::with_bounds(point!(-1, -1, 3), point!(1, 1, 4)),
+            BoundingBox::with_bounds(point!(-1, 5, -1), point!(1, 6, 1)),
+            BoundingBox::with_bounds(point!(0, -1, -10), point!(0, 1, -9)),
+            BoundingBox::with_bounds(point!(-10, -1, -10), point!(10, 1, 10)),
+        ];
+        let packets = BoundingBoxPacket::pack(&boxes);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].len(), 2);
+
+        let rays = vec![
+            Ray::new(point!(0, 0, -5), vector!(0, 0, 1)),
+            Ray::new(point!(0, 0, 5), vector!(0, 0, 1)),
+            Ray::new(point!(0, 0, 0), vector!(1, 0, 0)),
+            Ray::new(point!(0, 10, 0), vector!(0, -1, 0)),
+            Ray::new(point!(-5, -5, -5), vector!(1, 1, 1).norm()),
+        ];
+        for (ray_index, r) in rays.into_iter().enumerate() {
+            let hits: Vec<bool> = packets
+                .iter()
+                .flat_map(|p| p.intersects(r)[..p.len()].to_vec())
+                .collect();
+            let expected: Vec<bool> = boxes.iter().map(|b| b.intersects(r)).collect();
+            assert_eq!(hits, expected, "Case {}", ray_index);
+        }
+    }
 }
@@ -1,10 +1,111 @@
+use crate::bounding_box::BoundingBox;
 use crate::canvas::Canvas;
+use crate::checkpoint::{RenderCheckpoint, Tile};
+use crate::color::Color;
 use crate::matrix::Matrix;
+use crate::post::colormap::viridis;
+use crate::preview::{Preview, PreviewAction};
+use crate::primary_rays::PrimaryRayBackend;
 use crate::ray::Ray;
+use crate::render_settings::{RenderSettings, ShadingMode};
+use crate::sampler;
+use crate::stats::{self, Counter, Phase};
+use crate::transformations::view_transform;
 use crate::tuple::Tuple;
-use crate::world::World;
+use crate::world::{self, RemainingBounces, World};
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
 use std::time::Instant;
 
+// width and height in pixels of the tiles rendered between checkpoints
+pub const CHECKPOINT_TILE_SIZE: usize = 32;
+
+// The opening of a thin lens, which blurs everything not at focal_distance from the camera.
+// Out-of-focus highlights take the aperture's shape: a disc, or a regular polygon with one side
+// per blade of the diaphragm.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aperture {
+    // world space units; for polygons, the distance from the center to each corner
+    pub radius: f32,
+    pub focal_distance: f32,
+    // fewer than 3 blades make a disc
+    pub blades: u32,
+    // radians counter-clockwise from a corner pointing right
+    pub rotation: f32,
+}
+
+impl Aperture {
+    pub fn disc(radius: f32, focal_distance: f32) -> Aperture {
+        Aperture {
+            radius,
+            focal_distance,
+            blades: 0,
+            rotation: 0.,
+        }
+    }
+
+    pub fn polygon(radius: f32, focal_distance: f32, blades: u32, rotation: f32) -> Aperture {
+        Aperture {
+            radius,
+            focal_distance,
+            blades,
+            rotation,
+        }
+    }
+
+    // Maps a pair of numbers in [0, 1) evenly onto the aperture, as an (x, y) offset from its
+    // center
+    fn point_at(&self, u: f32, v: f32) -> (f32, f32) {
+        if self.blades < 3 {
+            let r = self.radius * u.sqrt();
+            let angle = 2. * PI * v + self.rotation;
+            return (r * angle.cos(), r * angle.sin());
+        }
+        // u picks one of the triangles between the center and each side, and is then reused for
+        // the distance from the center; square roots keep the points from bunching up there
+        let sides = self.blades as f32;
+        let scaled = u * sides;
+        let side = scaled.floor().min(sides - 1.);
+        let s = (scaled - side).sqrt();
+        let corner = |i: f32| {
+            let angle = self.rotation + 2. * PI * i / sides;
+            (self.radius * angle.cos(), self.radius * angle.sin())
+        };
+        let (a, b) = (corner(side), corner(side + 1.));
+        (
+            s * ((1. - v) * a.0 + v * b.0),
+            s * ((1. - v) * a.1 + v * b.1),
+        )
+    }
+}
+
+// Radial distortion of a real lens (the Brown-Conrady model without its tangential terms). A point
+// on the canvas at distance r from its center is seen through the point (1 + k1*r^2 + k2*r^4)
+// times as far out, where r is measured in canvas units, i.e. the tangent of the angle to the
+// view direction. Positive coefficients squeeze more of the scene into the edges of the image,
+// bowing straight lines outward (barrel distortion); negative ones bow them inward (pincushion).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensDistortion {
+    pub k1: f32,
+    pub k2: f32,
+}
+
+impl LensDistortion {
+    pub fn new(k1: f32, k2: f32) -> LensDistortion {
+        LensDistortion { k1, k2 }
+    }
+
+    // the point on the canvas seen through canvas point (x, y)
+    fn distort(&self, x: f32, y: f32) -> (f32, f32) {
+        let r_squared = x * x + y * y;
+        let scale = 1. + r_squared * (self.k1 + self.k2 * r_squared);
+        (x * scale, y * scale)
+    }
+}
+
 pub struct Camera {
     // in pixels
     width_pixels: u32,
@@ -16,7 +117,12 @@ pub struct Camera {
     half_height_world: f32,
     pixel_size: f32,
 
+    transform: Matrix,
     transform_inverse: Matrix,
+
+    // None for a pinhole camera, which keeps everything in focus
+    aperture: Option<Aperture>,
+    distortion: Option<LensDistortion>,
 }
 
 impl Camera {
@@ -49,62 +155,777 @@ impl Camera {
             height_pixels,
             field_of_view,
             transform_inverse: transform.inverse(),
+            transform,
             half_width_world,
             half_height_world,
             pixel_size,
+            aperture: None,
+            distortion: None,
+        }
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Camera {
+        self.aperture = Some(aperture);
+        self
+    }
+
+    pub fn with_distortion(mut self, distortion: LensDistortion) -> Camera {
+        self.distortion = Some(distortion);
+        self
+    }
+
+    // Describe a camera by where it is and what it looks at instead of by its view transform:
+    // Camera::builder().size(800, 400).fov(1.2).look_from(point!(0, 1.5, -5)).look_at(point!(0, 1, 0)).build()
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder {
+            width: 640,
+            height: 480,
+            field_of_view: PI / 3.,
+            from: point!(0, 0, 0),
+            to: point!(0, 0, -1),
+            up: vector!(0, 1, 0),
+            aperture: None,
+            distortion: None,
         }
     }
 }
 
+// Camera::builder() starts a 640x480 pinhole camera with a 60 degree field of view at the origin,
+// looking down -z with y up, which is the same as Camera::new with the identity transform.
+pub struct CameraBuilder {
+    width: u32,
+    height: u32,
+    field_of_view: f32,
+    from: Tuple,
+    to: Tuple,
+    up: Tuple,
+    aperture: Option<Aperture>,
+    distortion: Option<LensDistortion>,
+}
+
+impl CameraBuilder {
+    // in pixels
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    // in radians
+    pub fn fov(mut self, field_of_view: f32) -> Self {
+        self.field_of_view = field_of_view;
+        self
+    }
+
+    pub fn look_from(mut self, from: Tuple) -> Self {
+        self.from = from;
+        self
+    }
+
+    pub fn look_at(mut self, to: Tuple) -> Self {
+        self.to = to;
+        self
+    }
+
+    // only needs to point roughly upwards; see view_transform
+    pub fn up(mut self, up: Tuple) -> Self {
+        self.up = up;
+        self
+    }
+
+    pub fn aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = Some(aperture);
+        self
+    }
+
+    pub fn distortion(mut self, distortion: LensDistortion) -> Self {
+        self.distortion = Some(distortion);
+        self
+    }
+
+    pub fn build(self) -> Camera {
+        let mut camera = Camera::new(
+            self.width,
+            self.height,
+            self.field_of_view,
+            view_transform(self.from, self.to, self.up),
+        );
+        camera.aperture = self.aperture;
+        camera.distortion = self.distortion;
+        camera
+    }
+}
+
 impl Camera {
+    pub fn width(&self) -> u32 {
+        self.width_pixels
+    }
+    pub fn height(&self) -> u32 {
+        self.height_pixels
+    }
+    pub fn field_of_view(&self) -> f32 {
+        self.field_of_view
+    }
+    pub fn transformation(&self) -> &Matrix {
+        &self.transform
+    }
+    pub fn aperture(&self) -> Option<&Aperture> {
+        self.aperture.as_ref()
+    }
+    pub fn distortion(&self) -> Option<&LensDistortion> {
+        self.distortion.as_ref()
+    }
+
     pub fn ray_for_pixel(&self, x: u32, y: u32) -> Ray {
-        // offset from edge of canvas to pixel's center
-        let x_offset = (x as f32 + 0.5) * self.pixel_size;
-        let y_offset = (y as f32 + 0.5) * self.pixel_size;
+        self.ray_through(x as f32 + 0.5, y as f32 + 0.5)
+    }
+
+    // The ray through the given point on the canvas, measured in pixels from its top left corner,
+    // from the center of the lens
+    fn ray_through(&self, x: f32, y: f32) -> Ray {
+        self.ray_through_lens(x, y, (0., 0.))
+    }
+
+    // Like ray_through, but starting from the given offset from the center of the lens, in world
+    // space units. Rays from anywhere on the lens meet again at the focal distance.
+    fn ray_through_lens(&self, x: f32, y: f32, lens: (f32, f32)) -> Ray {
+        // offset from edge of canvas to the point
+        let x_offset = x * self.pixel_size;
+        let y_offset = y * self.pixel_size;
         // untransformed coordinates of the pixel in world space
         // camera looks toward -z, so +x is to the left
         let world_x = self.half_width_world - x_offset;
         let world_y = self.half_height_world - y_offset;
+        let (world_x, world_y) = match &self.distortion {
+            Some(distortion) => distortion.distort(world_x, world_y),
+            None => (world_x, world_y),
+        };
         // use camera matrix to transform the canvas point and the origin, then get ray's direction vector
         // canvas is located at z=-1
         let pixel: Tuple = &self.transform_inverse * &point!(world_x, world_y, -1);
-        let origin: Tuple = &self.transform_inverse * &point!(0, 0, 0);
-        let direction = (pixel - origin).norm();
+        let eye: Tuple = &self.transform_inverse * &point!(0, 0, 0);
+        let (origin, direction) = match &self.aperture {
+            None => (eye, (pixel - eye).norm()),
+            Some(aperture) => {
+                let d = aperture.focal_distance;
+                let focus: Tuple = &self.transform_inverse * point!(world_x * d, world_y * d, -d);
+                let origin: Tuple = &self.transform_inverse * point!(lens.0, lens.1, 0);
+                (origin, (focus - origin).norm())
+            }
+        };
+        // the distance to the neighboring pixel, per unit of distance from the camera
+        let neighbor: Tuple =
+            &self.transform_inverse * point!(world_x - self.pixel_size, world_y, -1);
+        let spread = (neighbor - pixel).magnitude() / (pixel - eye).magnitude();
         Ray::new(origin, direction)
+            .with_cone(0., spread)
+            .as_primary()
     }
 
-    pub fn render(&self, world: World, reflection_recursion_depth: i16) -> Canvas {
-        let mut canvas = Canvas::new(self.width_pixels as usize, self.height_pixels as usize);
+    // A camera with the same view whose image is scaled by the given factor
+    pub fn scaled(&self, scale: f32) -> Camera {
+        let scale_pixels = |pixels: u32| ((pixels as f32 * scale).round() as u32).max(1);
+        Camera {
+            aperture: self.aperture,
+            distortion: self.distortion,
+            ..Camera::new(
+                scale_pixels(self.width_pixels),
+                scale_pixels(self.height_pixels),
+                self.field_of_view,
+                self.transform.clone(),
+            )
+        }
+    }
+
+    // The average color seen through the given number of points spread over the pixel by the
+    // sampler. A single sample goes through the pixel's center, like ray_for_pixel.
+    pub fn sample_pixel(
+        &self,
+        world: &World,
+        x: u32,
+        y: u32,
+        samples: u32,
+        remaining_bounces: impl Into<RemainingBounces>,
+    ) -> Color {
+        let mut estimate = PixelEstimate::default();
+        self.sample_batch(
+            world,
+            x,
+            y,
+            samples,
+            0,
+            remaining_bounces.into(),
+            &mut estimate,
+        );
+        estimate.mean()
+    }
+
+    // Like sample_pixel, but keeps adding batches of samples while they disagree, as described by
+    // RenderSettings::max_samples_per_pixel. The first batch takes the same samples as sample_pixel.
+    pub fn sample_pixel_adaptively(
+        &self,
+        world: &World,
+        x: u32,
+        y: u32,
+        settings: &RenderSettings,
+    ) -> Color {
+        let batch_size = settings.samples_per_pixel.max(1);
+        let max_samples = settings.max_samples_per_pixel.unwrap_or(0).max(batch_size);
+        let mut estimate = PixelEstimate::default();
+        let mut batch = 0;
+        loop {
+            let bounces = settings.max_bounces();
+            self.sample_batch(world, x, y, batch_size, batch, bounces, &mut estimate);
+            if estimate.samples + batch_size > max_samples
+                || estimate.standard_error() <= settings.adaptive_tolerance
+            {
+                return estimate.mean();
+            }
+            batch += 1;
+        }
+    }
+
+    // Adds a batch of samples of the pixel to estimate, continuing the sampler's sequence from
+    // the previous batches
+    #[allow(clippy::too_many_arguments)]
+    fn sample_batch(
+        &self,
+        world: &World,
+        x: u32,
+        y: u32,
+        samples: u32,
+        batch: u32,
+        remaining_bounces: RemainingBounces,
+        estimate: &mut PixelEstimate,
+    ) {
+        let samples = samples.max(1);
+        for i in 0..samples {
+            stats::count(Counter::PixelSamples);
+            let seed = world.render_settings.seed;
+            sampler::start_sample(seed, x, y, batch * samples + i);
+            // the first dimensions place the ray on the pixel and the lens, and the rest are
+            // left for shading
+            let (x_fraction, y_fraction) = (sampler::next_1d(), sampler::next_1d());
+            let lens = match &self.aperture {
+                Some(aperture) => aperture.point_at(sampler::next_1d(), sampler::next_1d()),
+                None => (0., 0.),
+            };
+            let (x_fraction, y_fraction, lens) = if samples == 1 && batch == 0 {
+                (0.5, 0.5, (0., 0.))
+            } else {
+                (x_fraction, y_fraction, lens)
+            };
+            let ray = self.ray_through_lens(x as f32 + x_fraction, y as f32 + y_fraction, lens);
+            estimate.add(world.color_at(ray, remaining_bounces));
+        }
+    }
+
+    // the color of the pixel as settings ask for it to be sampled
+    fn pixel_color(&self, world: &World, x: u32, y: u32, settings: &RenderSettings) -> Color {
+        let _timer = stats::time_phase(Phase::PrimaryRays);
+        if let ShadingMode::Heatmap { max_tests } = settings.shading {
+            let (_, counts) =
+                stats::count_on_thread(|| self.shaded_pixel_color(world, x, y, settings));
+            let tests = counts[Counter::RayAabbTests] + counts[Counter::RayPrimitiveTests];
+            return viridis(tests as f32 / max_tests.max(1) as f32);
+        }
+        self.shaded_pixel_color(world, x, y, settings)
+    }
+
+    fn shaded_pixel_color(
+        &self,
+        world: &World,
+        x: u32,
+        y: u32,
+        settings: &RenderSettings,
+    ) -> Color {
+        if settings.max_samples_per_pixel.is_some() {
+            self.sample_pixel_adaptively(world, x, y, settings)
+        } else {
+            self.sample_pixel(
+                world,
+                x,
+                y,
+                settings.samples_per_pixel,
+                settings.max_bounces(),
+            )
+        }
+    }
+
+    // Render with the resolution and quality given by settings, which also replace the world's
+    // own render_settings. The image is this camera's size scaled by settings.resolution_scale.
+    // The world is only borrowed, so that it can be edited and passed to rerender_dirty afterwards.
+    pub fn render(&self, world: &mut World, settings: RenderSettings) -> Canvas {
+        world.render_settings = settings;
+        let world = &*world;
+        let camera = self.scaled(settings.resolution_scale);
+        let mut canvas = Canvas::new(camera.width_pixels as usize, camera.height_pixels as usize);
 
         let start = Instant::now();
-        for y in 0..self.height_pixels - 1 {
-            for x in 0..self.width_pixels - 1 {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(ray, reflection_recursion_depth);
-                canvas.write_pixel(x as usize, y as usize, color);
+        let threads = settings.render_threads.max(1) as u32;
+        if threads == 1 {
+            for y in 0..camera.height_pixels {
+                write_row(&mut canvas, y, &camera.render_row(world, y, &settings));
             }
-            eprintln!("Rendered y {}/{}", y, self.height_pixels);
+        } else {
+            // interleaved rows, so that each thread gets a share of every part of the image
+            let camera = &camera;
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|thread| {
+                        scope.spawn(move || {
+                            let rows: Vec<(u32, Vec<Color>)> = (thread..camera.height_pixels)
+                                .step_by(threads as usize)
+                                .map(|y| (y, camera.render_row(world, y, &settings)))
+                                .collect();
+                            if stats::is_enabled() {
+                                stats::flush_thread();
+                            }
+                            rows
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    for (y, row) in handle.join().expect("Render thread panicked") {
+                        write_row(&mut canvas, y, &row);
+                    }
+                }
+            });
         }
         let duration = start.elapsed();
         eprintln!("Time elapsed in render() is: {:?}", duration);
+        stats::print_summary();
+        canvas
+    }
+
+    fn render_row(&self, world: &World, y: u32, settings: &RenderSettings) -> Vec<Color> {
+        world::clear_shadow_cache();
+        let row = (0..self.width_pixels)
+            .map(|x| self.pixel_color(world, x, y, settings))
+            .collect();
+        eprintln!("Rendered y {}/{}", y, self.height_pixels);
+        row
+    }
+
+    // Render like render, but with the primary rays traced by backend. Pixels that the backend
+    // leaves out are traced on the CPU, on this thread.
+    pub fn render_with_backend(
+        &self,
+        world: &mut World,
+        settings: RenderSettings,
+        backend: &mut dyn PrimaryRayBackend,
+    ) -> Canvas {
+        world.render_settings = settings;
+        let world = &*world;
+        let camera = self.scaled(settings.resolution_scale);
+        let width = camera.width_pixels as usize;
+        let mut canvas = Canvas::new(width, camera.height_pixels as usize);
+
+        let start = Instant::now();
+        let colors = backend.trace(&camera, world);
+        assert_eq!(
+            colors.len(),
+            width * canvas.height,
+            "Backend must return a color or None for every pixel"
+        );
+        let mut cpu_pixels = 0;
+        for (i, color) in colors.into_iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let color = color.unwrap_or_else(|| {
+                cpu_pixels += 1;
+                camera.pixel_color(world, x as u32, y as u32, &settings)
+            });
+            canvas.write_pixel(x, y, color);
+        }
+        eprintln!(
+            "Traced {} of {} pixels on the CPU; time elapsed in render_with_backend() is: {:?}",
+            cpu_pixels,
+            width * canvas.height,
+            start.elapsed()
+        );
+        stats::print_summary();
         canvas
     }
+
+    // Render tile by tile, saving progress to checkpoint_path after every tiles_per_checkpoint
+    // tiles. If a checkpoint from an interrupted render already exists at that path, its
+    // completed tiles are kept and only the remaining ones are rendered. The checkpoint file is
+    // removed once the render is finished.
+    pub fn render_with_checkpoints(
+        &self,
+        mut world: World,
+        settings: RenderSettings,
+        checkpoint_path: &Path,
+        tiles_per_checkpoint: usize,
+    ) -> io::Result<Canvas> {
+        world.render_settings = settings;
+        let camera = self.scaled(settings.resolution_scale);
+        let mut checkpoint = if checkpoint_path.exists() {
+            let checkpoint = RenderCheckpoint::load(checkpoint_path)?;
+            if checkpoint.canvas.width != camera.width_pixels as usize
+                || checkpoint.canvas.height != camera.height_pixels as usize
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Checkpoint is for a {}x{} render, but camera is {}x{}",
+                        checkpoint.canvas.width,
+                        checkpoint.canvas.height,
+                        camera.width_pixels,
+                        camera.height_pixels
+                    ),
+                ));
+            }
+            eprintln!(
+                "Resuming render from checkpoint; {}/{} tiles remaining",
+                checkpoint.remaining_tiles().len(),
+                checkpoint.num_tiles()
+            );
+            checkpoint
+        } else {
+            RenderCheckpoint::new(
+                camera.width_pixels as usize,
+                camera.height_pixels as usize,
+                CHECKPOINT_TILE_SIZE,
+            )
+        };
+
+        let start = Instant::now();
+        let remaining = checkpoint.remaining_tiles();
+        for (rendered, &index) in remaining.iter().enumerate() {
+            let tile = checkpoint.tile(index);
+            camera.render_tile(&world, settings, tile, &mut checkpoint.canvas);
+            checkpoint.mark_tile_complete(index);
+            if (rendered + 1) % tiles_per_checkpoint.max(1) == 0 && !checkpoint.is_complete() {
+                checkpoint.save(checkpoint_path)?;
+                eprintln!("Saved checkpoint after tile {}", index);
+            }
+        }
+        eprintln!(
+            "Time elapsed in render_with_checkpoints() is: {:?}",
+            start.elapsed()
+        );
+
+        stats::print_summary();
+
+        if checkpoint_path.exists() {
+            fs::remove_file(checkpoint_path)?;
+        }
+        Ok(checkpoint.canvas)
+    }
+
+    // Render while showing the progress in preview, as described there: one pass over all of the
+    // tiles for each of settings.samples_per_pixel. Adaptive sampling is not used. None if the
+    // preview asked to abort.
+    pub fn render_progressive(
+        &self,
+        mut world: World,
+        settings: RenderSettings,
+        preview: &mut dyn Preview,
+    ) -> io::Result<Option<Canvas>> {
+        world.render_settings = settings;
+        let camera = self.scaled(settings.resolution_scale);
+        let width = camera.width_pixels as usize;
+        let height = camera.height_pixels as usize;
+        // only used to split the canvas into tiles
+        let tiles = RenderCheckpoint::new(width, height, CHECKPOINT_TILE_SIZE);
+        let mut canvas = Canvas::new(width, height);
+        let mut estimates: Vec<PixelEstimate> = (0..width * height)
+            .map(|_| PixelEstimate::default())
+            .collect();
+
+        let start = Instant::now();
+        let passes = settings.samples_per_pixel.max(1);
+        for pass in 0..passes {
+            for index in 0..tiles.num_tiles() {
+                let tile = tiles.tile(index);
+                world::clear_shadow_cache();
+                {
+                    let _timer = stats::time_phase(Phase::PrimaryRays);
+                    for y in tile.y_min..tile.y_max {
+                        for x in tile.x_min..tile.x_max {
+                            let estimate = &mut estimates[y * width + x];
+                            let bounces = settings.max_bounces();
+                            camera.sample_batch(
+                                &world, x as u32, y as u32, 1, pass, bounces, estimate,
+                            );
+                            canvas.write_pixel(x, y, estimate.mean());
+                        }
+                    }
+                }
+                preview.show(&canvas, tile);
+                match preview.poll() {
+                    PreviewAction::Continue => {}
+                    PreviewAction::Save(path) => fs::write(path, canvas.to_ppm())?,
+                    PreviewAction::Abort => return Ok(None),
+                }
+            }
+            eprintln!("Finished pass {}/{}", pass + 1, passes);
+        }
+        eprintln!(
+            "Time elapsed in render_progressive() is: {:?}",
+            start.elapsed()
+        );
+        stats::print_summary();
+        Ok(Some(canvas))
+    }
+
+    // Re-render only the pixels of canvas, an earlier render of world with this camera, whose rays
+    // could reach objects changed through World::add, edit or remove since the last clear_changes,
+    // and then clear the changes. Besides the pixels that see the changed regions directly, these
+    // are the pixels whose surfaces the changes could shadow and those showing reflective or
+    // transparent surfaces, whose secondary rays could go anywhere. Settings come from
+    // world.render_settings, as set by render. Changes to the light are not caught; render
+    // everything again after those. Returns the number of pixels rendered.
+    pub fn rerender_dirty(&self, world: &mut World, canvas: &mut Canvas) -> usize {
+        let settings = world.render_settings;
+        let camera = self.scaled(settings.resolution_scale);
+        let width = camera.width_pixels as usize;
+        let height = camera.height_pixels as usize;
+        assert!(
+            canvas.width == width && canvas.height == height,
+            "Canvas is {}x{}, but camera renders {}x{}",
+            canvas.width,
+            canvas.height,
+            width,
+            height
+        );
+
+        world::clear_shadow_cache();
+        let mut dirty = vec![false; width * height];
+        for &bbox in &world.changed_regions {
+            if let Some(tile) = camera.pixels_seeing(bbox) {
+                for y in tile.y_min..tile.y_max {
+                    for x in tile.x_min..tile.x_max {
+                        dirty[y * width + x] = true;
+                    }
+                }
+            }
+        }
+        if !world.changed_regions.is_empty() {
+            // the pixel's samples may hit other surfaces than its center, so neighbors are
+            // included, as in pixels_seeing
+            let indirect: Vec<usize> = (0..dirty.len())
+                .filter(|&i| {
+                    !dirty[i]
+                        && camera.sees_changes_indirectly(
+                            world,
+                            (i % width) as u32,
+                            (i / width) as u32,
+                        )
+                })
+                .collect();
+            for index in indirect {
+                let (x, y) = (index % width, index / width);
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        dirty[ny * width + nx] = true;
+                    }
+                }
+            }
+        }
+
+        let _timer = stats::time_phase(Phase::PrimaryRays);
+        let mut rendered = 0;
+        for index in (0..dirty.len()).filter(|&i| dirty[i]) {
+            let (x, y) = (index % width, index / width);
+            let color = camera.pixel_color(world, x as u32, y as u32, &settings);
+            canvas.write_pixel(x, y, color);
+            rendered += 1;
+        }
+        world.clear_changes();
+        rendered
+    }
+
+    // Whether the surface seen at the center of pixel (x, y) could look different because of
+    // world.changed_regions without the pixel's rays passing through them: because a changed
+    // region lies between it and the light, or because it reflects or refracts.
+    fn sees_changes_indirectly(&self, world: &World, x: u32, y: u32) -> bool {
+        let ray = self.ray_for_pixel(x, y);
+        let xs = world.intersect(ray);
+        let hit = match xs.hit() {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let material = hit.material();
+        if material.reflective > 0. || material.transparency > 0. {
+            return true;
+        }
+        let light = match &world.light {
+            Some(light) if light.casts_shadows() && world.render_settings.shadows => light,
+            _ => return false,
+        };
+        // every shadow ray from the point lies within this box
+        let mut shadow_rays = light.bounding_box();
+        shadow_rays.add_point(ray.position(hit.distance));
+        world
+            .changed_regions
+            .iter()
+            .any(|region| region.overlaps(shadow_rays))
+    }
+
+    // The pixels whose rays may pass through bbox, which is in world space; None if there are
+    // none. Found by projecting the corners of bbox onto the canvas, plus a pixel of margin for
+    // samples spread over each pixel.
+    fn pixels_seeing(&self, bbox: BoundingBox) -> Option<Tile> {
+        if bbox.min.x > bbox.max.x || bbox.min.y > bbox.max.y || bbox.min.z > bbox.max.z {
+            return None;
+        }
+        let everything = Tile {
+            x_min: 0,
+            y_min: 0,
+            x_max: self.width_pixels as usize,
+            y_max: self.height_pixels as usize,
+        };
+        // lenses send rays in directions that don't match a simple projection
+        if self.aperture.is_some() || self.distortion.is_some() || !bbox.is_finite() {
+            return Some(everything);
+        }
+        // the camera looks down -z from the origin in camera space; corners at or behind the eye
+        // can't be projected
+        let camera_space = bbox.transform(&self.transform);
+        if camera_space.max.z >= 0. {
+            return Some(everything);
+        }
+        let (mut x_min, mut x_max) = (f32::INFINITY, f32::NEG_INFINITY);
+        let (mut y_min, mut y_max) = (f32::INFINITY, f32::NEG_INFINITY);
+        for &x in &[camera_space.min.x, camera_space.max.x] {
+            for &y in &[camera_space.min.y, camera_space.max.y] {
+                for &z in &[camera_space.min.z, camera_space.max.z] {
+                    // inverse of the canvas to world mapping in ray_through
+                    let canvas_x = (self.half_width_world - x / -z) / self.pixel_size;
+                    let canvas_y = (self.half_height_world - y / -z) / self.pixel_size;
+                    x_min = x_min.min(canvas_x);
+                    x_max = x_max.max(canvas_x);
+                    y_min = y_min.min(canvas_y);
+                    y_max = y_max.max(canvas_y);
+                }
+            }
+        }
+        let to_pixels = |min: f32, max: f32, size: u32| {
+            let first = (min.floor() - 1.).max(0.) as usize;
+            let end = ((max.ceil() + 1.).max(0.) as usize).min(size as usize);
+            (first, end)
+        };
+        let (x_first, x_end) = to_pixels(x_min, x_max, self.width_pixels);
+        let (y_first, y_end) = to_pixels(y_min, y_max, self.height_pixels);
+        if x_first >= x_end || y_first >= y_end {
+            return None;
+        }
+        Some(Tile {
+            x_min: x_first,
+            y_min: y_first,
+            x_max: x_end,
+            y_max: y_end,
+        })
+    }
+
+    fn render_tile(
+        &self,
+        world: &World,
+        settings: RenderSettings,
+        tile: Tile,
+        canvas: &mut Canvas,
+    ) {
+        world::clear_shadow_cache();
+        for y in tile.y_min..tile.y_max {
+            for x in tile.x_min..tile.x_max {
+                let color = self.pixel_color(world, x as u32, y as u32, &settings);
+                canvas.write_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+fn write_row(canvas: &mut Canvas, y: u32, row: &[Color]) {
+    for (x, &color) in row.iter().enumerate() {
+        canvas.write_pixel(x, y as usize, color);
+    }
+}
+
+// Running totals of a pixel's samples, for their average and how far off it may still be
+#[derive(Default)]
+struct PixelEstimate {
+    samples: u32,
+    sum: Color,
+    sum_of_squares: Color,
+}
+
+impl PixelEstimate {
+    fn add(&mut self, sample: Color) {
+        self.samples += 1;
+        self.sum += sample;
+        self.sum_of_squares += sample * sample;
+    }
+
+    fn mean(&self) -> Color {
+        self.sum / self.samples as f32
+    }
+
+    // The expected distance of the mean from the pixel's true color, estimated from how much the
+    // samples vary, in the channel where it is largest. A single sample tells nothing about it.
+    fn standard_error(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.samples as f32;
+        let mean = self.mean();
+        let variance = (self.sum_of_squares / n - mean * mean) * (n / (n - 1.));
+        let largest = variance.r.max(variance.g).max(variance.b).max(0.);
+        (largest / n).sqrt()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::color::Color;
-    use crate::constants::DEFAULT_RAY_RECURSION_DEPTH;
+    use crate::constants::{red, DEFAULT_RAY_RECURSION_DEPTH};
+    use crate::light::rectangle_light::RectangleLight;
+    use crate::material::Material;
     use crate::matrix::identity_4x4;
+    use crate::shape::sphere::Sphere;
     use crate::transformations::rotation_y;
+    use crate::transformations::scaling;
     use crate::transformations::translation;
-    use crate::transformations::view_transform;
     use crate::world::World;
     use approx::AbsDiffEq;
     use std::f32::consts::FRAC_1_SQRT_2;
     use std::f32::consts::PI;
 
+    #[test]
+    fn builder_computes_view_transform() {
+        let c = Camera::builder()
+            .size(80, 40)
+            .fov(1.2)
+            .look_from(point!(1, 3, 2))
+            .look_at(point!(4, -2, 8))
+            .up(vector!(1, 1, 0))
+            .aperture(Aperture::disc(0.1, 5.))
+            .build();
+        let expected = Camera::new(
+            80,
+            40,
+            1.2,
+            view_transform(point!(1, 3, 2), point!(4, -2, 8), vector!(1, 1, 0)),
+        );
+        assert_eq!((c.width(), c.height()), (80, 40));
+        assert_eq!(c.field_of_view(), 1.2);
+        assert_eq!(c.transformation(), expected.transformation());
+        assert_eq!(c.pixel_size, expected.pixel_size);
+        assert_eq!(c.aperture(), Some(&Aperture::disc(0.1, 5.)));
+        assert_eq!(c.distortion(), None);
+    }
+
+    #[test]
+    fn builder_defaults_to_identity_view() {
+        let c = Camera::builder().build();
+        assert_eq!((c.width(), c.height()), (640, 480));
+        assert_eq!(c.field_of_view(), PI / 3.);
+        assert_eq!(c.transformation(), &identity_4x4());
+        assert!(c.aperture().is_none());
+    }
+
     #[test]
     fn horizontal_canvas_pixel_size() {
         let c = Camera::new(200, 125, PI / 2.0, identity_4x4());
@@ -125,6 +946,16 @@ mod tests {
         assert_abs_diff_eq!(r.direction, vector!(0, 0, -1));
     }
 
+    #[test]
+    fn ray_for_pixel_widens_by_pixel_size() {
+        let c = Camera::new(201, 101, PI / 2.0, identity_4x4());
+        let r = c.ray_for_pixel(100, 50);
+        assert_eq!(r.footprint, 0.);
+        assert!(r.primary);
+        assert_abs_diff_eq!(r.spread, c.pixel_size);
+        assert_abs_diff_eq!(r.width_at(10.), 10. * c.pixel_size);
+    }
+
     #[test]
     fn construct_ray_through_canvas_corner() {
         let c = Camera::new(201, 101, PI / 2.0, identity_4x4());
@@ -152,17 +983,544 @@ mod tests {
         assert_abs_diff_eq!(r.direction, vector!(FRAC_1_SQRT_2, 0, -FRAC_1_SQRT_2));
     }
 
+    #[test]
+    fn aperture_points_stay_inside_its_shape() {
+        let test_data = vec![
+            ("disc", Aperture::disc(2., 10.)),
+            ("triangle", Aperture::polygon(2., 10., 3, 0.)),
+            ("rotated hexagon", Aperture::polygon(2., 10., 6, PI / 6.)),
+        ];
+        for (name, aperture) in test_data {
+            let sides = aperture.blades.max(3) as f32;
+            // the distance from the center to the middle of a side
+            let apothem = if aperture.blades < 3 {
+                2.
+            } else {
+                2. * (PI / sides).cos()
+            };
+            let mut farthest: f32 = 0.;
+            for i in 0..64 {
+                let (x, y) = aperture.point_at(
+                    sampler::sample(0, 0, 0, i, 2),
+                    sampler::sample(0, 0, 0, i, 3),
+                );
+                let distance = (x * x + y * y).sqrt();
+                assert!(distance <= 2. + 1e-5, "Case {}: {}, {}", name, x, y);
+                if aperture.blades >= 3 {
+                    // project onto the normal of the side the point's angle falls in
+                    let angle = (y.atan2(x) - aperture.rotation).rem_euclid(2. * PI);
+                    let side = (angle / (2. * PI / sides)).floor();
+                    let normal = aperture.rotation + (side + 0.5) * 2. * PI / sides;
+                    let along_normal = x * normal.cos() + y * normal.sin();
+                    assert!(
+                        along_normal <= apothem + 1e-5,
+                        "Case {}: {}, {}",
+                        name,
+                        x,
+                        y
+                    );
+                }
+                farthest = farthest.max(distance);
+            }
+            assert!(farthest > apothem * 0.8, "Case {}: {}", name, farthest);
+        }
+    }
+
+    #[test]
+    fn rays_through_lens_meet_at_focal_distance() {
+        let c = Camera::new(201, 101, PI / 2.0, identity_4x4())
+            .with_aperture(Aperture::polygon(0.5, 4., 5, 0.3));
+        let pinhole = c.ray_for_pixel(30, 20);
+        let focus = pinhole.position(4. / -pinhole.direction.z);
+        for &lens in &[(0.2, 0.1), (-0.3, 0.25), (0., -0.4)] {
+            let r = c.ray_through_lens(30.5, 20.5, lens);
+            assert_abs_diff_eq!(r.origin, point!(lens.0, lens.1, 0));
+            assert_abs_diff_eq!(r.position(-4. / r.direction.z), focus, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn scaling_keeps_aperture_and_distortion() {
+        let aperture = Aperture::polygon(0.1, 3., 6, 0.);
+        let distortion = LensDistortion::new(0.1, 0.);
+        let c = default_world_camera(20, 10)
+            .with_aperture(aperture)
+            .with_distortion(distortion);
+        let scaled = c.scaled(0.5);
+        assert_eq!(scaled.aperture(), Some(&aperture));
+        assert_eq!(scaled.distortion(), Some(&distortion));
+    }
+
+    #[test]
+    fn lens_distortion_bends_rays_away_from_center() {
+        let plain = Camera::new(201, 101, PI / 2.0, identity_4x4());
+        let test_data = vec![
+            ("barrel", LensDistortion::new(0.2, 0.05), 1.),
+            ("pincushion", LensDistortion::new(-0.2, 0.), -1.),
+        ];
+        for (name, distortion, outward) in test_data {
+            let c = Camera::new(201, 101, PI / 2.0, identity_4x4()).with_distortion(distortion);
+            // the center is undistorted
+            assert_abs_diff_eq!(c.ray_for_pixel(100, 50).direction, vector!(0, 0, -1));
+            let canvas_point = |r: Ray| {
+                (
+                    r.direction.x / -r.direction.z,
+                    r.direction.y / -r.direction.z,
+                )
+            };
+            let (x, y) = canvas_point(plain.ray_for_pixel(0, 0));
+            let (distorted_x, distorted_y) = canvas_point(c.ray_for_pixel(0, 0));
+            let r_squared = x * x + y * y;
+            let expected_scale =
+                1. + distortion.k1 * r_squared + distortion.k2 * r_squared * r_squared;
+            assert_abs_diff_eq!(distorted_x, x * expected_scale, epsilon = 1e-5);
+            assert_abs_diff_eq!(distorted_y, y * expected_scale, epsilon = 1e-5);
+            assert!((expected_scale - 1.) * outward > 0., "Case {}", name);
+        }
+    }
+
     #[test]
     fn render_world() {
-        let w = World::default();
+        let mut w = World::default();
         let from = point!(0, 0, -5);
         let to = point!(0, 0, 0);
         let up = vector!(0, 1, 0);
         let c = Camera::new(11, 11, PI / 2.0, view_transform(from, to, up));
-        let image = c.render(w, DEFAULT_RAY_RECURSION_DEPTH);
+        let image = c.render(&mut w, RenderSettings::default());
+        assert_abs_diff_eq!(
+            image.pixel_at(5, 5),
+            color!(0.380_632_88, 0.475_791_04, 0.285_474_66)
+        );
+    }
+
+    #[test]
+    fn heatmap_shows_intersection_tests() {
+        let c = Camera::new(
+            11,
+            11,
+            PI / 2.0,
+            view_transform(point!(0, 0, -5), point!(0, 0, 0), vector!(0, 1, 0)),
+        );
+        let settings = |render_threads| RenderSettings {
+            shading: ShadingMode::Heatmap { max_tests: 10 },
+            render_threads,
+            ..RenderSettings::default()
+        };
+        let image = c.render(&mut World::default(), settings(1));
+        // the ray through the corner only tests the two spheres, but the one through the center
+        // also casts a shadow ray, which tests them again
+        assert_abs_diff_eq!(image.pixel_at(0, 0), viridis(0.2));
+        assert_abs_diff_eq!(image.pixel_at(5, 5), viridis(0.4));
+        assert_eq!(
+            c.render(&mut World::default(), settings(3)).to_ppm(),
+            image.to_ppm()
+        );
+    }
+
+    #[test]
+    fn heatmap_counts_tests_on_intersection_threads() {
+        let c = Camera::new(
+            3,
+            3,
+            PI / 2.0,
+            view_transform(point!(0, 0, -5), point!(0, 0, 0), vector!(0, 1, 0)),
+        );
+        let render = |intersect_threads| {
+            let mut w = World::default();
+            w.objects.clear();
+            for i in 0..128 {
+                w.objects.push(Box::new(Sphere::build(
+                    translation(0., 0., i as f32 * 3.),
+                    Material::default(),
+                )));
+            }
+            w.intersect_threads = intersect_threads;
+            c.render(
+                &mut w,
+                RenderSettings {
+                    shading: ShadingMode::Heatmap { max_tests: 1000 },
+                    ..RenderSettings::default()
+                },
+            )
+        };
+        let serial = render(1);
+        let parallel = render(4);
+        // the center ray tests all 128 spheres, and its shadow ray tests them again
+        assert_abs_diff_eq!(serial.pixel_at(1, 1), viridis(0.256));
+        assert_eq!(parallel.pixel_at(1, 1), serial.pixel_at(1, 1));
+        assert_eq!(parallel.to_ppm(), serial.to_ppm());
+    }
+
+    #[test]
+    fn render_size_is_scaled_by_settings() {
+        let c = default_world_camera(40, 20);
+        let test_data = vec![
+            ("draft", RenderSettings::draft(), 10, 5),
+            ("medium", RenderSettings::medium(), 20, 10),
+            ("final", RenderSettings::final_(), 40, 20),
+        ];
+        for (name, settings, width, height) in test_data {
+            let image = c.render(&mut World::default(), settings);
+            assert_eq!(
+                (image.width, image.height),
+                (width, height),
+                "Case {}",
+                name
+            );
+        }
+
+        let c = default_world_camera(11, 11);
+        let image = c.render(&mut World::default(), RenderSettings::default());
+        assert_abs_diff_eq!(
+            image.pixel_at(5, 5),
+            color!(0.380_632_88, 0.475_791_04, 0.285_474_66)
+        );
+    }
+
+    #[test]
+    fn samples_are_averaged_over_pixel() {
+        let c = default_world_camera(11, 11);
+        let w = World::default();
+        assert_eq!(
+            c.sample_pixel(&w, 3, 4, 1, DEFAULT_RAY_RECURSION_DEPTH),
+            w.color_at(c.ray_for_pixel(3, 4), DEFAULT_RAY_RECURSION_DEPTH)
+        );
+        // the pixel at the sphere's edge is partly covered by the background
+        let edge = c.sample_pixel(&w, 4, 5, 16, DEFAULT_RAY_RECURSION_DEPTH);
+        let center = w.color_at(c.ray_for_pixel(4, 5), DEFAULT_RAY_RECURSION_DEPTH);
+        assert!(edge.g > 0. && edge.g < center.g);
+    }
+
+    #[test]
+    fn standard_error_of_pixel_samples() {
+        let error = |samples: &[Color]| {
+            let mut estimate = PixelEstimate::default();
+            for &sample in samples {
+                estimate.add(sample);
+            }
+            estimate.standard_error()
+        };
+        let (black, white) = (color!(0, 0, 0), color!(1, 1, 1));
+        assert_eq!(error(&[white]), f32::INFINITY);
+        assert_eq!(error(&[white, white, white]), 0.);
+        // the variance of the samples is 1/3, and the mean's is a quarter of that
+        assert_abs_diff_eq!(error(&[black, white, black, white]), (1. / 12_f32).sqrt());
+        // the largest channel counts
+        assert_abs_diff_eq!(
+            error(&[color!(0, 0.5, 0), color!(0, 0.5, 1)]),
+            error(&[black, white])
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_in_smooth_areas() {
+        let c = default_world_camera(11, 11);
+        let w = World::default();
+        let settings = RenderSettings {
+            samples_per_pixel: 4,
+            max_samples_per_pixel: Some(64),
+            ..RenderSettings::default()
+        };
+        // the background: all samples agree, so only the first batch is taken
+        assert_eq!(
+            c.sample_pixel_adaptively(&w, 0, 0, &settings),
+            c.sample_pixel(&w, 0, 0, 4, settings.max_bounces())
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_refines_edges() {
+        let c = default_world_camera(11, 11);
+        let w = World::default();
+        let settings = RenderSettings {
+            samples_per_pixel: 2,
+            max_samples_per_pixel: Some(64),
+            adaptive_tolerance: 0.001,
+            ..RenderSettings::default()
+        };
+        let bounces = settings.max_bounces();
+        let reference = c.sample_pixel(&w, 4, 5, 256, bounces);
+        let coarse = c.sample_pixel(&w, 4, 5, 2, bounces);
+        let adaptive = c.sample_pixel_adaptively(&w, 4, 5, &settings);
+        // more samples are taken where the sphere's edge crosses the pixel
+        assert!((adaptive.g - reference.g).abs() < (coarse.g - reference.g).abs() / 1.5);
+    }
+
+    fn default_world_camera(width: u32, height: u32) -> Camera {
+        let from = point!(0, 0, -5);
+        let to = point!(0, 0, 0);
+        let up = vector!(0, 1, 0);
+        Camera::new(width, height, PI / 2.0, view_transform(from, to, up))
+    }
+
+    fn temp_checkpoint_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "ray_tracer_challenge_{}_{}.checkpoint",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn render_with_checkpoints_matches_plain_render() {
+        let c = default_world_camera(11, 11);
+        let path = temp_checkpoint_path("plain");
+        let image = c
+            .render_with_checkpoints(World::default(), RenderSettings::default(), &path, 1)
+            .unwrap();
         assert_abs_diff_eq!(
             image.pixel_at(5, 5),
             color!(0.380_632_88, 0.475_791_04, 0.285_474_66)
         );
+        assert!(!path.exists(), "Checkpoint should be removed after render");
+    }
+
+    #[test]
+    fn render_with_checkpoints_resumes_from_saved_tiles() {
+        let c = default_world_camera(40, 40);
+        let path = temp_checkpoint_path("resume");
+
+        // pretend that the first tile was already rendered before the program was interrupted
+        let mut checkpoint = RenderCheckpoint::new(40, 40, CHECKPOINT_TILE_SIZE);
+        checkpoint.canvas.write_pixel(0, 0, color!(1, 0, 1));
+        checkpoint.mark_tile_complete(0);
+        checkpoint.save(&path).unwrap();
+
+        let image = c
+            .render_with_checkpoints(World::default(), RenderSettings::default(), &path, 1)
+            .unwrap();
+        // completed tile was not re-rendered
+        assert_eq!(image.pixel_at(0, 0), color!(1, 0, 1));
+        // other tiles were rendered
+        assert_abs_diff_eq!(
+            image.pixel_at(39, 39),
+            World::default().color_at(c.ray_for_pixel(39, 39), DEFAULT_RAY_RECURSION_DEPTH)
+        );
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn rerender_dirty_matches_full_render() {
+        let c = default_world_camera(21, 21);
+        let mut w = World::default();
+        let mut canvas = c.render(&mut w, RenderSettings::default());
+        let small = w.add(Box::new(Sphere::build(
+            translation(0.5, 0.5, -1.5) * scaling(0.2, 0.2, 0.2),
+            Material::default(),
+        )));
+
+        let rendered = c.rerender_dirty(&mut w, &mut canvas);
+        assert!(rendered > 0 && rendered < 21 * 21 / 4, "{}", rendered);
+        assert!(w.changed_regions.is_empty());
+
+        w.edit(small, |s| {
+            s.set_transformation(translation(-0.5, -0.2, -1.5) * scaling(0.2, 0.2, 0.2))
+        });
+        c.rerender_dirty(&mut w, &mut canvas);
+
+        let mut expected_world = World::default();
+        expected_world.add(Box::new(Sphere::build(
+            translation(-0.5, -0.2, -1.5) * scaling(0.2, 0.2, 0.2),
+            Material::default(),
+        )));
+        let expected = c.render(&mut expected_world, RenderSettings::default());
+        // including the small sphere's shadow on the big one
+        for y in 0..21 {
+            for x in 0..21 {
+                assert_eq!(
+                    canvas.pixel_at(x, y),
+                    expected.pixel_at(x, y),
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_is_rerendered_for_changes_out_of_view() {
+        let c = default_world_camera(11, 11);
+        let mut w = World::default();
+        let mut canvas = c.render(&mut w, RenderSettings::default());
+        w.add(Box::new(Sphere::build(
+            translation(20., 0., 0.),
+            Material::default(),
+        )));
+        assert_eq!(c.rerender_dirty(&mut w, &mut canvas), 0);
+        // unless it could be between the camera and the scene
+        w.add(Box::new(Sphere::build(
+            translation(0., 0., -4.),
+            Material::default(),
+        )));
+        assert_eq!(c.rerender_dirty(&mut w, &mut canvas), 11 * 11);
+    }
+
+    // Answers polls with the given actions in order, then continues
+    struct ScriptedPreview {
+        actions: Vec<PreviewAction>,
+        shown: Vec<Tile>,
+    }
+
+    impl Preview for ScriptedPreview {
+        fn show(&mut self, _canvas: &Canvas, tile: Tile) {
+            self.shown.push(tile);
+        }
+        fn poll(&mut self) -> PreviewAction {
+            if self.actions.is_empty() {
+                PreviewAction::Continue
+            } else {
+                self.actions.remove(0)
+            }
+        }
+    }
+
+    #[test]
+    fn progressive_render_refines_every_tile_in_each_pass() {
+        let c = default_world_camera(40, 40);
+        let settings = RenderSettings {
+            samples_per_pixel: 3,
+            ..RenderSettings::default()
+        };
+        let mut preview = ScriptedPreview {
+            actions: vec![],
+            shown: vec![],
+        };
+        let image = c
+            .render_progressive(World::default(), settings, &mut preview)
+            .unwrap()
+            .unwrap();
+        // 4 tiles, shown once per pass
+        assert_eq!(preview.shown.len(), 12);
+        assert_eq!(preview.shown[0], preview.shown[4]);
+        // each pass added one more sample to the pixels
+        let mut expected = PixelEstimate::default();
+        for pass in 0..3 {
+            let bounces = settings.max_bounces();
+            c.sample_batch(&World::default(), 20, 20, 1, pass, bounces, &mut expected);
+        }
+        assert_eq!(image.pixel_at(20, 20), expected.mean());
+    }
+
+    #[test]
+    fn progressive_render_can_be_saved_and_aborted() {
+        let c = default_world_camera(40, 40);
+        let path = temp_checkpoint_path("preview").with_extension("ppm");
+        let mut preview = ScriptedPreview {
+            actions: vec![
+                PreviewAction::Save(path.clone()),
+                PreviewAction::Continue,
+                PreviewAction::Abort,
+            ],
+            shown: vec![],
+        };
+        let result = c
+            .render_progressive(World::default(), RenderSettings::default(), &mut preview)
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(preview.shown.len(), 3);
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("P3\n40 40\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn render_is_the_same_on_any_number_of_threads() {
+        // area light and Russian roulette make random choices besides the sample positions
+        let world = || {
+            let mut w = World {
+                light: Some(Box::new(RectangleLight::new(
+                    color!(1, 1, 1),
+                    point!(-10, 10, -10),
+                    vector!(2, 0, 0),
+                    4,
+                    vector!(0, 2, 0),
+                    4,
+                    None,
+                ))),
+                ..World::default()
+            };
+            w.objects[0].set_material(Material::builder().reflective(0.5).build());
+            w
+        };
+        let c = default_world_camera(24, 24);
+        let settings = |render_threads, seed| RenderSettings {
+            samples_per_pixel: 4,
+            soft_shadow_samples: Some(2),
+            russian_roulette_depth: Some(0),
+            render_threads,
+            seed,
+            ..RenderSettings::default()
+        };
+
+        let single = c.render(&mut world(), settings(1, 0)).to_ppm();
+        assert_eq!(c.render(&mut world(), settings(4, 0)).to_ppm(), single);
+        assert_eq!(c.render(&mut world(), settings(7, 0)).to_ppm(), single);
+        assert_ne!(c.render(&mut world(), settings(1, 1)).to_ppm(), single);
+    }
+
+    #[test]
+    fn shadow_cache_does_not_depend_on_number_of_threads() {
+        let c = default_world_camera(24, 24);
+        let settings = |render_threads| RenderSettings {
+            samples_per_pixel: 4,
+            shadow_cache_cell_size: Some(0.5),
+            render_threads,
+            ..RenderSettings::default()
+        };
+        let single = c.render(&mut World::default(), settings(1)).to_ppm();
+        assert_eq!(
+            c.render(&mut World::default(), settings(3)).to_ppm(),
+            single
+        );
+    }
+
+    // Shades the left half of the image red and leaves the rest to the CPU
+    struct LeftHalfBackend;
+
+    impl PrimaryRayBackend for LeftHalfBackend {
+        fn trace(&mut self, camera: &Camera, _world: &World) -> Vec<Option<Color>> {
+            let width = camera.width_pixels;
+            (0..width * camera.height_pixels)
+                .map(|i| {
+                    if i % width < width / 2 {
+                        Some(red())
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn render_with_backend_traces_pixels_left_out_on_cpu() {
+        let c = default_world_camera(11, 11);
+        let mut w = World::default();
+        let image = c.render_with_backend(&mut w, RenderSettings::default(), &mut LeftHalfBackend);
+        let expected = c.render(&mut w, RenderSettings::default());
+        for y in 0..11 {
+            for x in 0..11 {
+                if x < 5 {
+                    assert_eq!(image.pixel_at(x, y), red());
+                } else {
+                    assert_eq!(image.pixel_at(x, y), expected.pixel_at(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn render_with_checkpoints_rejects_checkpoint_of_wrong_size() {
+        let c = default_world_camera(11, 11);
+        let path = temp_checkpoint_path("wrong_size");
+        RenderCheckpoint::new(12, 11, CHECKPOINT_TILE_SIZE)
+            .save(&path)
+            .unwrap();
+        let result =
+            c.render_with_checkpoints(World::default(), RenderSettings::default(), &path, 1);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -1,14 +1,24 @@
 use crate::color::Color;
 use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
 use std::io::{self, BufRead, BufReader, Read};
 
 #[derive(Clone, Debug)]
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
+    // written in place of NaN or infinite pixels by sanitize and to_ppm, so they stand out
+    pub nonfinite_color: Color,
     data: Vec<Vec<Color>>,
 }
 
+// magenta rarely appears in a correct render
+pub const DEFAULT_NONFINITE_COLOR: Color = Color {
+    r: 1.,
+    g: 0.,
+    b: 1.,
+};
+
 const MAX_COLOR_VAL: u16 = 255;
 const MAX_PPM_LINE_LENGTH: usize = 70;
 // length of "255" is 3
@@ -20,11 +30,12 @@ impl Canvas {
         Canvas {
             width,
             height,
+            nonfinite_color: DEFAULT_NONFINITE_COLOR,
             data: vec![vec![color!(0, 0, 0); width]; height],
         }
     }
     pub fn write_pixel(&mut self, x: usize, y: usize, color: Color) {
-        if x <= self.width && y <= self.height {
+        if x < self.width && y < self.height {
             self.data[y][x] = color;
         } else {
             // return fail result
@@ -35,6 +46,76 @@ impl Canvas {
         self.data[y][x]
     }
 
+    // Half the width and height (rounded up), each pixel averaging the up to 2x2 pixels it covers;
+    // repeated, this gives the levels of a mipmap
+    pub fn downsampled(&self) -> Canvas {
+        let width = self.width.div_ceil(2).max(1);
+        let height = self.height.div_ceil(2).max(1);
+        let mut canvas = Canvas::new(width, height);
+        canvas.nonfinite_color = self.nonfinite_color;
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = color!(0, 0, 0);
+                let mut count = 0.;
+                for source_y in (2 * y)..(2 * y + 2).min(self.height) {
+                    for source_x in (2 * x)..(2 * x + 2).min(self.width) {
+                        sum += self.pixel_at(source_x, source_y);
+                        count += 1.;
+                    }
+                }
+                canvas.write_pixel(x, y, sum / count);
+            }
+        }
+        canvas
+    }
+
+    // Coordinates (x, y) of pixels with NaN or infinite channels, which usually indicate a bug in
+    // the shading code
+    pub fn find_nonfinite(&self) -> Vec<(usize, usize)> {
+        let mut found = vec![];
+        for (y, row) in self.data.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                if !color.is_finite() {
+                    found.push((x, y));
+                }
+            }
+        }
+        found
+    }
+
+    // Counts of pixels by luminance in the given number of equally wide bins, from 0 to the
+    // luminance of the brightest pixel; NaN or infinite pixels are left out
+    pub fn histogram(&self, bins: usize) -> Histogram {
+        assert!(bins > 0, "A histogram needs at least one bin");
+        let luminances: Vec<f32> = self
+            .data
+            .iter()
+            .flatten()
+            .filter(|c| c.is_finite())
+            .map(|c| c.luminance().max(0.))
+            .collect();
+        let max = luminances.iter().cloned().fold(0., f32::max);
+        let mut counts = vec![0; bins];
+        for luminance in luminances {
+            let bin = if max == 0. {
+                0
+            } else {
+                ((luminance / max * bins as f32) as usize).min(bins - 1)
+            };
+            counts[bin] += 1;
+        }
+        Histogram { max, counts }
+    }
+
+    // Replace NaN or infinite pixels with nonfinite_color and return their coordinates (x, y)
+    pub fn sanitize(&mut self) -> Vec<(usize, usize)> {
+        let found = self.find_nonfinite();
+        for &(x, y) in &found {
+            self.data[y][x] = self.nonfinite_color;
+        }
+        found
+    }
+
     // scale/clamp color values from 0-1 to 0-255
     fn scale_color(&self, rgb: f32) -> u8 {
         (rgb * MAX_COLOR_VAL as f32)
@@ -54,8 +135,18 @@ impl Canvas {
         }
     }
 
-    // Return string containing PPM (portable pixel map) data representing current canvas
+    // Return string containing PPM (portable pixel map) data representing current canvas. NaN or
+    // infinite pixels are written as nonfinite_color and reported on stderr.
     pub fn to_ppm(&self) -> String {
+        let nonfinite = self.find_nonfinite();
+        if !nonfinite.is_empty() {
+            eprintln!(
+                "Warning: {} pixels are NaN or infinite and were written as {}; first at {:?}",
+                nonfinite.len(),
+                self.nonfinite_color,
+                nonfinite[0]
+            );
+        }
         let mut ppm = String::new();
         // write header
         ppm.push_str("P3\n");
@@ -69,7 +160,10 @@ impl Canvas {
         for row in 0..self.height {
             current_line.clear();
             for (i, column) in (0..self.width).enumerate() {
-                let color = self.pixel_at(column, row);
+                let mut color = self.pixel_at(column, row);
+                if !color.is_finite() {
+                    color = self.nonfinite_color;
+                }
                 let r = self.scale_color(color.r);
                 let g = self.scale_color(color.g);
                 let b = self.scale_color(color.b);
@@ -96,13 +190,42 @@ impl Canvas {
     }
 }
 
-// TODO: proper parsing errors should also contain the line and column number
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    // the luminance at the top of the last bin
+    pub max: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn bin_width(&self) -> f32 {
+        self.max / self.counts.len() as f32
+    }
+
+    // The luminance that the given fraction (0 to 1) of the pixels are no brighter than, to the
+    // nearest bin; e.g. 0.5 for the median
+    pub fn percentile(&self, fraction: f32) -> f32 {
+        let total: usize = self.counts.iter().sum();
+        let wanted = (fraction * total as f32).ceil() as usize;
+        let mut seen = 0;
+        for (bin, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= wanted.max(1) {
+                return (bin + 1) as f32 * self.bin_width();
+            }
+        }
+        self.max
+    }
+}
+
 #[derive(Debug)]
 pub enum ParseError {
     IoError(io::Error),
     IncorrectFormat(String),
     ParseIntError(std::num::ParseIntError),
     MalformedDimensionHeader(String),
+    // the file ended before the whole header was read
+    TruncatedHeader(String),
 }
 
 impl From<io::Error> for ParseError {
@@ -115,90 +238,415 @@ impl From<std::num::ParseIntError> for ParseError {
         ParseError::ParseIntError(err)
     }
 }
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ParseError::IoError(ref e) => e.fmt(f),
+            ParseError::ParseIntError(ref e) => e.fmt(f),
+            ParseError::IncorrectFormat(ref s) => f.write_str(s),
+            ParseError::MalformedDimensionHeader(ref s) => f.write_str(s),
+            ParseError::TruncatedHeader(ref s) => f.write_str(s),
+        }
+    }
+}
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::IoError(e) => Some(e),
+            ParseError::ParseIntError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 type RgbElement = u32;
+
+// Reads whitespace-separated tokens from PPM text, skipping comments, which run from '#' to the
+// end of the line and may appear anywhere. Line numbers are 1-based.
+struct PpmTokens<T: BufRead> {
+    lines: std::iter::Enumerate<io::Lines<T>>,
+    pending: VecDeque<(usize, String)>,
+}
+
+impl<T: BufRead> PpmTokens<T> {
+    fn new(reader: T) -> Self {
+        PpmTokens {
+            lines: reader.lines().enumerate(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<(usize, String)>, ParseError> {
+        while self.pending.is_empty() {
+            match self.lines.next() {
+                None => return Ok(None),
+                Some((index, line)) => {
+                    let line = line?;
+                    let content = line.split('#').next().unwrap_or("");
+                    self.pending.extend(
+                        content
+                            .split_whitespace()
+                            .map(|token| (index + 1, token.to_string())),
+                    );
+                }
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    // Like next_token, but a missing token is an error explaining what was expected
+    fn expect_token(&mut self, description: &str) -> Result<(usize, String), ParseError> {
+        self.next_token()?.ok_or_else(|| {
+            ParseError::TruncatedHeader(format!(
+                "File ended before the {} was specified",
+                description
+            ))
+        })
+    }
+}
+
+fn parse_dimension(
+    (line_number, token): (usize, String),
+    description: &str,
+) -> Result<usize, ParseError> {
+    token.parse::<usize>().map_err(|e| {
+        ParseError::MalformedDimensionHeader(format!(
+            "Expected {} at line {}; found {} ({})",
+            description, line_number, token, e
+        ))
+    })
+}
+
+// Rejects image dimensions that are zero or too large to address, returning the number of pixels.
+// The pixels themselves should still be read before allocating a canvas, so that a damaged header is
+// an error rather than a huge allocation.
+fn check_dimensions(width: usize, height: usize) -> Result<usize, ParseError> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels > 0 => Ok(pixels),
+        _ => Err(ParseError::MalformedDimensionHeader(format!(
+            "Image dimensions {}x{} are empty or too large",
+            width, height
+        ))),
+    }
+}
+
 pub fn canvas_from_ppm<T: Read>(reader: T) -> Result<Canvas, ParseError> {
-    let buf_reader = BufReader::new(reader);
-    let mut line_iter = buf_reader.lines().enumerate().filter_map(clean_line);
-
-    // TODO: these unwrap()'s are not great; should really fail properly if the file doesn't
-    // contain this many lines
-    let (_, line) = line_iter.next().unwrap();
-    let line = line?;
-    let line = line.trim();
-    if line != "P3" {
+    let mut tokens = PpmTokens::new(BufReader::new(reader));
+
+    let (line_number, magic) = tokens.expect_token("magic number (P3)")?;
+    if magic != "P3" {
         return Err(ParseError::IncorrectFormat(format!(
-            "Incorrect magic number at line 1: expected P3, found {}",
-            line
+            "Incorrect magic number at line {}: expected P3, found {}",
+            line_number, magic
         )));
     }
 
-    let (_, line) = line_iter.next().unwrap();
-    let line = line?;
-    let line = line.trim();
-    let elements: Vec<&str> = line.split_whitespace().collect();
-    if elements.len() != 2 {
-        return Err(ParseError::MalformedDimensionHeader(format!(
-            "Expected width and height at line 2; found {}",
-            line
-        )));
-    }
-    let width = elements[0].parse::<usize>()?;
-    let height = elements[1].parse::<usize>()?;
+    let width = parse_dimension(tokens.expect_token("image width")?, "width")?;
+    let height = parse_dimension(tokens.expect_token("image height")?, "height")?;
+    let num_pixels = check_dimensions(width, height)?;
 
-    let (_, line) = line_iter.next().unwrap();
-    let line = line?;
-    let line = line.trim();
-    let scale = line.parse::<RgbElement>()? as f32;
+    let (line_number, scale) = tokens.expect_token("maximum color value")?;
+    let scale = match scale.parse::<RgbElement>() {
+        Ok(scale) if scale > 0 => scale as f32,
+        _ => {
+            return Err(ParseError::IncorrectFormat(format!(
+                "Expected a positive maximum color value at line {}; found {}",
+                line_number, scale
+            )))
+        }
+    };
 
-    let mut canvas = Canvas::new(width, height);
+    let mut pixels = vec![];
     let mut raw_rgb: VecDeque<RgbElement> = VecDeque::new();
-    let mut x = 0;
-    let mut y = 0;
-    for (_, (_index, line)) in line_iter.enumerate() {
-        let line = line?;
-        let line = line.trim();
-        let line_rgb = line
-            .split_whitespace()
-            .map(|s| s.parse::<RgbElement>())
-            .collect::<Result<Vec<RgbElement>, std::num::ParseIntError>>()?;
-        raw_rgb.extend(line_rgb);
-        while raw_rgb.len() >= 3 {
+    while let Some((_, token)) = tokens.next_token()? {
+        raw_rgb.push_back(token.parse::<RgbElement>()?);
+        if raw_rgb.len() == 3 {
+            if pixels.len() >= num_pixels {
+                return Err(ParseError::IncorrectFormat(format!(
+                    "Found more than the {} pixels specified in the header",
+                    num_pixels
+                )));
+            }
             let r = raw_rgb.pop_front().unwrap() as f32 / scale;
             let g = raw_rgb.pop_front().unwrap() as f32 / scale;
             let b = raw_rgb.pop_front().unwrap() as f32 / scale;
-            canvas.write_pixel(x, y, color!(r, g, b));
+            pixels.push(color!(r, g, b));
+        }
+    }
+    if pixels.len() < num_pixels {
+        return Err(ParseError::IncorrectFormat(format!(
+            "Found only {} of the {} pixels specified in the header",
+            pixels.len(),
+            num_pixels
+        )));
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    for (i, color) in pixels.into_iter().enumerate() {
+        canvas.write_pixel(i % width, i / width, color);
+    }
+    Ok(canvas)
+}
+
+// Reads a line of an image header, without the trailing newline
+fn read_header_line<T: BufRead>(reader: &mut T, description: &str) -> Result<String, ParseError> {
+    let mut line = vec![];
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Err(ParseError::TruncatedHeader(format!(
+            "File ended before the {} was specified",
+            description
+        )));
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_string())
+}
+
+// Radiance RGBE: the shared exponent e scales all three 8-bit mantissas
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return color!(0, 0, 0);
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    color!(
+        rgbe[0] as f32 * scale,
+        rgbe[1] as f32 * scale,
+        rgbe[2] as f32 * scale
+    )
+}
 
-            // move to next canvas pixel
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
+// Reads one scanline of RGBE pixels, which is either flat or run-length encoded one channel at a
+// time (the "new" RLE scheme written by all current tools)
+fn read_rgbe_scanline<T: Read>(reader: &mut T, width: usize) -> Result<Vec<[u8; 4]>, ParseError> {
+    let mut start = [0u8; 4];
+    reader.read_exact(&mut start)?;
+    let is_rle = (8..0x8000).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && start[2] & 0x80 == 0
+        && ((start[2] as usize) << 8 | start[3] as usize) == width;
+    if !is_rle {
+        // width comes from the header, so the scanline grows only as its pixels are read
+        let mut scanline = vec![start];
+        for _ in 1..width {
+            let mut pixel = [0u8; 4];
+            reader.read_exact(&mut pixel)?;
+            scanline.push(pixel);
+        }
+        return Ok(scanline);
+    }
+    let mut scanline = vec![[0u8; 4]; width];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let (run, count) = if count[0] > 128 {
+                (true, (count[0] - 128) as usize)
+            } else {
+                (false, count[0] as usize)
+            };
+            if count == 0 || x + count > width {
+                return Err(ParseError::IncorrectFormat(format!(
+                    "Bad run length {} in scanline of width {}",
+                    count, width
+                )));
+            }
+            if run {
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
             }
+            x += count;
         }
     }
+    Ok(scanline)
+}
+
+// Reads a Radiance RGBE (.hdr) image. Unlike PPM, the colors are not limited to [0, 1], so the
+// image can hold real light intensities (e.g. the sun in an environment map).
+pub fn canvas_from_hdr<T: Read>(reader: T) -> Result<Canvas, ParseError> {
+    let mut reader = BufReader::new(reader);
+
+    let magic = read_header_line(&mut reader, "magic number (#?RADIANCE)")?;
+    if !magic.starts_with("#?") {
+        return Err(ParseError::IncorrectFormat(format!(
+            "Incorrect magic number: expected #?RADIANCE, found {}",
+            magic
+        )));
+    }
+    // header variables run until an empty line
+    let mut line_number = 1;
+    loop {
+        let line = read_header_line(&mut reader, "image resolution")?;
+        line_number += 1;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(ParseError::IncorrectFormat(format!(
+                    "Unsupported HDR pixel format {}",
+                    format
+                )));
+            }
+        }
+    }
+
+    let resolution = read_header_line(&mut reader, "image resolution")?;
+    line_number += 1;
+    let tokens: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match tokens.as_slice() {
+        ["-Y", height, "+X", width] => (
+            parse_dimension((line_number, height.to_string()), "height")?,
+            parse_dimension((line_number, width.to_string()), "width")?,
+        ),
+        _ => {
+            return Err(ParseError::MalformedDimensionHeader(format!(
+                "Expected a resolution of the form -Y <height> +X <width> at line {}; found {}",
+                line_number, resolution
+            )))
+        }
+    };
+
+    check_dimensions(width, height)?;
+
+    let mut pixels = vec![];
+    for _ in 0..height {
+        pixels.extend(read_rgbe_scanline(&mut reader, width)?);
+    }
+    let mut canvas = Canvas::new(width, height);
+    for (i, rgbe) in pixels.into_iter().enumerate() {
+        canvas.write_pixel(i % width, i / width, rgbe_to_color(rgbe));
+    }
     Ok(canvas)
 }
 
-fn clean_line(
-    (index, line): (usize, Result<String, std::io::Error>),
-) -> Option<(usize, Result<String, std::io::Error>)> {
-    match line {
-        Ok(s) => {
-            let s = s.trim();
-            if s.starts_with("#") || s.is_empty() {
-                None
+// Reads a Portable Float Map (.pfm): PF for color or Pf for grayscale, followed by the size and a
+// scale whose sign gives the byte order (negative for little-endian). Rows are stored bottom to top.
+pub fn canvas_from_pfm<T: Read>(reader: T) -> Result<Canvas, ParseError> {
+    let mut reader = BufReader::new(reader);
+
+    let magic = read_header_line(&mut reader, "magic number (PF)")?;
+    let channels = match magic.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => {
+            return Err(ParseError::IncorrectFormat(format!(
+                "Incorrect magic number: expected PF or Pf, found {}",
+                magic
+            )))
+        }
+    };
+
+    let size = read_header_line(&mut reader, "image size")?;
+    let mut dimensions = size.split_whitespace().map(|token| (2, token.to_string()));
+    let width = parse_dimension(dimensions.next().unwrap_or((2, String::new())), "width")?;
+    let height = parse_dimension(dimensions.next().unwrap_or((2, String::new())), "height")?;
+
+    let scale = read_header_line(&mut reader, "scale")?;
+    let little_endian = match scale.parse::<f32>() {
+        Ok(scale) if scale != 0. && scale.is_finite() => scale < 0.,
+        _ => {
+            return Err(ParseError::IncorrectFormat(format!(
+                "Expected a non-zero scale at line 3; found {}",
+                scale
+            )))
+        }
+    };
+
+    let data_len = check_dimensions(width, height)?
+        .checked_mul(channels * 4)
+        .ok_or_else(|| {
+            ParseError::MalformedDimensionHeader(format!(
+                "Image dimensions {}x{} are too large",
+                width, height
+            ))
+        })?;
+    // read everything before allocating the canvas, so that the header can't claim more than the
+    // file holds
+    let mut data = vec![];
+    reader.take(data_len as u64).read_to_end(&mut data)?;
+    if data.len() < data_len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    let mut samples = [0f32; 3];
+    for (i, pixel) in data.chunks_exact(channels * 4).enumerate() {
+        for (sample, value) in samples.iter_mut().zip(pixel.chunks_exact(4)) {
+            let value = [value[0], value[1], value[2], value[3]];
+            *sample = if little_endian {
+                f32::from_le_bytes(value)
             } else {
-                Some((index, Ok(s.to_string())))
-            }
+                f32::from_be_bytes(value)
+            };
+        }
+        let color = if channels == 1 {
+            Color::try_new(samples[0], samples[0], samples[0])
+        } else {
+            Color::try_new(samples[0], samples[1], samples[2])
         }
-        Err(_) => Some((index, line)),
+        .map_err(|e| ParseError::IncorrectFormat(format!("Invalid pixel {}: {}", i, e)))?;
+        canvas.write_pixel(i % width, height - 1 - i / width, color);
+    }
+    Ok(canvas)
+}
+
+// Reads a PPM, Radiance HDR or PFM image, depending on its magic number
+pub fn canvas_from_image<T: Read>(reader: T) -> Result<Canvas, ParseError> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(b"#?") {
+        canvas_from_hdr(reader)
+    } else if magic.starts_with(b"PF") || magic.starts_with(b"Pf") {
+        canvas_from_pfm(reader)
+    } else {
+        canvas_from_ppm(reader)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn histogram_counts_pixels_by_luminance() {
+        let mut c = Canvas::new(5, 1);
+        c.write_pixel(0, 0, color!(1, 1, 1));
+        c.write_pixel(1, 0, color!(0.5, 0.5, 0.5));
+        c.write_pixel(2, 0, color!(0.3, 0.3, 0.3));
+        c.write_pixel(
+            3,
+            0,
+            Color {
+                r: f32::NAN,
+                g: 0.,
+                b: 0.,
+            },
+        );
+        let h = c.histogram(4);
+        assert_abs_diff_eq!(h.max, 1.);
+        assert_eq!(h.counts, vec![1, 1, 1, 1]);
+        assert_abs_diff_eq!(h.bin_width(), 0.25);
+        assert_abs_diff_eq!(h.percentile(0.5), 0.5);
+        assert_abs_diff_eq!(h.percentile(1.), 1.);
+        assert_abs_diff_eq!(h.percentile(0.), 0.25);
+
+        let black = Canvas::new(2, 2).histogram(3);
+        assert_eq!(black.max, 0.);
+        assert_eq!(black.counts, vec![4, 0, 0]);
+        assert_eq!(black.percentile(0.9), 0.);
+    }
+
     #[test]
     fn test_height_and_width() {
         let c = Canvas::new(15, 10);
@@ -214,6 +662,68 @@ mod tests {
         assert_eq!(canvas.pixel_at(7, 4), color);
     }
 
+    #[test]
+    fn downsampling_averages_pixels() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(0, 0, color!(1, 0, 0));
+        canvas.write_pixel(1, 1, color!(0, 1, 0));
+        canvas.write_pixel(2, 0, color!(0, 0, 1));
+        let half = canvas.downsampled();
+        assert_eq!((half.width, half.height), (2, 1));
+        assert_eq!(half.pixel_at(0, 0), color!(0.25, 0.25, 0));
+        // the odd column is averaged on its own
+        assert_eq!(half.pixel_at(1, 0), color!(0, 0, 0.5));
+        let smallest = half.downsampled();
+        assert_eq!((smallest.width, smallest.height), (1, 1));
+    }
+
+    #[test]
+    fn find_nonfinite_pixels() {
+        let mut canvas = Canvas::new(4, 3);
+        canvas.write_pixel(1, 2, color!(0.5, 0.5, 0.5));
+        canvas.write_pixel(
+            3,
+            0,
+            Color {
+                r: 0.,
+                g: f32::NAN,
+                b: 0.,
+            },
+        );
+        canvas.write_pixel(2, 1, color!(f32::INFINITY, 0, 0));
+        assert_eq!(canvas.find_nonfinite(), vec![(3, 0), (2, 1)]);
+    }
+
+    fn canvas_with_nan_pixel() -> Canvas {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(
+            1,
+            0,
+            Color {
+                r: f32::NAN,
+                g: 0.5,
+                b: 0.,
+            },
+        );
+        canvas
+    }
+
+    #[test]
+    fn sanitize_replaces_nonfinite_pixels() {
+        let mut canvas = canvas_with_nan_pixel();
+        canvas.nonfinite_color = color!(0, 1, 0);
+        assert_eq!(canvas.sanitize(), vec![(1, 0)]);
+        assert_eq!(canvas.pixel_at(1, 0), color!(0, 1, 0));
+        assert_eq!(canvas.pixel_at(0, 0), color!(0, 0, 0));
+        assert!(canvas.sanitize().is_empty());
+    }
+
+    #[test]
+    fn ppm_writes_nonfinite_pixels_as_debug_color() {
+        let canvas = canvas_with_nan_pixel();
+        assert_eq!(canvas.to_ppm(), "P3\n2 1\n255\n0 0 0 255 0 255\n");
+    }
+
     #[test]
     fn test_ppm_header() {
         let c = Canvas::new(20, 5);
@@ -354,6 +864,90 @@ mod tests {
         assert_eq!(canvas.pixel_at(1, 0), color!(1, 0, 1));
     }
 
+    #[test]
+    fn ppm_parsing_ignores_comments_anywhere() {
+        let ppm = "P3 # magic
+        2 # width
+        1 # height
+        255 # scale
+        255 255 255 # first pixel
+        255 0 255 #second pixel";
+        let canvas = canvas_from_ppm(ppm.as_bytes()).unwrap();
+        assert_eq!(canvas.width, 2);
+        assert_eq!(canvas.pixel_at(0, 0), color!(1, 1, 1));
+        assert_eq!(canvas.pixel_at(1, 0), color!(1, 0, 1));
+    }
+
+    #[test]
+    fn reading_truncated_ppm_header_returns_error() {
+        let test_data = vec![
+            ("empty", "", "magic number"),
+            ("only comments", "# nothing here", "magic number"),
+            ("no dimensions", "P3\n", "image width"),
+            ("no height", "P3\n10 # height is missing", "image height"),
+            ("no scale", "P3\n10 2", "maximum color value"),
+        ];
+        for (name, ppm, expected) in test_data {
+            match canvas_from_ppm(ppm.as_bytes()) {
+                Err(ParseError::TruncatedHeader(msg)) => {
+                    assert!(msg.contains(expected), "Case {}: {}", name, msg)
+                }
+                other => panic!("Case {}: expected TruncatedHeader, got {:?}", name, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reading_malformed_ppm_header_reports_line() {
+        let ppm = "P3\n# comment\n10 two\n255";
+        match canvas_from_ppm(ppm.as_bytes()) {
+            Err(ParseError::MalformedDimensionHeader(msg)) => {
+                assert!(msg.contains("height at line 3"), "{}", msg)
+            }
+            other => panic!("Expected MalformedDimensionHeader, got {:?}", other),
+        }
+        match canvas_from_ppm("P3\n1 1\n0\n0 0 0".as_bytes()) {
+            Err(ParseError::IncorrectFormat(msg)) => assert!(msg.contains("line 3"), "{}", msg),
+            other => panic!("Expected IncorrectFormat, got {:?}", other),
+        }
+        match canvas_from_ppm("P3\n1 1\n255\n0 0 0 1 1 1".as_bytes()) {
+            Err(ParseError::IncorrectFormat(msg)) => assert!(msg.contains("more than"), "{}", msg),
+            other => panic!("Expected IncorrectFormat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reading_ppm_with_bad_dimensions_returns_error() {
+        let test_data = vec![
+            ("zero width", "P3\n0 1\n255\n0 0 0", "empty or too large"),
+            (
+                "overflowing",
+                "P3\n18446744073709551615 2\n255\n0 0 0",
+                "empty or too large",
+            ),
+            ("more than data", "P3\n100000000 100000000\n255\n", "only 0"),
+            ("short data", "P3\n2 1\n255\n0 0 0", "only 1 of the 2"),
+        ];
+        for (name, ppm, expected) in test_data {
+            println!("Case {}", name);
+            match canvas_from_ppm(ppm.as_bytes()) {
+                Err(ParseError::MalformedDimensionHeader(msg))
+                | Err(ParseError::IncorrectFormat(msg)) => {
+                    assert!(msg.contains(expected), "{}", msg)
+                }
+                other => panic!("Expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn writing_pixel_outside_canvas_is_ignored() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(2, 0, color!(1, 1, 1));
+        canvas.write_pixel(0, 1, color!(1, 1, 1));
+        assert_eq!(canvas.pixel_at(1, 0), color!(0, 0, 0));
+    }
+
     #[test]
     fn ppm_parsing_allows_rgb_triplet_to_span_lines() {
         let ppm = "P3
@@ -396,4 +990,129 @@ mod tests {
         let canvas = canvas_from_ppm(ppm.as_bytes()).unwrap();
         assert_eq!(canvas.pixel_at(0, 1), color!(0.75, 0.5, 0.25));
     }
+
+    fn hdr_header(width: usize, height: usize) -> Vec<u8> {
+        format!(
+            "#?RADIANCE\n# made by hand\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y {} +X {}\n",
+            height, width
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn reading_flat_hdr_file() {
+        let mut hdr = hdr_header(2, 1);
+        // 0.5, 1 and 8 (beyond the range of PPM), then black
+        hdr.extend_from_slice(&[128, 128, 128, 128, 128, 128, 128, 132, 0, 0, 0, 0]);
+        let canvas = canvas_from_hdr(hdr.as_slice()).unwrap();
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_eq!(canvas.pixel_at(0, 0), color!(0.5, 0.5, 0.5));
+        assert_eq!(canvas.pixel_at(1, 0), color!(8, 8, 8));
+    }
+
+    #[test]
+    fn reading_run_length_encoded_hdr_file() {
+        let mut hdr = hdr_header(8, 1);
+        hdr.extend_from_slice(&[2, 2, 0, 8]);
+        // red: a run of 8
+        hdr.extend_from_slice(&[128 + 8, 128]);
+        // green: 4 literal values, then a run of 4
+        hdr.extend_from_slice(&[4, 0, 64, 128, 255, 128 + 4, 0]);
+        // blue: a run of 8
+        hdr.extend_from_slice(&[128 + 8, 0]);
+        // exponent: a run of 8
+        hdr.extend_from_slice(&[128 + 8, 129]);
+        let canvas = canvas_from_hdr(hdr.as_slice()).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color!(1, 0, 0));
+        assert_eq!(canvas.pixel_at(2, 0), color!(1, 1, 0));
+        assert_eq!(canvas.pixel_at(7, 0), color!(1, 0, 0));
+    }
+
+    #[test]
+    fn reading_hdr_file_with_unsupported_orientation() {
+        let mut hdr = b"#?RADIANCE\n\n+Y 1 +X 1\n".to_vec();
+        hdr.extend_from_slice(&[128, 128, 128, 128]);
+        match canvas_from_hdr(hdr.as_slice()) {
+            Err(ParseError::MalformedDimensionHeader(_)) => (),
+            other => panic!("Expected malformed dimension error; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reading_pfm_file() {
+        let mut pfm = b"PF\n1 2\n-1.0\n".to_vec();
+        // the bottom row comes first
+        for value in &[0.25f32, 0.5, 0.75, 2., 4., 6.] {
+            pfm.extend_from_slice(&value.to_le_bytes());
+        }
+        let canvas = canvas_from_pfm(pfm.as_slice()).unwrap();
+        assert_eq!(canvas.pixel_at(0, 1), color!(0.25, 0.5, 0.75));
+        assert_eq!(canvas.pixel_at(0, 0), color!(2, 4, 6));
+    }
+
+    #[test]
+    fn reading_pfm_file_with_nan_fails() {
+        let mut pfm = b"Pf\n1 1\n-1.0\n".to_vec();
+        pfm.extend_from_slice(&f32::NAN.to_le_bytes());
+        match canvas_from_pfm(pfm.as_slice()) {
+            Err(ParseError::IncorrectFormat(msg)) => assert!(msg.contains("NaN"), "{}", msg),
+            other => panic!("Expected IncorrectFormat, got {:?}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn reading_hdr_or_pfm_with_bad_dimensions_returns_error() {
+        let pfm = |size: &str| format!("PF\n{}\n-1.0\n", size).into_bytes();
+        let test_data = vec![
+            (
+                "empty hdr",
+                canvas_from_hdr(hdr_header(0, 1).as_slice()),
+                false,
+            ),
+            (
+                "huge hdr",
+                canvas_from_hdr(hdr_header(100_000_000, 100_000_000).as_slice()),
+                true,
+            ),
+            ("empty pfm", canvas_from_pfm(pfm("1 0").as_slice()), false),
+            (
+                "overflowing pfm",
+                canvas_from_pfm(pfm("18446744073709551615 1").as_slice()),
+                false,
+            ),
+            (
+                "huge pfm",
+                canvas_from_pfm(pfm("100000000 100000000").as_slice()),
+                true,
+            ),
+        ];
+        for (name, result, truncated) in test_data {
+            println!("Case {}", name);
+            match result {
+                Err(ParseError::MalformedDimensionHeader(_)) => assert!(!truncated),
+                Err(ParseError::IoError(e)) => {
+                    assert!(truncated);
+                    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                }
+                other => panic!("Expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn reading_image_detects_format() {
+        let ppm = "P3\n1 1\n255\n255 0 0\n";
+        let canvas = canvas_from_image(ppm.as_bytes()).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color!(1, 0, 0));
+
+        let mut pfm = b"Pf\n1 1\n1.0\n".to_vec();
+        pfm.extend_from_slice(&3f32.to_be_bytes());
+        let canvas = canvas_from_image(pfm.as_slice()).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color!(3, 3, 3));
+
+        let mut hdr = hdr_header(1, 1);
+        hdr.extend_from_slice(&[128, 0, 0, 130]);
+        let canvas = canvas_from_image(hdr.as_slice()).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color!(2, 0, 0));
+    }
 }
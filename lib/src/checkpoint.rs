@@ -0,0 +1,261 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Identifies a checkpoint file and the version of its layout
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCCKPT1";
+
+// Rectangular region of the canvas; max values are exclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x_min: usize,
+    pub y_min: usize,
+    pub x_max: usize,
+    pub y_max: usize,
+}
+
+// State of a partially-finished render: the canvas rendered so far plus a record of which tiles
+// have been completed. Written to disk periodically so that a long render can be resumed after
+// being interrupted.
+#[derive(Clone, Debug)]
+pub struct RenderCheckpoint {
+    pub canvas: Canvas,
+    tile_size: usize,
+    completed: Vec<bool>,
+}
+
+impl RenderCheckpoint {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> RenderCheckpoint {
+        assert!(tile_size > 0, "Tile size must be positive");
+        let num_tiles = tiles_along(width, tile_size) * tiles_along(height, tile_size);
+        RenderCheckpoint {
+            canvas: Canvas::new(width, height),
+            tile_size,
+            completed: vec![false; num_tiles],
+        }
+    }
+
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    pub fn num_tiles(&self) -> usize {
+        self.completed.len()
+    }
+
+    // Tiles are numbered in row-major order
+    pub fn tile(&self, index: usize) -> Tile {
+        let tiles_per_row = tiles_along(self.canvas.width, self.tile_size);
+        let x_min = (index % tiles_per_row) * self.tile_size;
+        let y_min = (index / tiles_per_row) * self.tile_size;
+        Tile {
+            x_min,
+            y_min,
+            x_max: (x_min + self.tile_size).min(self.canvas.width),
+            y_max: (y_min + self.tile_size).min(self.canvas.height),
+        }
+    }
+
+    pub fn is_tile_complete(&self, index: usize) -> bool {
+        self.completed[index]
+    }
+
+    pub fn mark_tile_complete(&mut self, index: usize) {
+        self.completed[index] = true;
+    }
+
+    pub fn remaining_tiles(&self) -> Vec<usize> {
+        (0..self.num_tiles())
+            .filter(|&i| !self.completed[i])
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed.iter().all(|&c| c)
+    }
+
+    // Layout (all numbers little-endian): magic, width, height and tile size as u32, one byte per
+    // tile for completion, then r, g, b as f32 for every pixel in row-major order. Colors are
+    // stored at full precision so that a resumed render is identical to an uninterrupted one.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        for dimension in &[self.canvas.width, self.canvas.height, self.tile_size] {
+            writer.write_all(&(*dimension as u32).to_le_bytes())?;
+        }
+        let completed: Vec<u8> = self.completed.iter().map(|&c| c as u8).collect();
+        writer.write_all(&completed)?;
+        for y in 0..self.canvas.height {
+            for x in 0..self.canvas.width {
+                let color = self.canvas.pixel_at(x, y);
+                for channel in &[color.r, color.g, color.b] {
+                    writer.write_all(&channel.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<RenderCheckpoint> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a render checkpoint file",
+            ));
+        }
+        let width = read_u32(&mut reader)? as usize;
+        let height = read_u32(&mut reader)? as usize;
+        let tile_size = read_u32(&mut reader)? as usize;
+        if tile_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Checkpoint has a tile size of 0",
+            ));
+        }
+        // the dimensions can't be trusted until the data for them has actually been read, so that a
+        // damaged header is an error rather than a huge allocation
+        let pixel_bytes = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3 * 4))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Checkpoint dimensions are too large",
+                )
+            })?;
+        let num_tiles = tiles_along(width, tile_size) * tiles_along(height, tile_size);
+        let completed = read_bytes(&mut reader, num_tiles)?;
+        let pixels = read_bytes(&mut reader, pixel_bytes)?;
+
+        let mut checkpoint = RenderCheckpoint::new(width, height, tile_size);
+        checkpoint.completed = completed.into_iter().map(|c| c != 0).collect();
+        for (i, rgb) in pixels.chunks_exact(3 * 4).enumerate() {
+            let channel =
+                |o: usize| f32::from_le_bytes([rgb[o], rgb[o + 1], rgb[o + 2], rgb[o + 3]]);
+            let color = Color::new(channel(0), channel(4), channel(8));
+            checkpoint.canvas.write_pixel(i % width, i / width, color);
+        }
+        Ok(checkpoint)
+    }
+
+    // Write to a temporary file first and then move it into place, so that being killed in the
+    // middle of saving never leaves behind a corrupt checkpoint.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        self.write_to(BufWriter::new(File::create(&temp_path)?))?;
+        fs::rename(&temp_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<RenderCheckpoint> {
+        RenderCheckpoint::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+fn tiles_along(length: usize, tile_size: usize) -> usize {
+    length.div_ceil(tile_size)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// Reads exactly len bytes, growing the buffer only as they arrive
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_canvas_with_partial_tiles_at_edges() {
+        let checkpoint = RenderCheckpoint::new(5, 3, 2);
+        assert_eq!(checkpoint.num_tiles(), 6);
+        assert_eq!(
+            checkpoint.tile(0),
+            Tile {
+                x_min: 0,
+                y_min: 0,
+                x_max: 2,
+                y_max: 2
+            }
+        );
+        assert_eq!(
+            checkpoint.tile(5),
+            Tile {
+                x_min: 4,
+                y_min: 2,
+                x_max: 5,
+                y_max: 3
+            }
+        );
+    }
+
+    #[test]
+    fn checkpoint_survives_round_trip() {
+        let mut checkpoint = RenderCheckpoint::new(3, 2, 2);
+        checkpoint.canvas.write_pixel(2, 1, color!(0.1, 1.5, -0.25));
+        checkpoint.mark_tile_complete(1);
+
+        let mut bytes = vec![];
+        checkpoint.write_to(&mut bytes).unwrap();
+        let read = RenderCheckpoint::read_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(read.canvas.width, 3);
+        assert_eq!(read.canvas.height, 2);
+        assert_eq!(read.tile_size(), 2);
+        assert_eq!(read.remaining_tiles(), vec![0]);
+        assert_eq!(read.canvas.pixel_at(2, 1), color!(0.1, 1.5, -0.25));
+    }
+
+    #[test]
+    fn reading_checkpoint_with_wrong_magic_number_fails() {
+        let result = RenderCheckpoint::read_from(&b"P3\n1 1\n255\n0 0 0"[..]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reading_truncated_checkpoint_fails() {
+        let checkpoint = RenderCheckpoint::new(2, 2, 1);
+        let mut bytes = vec![];
+        checkpoint.write_to(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        let result = RenderCheckpoint::read_from(bytes.as_slice());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reading_checkpoint_with_corrupt_header_fails() {
+        let checkpoint = RenderCheckpoint::new(2, 2, 1);
+        let mut bytes = vec![];
+        checkpoint.write_to(&mut bytes).unwrap();
+        let test_data = vec![
+            (
+                "overflowing size",
+                u32::MAX,
+                u32::MAX,
+                io::ErrorKind::InvalidData,
+            ),
+            ("huge size", 100_000, 100_000, io::ErrorKind::UnexpectedEof),
+        ];
+        for (name, width, height, expected) in test_data {
+            println!("Case {}", name);
+            let mut corrupt = bytes.clone();
+            corrupt[8..12].copy_from_slice(&width.to_le_bytes());
+            corrupt[12..16].copy_from_slice(&height.to_le_bytes());
+            let result = RenderCheckpoint::read_from(corrupt.as_slice());
+            assert_eq!(result.unwrap_err().kind(), expected);
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use crate::constants::NAMED_COLORS;
 use approx::AbsDiffEq;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt::Display;
 use std::ops;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ColorDescription")]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -11,9 +15,235 @@ pub struct Color {
 }
 
 impl Color {
+    // NaN channels are only rejected when the validate feature is enabled, since checking every
+    // intermediate color is expensive; use try_new to check regardless of build settings
     pub fn new(r: f32, g: f32, b: f32) -> Color {
+        if cfg!(feature = "validate") {
+            if let Err(e) = Color::try_new(r, g, b) {
+                panic!("{}", e);
+            }
+        }
         Color { r, g, b }
     }
+    pub fn try_new(r: f32, g: f32, b: f32) -> Result<Color, InvalidColorError> {
+        for (name, value) in [("r", r), ("g", g), ("b", b)].iter() {
+            if value.is_nan() {
+                return Err(InvalidColorError { channel: name });
+            }
+        }
+        Ok(Color { r, g, b })
+    }
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+    // Parses a color hex code of the form '#rrggbb' or the shorthand '#rgb'; the '#' is optional
+    pub fn from_hex(hex_code: &str) -> Result<Color, ParseColorError> {
+        let error = || ParseColorError {
+            hex_code: hex_code.to_string(),
+        };
+        let digits = hex_code.strip_prefix('#').unwrap_or(hex_code);
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).unwrap() as f32 / 255.0;
+        match digits.len() {
+            6 => Ok(Color::new(
+                channel(&digits[0..2]),
+                channel(&digits[2..4]),
+                channel(&digits[4..6]),
+            )),
+            // each digit is repeated: #1ef is #11eeff
+            3 => Ok(Color::new(
+                channel(&digits[0..1].repeat(2)),
+                channel(&digits[1..2].repeat(2)),
+                channel(&digits[2..3].repeat(2)),
+            )),
+            _ => Err(error()),
+        }
+    }
+    // One of the CSS named colors in constants::NAMED_COLORS. Case, spaces, underscores and dashes
+    // are ignored, so "cornflower_blue" and "CornflowerBlue" both find cornflowerblue.
+    pub fn by_name(name: &str) -> Option<Color> {
+        let name: String = name
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '_' | '-'))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        NAMED_COLORS
+            .binary_search_by_key(&name.as_str(), |&(n, _)| n)
+            .ok()
+            .map(|i| {
+                let rgb = NAMED_COLORS[i].1;
+                let channel = |shift: u32| ((rgb >> shift) & 0xff) as f32 / 255.0;
+                Color::new(channel(16), channel(8), channel(0))
+            })
+    }
+    // The color of a black body glowing at the given temperature in Kelvin (1000 to 40000),
+    // normalized so that the brightest channel is 1: candles are around 1900K, household bulbs
+    // 2700K, noon daylight 5500K and an overcast sky 6500K (white). Uses Tanner Helland's fit.
+    pub fn from_temperature(kelvin: f32) -> Color {
+        let t = kelvin.clamp(1000., 40000.) / 100.;
+        let r = if t <= 66. {
+            255.
+        } else {
+            329.698_73 * (t - 60.).powf(-0.133_204_76)
+        };
+        let g = if t <= 66. {
+            99.470_8 * t.ln() - 161.119_57
+        } else {
+            288.122_17 * (t - 60.).powf(-0.075_514_85)
+        };
+        let b = if t >= 66. {
+            255.
+        } else if t <= 19. {
+            0.
+        } else {
+            138.517_73 * (t - 10.).ln() - 305.044_8
+        };
+        let channel = |c: f32| c.clamp(0., 255.) / 255.;
+        Color::new(channel(r), channel(g), channel(b))
+    }
+    // hue is in degrees and wraps around; saturation and value are between 0 and 1
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Color {
+        let chroma = value * saturation;
+        Color::from_hue_and_chroma(hue, chroma, value - chroma)
+    }
+    // hue is in degrees and wraps around; saturation and lightness are between 0 and 1
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Color {
+        let chroma = (1. - (2. * lightness - 1.).abs()) * saturation;
+        Color::from_hue_and_chroma(hue, chroma, lightness - chroma / 2.)
+    }
+    // the brightest channel is min + chroma, the dimmest is min
+    fn from_hue_and_chroma(hue: f32, chroma: f32, min: f32) -> Color {
+        let sector = hue.rem_euclid(360.) / 60.;
+        let x = chroma * (1. - (sector % 2. - 1.).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x),
+        };
+        Color::new(r + min, g + min, b + min)
+    }
+    // (hue in degrees from 0 to 360, saturation, value); grays have a hue of 0
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (hue, chroma, max, _) = self.hue_and_chroma();
+        let saturation = if max == 0. { 0. } else { chroma / max };
+        (hue, saturation, max)
+    }
+    // (hue in degrees from 0 to 360, saturation, lightness); grays have a hue of 0
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (hue, chroma, max, min) = self.hue_and_chroma();
+        let lightness = (max + min) / 2.;
+        let saturation = if lightness == 0. || lightness == 1. {
+            0.
+        } else {
+            chroma / (1. - (2. * lightness - 1.).abs())
+        };
+        (hue, saturation, lightness)
+    }
+    // (hue, chroma, brightest channel, dimmest channel)
+    fn hue_and_chroma(&self) -> (f32, f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+        let sector = if chroma == 0. {
+            0.
+        } else if max == self.r {
+            ((self.g - self.b) / chroma).rem_euclid(6.)
+        } else if max == self.g {
+            (self.b - self.r) / chroma + 2.
+        } else {
+            (self.r - self.g) / chroma + 4.
+        };
+        (sector * 60., chroma, max, min)
+    }
+    // each channel limited to between 0 and 1
+    pub fn clamp01(&self) -> Color {
+        Color::new(
+            self.r.clamp(0., 1.),
+            self.g.clamp(0., 1.),
+            self.b.clamp(0., 1.),
+        )
+    }
+    pub fn min(&self, other: Color) -> Color {
+        Color::new(
+            self.r.min(other.r),
+            self.g.min(other.g),
+            self.b.min(other.b),
+        )
+    }
+    pub fn max(&self, other: Color) -> Color {
+        Color::new(
+            self.r.max(other.r),
+            self.g.max(other.g),
+            self.b.max(other.b),
+        )
+    }
+    // perceived brightness, weighting the channels as the (linear) Rec. 709 standard does
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+    // scaled down so that no channel is brighter than max, keeping the hue
+    pub fn limit_brightness(&self, max: f32) -> Color {
+        let brightest = self.r.max(self.g).max(self.b);
+        if brightest > max {
+            self * (max / brightest)
+        } else {
+            *self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InvalidColorError {
+    pub channel: &'static str,
+}
+
+impl Display for InvalidColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{} cannot be NaN", self.channel)
+    }
+}
+
+impl std::error::Error for InvalidColorError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseColorError {
+    pub hex_code: String,
+}
+
+impl Display for ParseColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{:?} is not a color name or a hex color code like #1e90ff or #fff",
+            self.hex_code
+        )
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+// scene files may give colors as {"r": 1, "g": 0.5, "b": 0}, as hex codes or by name
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDescription {
+    Channels { r: f32, g: f32, b: f32 },
+    Text(String),
+}
+
+impl TryFrom<ColorDescription> for Color {
+    type Error = ParseColorError;
+
+    fn try_from(description: ColorDescription) -> Result<Self, Self::Error> {
+        match description {
+            ColorDescription::Channels { r, g, b } => Ok(Color { r, g, b }),
+            ColorDescription::Text(text) => text.parse(),
+        }
+    }
 }
 
 impl Display for Color {
@@ -22,9 +252,12 @@ impl Display for Color {
     }
 }
 
-// use like this: color!(1,1,0.5)
+// use like this: color!(1,1,0.5) or color!("#1e90ff")
 #[macro_export]
 macro_rules! color {
+    ($hex_code:literal) => {{
+        Color::from_hex($hex_code).unwrap()
+    }};
     ($x:expr, $y:expr, $z:expr) => {{
         Color::new($x as f32, $y as f32, $z as f32)
     }};
@@ -75,6 +308,24 @@ impl_op_ex!(*|a: &Color, b: &Color| -> Color {
     }
 });
 
+impl_op_ex!(+=|a: &mut Color, b: &Color| {
+    a.r += b.r;
+    a.g += b.g;
+    a.b += b.b;
+});
+
+impl_op_ex!(*=|color: &mut Color, scalar: &f32| {
+    color.r *= scalar;
+    color.g *= scalar;
+    color.b *= scalar;
+});
+
+impl_op_ex!(*=|a: &mut Color, b: &Color| {
+    a.r *= b.r;
+    a.g *= b.g;
+    a.b *= b.b;
+});
+
 // required for equality tests because floating point numbers must be compared approximately
 impl AbsDiffEq for Color {
     type Epsilon = f32;
@@ -91,19 +342,11 @@ impl AbsDiffEq for Color {
 }
 
 impl FromStr for Color {
-    type Err = std::num::ParseIntError;
-
-    // Parses a color hex code of the form '#rRgGbB..'
-    fn from_str(hex_code: &str) -> Result<Self, Self::Err> {
-        let r: u8 = u8::from_str_radix(&hex_code[1..3], 16)?;
-        let g: u8 = u8::from_str_radix(&hex_code[3..5], 16)?;
-        let b: u8 = u8::from_str_radix(&hex_code[5..7], 16)?;
+    type Err = ParseColorError;
 
-        Ok(Color::new(
-            r as f32 / 255.0,
-            g as f32 / 255.0,
-            b as f32 / 255.0,
-        ))
+    // a hex code or a color name
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Color::from_hex(text).or_else(|e| Color::by_name(text).ok_or(e))
     }
 }
 
@@ -111,6 +354,15 @@ impl FromStr for Color {
 mod tests {
     use super::*;
 
+    #[test]
+    fn try_new_rejects_nan() {
+        assert_eq!(
+            Color::try_new(0.5, f32::NAN, 0.),
+            Err(InvalidColorError { channel: "g" })
+        );
+        assert_eq!(Color::try_new(0.5, 1., 0.), Ok(color!(0.5, 1, 0)));
+    }
+
     #[test]
     fn test_adding_colors() {
         let c1 = color!(0.9, 0.6, 0.75);
@@ -139,10 +391,160 @@ mod tests {
         assert_abs_diff_eq!(c1 * c2, color!(0.9, 0.2, 0.04));
     }
 
+    #[test]
+    fn compound_assignment() {
+        let mut c = color!(0.2, 0.3, 0.4);
+        c += color!(0.1, 0.1, 0.1);
+        assert_abs_diff_eq!(c, color!(0.3, 0.4, 0.5));
+        c *= 2.;
+        assert_abs_diff_eq!(c, color!(0.6, 0.8, 1));
+        c *= color!(0.5, 1, 0);
+        assert_abs_diff_eq!(c, color!(0.3, 0.8, 0));
+    }
+
+    #[test]
+    fn componentwise_operations() {
+        let a = color!(-0.5, 0.5, 1.5);
+        let b = color!(0, 1, 1);
+        assert_eq!(a.clamp01(), color!(0, 0.5, 1));
+        assert_eq!(a.min(b), color!(-0.5, 0.5, 1));
+        assert_eq!(a.max(b), color!(0, 1, 1.5));
+    }
+
+    #[test]
+    fn luminance_weights_green_most() {
+        assert_abs_diff_eq!(color!(1, 1, 1).luminance(), 1.);
+        assert_abs_diff_eq!(color!(0, 0, 0).luminance(), 0.);
+        assert!(color!(0, 1, 0).luminance() > color!(1, 0, 0).luminance());
+        assert!(color!(1, 0, 0).luminance() > color!(0, 0, 1).luminance());
+    }
+
+    #[test]
+    fn hsv_and_hsl_conversions() {
+        // name, color, (h, s, v), (h, s, l)
+        let test_data = vec![
+            ("black", color!(0, 0, 0), (0., 0., 0.), (0., 0., 0.)),
+            ("white", color!(1, 1, 1), (0., 0., 1.), (0., 0., 1.)),
+            ("gray", color!(0.5, 0.5, 0.5), (0., 0., 0.5), (0., 0., 0.5)),
+            ("red", color!(1, 0, 0), (0., 1., 1.), (0., 1., 0.5)),
+            ("yellow", color!(1, 1, 0), (60., 1., 1.), (60., 1., 0.5)),
+            ("cyan", color!(0, 1, 1), (180., 1., 1.), (180., 1., 0.5)),
+            ("magenta", color!(1, 0, 1), (300., 1., 1.), (300., 1., 0.5)),
+            (
+                "dark orange",
+                color!(0.5, 0.25, 0),
+                (30., 1., 0.5),
+                (30., 1., 0.25),
+            ),
+            (
+                "pale blue",
+                color!(0.6, 0.7, 0.9),
+                (220., 1. / 3., 0.9),
+                (220., 0.6, 0.75),
+            ),
+        ];
+        for (name, c, (h, s, v), (hl, sl, l)) in test_data {
+            println!("Case {}", name);
+            assert_abs_diff_eq!(Color::from_hsv(h, s, v), c);
+            assert_abs_diff_eq!(Color::from_hsl(hl, sl, l), c);
+            let hsv = c.to_hsv();
+            let hsl = c.to_hsl();
+            assert_abs_diff_eq!(hsv.0, h, epsilon = 0.001);
+            assert_abs_diff_eq!(hsv.1, s, epsilon = 0.001);
+            assert_abs_diff_eq!(hsv.2, v, epsilon = 0.001);
+            assert_abs_diff_eq!(hsl.0, hl, epsilon = 0.001);
+            assert_abs_diff_eq!(hsl.1, sl, epsilon = 0.001);
+            assert_abs_diff_eq!(hsl.2, l, epsilon = 0.001);
+        }
+    }
+
+    #[test]
+    fn hue_wraps_around() {
+        assert_abs_diff_eq!(Color::from_hsv(360., 1., 1.), color!(1, 0, 0));
+        assert_abs_diff_eq!(Color::from_hsv(-120., 1., 1.), color!(0, 0, 1));
+        assert_abs_diff_eq!(Color::from_hsl(480., 1., 0.5), color!(0, 1, 0));
+    }
+
+    #[test]
+    fn color_temperature() {
+        let test_data = vec![
+            ("candle", 1900., color!(1, 0.513, 0.0)),
+            ("bulb", 2700., color!(1, 0.655, 0.341)),
+            ("overcast", 6600., color!(1, 1, 1)),
+            ("blue sky", 15000., color!(0.710, 0.804, 1)),
+        ];
+        for (name, kelvin, expected) in test_data {
+            println!("Case {}", name);
+            assert_abs_diff_eq!(Color::from_temperature(kelvin), expected, epsilon = 0.005);
+        }
+        // warmer is redder
+        assert!(Color::from_temperature(3000.).b < Color::from_temperature(5000.).b);
+    }
+
+    #[test]
+    fn limit_brightness_keeps_hue() {
+        let c = color!(4, 2, 1);
+        assert_abs_diff_eq!(c.limit_brightness(2.), color!(2, 1, 0.5));
+        assert_abs_diff_eq!(c.limit_brightness(5.), c);
+    }
+
     #[test]
     fn test_parse_hex() {
         let c = Color::from_str("#0ab33f").unwrap();
         println!("{:?}", c);
         assert_abs_diff_eq!(c, color!(0.039_215_688, 0.701_960_8, 0.247_058_82));
+        assert_eq!(Color::from_hex("0AB33F"), Ok(c));
+        assert_eq!(color!("#0ab33f"), c);
+        assert_eq!(color!("#fff"), color!(1, 1, 1));
+        assert_eq!(Color::from_hex("#1e9"), Color::from_hex("#11ee99"));
+    }
+
+    #[test]
+    fn invalid_hex_codes_are_rejected() {
+        for hex_code in &["", "#", "#0ab33", "#0ab33f0", "#0ag33f", "#0ab3\u{e9}"] {
+            assert_eq!(
+                Color::from_hex(hex_code),
+                Err(ParseColorError {
+                    hex_code: hex_code.to_string()
+                }),
+                "Case {:?}",
+                hex_code
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_channels_or_hex_code() {
+        let c: Color = serde_json::from_str(r#"{"r": 1, "g": 0.5, "b": 0}"#).unwrap();
+        assert_eq!(c, color!(1, 0.5, 0));
+        let c: Color = serde_json::from_str(r##""#ff0000""##).unwrap();
+        assert_eq!(c, color!(1, 0, 0));
+        let c: Color = serde_json::from_str(r#""dodger_blue""#).unwrap();
+        assert_eq!(c, color!("#1e90ff"));
+        assert!(serde_json::from_str::<Color>(r#""reddish""#).is_err());
+    }
+
+    #[test]
+    fn colors_by_name() {
+        let test_data = vec![
+            ("cornflower_blue", Some(color!("#6495ed"))),
+            ("CornflowerBlue", Some(color!("#6495ed"))),
+            ("rebecca-purple", Some(color!("#663399"))),
+            ("gray", Some(color!("#808080"))),
+            ("grey", Some(color!("#808080"))),
+            ("white", Some(color!(1, 1, 1))),
+            ("aliceblue", Some(color!("#f0f8ff"))),
+            ("yellowgreen", Some(color!("#9acd32"))),
+            ("not a color", None),
+        ];
+        for (name, expected) in test_data {
+            assert_eq!(Color::by_name(name), expected, "Case {}", name);
+        }
+        assert_eq!(Color::from_str("olive"), Ok(color!("#808000")));
+    }
+
+    #[test]
+    fn named_colors_are_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
     }
 }
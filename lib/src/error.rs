@@ -0,0 +1,120 @@
+use crate::canvas;
+use crate::color::{InvalidColorError, ParseColorError};
+use crate::font::FontError;
+use crate::material::InvalidMaterial;
+use crate::matrix::SingularMatrixError;
+use crate::obj_parser;
+use crate::scene_file::SceneError;
+use crate::tuple::InvalidTupleError;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+// Any error returned by the crate. Each module returns its own error type, which can be matched
+// on to find out exactly what went wrong; all of them convert into this one with `?`, so code
+// that loads meshes, images, fonts and scenes together can return one Result.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Obj(obj_parser::ParseError),
+    // PPM, PFM and HDR images and .cube LUTs
+    Image(canvas::ParseError),
+    Scene(SceneError),
+    Font(FontError),
+    SingularMatrix(SingularMatrixError),
+    InvalidTuple(InvalidTupleError),
+    InvalidColor(InvalidColorError),
+    ParseColor(ParseColorError),
+    InvalidMaterial(InvalidMaterial),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(f),
+            Error::Obj(err) => write!(f, "Invalid OBJ file: {}", err),
+            Error::Image(err) => write!(f, "Invalid image file: {}", err),
+            Error::Scene(err) => err.fmt(f),
+            Error::Font(err) => write!(f, "Invalid font: {}", err),
+            Error::SingularMatrix(err) => err.fmt(f),
+            Error::InvalidTuple(err) => err.fmt(f),
+            Error::InvalidColor(err) => err.fmt(f),
+            Error::ParseColor(err) => err.fmt(f),
+            Error::InvalidMaterial(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Obj(err) => Some(err),
+            Error::Image(err) => Some(err),
+            Error::Scene(err) => Some(err),
+            Error::Font(err) => Some(err),
+            Error::SingularMatrix(err) => Some(err),
+            Error::InvalidTuple(err) => Some(err),
+            Error::InvalidColor(err) => Some(err),
+            Error::ParseColor(err) => Some(err),
+            Error::InvalidMaterial(err) => Some(err),
+        }
+    }
+}
+
+macro_rules! impl_from_error {
+    ($($variant:ident($error:ty)),* $(,)?) => {
+        $(impl From<$error> for Error {
+            fn from(err: $error) -> Error {
+                Error::$variant(err)
+            }
+        })*
+    };
+}
+
+impl_from_error!(
+    Io(io::Error),
+    Obj(obj_parser::ParseError),
+    Image(canvas::ParseError),
+    Scene(SceneError),
+    Font(FontError),
+    SingularMatrix(SingularMatrixError),
+    InvalidTuple(InvalidTupleError),
+    InvalidColor(InvalidColorError),
+    ParseColor(ParseColorError),
+    InvalidMaterial(InvalidMaterial),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::canvas_from_ppm;
+    use crate::obj_parser::parse_obj;
+    use std::error::Error as StdError;
+
+    fn load_both(obj: &str, ppm: &str) -> Result<usize> {
+        parse_obj(obj.as_bytes())?;
+        let canvas = canvas_from_ppm(ppm.as_bytes())?;
+        Ok(canvas.width)
+    }
+
+    #[test]
+    fn module_errors_convert_with_question_mark() {
+        let ppm = "P3\n1 1\n255\n0 0 0\n";
+        assert_eq!(load_both("v 0 0 0\n", ppm).unwrap(), 1);
+
+        let err = load_both("v 0 0 0\n", "P6\n1 1\n255\n").unwrap_err();
+        assert!(matches!(err, Error::Image(_)), "{:?}", err);
+        assert!(err.to_string().starts_with("Invalid image file: "));
+    }
+
+    #[test]
+    fn sources_are_chained() {
+        let io_error = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let err = Error::from(obj_parser::ParseError::from(io_error));
+        let obj_error = err.source().unwrap();
+        assert_eq!(obj_error.to_string(), "no such file");
+        assert_eq!(obj_error.source().unwrap().to_string(), "no such file");
+    }
+}
@@ -0,0 +1,75 @@
+// Helpers for hashing the structure of a scene (geometry, transformations and materials) while
+// ignoring object identity. See Shape::fingerprint and Shape::structurally_eq.
+use crate::color::Color;
+use crate::matrix::Matrix;
+use crate::tuple::Tuple;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+// -0.0 and 0.0 are hashed the same, since they render identically
+pub fn hash_f32(hasher: &mut dyn Hasher, value: f32) {
+    let value = if value == 0. { 0. } else { value };
+    hasher.write_u32(value.to_bits());
+}
+
+pub fn hash_tuple(hasher: &mut dyn Hasher, t: &Tuple) {
+    for &value in &[t.x, t.y, t.z, t.w] {
+        hash_f32(hasher, value);
+    }
+}
+
+pub fn hash_color(hasher: &mut dyn Hasher, c: &Color) {
+    for &value in &[c.r, c.g, c.b] {
+        hash_f32(hasher, value);
+    }
+}
+
+pub fn hash_matrix(hasher: &mut dyn Hasher, m: &Matrix) {
+    hasher.write_usize(m.size());
+    for &value in m.as_slice() {
+        hash_f32(hasher, value);
+    }
+}
+
+// Hasher that records everything written to it, so that two structures can be compared exactly
+// instead of relying on their hashes not colliding. It can still be used as an ordinary hasher.
+#[derive(Default)]
+pub(crate) struct StructureRecorder {
+    pub bytes: Vec<u8>,
+}
+
+impl Hasher for StructureRecorder {
+    fn finish(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&self.bytes);
+        hasher.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_zeros_are_recorded_the_same() {
+        let mut positive = StructureRecorder::default();
+        let mut negative = StructureRecorder::default();
+        hash_tuple(&mut positive, &vector!(0, 1, 0));
+        hash_tuple(&mut negative, &vector!(-0., 1, 0));
+        assert_eq!(positive.bytes, negative.bytes);
+        assert_eq!(positive.finish(), negative.finish());
+    }
+
+    #[test]
+    fn recorder_hashes_what_it_recorded() {
+        let mut a = StructureRecorder::default();
+        let mut b = StructureRecorder::default();
+        hash_color(&mut a, &color!(1, 0, 0));
+        hash_color(&mut b, &color!(0, 1, 0));
+        assert_ne!(a.finish(), b.finish());
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read};
+
+// Reads the glyph outlines of TrueType (.ttf) fonts: just enough of the format to turn text into
+// shapes. Hinting, kerning and CFF (PostScript-flavored .otf) outlines are not supported.
+
+// straight segments used for each quadratic curve of an outline
+const CURVE_STEPS: usize = 4;
+// compound glyphs may include other compound glyphs; stop following them after this many levels
+const MAX_COMPONENT_DEPTH: usize = 8;
+// components followed in total for one glyph; each level may use the level below many times, so
+// the depth limit alone still allows a malformed font to expand into billions of outlines
+const MAX_COMPONENTS: usize = 1024;
+
+#[derive(Debug)]
+pub enum FontError {
+    IoError(io::Error),
+    MissingTable(&'static str),
+    Malformed(String),
+    Unsupported(String),
+}
+
+impl From<io::Error> for FontError {
+    fn from(err: io::Error) -> FontError {
+        FontError::IoError(err)
+    }
+}
+
+impl Display for FontError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            FontError::IoError(ref e) => e.fmt(f),
+            FontError::MissingTable(tag) => write!(f, "Font has no '{}' table", tag),
+            FontError::Malformed(ref s) => f.write_str(s),
+            FontError::Unsupported(ref s) => f.write_str(s),
+        }
+    }
+}
+
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FontError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// A closed loop of an outline, as (x, y) points in ems, with y pointing up from the baseline.
+// Outer loops run clockwise and the loops of holes run counter-clockwise.
+pub type Contour = Vec<(f32, f32)>;
+
+// a point of a glyph's outline as stored in the font: x, y and whether it is on the curve (rather
+// than a control point pulling the curve towards it)
+type GlyphPoint = (f32, f32, bool);
+
+#[derive(Debug, Clone)]
+pub struct Font {
+    units_per_em: f32,
+    // distances from the baseline, in ems
+    ascender: f32,
+    descender: f32,
+    line_gap: f32,
+    glyph_indices: HashMap<char, u16>,
+    advance_widths: Vec<u16>,
+    // offsets into glyph_data of each glyph's outline; glyph i ends where glyph i + 1 starts
+    glyph_offsets: Vec<usize>,
+    glyph_data: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, FontError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| FontError::Malformed(format!("Font data ended at byte {}", offset)))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Result<i16, FontError> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FontError> {
+    Ok((u32::from(read_u16(data, offset)?) << 16) | u32::from(read_u16(data, offset + 2)?))
+}
+
+// fixed point number with 2 integer bits and 14 fractional bits
+fn read_f2dot14(data: &[u8], offset: usize) -> Result<f32, FontError> {
+    Ok(f32::from(read_i16(data, offset)?) / 16384.)
+}
+
+fn find_table<'a>(data: &'a [u8], tag: &'static str) -> Result<&'a [u8], FontError> {
+    let num_tables = read_u16(data, 4)? as usize;
+    for i in 0..num_tables {
+        let record = 12 + 16 * i;
+        if data.get(record..record + 4) == Some(tag.as_bytes()) {
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            return data.get(offset..offset + length).ok_or_else(|| {
+                FontError::Malformed(format!("Table '{}' extends past the end of the font", tag))
+            });
+        }
+    }
+    Err(FontError::MissingTable(tag))
+}
+
+// Reads the mapping from characters to glyphs out of a format 4 (Unicode basic multilingual
+// plane) character map
+fn read_glyph_indices(cmap: &[u8]) -> Result<HashMap<char, u16>, FontError> {
+    let num_subtables = read_u16(cmap, 2)? as usize;
+    let mut subtable = None;
+    for i in 0..num_subtables {
+        let record = 4 + 8 * i;
+        let platform = read_u16(cmap, record)?;
+        let encoding = read_u16(cmap, record + 2)?;
+        let offset = read_u32(cmap, record + 4)? as usize;
+        let is_unicode = platform == 0 || (platform == 3 && encoding == 1);
+        if is_unicode && read_u16(cmap, offset)? == 4 {
+            subtable = Some(offset);
+            break;
+        }
+    }
+    let subtable = subtable.ok_or_else(|| {
+        FontError::Unsupported("Font has no format 4 Unicode character map".to_string())
+    })?;
+
+    let segment_count = read_u16(cmap, subtable + 6)? as usize / 2;
+    let end_codes = subtable + 14;
+    let start_codes = end_codes + 2 * segment_count + 2;
+    let deltas = start_codes + 2 * segment_count;
+    let range_offsets = deltas + 2 * segment_count;
+    let mut glyph_indices = HashMap::new();
+    for segment in 0..segment_count {
+        let end = read_u16(cmap, end_codes + 2 * segment)?;
+        let start = read_u16(cmap, start_codes + 2 * segment)?;
+        let delta = read_u16(cmap, deltas + 2 * segment)?;
+        let range_offset_position = range_offsets + 2 * segment;
+        let range_offset = read_u16(cmap, range_offset_position)? as usize;
+        for code in start..=end {
+            // 0xFFFF ends the last segment and is not a character
+            if code == 0xFFFF {
+                break;
+            }
+            let glyph = if range_offset == 0 {
+                code.wrapping_add(delta)
+            } else {
+                let position = range_offset_position + range_offset + 2 * (code - start) as usize;
+                match read_u16(cmap, position)? {
+                    0 => 0,
+                    glyph => glyph.wrapping_add(delta),
+                }
+            };
+            if let (Some(c), true) = (std::char::from_u32(u32::from(code)), glyph != 0) {
+                glyph_indices.insert(c, glyph);
+            }
+        }
+    }
+    Ok(glyph_indices)
+}
+
+// Turns a loop of TrueType points into line segments. Two off-curve points in a row have an
+// implied on-curve point halfway between them.
+fn flatten_contour(points: &[GlyphPoint]) -> Contour {
+    let midpoint = |a: GlyphPoint, b: GlyphPoint| ((a.0 + b.0) / 2., (a.1 + b.1) / 2.);
+    let count = points.len();
+    // start on a point that is on the curve, or between two off-curve points if there is none
+    let (start_index, start) = match points.iter().position(|p| p.2) {
+        Some(i) => (i, (points[i].0, points[i].1)),
+        None => (0, midpoint(points[0], points[1 % count])),
+    };
+    let mut contour = vec![start];
+    let mut current = start;
+    let mut control: Option<(f32, f32)> = None;
+    // without an on-curve point, the loop also passes the first point again to get back to start
+    let steps = if points[start_index].2 {
+        count
+    } else {
+        count + 1
+    };
+    for step in 1..=steps {
+        let p = points[(start_index + step) % count];
+        let on_curve = if p.2 {
+            Some((p.0, p.1))
+        } else if let Some(c) = control {
+            // implied point between two controls
+            let implied = ((c.0 + p.0) / 2., (c.1 + p.1) / 2.);
+            curve_to(&mut contour, current, c, implied);
+            current = implied;
+            control = Some((p.0, p.1));
+            continue;
+        } else {
+            control = Some((p.0, p.1));
+            None
+        };
+        if let Some(end) = on_curve {
+            match control.take() {
+                Some(c) => curve_to(&mut contour, current, c, end),
+                None => contour.push(end),
+            }
+            current = end;
+        }
+    }
+    // the loop ends where it started
+    if contour.len() > 1 && contour.first() == contour.last() {
+        contour.pop();
+    }
+    contour.dedup();
+    contour
+}
+
+fn curve_to(contour: &mut Contour, from: (f32, f32), control: (f32, f32), to: (f32, f32)) {
+    for step in 1..=CURVE_STEPS {
+        let t = step as f32 / CURVE_STEPS as f32;
+        let s = 1. - t;
+        contour.push((
+            s * s * from.0 + 2. * s * t * control.0 + t * t * to.0,
+            s * s * from.1 + 2. * s * t * control.1 + t * t * to.1,
+        ));
+    }
+}
+
+impl Font {
+    pub fn from_bytes(data: &[u8]) -> Result<Font, FontError> {
+        match read_u32(data, 0)? {
+            // TrueType outlines; 'true' is used by some older Apple fonts
+            0x0001_0000 | 0x7472_7565 => {}
+            0x4F54_544F => {
+                return Err(FontError::Unsupported(
+                    "Fonts with PostScript (CFF) outlines are not supported".to_string(),
+                ))
+            }
+            other => {
+                return Err(FontError::Malformed(format!(
+                    "Not a TrueType font; unexpected version {:#x}",
+                    other
+                )))
+            }
+        }
+        let head = find_table(data, "head")?;
+        let units_per_em = f32::from(read_u16(head, 18)?);
+        let long_offsets = read_i16(head, 50)? != 0;
+        let num_glyphs = read_u16(find_table(data, "maxp")?, 4)? as usize;
+
+        let hhea = find_table(data, "hhea")?;
+        let ascender = f32::from(read_i16(hhea, 4)?) / units_per_em;
+        let descender = f32::from(read_i16(hhea, 6)?) / units_per_em;
+        let line_gap = f32::from(read_i16(hhea, 8)?) / units_per_em;
+        let num_metrics = read_u16(hhea, 34)? as usize;
+        let hmtx = find_table(data, "hmtx")?;
+        let advance_widths = (0..num_metrics)
+            .map(|i| read_u16(hmtx, 4 * i))
+            .collect::<Result<Vec<u16>, FontError>>()?;
+
+        let loca = find_table(data, "loca")?;
+        let glyph_offsets = (0..=num_glyphs)
+            .map(|i| {
+                if long_offsets {
+                    read_u32(loca, 4 * i).map(|offset| offset as usize)
+                } else {
+                    read_u16(loca, 2 * i).map(|offset| 2 * offset as usize)
+                }
+            })
+            .collect::<Result<Vec<usize>, FontError>>()?;
+
+        Ok(Font {
+            units_per_em,
+            ascender,
+            descender,
+            line_gap,
+            glyph_indices: read_glyph_indices(find_table(data, "cmap")?)?,
+            advance_widths,
+            glyph_offsets,
+            glyph_data: find_table(data, "glyf")?.to_vec(),
+        })
+    }
+
+    pub fn from_reader<T: Read>(mut reader: T) -> Result<Font, FontError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Font::from_bytes(&data)
+    }
+
+    // distance between the baselines of consecutive lines, in ems
+    pub fn line_height(&self) -> f32 {
+        self.ascender - self.descender + self.line_gap
+    }
+
+    // characters missing from the font get glyph 0, which usually draws an empty box
+    fn glyph_index(&self, c: char) -> u16 {
+        *self.glyph_indices.get(&c).unwrap_or(&0)
+    }
+
+    // how far to move along the baseline after the character, in ems
+    pub fn advance_width(&self, c: char) -> f32 {
+        let glyph = self.glyph_index(c) as usize;
+        let width = self
+            .advance_widths
+            .get(glyph)
+            .or_else(|| self.advance_widths.last())
+            .copied()
+            .unwrap_or(0);
+        f32::from(width) / self.units_per_em
+    }
+
+    // The outline of the character as closed loops, in ems. Whitespace has no loops.
+    pub fn glyph_contours(&self, c: char) -> Result<Vec<Contour>, FontError> {
+        let mut contours = vec![];
+        let mut components = 0;
+        self.read_glyph(
+            self.glyph_index(c),
+            &|x, y| (x, y),
+            0,
+            &mut components,
+            &mut contours,
+        )?;
+        Ok(contours
+            .into_iter()
+            .map(|contour| {
+                contour
+                    .into_iter()
+                    .map(|(x, y)| (x / self.units_per_em, y / self.units_per_em))
+                    .collect()
+            })
+            .collect())
+    }
+
+    // Adds the contours of the glyph to contours, placing its points with transform
+    fn read_glyph(
+        &self,
+        glyph: u16,
+        transform: &dyn Fn(f32, f32) -> (f32, f32),
+        depth: usize,
+        components: &mut usize,
+        contours: &mut Vec<Contour>,
+    ) -> Result<(), FontError> {
+        let glyph = glyph as usize;
+        let (start, end) = match (
+            self.glyph_offsets.get(glyph),
+            self.glyph_offsets.get(glyph + 1),
+        ) {
+            (Some(&start), Some(&end)) => (start, end),
+            _ => {
+                return Err(FontError::Malformed(format!(
+                    "Glyph {} is not in the font",
+                    glyph
+                )))
+            }
+        };
+        if start == end {
+            // no outline, like a space
+            return Ok(());
+        }
+        let data = self.glyph_data.get(start..end).ok_or_else(|| {
+            FontError::Malformed(format!("Glyph {} extends past the end of the font", glyph))
+        })?;
+        let num_contours = read_i16(data, 0)?;
+        if num_contours >= 0 {
+            for contour in read_simple_glyph(data, num_contours as usize)? {
+                let points: Vec<GlyphPoint> = contour
+                    .into_iter()
+                    .map(|(x, y, on_curve)| {
+                        let (x, y) = transform(x, y);
+                        (x, y, on_curve)
+                    })
+                    .collect();
+                if points.len() >= 2 {
+                    contours.push(flatten_contour(&points));
+                }
+            }
+            Ok(())
+        } else if depth >= MAX_COMPONENT_DEPTH {
+            Err(FontError::Malformed(format!(
+                "Glyph {} nests components too deeply",
+                glyph
+            )))
+        } else {
+            self.read_compound_glyph(data, transform, depth, components, contours)
+        }
+    }
+
+    // A glyph made of other glyphs, each moved and possibly scaled
+    fn read_compound_glyph(
+        &self,
+        data: &[u8],
+        transform: &dyn Fn(f32, f32) -> (f32, f32),
+        depth: usize,
+        components: &mut usize,
+        contours: &mut Vec<Contour>,
+    ) -> Result<(), FontError> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAS_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAS_X_AND_Y_SCALE: u16 = 0x0040;
+        const HAS_TWO_BY_TWO: u16 = 0x0080;
+
+        let mut offset = 10;
+        loop {
+            let flags = read_u16(data, offset)?;
+            let component = read_u16(data, offset + 2)?;
+            offset += 4;
+            *components += 1;
+            if *components > MAX_COMPONENTS {
+                return Err(FontError::Malformed(format!(
+                    "Glyph has more than {} components",
+                    MAX_COMPONENTS
+                )));
+            }
+            let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                offset += 4;
+                (
+                    f32::from(read_i16(data, offset - 4)?),
+                    f32::from(read_i16(data, offset - 2)?),
+                )
+            } else {
+                offset += 2;
+                let args = read_u16(data, offset - 2)?;
+                (
+                    f32::from((args >> 8) as u8 as i8),
+                    f32::from(args as u8 as i8),
+                )
+            };
+            // otherwise the arguments are points to line up, which is rare; ignore the offset
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0., 0.)
+            };
+            // [[xx, yx], [xy, yy]]
+            let (xx, xy, yx, yy) = if flags & HAS_SCALE != 0 {
+                offset += 2;
+                let scale = read_f2dot14(data, offset - 2)?;
+                (scale, 0., 0., scale)
+            } else if flags & HAS_X_AND_Y_SCALE != 0 {
+                offset += 4;
+                (
+                    read_f2dot14(data, offset - 4)?,
+                    0.,
+                    0.,
+                    read_f2dot14(data, offset - 2)?,
+                )
+            } else if flags & HAS_TWO_BY_TWO != 0 {
+                offset += 8;
+                (
+                    read_f2dot14(data, offset - 8)?,
+                    read_f2dot14(data, offset - 6)?,
+                    read_f2dot14(data, offset - 4)?,
+                    read_f2dot14(data, offset - 2)?,
+                )
+            } else {
+                (1., 0., 0., 1.)
+            };
+            let component_transform =
+                |x: f32, y: f32| transform(xx * x + yx * y + dx, xy * x + yy * y + dy);
+            self.read_glyph(
+                component,
+                &component_transform,
+                depth + 1,
+                components,
+                contours,
+            )?;
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// The points of each contour of a simple glyph, in font units, with whether they are on the curve
+fn read_simple_glyph(data: &[u8], num_contours: usize) -> Result<Vec<Vec<GlyphPoint>>, FontError> {
+    const ON_CURVE: u8 = 0x01;
+    const X_IS_BYTE: u8 = 0x02;
+    const Y_IS_BYTE: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    // for bytes, whether the value is positive; for words, whether the value is unchanged
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let contour_ends = (0..num_contours)
+        .map(|i| read_u16(data, 10 + 2 * i).map(|end| end as usize))
+        .collect::<Result<Vec<usize>, FontError>>()?;
+    let num_points = contour_ends.last().map_or(0, |end| end + 1);
+    let instructions_length = read_u16(data, 10 + 2 * num_contours)? as usize;
+    let mut offset = 12 + 2 * num_contours + instructions_length;
+    let byte = |offset: usize| {
+        data.get(offset)
+            .copied()
+            .ok_or_else(|| FontError::Malformed("Glyph data ended early".to_string()))
+    };
+
+    let mut flags = Vec::with_capacity(num_points);
+    while flags.len() < num_points {
+        let flag = byte(offset)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let repeats = byte(offset)?;
+            offset += 1;
+            for _ in 0..repeats {
+                flags.push(flag);
+            }
+        }
+    }
+
+    // coordinates are stored as differences from the previous point's
+    let mut read_coordinates = |is_byte: u8, same_or_positive: u8| {
+        let mut value = 0i32;
+        let mut values = Vec::with_capacity(num_points);
+        for &flag in &flags[..num_points] {
+            if flag & is_byte != 0 {
+                let delta = i32::from(byte(offset)?);
+                offset += 1;
+                value += if flag & same_or_positive != 0 {
+                    delta
+                } else {
+                    -delta
+                };
+            } else if flag & same_or_positive == 0 {
+                value += i32::from(read_i16(data, offset)?);
+                offset += 2;
+            }
+            values.push(value as f32);
+        }
+        Ok::<Vec<f32>, FontError>(values)
+    };
+    let xs = read_coordinates(X_IS_BYTE, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_IS_BYTE, Y_SAME_OR_POSITIVE)?;
+
+    let mut contours = Vec::with_capacity(num_contours);
+    let mut start = 0;
+    for end in contour_ends {
+        if end < start || end >= num_points {
+            return Err(FontError::Malformed(format!(
+                "Contour ends at point {}, out of order",
+                end
+            )));
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| (xs[i], ys[i], flags[i] & ON_CURVE != 0))
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Ok(contours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::utils::test_font_bytes;
+
+    #[test]
+    fn read_metrics() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        assert_eq!(font.advance_width('O'), 0.75);
+        assert_eq!(font.advance_width(' '), 0.25);
+        // missing characters use glyph 0
+        assert_eq!(font.advance_width('x'), 0.5);
+        assert_eq!(font.line_height(), 1.25);
+    }
+
+    #[test]
+    fn read_glyph_with_hole() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        let contours = font.glyph_contours('O').unwrap();
+        assert_eq!(
+            contours,
+            vec![
+                vec![(0., 0.), (0., 0.5), (0.5, 0.5), (0.5, 0.)],
+                vec![
+                    (0.125, 0.125),
+                    (0.375, 0.125),
+                    (0.375, 0.375),
+                    (0.125, 0.375)
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn read_curved_glyph() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        let contours = font.glyph_contours('D').unwrap();
+        assert_eq!(contours.len(), 1);
+        // the corner at (0.5, 0) pulls the curve from (0, 0) to (0.5, 0.5)
+        let contour = &contours[0];
+        assert_eq!(contour.len(), 1 + CURVE_STEPS);
+        assert_eq!(contour[0], (0., 0.));
+        assert_eq!(contour[2], (0.375, 0.125));
+        assert_eq!(contour[CURVE_STEPS], (0.5, 0.5));
+    }
+
+    #[test]
+    fn read_compound_glyph() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        let contours = font.glyph_contours('8').unwrap();
+        // two copies of O's outline, one shifted up by 0.5 ems
+        assert_eq!(contours.len(), 4);
+        assert_eq!(contours[2][1], (0., 1.));
+    }
+
+    #[test]
+    fn reject_compound_glyph_with_too_many_components() {
+        let mut font = Font::from_bytes(&test_font_bytes()).unwrap();
+        // a chain of glyphs that each use the next one 4 times, ending with O: 4^8 O's in all
+        let first = font.glyph_offsets.len() as u16 - 1;
+        let levels = MAX_COMPONENT_DEPTH as u16;
+        for level in 0..levels {
+            let next = if level + 1 == levels {
+                1
+            } else {
+                first + level + 1
+            };
+            let mut glyph = vec![0xFFFF, 0, 0, 0, 0];
+            for i in 0..4 {
+                // words as arguments, arguments are offsets, more components follow but the last
+                glyph.extend(&[if i < 3 { 0x0023 } else { 0x0003 }, next, 0, 0]);
+            }
+            font.glyph_data
+                .extend(glyph.iter().flat_map(|v: &u16| v.to_be_bytes().to_vec()));
+            font.glyph_offsets.push(font.glyph_data.len());
+        }
+        font.glyph_indices.insert('x', first);
+        match font.glyph_contours('x') {
+            Err(FontError::Malformed(message)) => assert!(message.contains("more than")),
+            other => panic!(
+                "Expected malformed font error; got {:?}",
+                other.map(|c| c.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn whitespace_has_no_outline() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        assert!(font.glyph_contours(' ').unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_non_font() {
+        match Font::from_bytes(b"P3\n1 1\n255\n") {
+            Err(FontError::Malformed(_)) => {}
+            other => panic!("Expected malformed font error; got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::material::Material;
+use crate::matrix::{identity_4x4, Matrix};
+use crate::primary_rays::PrimaryRayBackend;
+use crate::scene_file::LightDescription;
+use crate::shape::group::GroupShape;
+use crate::shape::plane::Plane;
+use crate::shape::shape::Shape;
+use crate::shape::sphere::Sphere;
+use crate::shape::triangle::Triangle;
+use crate::tuple::Tuple;
+use crate::world::World;
+
+// Experimental GPU primary rays, built with the gpu feature. A compute shader is meant to trace
+// one ray per pixel through spheres, planes and triangles, and to shade hits with Phong lighting
+// and a hard shadow from a single point light. Hits on objects that need more than that, such as
+// patterned, reflective or transparent ones, are left to the CPU.
+//
+// So far only the scene upload is written: GpuScene packs a world into the buffers that the
+// shader reads. The wgpu device and the shader itself are not, since wgpu is not available to
+// this build, so GpuBackend leaves every pixel to the CPU for now.
+
+// GpuObject and GpuTriangle flags
+pub const NEEDS_CPU: u32 = 0x1;
+pub const CASTS_SHADOW: u32 = 0x2;
+
+// Structs are laid out as in WGSL storage buffers: vectors and matrices take 16 bytes per column
+// and every struct is padded to a multiple of 16 bytes.
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuMaterial {
+    // rgb; the 4th value is padding
+    pub color: [f32; 4],
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+}
+
+// A sphere or plane
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuObject {
+    // columns of the world to object space transformation, as WGSL's mat4x4 stores them
+    pub inverse_transform: [f32; 16],
+    pub material: GpuMaterial,
+    pub flags: u32,
+    pub padding: [u32; 3],
+}
+
+// A triangle in world space, so that the shader needs no transformation for it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuTriangle {
+    // points with w = 1
+    pub p1: [f32; 4],
+    pub p2: [f32; 4],
+    pub p3: [f32; 4],
+    pub material: GpuMaterial,
+    pub flags: u32,
+    pub padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuLight {
+    pub position: [f32; 4],
+    pub intensity: [f32; 4],
+    pub casts_shadows: u32,
+    pub padding: [u32; 3],
+}
+
+// The contents of the shader's storage buffers for a world
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuScene {
+    pub spheres: Vec<GpuObject>,
+    pub planes: Vec<GpuObject>,
+    pub triangles: Vec<GpuTriangle>,
+    pub light: GpuLight,
+}
+
+impl GpuScene {
+    // None if the shader can't draw any of world correctly: when it has shapes other than
+    // spheres, planes and triangles (e.g. in groups), which could hide or shadow the others, a
+    // light other than a point light, or an environment for rays that miss everything.
+    pub fn from_world(world: &World) -> Option<GpuScene> {
+        if world.environment.is_some() {
+            return None;
+        }
+        let light = match world.light.as_ref()?.description()? {
+            LightDescription::Point {
+                position,
+                intensity,
+                cast_shadows,
+            } => GpuLight {
+                position: [position.x, position.y, position.z, 1.],
+                intensity: color_to_vec4(intensity),
+                casts_shadows: cast_shadows as u32,
+                padding: [0; 3],
+            },
+            _ => return None,
+        };
+        let mut scene = GpuScene {
+            spheres: vec![],
+            planes: vec![],
+            triangles: vec![],
+            light,
+        };
+        for object in &world.objects {
+            scene.add(object.as_ref(), &identity_4x4())?;
+        }
+        Some(scene)
+    }
+
+    // parent_transform takes object's parent space to world space
+    fn add(&mut self, object: &dyn Shape, parent_transform: &Matrix) -> Option<()> {
+        let transform = parent_transform * object.transformation();
+        let material = gpu_material(object.material());
+        let mut flags = 0;
+        if needs_cpu(object.material()) {
+            flags |= NEEDS_CPU;
+        }
+        if object.casts_shadow() {
+            flags |= CASTS_SHADOW;
+        }
+        if let Some(group) = object.downcast_ref::<GroupShape>() {
+            for child in group.get_children() {
+                self.add(child.as_ref(), &transform)?;
+            }
+        } else if let Some(triangle) = object.downcast_ref::<Triangle>() {
+            let point = |p: Tuple| {
+                let p = &transform * p;
+                [p.x, p.y, p.z, 1.]
+            };
+            self.triangles.push(GpuTriangle {
+                p1: point(triangle.p1),
+                p2: point(triangle.p2),
+                p3: point(triangle.p3),
+                material,
+                flags,
+                padding: [0; 3],
+            });
+        } else {
+            let gpu_object = GpuObject {
+                inverse_transform: columns(&transform.try_inverse()?),
+                material,
+                flags,
+                padding: [0; 3],
+            };
+            if object.downcast_ref::<Sphere>().is_some() {
+                self.spheres.push(gpu_object);
+            } else if object.downcast_ref::<Plane>().is_some() {
+                self.planes.push(gpu_object);
+            } else {
+                return None;
+            }
+        }
+        Some(())
+    }
+}
+
+// Whether hits on an object with material m have to be shaded on the CPU
+fn needs_cpu(m: &Material) -> bool {
+    m.pattern.is_some()
+        || m.reflective_pattern.is_some()
+        || m.specular_pattern.is_some()
+        || m.shininess_pattern.is_some()
+        || m.transparency_pattern.is_some()
+        || m.reflective > 0.
+        || m.transparency > 0.
+}
+
+fn gpu_material(m: &Material) -> GpuMaterial {
+    GpuMaterial {
+        color: color_to_vec4(m.color),
+        ambient: m.ambient,
+        diffuse: m.diffuse,
+        specular: m.specular,
+        shininess: m.shininess,
+    }
+}
+
+fn color_to_vec4(c: Color) -> [f32; 4] {
+    [c.r, c.g, c.b, 0.]
+}
+
+fn columns(m: &Matrix) -> [f32; 16] {
+    let mut columns = [0.; 16];
+    for (i, value) in columns.iter_mut().enumerate() {
+        *value = m[(i % 4, i / 4)];
+    }
+    columns
+}
+
+// Traces primary rays on the GPU, once the device code is written; see the top of this file
+#[derive(Debug, Default)]
+pub struct GpuBackend;
+
+impl PrimaryRayBackend for GpuBackend {
+    fn trace(&mut self, camera: &Camera, _world: &World) -> Vec<Option<Color>> {
+        vec![None; (camera.width() * camera.height()) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::constants::white;
+    use crate::light::rectangle_light::RectangleLight;
+    use crate::pattern::stripes::Stripes;
+    use crate::render_settings::RenderSettings;
+    use crate::shape::cube::Cube;
+    use crate::shape::shape::ShapeBuilder;
+    use crate::transformations::{scaling, translation, view_transform};
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+
+    #[test]
+    fn upload_default_world() {
+        let scene = GpuScene::from_world(&World::default()).unwrap();
+        assert_eq!(scene.spheres.len(), 2);
+        assert!(scene.planes.is_empty() && scene.triangles.is_empty());
+        let inner = &scene.spheres[1];
+        // inverse of scaling by 0.5
+        assert_eq!(inner.inverse_transform[0], 2.);
+        assert_eq!(inner.inverse_transform[15], 1.);
+        assert_eq!(inner.flags, CASTS_SHADOW);
+        assert_eq!(scene.spheres[0].material.color, [0.8, 1.0, 0.6, 0.]);
+        assert_eq!(scene.light.position, [-10., 10., -10., 1.]);
+        assert_eq!(scene.light.casts_shadows, 1);
+    }
+
+    #[test]
+    fn upload_triangles_in_world_space_and_flag_complex_materials() {
+        let mut group = GroupShape::new();
+        group.set_transformation(translation(0., 0., 5.));
+        group.add_child(Box::new(Triangle::new(
+            point!(0, 1, 0),
+            point!(-1, 0, 0),
+            point!(1, 0, 0),
+        )));
+        let striped = Material {
+            pattern: Some(Arc::new(Stripes::new(white(), Color::new(0., 0., 0.)))),
+            ..Material::default()
+        };
+        let mut world = World::default();
+        world.objects.push(Box::new(group));
+        world.objects.push(Box::new(
+            Plane::new().with_material(striped).with_shadow(false),
+        ));
+        let scene = GpuScene::from_world(&world).unwrap();
+        assert_eq!(scene.triangles[0].p1, [0., 1., 5., 1.]);
+        assert_eq!(scene.triangles[0].p3, [1., 0., 5., 1.]);
+        assert_eq!(scene.planes[0].flags, NEEDS_CPU);
+    }
+
+    #[test]
+    fn unsupported_worlds_are_not_uploaded() {
+        let mut with_cube = World::default();
+        with_cube.objects.push(Box::new(Cube::new()));
+        let with_area_light = World {
+            light: Some(Box::new(RectangleLight::new(
+                white(),
+                point!(-1, 2, 4),
+                vector!(2, 0, 0),
+                2,
+                vector!(0, 2, 0),
+                2,
+                None,
+            ))),
+            ..World::default()
+        };
+        let without_light = World {
+            light: None,
+            ..World::default()
+        };
+        let test_data = vec![
+            ("cube", with_cube),
+            ("area light", with_area_light),
+            ("no light", without_light),
+        ];
+        for (name, world) in test_data {
+            println!("Case {}", name);
+            assert_eq!(GpuScene::from_world(&world), None);
+        }
+    }
+
+    #[test]
+    fn gpu_backend_renders_like_the_cpu_for_now() {
+        let camera = Camera::new(
+            11,
+            11,
+            PI / 2.,
+            view_transform(point!(0, 0, -5), point!(0, 0, 0), vector!(0, 1, 0)),
+        );
+        let mut world = World::default();
+        let image =
+            camera.render_with_backend(&mut world, RenderSettings::default(), &mut GpuBackend);
+        let expected = camera.render(&mut world, RenderSettings::default());
+        assert_eq!(image.to_ppm(), expected.to_ppm());
+    }
+
+    #[test]
+    fn inverse_transform_is_stored_by_column() {
+        let world = World {
+            objects: vec![Box::new(
+                Sphere::new().with_transform(translation(1., 2., 3.) * scaling(2., 4., 8.)),
+            )],
+            ..World::default()
+        };
+        let scene = GpuScene::from_world(&world).unwrap();
+        let m = &scene.spheres[0].inverse_transform;
+        assert_eq!([m[0], m[5], m[10]], [0.5, 0.25, 0.125]);
+        // the translation is in the last column
+        assert_eq!(&m[12..], &[-0.5, -0.5, -0.375, 1.]);
+    }
+}
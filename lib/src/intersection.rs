@@ -1,12 +1,30 @@
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::shape::instance::Instance;
 use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::borrow::Cow;
 use std::cmp::Ordering::Equal;
+use std::ops::Deref;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+// Lists of intersections up to this length are merged into sorted lists by insertion, which is
+// faster than sorting afterwards; longer ones are appended and the result re-sorted.
+const MAX_INSERTED_INTERSECTIONS: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Intersection<'a> {
     pub distance: f32,
     pub object: &'a dyn Shape,
     pub u: f32,
     pub v: f32,
+    // the instance that object was hit through, if it belongs to an instance's prototype
+    pub instance: Option<&'a Instance>,
+    // the material of the face that was hit, if it replaces object's own (see
+    // GroupShape::assign_material)
+    pub material: Option<&'a Material>,
+    // maps world space to the space that object's transformation is relative to, i.e. the object
+    // space of the groups, CSGs and instances it was found through; None if that is world space
+    pub world_to_parent: Option<Cow<'a, Matrix>>,
 }
 
 impl Intersection<'_> {
@@ -16,6 +34,9 @@ impl Intersection<'_> {
             object,
             u: 0.,
             v: 0.,
+            instance: None,
+            material: None,
+            world_to_parent: None,
         }
     }
     pub fn new_with_uv(distance: f32, object: &dyn Shape, u: f32, v: f32) -> Intersection {
@@ -24,6 +45,9 @@ impl Intersection<'_> {
             object,
             u,
             v,
+            instance: None,
+            material: None,
+            world_to_parent: None,
         }
     }
     // returns the a reference to the intersection with the lowest non-negative distance (or None if all are negative)
@@ -33,6 +57,169 @@ impl Intersection<'_> {
             .filter(|i| i.distance >= 0.0)
             .min_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap_or(Equal))
     }
+
+    pub fn sort(intersections: &mut [Intersection]) {
+        intersections.sort_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap_or(Equal));
+    }
+
+    // Add new_intersections to sorted, which must already be sorted by distance, keeping it sorted
+    pub fn add_sorted<'a>(
+        sorted: &mut Vec<Intersection<'a>>,
+        new_intersections: Vec<Intersection<'a>>,
+    ) {
+        if new_intersections.len() <= MAX_INSERTED_INTERSECTIONS {
+            for i in new_intersections {
+                let index = sorted.partition_point(|other| other.distance <= i.distance);
+                sorted.insert(index, i);
+            }
+        } else {
+            sorted.extend(new_intersections);
+            Intersection::sort(sorted);
+        }
+    }
+}
+
+// Intersections kept sorted by distance, with the hit (the nearest one at a non-negative
+// distance) found as they are added. Dereferences to a slice, so it can be indexed, iterated and
+// passed to precompute_values like a Vec.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Intersections<'a> {
+    sorted: Vec<Intersection<'a>>,
+    // index of the hit in sorted
+    hit: Option<usize>,
+}
+
+impl<'a> Intersections<'a> {
+    pub fn new() -> Self {
+        Intersections::default()
+    }
+
+    pub fn from_unsorted(mut intersections: Vec<Intersection<'a>>) -> Self {
+        Intersection::sort(&mut intersections);
+        Intersections::from_sorted(intersections)
+    }
+
+    fn from_sorted(sorted: Vec<Intersection<'a>>) -> Self {
+        let mut intersections = Intersections { sorted, hit: None };
+        intersections.find_hit();
+        intersections
+    }
+
+    // Add the intersections returned by a shape, which need not be sorted
+    pub fn merge(&mut self, new_intersections: Vec<Intersection<'a>>) {
+        if new_intersections.is_empty() {
+            return;
+        }
+        Intersection::add_sorted(&mut self.sorted, new_intersections);
+        self.find_hit();
+    }
+
+    // Add all of other's intersections
+    pub fn merge_all(&mut self, other: Intersections<'a>) {
+        self.merge(other.sorted);
+    }
+
+    pub fn hit(&self) -> Option<&Intersection<'a>> {
+        self.hit.map(|index| &self.sorted[index])
+    }
+
+    // The intersections at non-negative distances less than distance, nearest first; e.g. the
+    // objects between a point and a light
+    pub fn hits_within(&self, distance: f32) -> impl Iterator<Item = &Intersection<'a>> {
+        let start = self.hit.unwrap_or(self.sorted.len());
+        self.sorted[start..]
+            .iter()
+            .take_while(move |i| i.distance < distance)
+    }
+
+    pub fn into_vec(self) -> Vec<Intersection<'a>> {
+        self.sorted
+    }
+
+    fn find_hit(&mut self) {
+        let index = self.sorted.partition_point(|i| i.distance < 0.);
+        self.hit = if index < self.sorted.len() {
+            Some(index)
+        } else {
+            None
+        };
+    }
+}
+
+impl<'a> Deref for Intersections<'a> {
+    type Target = [Intersection<'a>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.sorted
+    }
+}
+
+impl<'a> IntoIterator for Intersections<'a> {
+    type Item = Intersection<'a>;
+    type IntoIter = std::vec::IntoIter<Intersection<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sorted.into_iter()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b Intersections<'a> {
+    type Item = &'b Intersection<'a>;
+    type IntoIter = std::slice::Iter<'b, Intersection<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sorted.iter()
+    }
+}
+
+impl<'a> Intersection<'a> {
+    // Record that the intersection was found by a group, CSG or instance that intersected its
+    // children with a ray transformed by transformation_inverse, i.e. in that shape's object space
+    pub fn found_through(&mut self, transformation_inverse: &'a Matrix) {
+        self.world_to_parent = Some(match self.world_to_parent.take() {
+            None => Cow::Borrowed(transformation_inverse),
+            Some(inner) => Cow::Owned(inner.as_ref() * transformation_inverse),
+        });
+    }
+
+    // world_point in the space that the hit object's transformation is relative to
+    pub fn world_to_parent_point(&self, world_point: &Tuple) -> Tuple {
+        match &self.world_to_parent {
+            Some(m) => m.as_ref() * world_point,
+            None => *world_point,
+        }
+    }
+
+    // The world space normal of the hit object at world_point, which should be where it was hit
+    pub fn normal_at(&self, world_point: &Tuple) -> Tuple {
+        match &self.world_to_parent {
+            Some(m) => {
+                let parent_normal = self.object.normal_at_hit(&(m.as_ref() * world_point), self);
+                // see Shape::normal_to_world
+                let mut world_normal = &m.transpose() * parent_normal;
+                world_normal.w = 0.;
+                world_normal.norm()
+            }
+            None => self.object.normal_at_hit(world_point, self),
+        }
+    }
+
+    // the material of the hit object, unless the instance it was hit through or the face that was
+    // hit replaces it
+    pub fn material(&self) -> &'a Material {
+        match self
+            .instance
+            .and_then(|instance| instance.material_override())
+            .or(self.material)
+        {
+            Some(m) => m,
+            None => self.object.material(),
+        }
+    }
+
+    pub fn casts_shadow(&self) -> bool {
+        self.object.casts_shadow() && self.instance.is_none_or(|i| i.casts_shadow())
+    }
 }
 
 #[cfg(test)]
@@ -41,6 +228,75 @@ mod tests {
     use crate::shape::shape::Shape;
     use crate::shape::sphere::Sphere;
 
+    #[test]
+    fn add_sorted_keeps_intersections_sorted() {
+        let s = Sphere::new();
+        let distances = |xs: &Vec<Intersection>| xs.iter().map(|i| i.distance).collect::<Vec<_>>();
+        let test_data = vec![
+            ("into empty", vec![], vec![2., 1.], vec![1., 2.]),
+            (
+                "interleaved",
+                vec![1., 3.],
+                vec![4., 2., 0.],
+                vec![0., 1., 2., 3., 4.],
+            ),
+            (
+                "too many to insert",
+                vec![1., 5.],
+                vec![6., 4., 3., 2., 0.],
+                vec![0., 1., 2., 3., 4., 5., 6.],
+            ),
+            ("equal distances", vec![1.], vec![1., 1.], vec![1., 1., 1.]),
+        ];
+        for (name, sorted, new, expected) in test_data {
+            let mut sorted: Vec<Intersection> = sorted
+                .into_iter()
+                .map(|d| Intersection::new(d, &s))
+                .collect();
+            let new = new.into_iter().map(|d| Intersection::new(d, &s)).collect();
+            Intersection::add_sorted(&mut sorted, new);
+            assert_eq!(distances(&sorted), expected, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn intersections_stay_sorted_and_cache_hit() {
+        let s = Sphere::new();
+        let at = |distances: &[f32]| -> Vec<Intersection> {
+            distances
+                .iter()
+                .map(|&d| Intersection::new(d, &s))
+                .collect()
+        };
+        let mut xs = Intersections::from_unsorted(at(&[-1., -3.]));
+        assert_eq!(xs.hit(), None);
+        xs.merge(at(&[5., -2., 2.]));
+        assert_eq!(
+            xs.iter().map(|i| i.distance).collect::<Vec<_>>(),
+            vec![-3., -2., -1., 2., 5.]
+        );
+        assert_eq!(xs.hit().unwrap().distance, 2.);
+        xs.merge_all(Intersections::from_unsorted(at(&[0., 7.])));
+        assert_eq!(xs.hit().unwrap().distance, 0.);
+        assert_eq!(xs.len(), 7);
+    }
+
+    #[test]
+    fn hits_within_distance() {
+        let s = Sphere::new();
+        let xs = Intersections::from_unsorted(
+            [4., -1., 1., 3., 2.]
+                .iter()
+                .map(|&d| Intersection::new(d, &s))
+                .collect(),
+        );
+        let within = |d| xs.hits_within(d).map(|i| i.distance).collect::<Vec<_>>();
+        assert_eq!(within(3.), vec![1., 2.]);
+        assert_eq!(within(0.5), Vec::<f32>::new());
+        assert_eq!(within(10.), vec![1., 2., 3., 4.]);
+        assert_eq!(Intersections::new().hits_within(10.).count(), 0);
+    }
+
     #[test]
     fn basic_intersection_creation() {
         let s = Sphere::new();
@@ -55,7 +311,7 @@ mod tests {
         let i1 = Intersection::new(1.0, &s);
         let i2 = Intersection::new(2.0, &s);
 
-        let intersections = vec![i1, i2];
+        let intersections = vec![i1.clone(), i2];
         let i = Intersection::hit(&intersections).unwrap();
         assert_eq!(i, &i1);
     }
@@ -66,7 +322,7 @@ mod tests {
         let i1 = Intersection::new(-1.0, &s);
         let i2 = Intersection::new(1.0, &s);
         let i3 = Intersection::new(-0.5, &s);
-        let interactions = vec![i1, i2, i3];
+        let interactions = vec![i1, i2.clone(), i3];
         let i = Intersection::hit(&interactions).unwrap();
         assert_eq!(&i2, i);
     }
@@ -89,7 +345,7 @@ mod tests {
         let i2 = Intersection::new(7.0, &s);
         let i3 = Intersection::new(-3.0, &s);
         let i4 = Intersection::new(2.0, &s);
-        let interactions = vec![i1, i2, i3, i4];
+        let interactions = vec![i1, i2, i3, i4.clone()];
         let i = Intersection::hit(&interactions).unwrap();
         assert_eq!(&i4, i);
     }
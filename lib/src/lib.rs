@@ -16,7 +16,8 @@ extern crate impl_ops;
 // The macros will automatically be available in all following mods. However, for binaries in
 // the bin directory, the macros must be imported from the root crate, like
 // `use ray_tracer_challeng::point`, etc.
-// The client must also always import `Tuple`, etc. for the macro usage to compile.
+// The client must also always import `Tuple`, etc. for the macro usage to compile. The prelude
+// module re-exports the macros along with the types they need.
 #[macro_use]
 pub mod matrix;
 #[macro_use]
@@ -24,20 +25,41 @@ pub mod tuple;
 #[macro_use]
 pub mod color;
 
+pub mod animation;
 pub mod bounding_box;
 pub mod camera;
 pub mod canvas;
+pub mod checkpoint;
 pub mod constants;
+pub mod error;
+pub mod fingerprint;
+pub mod font;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod intersection;
 pub mod light;
 pub mod material;
+pub mod mesh;
+pub mod mesh_cache;
 pub mod obj_parser;
 mod object_id;
 pub mod pattern;
+pub mod post;
+pub mod prelude;
+pub mod preview;
+pub mod primary_rays;
+pub mod quaternion;
 pub mod ray;
+pub mod render_settings;
+pub mod sampler;
+pub mod scene_file;
+pub mod scenes;
 pub mod shape;
+pub mod stats;
 pub mod transformations;
 pub mod world;
 
+pub use error::{Error, Result};
+
 #[cfg(test)]
 mod test;
@@ -1,11 +1,24 @@
+use crate::bounding_box::BoundingBox;
 use crate::color::Color;
+use crate::scene_file::LightDescription;
 use crate::tuple::Tuple;
 use crate::world::World;
 
-pub trait Light {
+// Lights are Sync so that a world can be rendered from several threads at once
+pub trait Light: Send + Sync {
     //TODO: name is dumb
     fn intensity(&self) -> Color;
     fn position(&self) -> Tuple;
     // TODO: shouldn't be mut
     fn intensity_at(&self, point: Tuple, world: &World) -> f32;
+    // lights that don't cast shadows light every point fully, like the fill lights of a photo shoot
+    fn casts_shadows(&self) -> bool;
+    // world space box around every point that shadow rays may be cast towards
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::with_bounds(self.position(), self.position())
+    }
+    // for saving the light to a scene file; None if the light cannot be saved
+    fn description(&self) -> Option<LightDescription> {
+        None
+    }
 }
@@ -18,13 +18,70 @@ pub fn phong_lighting(
     surface_normal: Tuple,
     // this refers to how shadowed/unshadowed the light is at this point
     light_intensity: f32,
+) -> Color {
+    phong_lighting_filtered(
+        object,
+        material,
+        light,
+        point,
+        eye_vector,
+        surface_normal,
+        light_intensity,
+        0.,
+    )
+}
+
+// Like phong_lighting, but the material's pattern is filtered over footprint, the width of the
+// surface area seen by the ray (see Ray::width_at)
+#[allow(clippy::too_many_arguments)]
+pub fn phong_lighting_filtered(
+    object: &dyn Shape,
+    material: &Material,
+    light: &dyn Light,
+    point: Tuple,
+    eye_vector: Tuple,
+    surface_normal: Tuple,
+    light_intensity: f32,
+    footprint: f32,
+) -> Color {
+    phong_lighting_colored(
+        surface_color(object, material, point, footprint),
+        material,
+        light,
+        point,
+        eye_vector,
+        surface_normal,
+        light_intensity,
+    )
+}
+
+// The color of material at point before lighting, with its pattern filtered over footprint
+pub fn surface_color(
+    object: &dyn Shape,
+    material: &Material,
+    point: Tuple,
+    footprint: f32,
 ) -> Color {
     // TODO: would be more elegant for material to have the color_at_object method
-    // mix the surface color with the light's color
-    let material_color = match &material.pattern {
-        Some(p) => p.color_at_object(point, object),
+    // patterns which don't fully cover the surface let the material's color show through
+    match &material.pattern {
+        Some(p) => p.color_over_filtered(point, object, material.color, footprint),
         None => material.color,
-    };
+    }
+}
+
+// Like phong_lighting, but with the surface color already looked up (see surface_color), for
+// callers that evaluate patterns at a different point than the one being lit
+pub fn phong_lighting_colored(
+    material_color: Color,
+    material: &Material,
+    light: &dyn Light,
+    point: Tuple,
+    eye_vector: Tuple,
+    surface_normal: Tuple,
+    light_intensity: f32,
+) -> Color {
+    // mix the surface color with the light's color
     let effective_color = material_color * light.intensity();
 
     let ambient = effective_color * material.ambient;
@@ -73,6 +130,7 @@ mod tests {
     use crate::test::utils::any_shape;
     use crate::world::World;
     use std::f32::consts::FRAC_1_SQRT_2;
+    use std::sync::Arc;
 
     #[test]
     fn lighting_eye_between_light_and_surface() {
@@ -203,9 +261,10 @@ mod tests {
             reflective: 0.0,
             shininess: 200.0,
             color: color!(0.5, 0.5, 0.5),
-            pattern: Some(Box::new(pattern)),
+            pattern: Some(Arc::new(pattern)),
             transparency: 0.0,
             refractive_index: 1.0,
+            ..Material::default()
         };
         let eye_vector = vector!(0, 0, -1);
         let surface_normal = vector!(0, 0, -1);
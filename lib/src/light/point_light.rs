@@ -1,5 +1,6 @@
 use crate::color::Color;
 use crate::light::light::Light;
+use crate::scene_file::LightDescription;
 use crate::tuple::Tuple;
 use crate::world::World;
 // A point light: has no size and exists at single point.
@@ -7,6 +8,7 @@ use crate::world::World;
 pub struct PointLight {
     pub position: Tuple,
     pub intensity: Color,
+    pub cast_shadows: bool,
 }
 
 impl PointLight {
@@ -14,6 +16,7 @@ impl PointLight {
         PointLight {
             position,
             intensity,
+            cast_shadows: true,
         }
     }
 }
@@ -25,8 +28,18 @@ impl Light for PointLight {
     fn intensity(&self) -> Color {
         self.intensity
     }
+    fn description(&self) -> Option<LightDescription> {
+        Some(LightDescription::Point {
+            position: self.position,
+            intensity: self.intensity,
+            cast_shadows: self.cast_shadows,
+        })
+    }
+    fn casts_shadows(&self) -> bool {
+        self.cast_shadows
+    }
     fn intensity_at(&self, point: Tuple, world: &World) -> f32 {
-        if world.is_shadowed(self.position, point) {
+        if self.cast_shadows && world.is_shadowed(self.position, point) {
             0.
         } else {
             1.
@@ -47,4 +60,14 @@ mod tests {
         assert_eq!(light.position, position);
         assert_eq!(light.intensity, intensity);
     }
+
+    #[test]
+    fn shadowless_point_light_reaches_hidden_points() {
+        let w = World::default();
+        let mut light = PointLight::new(point!(-10, 10, -10), white());
+        let hidden = point!(10, -10, 10);
+        assert_eq!(light.intensity_at(hidden, &w), 0.);
+        light.cast_shadows = false;
+        assert_eq!(light.intensity_at(hidden, &w), 1.);
+    }
 }
@@ -1,10 +1,11 @@
+use crate::bounding_box::BoundingBox;
 use crate::color::Color;
 use crate::light::light::Light;
+use crate::sampler;
+use crate::scene_file::LightDescription;
 use crate::tuple::Tuple;
 use crate::world::World;
 use derivative::Derivative;
-use rand::distributions::OpenClosed01;
-use rand::{thread_rng, Rng};
 // A point light: has no size and exists at single point.
 #[derive(Derivative)]
 #[derivative(Debug, PartialEq)]
@@ -21,10 +22,11 @@ pub struct RectangleLight<'a> {
     pub v_vec: Tuple,
     pub v_steps: i32,
     pub cells: i32,
-    // for random light sampling
+    pub cast_shadows: bool,
+    // picks where in each cell to check for shadows; the sampler's numbers by default
     #[derivative(Debug = "ignore")]
     #[derivative(PartialEq = "ignore")]
-    jitter_fn: Box<dyn Fn() -> f32 + 'a>,
+    jitter_fn: Box<dyn Fn() -> f32 + Send + Sync + 'a>,
     // TODO: remove
     // the very center of the rectangle
     pub position: Tuple,
@@ -39,11 +41,11 @@ impl RectangleLight<'_> {
         v_vec: Tuple,
         v_steps: i32,
         // TODO: could probably be simplified with builder macros or something
-        jitter_fn_opt: Option<Box<dyn Fn() -> f32 + 'a>>,
+        jitter_fn_opt: Option<Box<dyn Fn() -> f32 + Send + Sync + 'a>>,
     ) -> RectangleLight {
         let jitter_fn = match jitter_fn_opt {
             Some(boxed_fn) => boxed_fn,
-            None => Box::new(|| thread_rng().sample(OpenClosed01)),
+            None => Box::new(sampler::next_1d),
         };
         RectangleLight {
             intensity,
@@ -53,6 +55,7 @@ impl RectangleLight<'_> {
             u_steps,
             v_steps,
             cells: u_steps * v_steps,
+            cast_shadows: true,
             jitter_fn,
             position: corner + (u_vec / 2.) + (v_vec / 2.),
         }
@@ -73,18 +76,54 @@ impl Light for RectangleLight<'_> {
     fn intensity(&self) -> Color {
         self.intensity
     }
+    // the jitter function is not saved; loaded lights always use the sampler
+    fn description(&self) -> Option<LightDescription> {
+        Some(LightDescription::Rectangle {
+            intensity: self.intensity,
+            corner: self.corner,
+            u_vec: self.u_vec * self.u_steps as f32,
+            u_steps: self.u_steps,
+            v_vec: self.v_vec * self.v_steps as f32,
+            v_steps: self.v_steps,
+            cast_shadows: self.cast_shadows,
+        })
+    }
+    fn casts_shadows(&self) -> bool {
+        self.cast_shadows
+    }
+    fn bounding_box(&self) -> BoundingBox {
+        let u = self.u_vec * self.u_steps as f32;
+        let v = self.v_vec * self.v_steps as f32;
+        let mut b = BoundingBox::empty();
+        for &p in &[
+            self.corner,
+            self.corner + u,
+            self.corner + v,
+            self.corner + u + v,
+        ] {
+            b.add_point(p);
+        }
+        b
+    }
     fn intensity_at(&self, point: Tuple, world: &World) -> f32 {
+        if !self.cast_shadows {
+            return 1.0;
+        }
+        // when the render settings limit the samples, use cells spread evenly over the light
+        let samples = match world.render_settings.soft_shadow_samples {
+            Some(limit) => (limit.max(1) as i32).min(self.cells),
+            None => self.cells,
+        };
         let mut total = 0.;
-        for v in 0..self.v_steps {
-            for u in 0..self.u_steps {
-                let light_position = self.point_on_light(u, v);
-                if !world.is_shadowed(light_position, point) {
-                    total += 1.0;
-                }
+        for sample in 0..samples {
+            let cell = sample * self.cells / samples;
+            let light_position = self.point_on_light(cell % self.u_steps, cell / self.u_steps);
+            if !world.is_shadowed(light_position, point) {
+                total += 1.0;
             }
         }
 
-        return total / self.cells as f32;
+        total / samples as f32
     }
 }
 
@@ -164,4 +203,41 @@ mod tests {
             assert_eq!(intensity, expected, "case: {:?}", name);
         }
     }
+
+    #[test]
+    fn intensity_at_with_limited_samples() {
+        let mut w = World::default();
+        w.render_settings.soft_shadow_samples = Some(2);
+        let light = RectangleLight::new(
+            white(),
+            point!(-0.5, -0.5, -5),
+            vector!(1, 0, 0),
+            2,
+            vector!(0, 1, 0),
+            2,
+            constant_jitter(),
+        );
+        // the sphere hides the cells with u = 1 from this point, and only the cells with u = 0
+        // are sampled
+        let p = point!(-1.5, 0, 2);
+        assert_eq!(light.intensity_at(p, &w), 1.);
+        w.render_settings.soft_shadow_samples = None;
+        assert_eq!(light.intensity_at(p, &w), 0.5);
+    }
+
+    #[test]
+    fn shadowless_light_reaches_hidden_points() {
+        let mut light = RectangleLight::new(
+            white(),
+            point!(-0.5, -0.5, -5),
+            vector!(1, 0, 0),
+            2,
+            vector!(0, 1, 0),
+            2,
+            constant_jitter(),
+        );
+        light.cast_shadows = false;
+        assert!(!light.casts_shadows());
+        assert_eq!(light.intensity_at(point!(0, 0, 2), &World::default()), 1.);
+    }
 }
@@ -1,21 +1,33 @@
 use crate::color::Color;
 use crate::constants::white;
+use crate::fingerprint::{hash_color, hash_f32};
 use crate::pattern::pattern::Pattern;
-use std::fmt::Debug;
-use std::ptr;
+use crate::pattern::scalar::ScalarPattern;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use derivative::Derivative;
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::Hasher;
+use std::sync::Arc;
 
-type BoxedPattern = Box<dyn Pattern>;
+// Patterns are shared between the materials they are cloned into (e.g. by GroupShape::set_material),
+// so that large patterns such as image textures are only stored once
+type SharedPattern = Arc<dyn Pattern>;
 
 // Just check that the objects are the same
 // TODO: delete after fixed in Rust: https://github.com/rust-lang/rust/issues/39128
-impl PartialEq for BoxedPattern {
-    fn eq(&self, other: &Self) -> bool {
-        ptr::eq(self as *const _, other as *const _)
+fn same_pattern(a: &Option<SharedPattern>, b: &Option<SharedPattern>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
     }
 }
 
 // Represents the reflective properties of a surface
-#[derive(PartialEq, Debug, Clone, TypedBuilder)]
+#[derive(Derivative, Debug, Clone, TypedBuilder)]
+#[derivative(PartialEq)]
 pub struct Material {
     #[builder(default = white())]
     pub color: Color,
@@ -46,8 +58,163 @@ pub struct Material {
     #[builder(default = 1.)]
     pub refractive_index: f32,
 
+    // how much the refractive index differs between colors, as the reciprocal of the Abbe number;
+    // refracted rays are traced separately for red, green and blue when this is above 0, which
+    // splits white light into rainbow fringes. refractive_index is then the index for green.
+    #[builder(default)]
+    pub dispersion: f32,
+
+    #[builder(default, setter(strip_option))]
+    #[derivative(PartialEq(compare_with = "same_pattern"))]
+    pub pattern: Option<SharedPattern>,
+
+    // Override the corresponding values per point on the surface
     #[builder(default, setter(strip_option))]
-    pub pattern: Option<BoxedPattern>,
+    pub reflective_pattern: Option<ScalarPattern>,
+    #[builder(default, setter(strip_option))]
+    pub specular_pattern: Option<ScalarPattern>,
+    #[builder(default, setter(strip_option))]
+    pub shininess_pattern: Option<ScalarPattern>,
+    #[builder(default, setter(strip_option))]
+    pub transparency_pattern: Option<ScalarPattern>,
+}
+
+// A material value outside of the range that makes sense for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidMaterial {
+    pub field: &'static str,
+    pub value: f32,
+    // the values allowed, e.g. "between 0 and 1"
+    pub expected: &'static str,
+}
+
+impl Display for InvalidMaterial {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Material {} must be {}, but was {}",
+            self.field, self.expected, self.value
+        )
+    }
+}
+
+impl std::error::Error for InvalidMaterial {}
+
+impl Material {
+    // This material, if all of its values are in range. Use after the builder to catch typos such
+    // as a diffuse of 9 instead of .9: Material::builder().diffuse(0.9).build().validated()?
+    pub fn validated(self) -> Result<Material, InvalidMaterial> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    // The first value of the material that is out of range, if any. The fractions of light that
+    // a surface reflects or lets through can't be negative or more than all of it; colors may be
+    // brighter than white, but not negative.
+    pub fn validate(&self) -> Result<(), InvalidMaterial> {
+        // field name, value, whether it's valid, description of valid values
+        type Check = (&'static str, f32, fn(f32) -> bool, &'static str);
+        let fraction: fn(f32) -> bool = |v| (0. ..=1.).contains(&v);
+        let positive: fn(f32) -> bool = |v| v > 0.;
+        let not_negative: fn(f32) -> bool = |v| v >= 0.;
+        let checks: [Check; 11] = [
+            ("color red", self.color.r, not_negative, "at least 0"),
+            ("color green", self.color.g, not_negative, "at least 0"),
+            ("color blue", self.color.b, not_negative, "at least 0"),
+            ("ambient", self.ambient, fraction, "between 0 and 1"),
+            ("diffuse", self.diffuse, fraction, "between 0 and 1"),
+            ("specular", self.specular, fraction, "between 0 and 1"),
+            ("shininess", self.shininess, positive, "greater than 0"),
+            ("reflective", self.reflective, fraction, "between 0 and 1"),
+            (
+                "transparency",
+                self.transparency,
+                fraction,
+                "between 0 and 1",
+            ),
+            (
+                "refractive_index",
+                self.refractive_index,
+                positive,
+                "greater than 0",
+            ),
+            ("dispersion", self.dispersion, not_negative, "at least 0"),
+        ];
+        match checks.iter().find(|(_, value, valid, _)| !valid(*value)) {
+            Some(&(field, value, _, expected)) => Err(InvalidMaterial {
+                field,
+                value,
+                expected,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn has_scalar_patterns(&self) -> bool {
+        self.reflective_pattern.is_some()
+            || self.specular_pattern.is_some()
+            || self.shininess_pattern.is_some()
+            || self.transparency_pattern.is_some()
+    }
+
+    // This material with the values of the scalar patterns at world_point on object filled in;
+    // only copied if there are any scalar patterns
+    pub fn at_point(&self, world_point: Tuple, object: &dyn Shape) -> Cow<'_, Material> {
+        if !self.has_scalar_patterns() {
+            return Cow::Borrowed(self);
+        }
+        let mut m = self.clone();
+        for (pattern, value) in [
+            (&self.reflective_pattern, &mut m.reflective),
+            (&self.specular_pattern, &mut m.specular),
+            (&self.shininess_pattern, &mut m.shininess),
+            (&self.transparency_pattern, &mut m.transparency),
+        ] {
+            if let Some(p) = pattern {
+                *value = p.value_at_object(world_point, object);
+            }
+        }
+        Cow::Owned(m)
+    }
+
+    // Feed everything that affects the material's appearance into hasher, including the structure
+    // of its patterns (see Pattern::hash_structure)
+    pub fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.color);
+        for &value in &[
+            self.ambient,
+            self.diffuse,
+            self.specular,
+            self.shininess,
+            self.reflective,
+            self.transparency,
+            self.refractive_index,
+            self.dispersion,
+        ] {
+            hash_f32(hasher, value);
+        }
+        match &self.pattern {
+            Some(pattern) => {
+                hasher.write_u8(1);
+                pattern.hash_structure(hasher);
+            }
+            None => hasher.write_u8(0),
+        }
+        for pattern in &[
+            &self.reflective_pattern,
+            &self.specular_pattern,
+            &self.shininess_pattern,
+            &self.transparency_pattern,
+        ] {
+            match pattern {
+                Some(p) => {
+                    hasher.write_u8(1);
+                    p.hash_structure(hasher);
+                }
+                None => hasher.write_u8(0),
+            }
+        }
+    }
 }
 
 impl Default for Material {
@@ -55,3 +222,73 @@ impl Default for Material {
         Self::builder().build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_materials_pass_validation() {
+        let glass = Material::builder()
+            .color(color!(0, 0, 0))
+            .ambient(0.)
+            .diffuse(0.)
+            .specular(1.)
+            .shininess(300.)
+            .reflective(1.)
+            .transparency(1.)
+            .refractive_index(1.5)
+            .dispersion(0.02)
+            .build();
+        assert_eq!(glass.clone().validated(), Ok(glass));
+        assert_eq!(Material::default().validate(), Ok(()));
+        // brighter than white is fine, e.g. for emissive-looking surfaces
+        assert!(Material::builder()
+            .color(color!(2, 2, 2))
+            .build()
+            .validated()
+            .is_ok());
+    }
+
+    #[test]
+    fn out_of_range_values_are_reported() {
+        let test_data = vec![
+            ("diffuse", Material::builder().diffuse(9.).build(), 9.),
+            ("ambient", Material::builder().ambient(-0.1).build(), -0.1),
+            (
+                "transparency",
+                Material::builder().transparency(1.5).build(),
+                1.5,
+            ),
+            ("shininess", Material::builder().shininess(0.).build(), 0.),
+            (
+                "refractive_index",
+                Material::builder().refractive_index(-1.).build(),
+                -1.,
+            ),
+            (
+                "color green",
+                Material::builder().color(color!(1, -1, 1)).build(),
+                -1.,
+            ),
+        ];
+        for (field, material, value) in test_data {
+            let err = material.validated().unwrap_err();
+            assert_eq!((err.field, err.value), (field, value), "Case {}", field);
+        }
+        assert!(Material::builder()
+            .reflective(f32::NAN)
+            .build()
+            .validate()
+            .is_err());
+        assert_eq!(
+            Material::builder()
+                .specular(2.)
+                .build()
+                .validate()
+                .unwrap_err()
+                .to_string(),
+            "Material specular must be between 0 and 1, but was 2"
+        );
+    }
+}
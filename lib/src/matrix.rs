@@ -1,29 +1,110 @@
 use crate::tuple::*;
 use approx::AbsDiffEq;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt::Display;
 use std::ops;
-use std::ops::Mul;
+use std::ops::{Index, IndexMut, Mul};
 
-// Only supports square matrices
-#[derive(Clone, Debug, PartialEq)]
+// Only supports square matrices. Elements are stored in a single row-major array; index with
+// m[(row, column)]. Serialized as a list of rows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Vec<f32>>", into = "Vec<Vec<f32>>")]
 pub struct Matrix {
-    // TODO: maybe this should be private with accessor
-    pub data: Vec<Vec<f32>>,
+    size: usize,
+    data: Vec<f32>,
 }
 
 impl Matrix {
     pub fn new(size: usize) -> Matrix {
         Matrix {
-            data: vec![vec![0.0; size]; size],
+            size,
+            data: vec![0.0; size * size],
+        }
+    }
+
+    // Panics if the rows do not form a square matrix
+    pub fn from_rows(rows: Vec<Vec<f32>>) -> Matrix {
+        match Matrix::try_from(rows) {
+            Ok(m) => m,
+            Err(e) => panic!("{}", e),
         }
     }
+
+    // position of (row, column) in data
+    #[inline]
+    fn offset(&self, row: usize, column: usize) -> usize {
+        debug_assert!(
+            row < self.size && column < self.size,
+            "({}, {}) is out of bounds for a {}x{} matrix",
+            row,
+            column,
+            self.size,
+            self.size
+        );
+        row * self.size + column
+    }
+
+    pub fn row(&self, row: usize) -> &[f32] {
+        &self.data[row * self.size..(row + 1) * self.size]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[f32]> {
+        self.data.chunks(self.size)
+    }
+
+    // all elements in row-major order
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = f32;
+    #[inline]
+    fn index(&self, (row, column): (usize, usize)) -> &f32 {
+        &self.data[self.offset(row, column)]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    #[inline]
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut f32 {
+        let offset = self.offset(row, column);
+        &mut self.data[offset]
+    }
+}
+
+impl TryFrom<Vec<Vec<f32>>> for Matrix {
+    type Error = String;
+    fn try_from(rows: Vec<Vec<f32>>) -> Result<Matrix, String> {
+        let size = rows.len();
+        let mut data = Vec::with_capacity(size * size);
+        for row in rows {
+            if row.len() != size {
+                return Err(format!(
+                    "Wrong row length; expected {}, found {}",
+                    size,
+                    row.len()
+                ));
+            }
+            data.extend(row);
+        }
+        Ok(Matrix { size, data })
+    }
+}
+
+impl From<Matrix> for Vec<Vec<f32>> {
+    fn from(m: Matrix) -> Self {
+        m.rows().map(|row| row.to_vec()).collect()
+    }
 }
 
 impl Display for Matrix {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         write!(f, "[")?;
-        for row in 0..self.size() {
-            write!(f, "\n    {:?}", self.data[row])?;
+        for row in self.rows() {
+            write!(f, "\n    {:?}", row)?;
         }
         write!(f, "\n]")
     }
@@ -33,16 +114,7 @@ impl Display for Matrix {
 #[macro_export]
 macro_rules! matrix {
     ($([$($x:expr),* $(,)*]),+ $(,)*) => {{
-        let data = vec![$(vec![$($x as f32,)*],)*];
-        if cfg!(debug_assertions) {
-            let expected_size = data.len();
-            for row in &data {
-                assert_eq!(expected_size, row.len(), "Wrong row length; expected {}, found {}", expected_size, row.len());
-            }
-        }
-        Matrix {
-            data
-        }
+        Matrix::from_rows(vec![$(vec![$($x as f32,)*],)*])
     }};
 }
 
@@ -60,13 +132,10 @@ pub fn identity_4x4() -> Matrix {
 impl Mul<f32> for &Matrix {
     type Output = Matrix;
     fn mul(self, other: f32) -> Matrix {
-        let mut m = Matrix::new(self.size());
-        for row in 0..self.size() {
-            for col in 0..self.size() {
-                m.data[row][col] = self.data[row][col] * other;
-            }
+        Matrix {
+            size: self.size,
+            data: self.data.iter().map(|value| value * other).collect(),
         }
-        m
     }
 }
 
@@ -76,30 +145,28 @@ impl_op_ex!(*|a: &Matrix, b: &Tuple| -> Tuple {
         4,
         "Only 4x4 matrices can be multiplied by tuples!"
     );
-    let x = a.data[0][0] * b.x + a.data[0][1] * b.y + a.data[0][2] * b.z + a.data[0][3] * b.w;
-    let y = a.data[1][0] * b.x + a.data[1][1] * b.y + a.data[1][2] * b.z + a.data[1][3] * b.w;
-    let z = a.data[2][0] * b.x + a.data[2][1] * b.y + a.data[2][2] * b.z + a.data[2][3] * b.w;
-    let w = a.data[3][0] * b.x + a.data[3][1] * b.y + a.data[3][2] * b.z + a.data[3][3] * b.w;
+    let m = &a.data;
+    let x = m[0] * b.x + m[1] * b.y + m[2] * b.z + m[3] * b.w;
+    let y = m[4] * b.x + m[5] * b.y + m[6] * b.z + m[7] * b.w;
+    let z = m[8] * b.x + m[9] * b.y + m[10] * b.z + m[11] * b.w;
+    let w = m[12] * b.x + m[13] * b.y + m[14] * b.z + m[15] * b.w;
     Tuple { x, y, z, w }
 });
 
 impl_op_ex!(*|a: &Matrix, b: &Matrix| -> Matrix {
-    debug_assert_eq!(
-        a.data.len(),
-        4,
-        "Only 4x4 matrices can be multiplied by tuples!"
-    );
-    let size = a.size();
-    let mut new_matrix = Matrix::new(size);
-    for r in 0..size {
-        for c in 0..size {
-            new_matrix.data[r][c] = a.data[r][0] * b.data[0][c]
-                + a.data[r][1] * b.data[1][c]
-                + a.data[r][2] * b.data[2][c]
-                + a.data[r][3] * b.data[3][c]
+    debug_assert_eq!(a.size(), 4, "Only 4x4 matrices can be multiplied!");
+    debug_assert_eq!(b.size(), 4, "Only 4x4 matrices can be multiplied!");
+    let (a, b) = (&a.data, &b.data);
+    let mut data = vec![0.0; 16];
+    for r in 0..4 {
+        for c in 0..4 {
+            data[r * 4 + c] = a[r * 4] * b[c]
+                + a[r * 4 + 1] * b[4 + c]
+                + a[r * 4 + 2] * b[8 + c]
+                + a[r * 4 + 3] * b[12 + c]
         }
     }
-    new_matrix
+    Matrix { size: 4, data }
 });
 
 // required for approximate comparisons due to use of floating point numbers
@@ -111,15 +178,17 @@ impl AbsDiffEq for Matrix {
     }
 
     fn abs_diff_eq(&self, other: &Self, epsilon: Self::Epsilon) -> bool {
-        for row in 0..self.size() {
-            for col in 0..self.size() {
-                if !f32::abs_diff_eq(&self.data[row][col], &other.data[row][col], epsilon) {
-                    println!(
-                        "{} not close enough to {}",
-                        self.data[row][col], other.data[row][col]
-                    );
-                    return false;
-                }
+        if self.size != other.size {
+            println!(
+                "{}x{} matrix compared with {}x{}",
+                self.size, self.size, other.size, other.size
+            );
+            return false;
+        }
+        for (a, b) in self.data.iter().zip(other.data.iter()) {
+            if !f32::abs_diff_eq(a, b, epsilon) {
+                println!("{} not close enough to {}", a, b);
+                return false;
             }
         }
         true
@@ -128,15 +197,14 @@ impl AbsDiffEq for Matrix {
 
 impl Matrix {
     pub fn size(&self) -> usize {
-        self.data.len()
+        self.size
     }
     // TODO: would it be better to mutate instead of copying?
     pub fn transpose(&self) -> Matrix {
-        // debug_assert!(self.rows == 4 && self.columns == 4, "Only 4x4 matrices can be tr");
-        let mut m = Matrix::new(self.size());
-        for row in 0..self.size() {
-            for col in 0..self.size() {
-                m.data[col][row] = self.data[row][col];
+        let mut m = Matrix::new(self.size);
+        for row in 0..self.size {
+            for col in 0..self.size {
+                m.data[col * self.size + row] = self.data[row * self.size + col];
             }
         }
         m
@@ -144,16 +212,16 @@ impl Matrix {
 
     pub fn determinant(&self) -> f32 {
         // base case: 2x2 matrix
-        if self.size() == 2 {
-            self.data[0][0] * self.data[1][1] - self.data[0][1] * self.data[1][0]
+        if self.size == 2 {
+            self.data[0] * self.data[3] - self.data[1] * self.data[2]
         } else {
             // recurse: combine determinants of submatrices
             let mut det = 0.0;
             // pivot on row 0 because it's simple
             // a human would probably choose the row with the most 0's
-            for col in 0..self.size() {
+            for col in 0..self.size {
                 let cofactor = self.cofactor(0, col);
-                det += cofactor * self.data[0][col];
+                det += cofactor * self.data[col];
             }
             det
         }
@@ -161,23 +229,21 @@ impl Matrix {
 
     // for an nxn matrix, return an n-1 x n-1 matrix with remove_row row and remove_col col removed
     pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix {
-        let mut m = Matrix::new(self.size() - 1);
-        let mut new_row = 0;
-        for old_row in 0..self.size() {
-            if old_row == remove_row {
+        let mut data = Vec::with_capacity((self.size - 1) * (self.size - 1));
+        for (row, values) in self.rows().enumerate() {
+            if row == remove_row {
                 continue;
             }
-            let mut new_col = 0;
-            for old_col in 0..self.size() {
-                if old_col == remove_col {
-                    continue;
+            for (col, &value) in values.iter().enumerate() {
+                if col != remove_col {
+                    data.push(value);
                 }
-                m.data[new_row][new_col] = self.data[old_row][old_col];
-                new_col += 1;
             }
-            new_row += 1;
         }
-        m
+        Matrix {
+            size: self.size - 1,
+            data,
+        }
     }
 
     pub fn cofactor(&self, row: usize, column: usize) -> f32 {
@@ -201,20 +267,78 @@ impl Matrix {
     pub fn inverse(&self) -> Matrix {
         debug_assert!(self.invertible());
         let determinant = self.determinant();
-        let mut matrix_inverse = Matrix::new(self.size());
-        for row in 0..self.size() {
-            for column in 0..self.size() {
+        self.inverse_with_determinant(determinant)
+    }
+
+    // Returns None for singular matrices (such as scaling(0, 0, 0)), which have no inverse, and
+    // for matrices containing NaN or infinite values
+    pub fn try_inverse(&self) -> Option<Matrix> {
+        let determinant = self.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            None
+        } else {
+            Some(self.inverse_with_determinant(determinant))
+        }
+    }
+
+    fn inverse_with_determinant(&self, determinant: f32) -> Matrix {
+        let mut matrix_inverse = Matrix::new(self.size);
+        for row in 0..self.size {
+            for column in 0..self.size {
                 let c = self.cofactor(row, column);
-                matrix_inverse.data[column][row] = c / determinant;
+                matrix_inverse[(column, row)] = c / determinant;
             }
         }
         matrix_inverse
     }
 }
 
+// Returned when a transformation is required to be invertible but isn't
+#[derive(Clone, Debug, PartialEq)]
+pub struct SingularMatrixError {
+    pub matrix: Matrix,
+}
+
+impl Display for SingularMatrixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "Matrix is not invertible; degenerate transformations such as scaling(0, 0, 0) cannot be used: {}",
+            self.matrix
+        )
+    }
+}
+
+impl std::error::Error for SingularMatrixError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test::strategies;
+    use crate::transformations::{scaling, translation};
+    use proptest::prelude::*;
+
+    #[test]
+    fn try_inverse_of_invertible_matrix() {
+        let m = translation(1., 2., 3.) * scaling(2., 4., 8.);
+        assert_abs_diff_eq!(m.try_inverse().unwrap(), m.inverse());
+    }
+
+    #[test]
+    fn try_inverse_of_singular_matrix_is_none() {
+        let test_data = vec![
+            ("zero scaling", scaling(0., 0., 0.)),
+            ("flattening", scaling(1., 0., 1.)),
+            ("NaN", scaling(f32::NAN, 1., 1.)),
+            (
+                "infinity",
+                translation(f32::INFINITY, 0., 0.) * scaling(f32::INFINITY, 1., 1.),
+            ),
+        ];
+        for (name, m) in test_data {
+            assert_eq!(m.try_inverse(), None, "Case {}", name);
+        }
+    }
     #[test]
     fn test_matrix_multiplied_by_scalar() {
         let matrix_a = matrix!([1, 2], [3, 4]);
@@ -260,6 +384,32 @@ mod tests {
         assert_eq!(m.transpose(), expected_m_transpose);
     }
 
+    #[test]
+    fn indexing_is_row_major() {
+        let mut m = matrix!([1, 2, 3], [4, 5, 6], [7, 8, 9]);
+        assert_eq!(m[(0, 2)], 3.);
+        assert_eq!(m[(2, 0)], 7.);
+        assert_eq!(m.row(1), &[4., 5., 6.]);
+        assert_eq!(m.as_slice(), &[1., 2., 3., 4., 5., 6., 7., 8., 9.]);
+        m[(1, 2)] = -6.;
+        assert_eq!(m, matrix!([1, 2, 3], [4, 5, -6], [7, 8, 9]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong row length; expected 2, found 3")]
+    fn rows_must_form_square_matrix() {
+        Matrix::from_rows(vec![vec![1., 2., 3.], vec![4., 5., 6.]]);
+    }
+
+    #[test]
+    fn serialized_as_list_of_rows() {
+        let m = matrix!([1, 2], [3, 4]);
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(json, "[[1.0,2.0],[3.0,4.0]]");
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), m);
+        assert!(serde_json::from_str::<Matrix>("[[1.0,2.0],[3.0]]").is_err());
+    }
+
     #[test]
     fn test_transposing_identity_is_identity() {
         let matrix_i = identity_4x4();
@@ -396,4 +546,29 @@ mod tests {
         // higher epsilon because of multiplications
         assert!(matrix_c_times_b_inverse.abs_diff_eq(&matrix_a, 10.0 * f32::default_epsilon()));
     }
+
+    proptest! {
+        #[test]
+        fn transform_times_inverse_is_identity(m in strategies::transform()) {
+            assert_abs_diff_eq!(&m * &m.inverse(), identity_4x4(), epsilon = 1e-3);
+            assert_abs_diff_eq!(&m.inverse() * &m, identity_4x4(), epsilon = 1e-3);
+        }
+
+        #[test]
+        fn transpose_of_product_is_reversed_product_of_transposes(
+            a in strategies::matrix(),
+            b in strategies::matrix(),
+        ) {
+            assert_abs_diff_eq!((&a * &b).transpose(), &b.transpose() * &a.transpose());
+        }
+
+        #[test]
+        fn inverse_of_transpose_is_transpose_of_inverse(m in strategies::transform()) {
+            assert_abs_diff_eq!(
+                m.transpose().inverse(),
+                m.inverse().transpose(),
+                epsilon = 1e-3
+            );
+        }
+    }
 }
@@ -0,0 +1,838 @@
+use crate::matrix::Matrix;
+use crate::shape::smooth_triangle::SmoothTriangle;
+use crate::shape::triangle::Triangle;
+use crate::tuple::Tuple;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+// how much more moving a vertex off of an open border costs than moving it off of its faces
+const BORDER_WEIGHT: f64 = 10.;
+
+// Triangles that share their corners: faces are indices into vertices, so that neighboring faces
+// can be found and each vertex is stored only once
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TriangleMesh {
+    pub vertices: Vec<Tuple>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    pub fn new(vertices: Vec<Tuple>, faces: Vec<[usize; 3]>) -> Self {
+        TriangleMesh { vertices, faces }
+    }
+
+    // Moves the vertices by the transformation, so that triangles made from the mesh can be left
+    // untransformed (see GroupShape::bake_transforms)
+    pub fn bake_transform(&mut self, transform: &Matrix) {
+        for vertex in &mut self.vertices {
+            *vertex = transform * *vertex;
+        }
+    }
+
+    // A mesh of separate triangles, like those in STL files or made one by one; each triangle gets
+    // vertices of its own until the mesh is welded
+    pub fn from_triangles(triangles: &[[Tuple; 3]]) -> Self {
+        TriangleMesh {
+            vertices: triangles.iter().flatten().copied().collect(),
+            faces: (0..triangles.len())
+                .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+                .collect(),
+        }
+    }
+
+    // Merges vertices within tolerance of each other (see weld_vertices), drops the faces that
+    // collapse as a result and any vertices that no face uses
+    pub fn weld(&self, tolerance: f32) -> TriangleMesh {
+        let merged_into = weld_vertices(&self.vertices, tolerance);
+        let faces = self.faces.iter().map(|face| face.map(|v| merged_into[v]));
+        compacted(&self.vertices, faces)
+    }
+
+    // Collapses edges, cheapest first, until at most target_faces faces are left, for quick
+    // previews of detailed models. Moving a vertex costs its squared distance to the planes of its
+    // original faces (Garland and Heckbert's quadric error metric), so flat areas are simplified
+    // before detailed ones and open borders stay in place where possible. Collapses that would
+    // turn a face over are skipped, so fewer faces may be left than asked for. Only edges shared
+    // through vertex indices are collapsed, so meshes should be welded first.
+    pub fn simplify(&self, target_faces: usize) -> TriangleMesh {
+        if self.faces.len() <= target_faces {
+            return self.clone();
+        }
+        let mut positions = self.vertices.clone();
+        let mut faces = self.faces.clone();
+        let mut alive = vec![true; faces.len()];
+        let mut alive_count = faces.len();
+        let mut faces_at_vertex = vec![vec![]; positions.len()];
+        let mut faces_at_edge: HashMap<(usize, usize), usize> = HashMap::new();
+        for (index, face) in faces.iter().enumerate() {
+            for &vertex in face {
+                faces_at_vertex[vertex].push(index);
+            }
+            for edge in edges(face) {
+                *faces_at_edge.entry(edge).or_default() += 1;
+            }
+        }
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        for face in &faces {
+            let normal = face_normal(&positions, face);
+            let area = normal.magnitude() / 2.;
+            if area == 0. {
+                continue;
+            }
+            let plane = Quadric::of_plane(normal.norm(), positions[face[0]], area as f64);
+            for &vertex in face {
+                quadrics[vertex].add(&plane);
+            }
+            // a plane standing on each border edge keeps the border from shrinking
+            for (a, b) in edges(face) {
+                let along = positions[b] - positions[a];
+                if faces_at_edge[&(a, b)] == 1 {
+                    let weight = BORDER_WEIGHT * along.dot(along) as f64;
+                    let border =
+                        Quadric::of_plane(along.cross(normal).norm(), positions[a], weight);
+                    quadrics[a].add(&border);
+                    quadrics[b].add(&border);
+                }
+            }
+        }
+
+        // collapses are invalidated by changing the version of either vertex
+        let mut versions = vec![0; positions.len()];
+        let mut removed = vec![false; positions.len()];
+        let mut collapses: BinaryHeap<Collapse> = faces_at_edge
+            .keys()
+            .map(|&(a, b)| Collapse::new(a, b, &positions, &quadrics, &versions))
+            .collect();
+        while alive_count > target_faces {
+            let collapse = match collapses.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            let (keep, remove) = (collapse.keep, collapse.remove);
+            if removed[keep]
+                || removed[remove]
+                || collapse.versions != (versions[keep], versions[remove])
+            {
+                continue;
+            }
+            let would_flip = [keep, remove].iter().any(|&vertex| {
+                faces_at_vertex[vertex].iter().any(|&f| {
+                    if !alive[f] || (faces[f].contains(&keep) && faces[f].contains(&remove)) {
+                        return false;
+                    }
+                    let before = face_normal(&positions, &faces[f]);
+                    let [a, b, c] = faces[f].map(|v| {
+                        if v == vertex {
+                            collapse.position
+                        } else {
+                            positions[v]
+                        }
+                    });
+                    let after = (b - a).cross(c - a);
+                    // turned over, or squashed flat
+                    before.magnitude() > 0. && after.dot(before) <= 0.
+                })
+            });
+            if would_flip {
+                continue;
+            }
+
+            positions[keep] = collapse.position;
+            let removed_quadric = quadrics[remove];
+            quadrics[keep].add(&removed_quadric);
+            removed[remove] = true;
+            versions[keep] += 1;
+            for f in std::mem::take(&mut faces_at_vertex[remove]) {
+                if !alive[f] {
+                    continue;
+                }
+                if faces[f].contains(&keep) {
+                    alive[f] = false;
+                    alive_count -= 1;
+                } else {
+                    faces[f] = faces[f].map(|v| if v == remove { keep } else { v });
+                    faces_at_vertex[keep].push(f);
+                }
+            }
+            faces_at_vertex[keep].retain(|&f| alive[f]);
+
+            let mut neighbors: Vec<usize> = faces_at_vertex[keep]
+                .iter()
+                .flat_map(|&f| faces[f])
+                .filter(|&v| v != keep)
+                .collect();
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            for neighbor in neighbors {
+                collapses.push(Collapse::new(
+                    keep, neighbor, &positions, &quadrics, &versions,
+                ));
+            }
+        }
+
+        let faces = faces
+            .into_iter()
+            .zip(alive)
+            .filter(|(_, alive)| *alive)
+            .map(|(face, _)| face);
+        compacted(&positions, faces)
+    }
+
+    // Smooths the mesh with the given number of rounds of Loop subdivision, each of which splits
+    // every face into four and moves the vertices towards a weighted average of their neighbors.
+    // The surface shrinks towards the original's smooth limit surface; open borders are smoothed
+    // along themselves and kept in place otherwise. Faces grow four-fold each round, so one or two
+    // rounds are usually plenty; smooth normals can be computed afterwards.
+    pub fn subdivide(&self, iterations: u32) -> TriangleMesh {
+        self.subdivide_with_values(iterations, &[]).0
+    }
+
+    // Like subdivide, also interpolating a value for each vertex, such as its texture coordinates,
+    // in the same way as the positions. values may be empty if there are none.
+    pub fn subdivide_with_values(
+        &self,
+        iterations: u32,
+        values: &[Tuple],
+    ) -> (TriangleMesh, Vec<Tuple>) {
+        let mut mesh = self.clone();
+        let mut values = values.to_vec();
+        for _ in 0..iterations {
+            let (stencils, faces) = mesh.loop_subdivision();
+            if !values.is_empty() {
+                values = blend(&stencils, &values);
+            }
+            mesh = TriangleMesh {
+                vertices: blend(&stencils, &mesh.vertices),
+                faces,
+            };
+        }
+        (mesh, values)
+    }
+
+    // The vertices of one round of subdivision, as stencils of the old ones, and its faces
+    fn loop_subdivision(&self) -> (Vec<Stencil>, Vec<[usize; 3]>) {
+        // the corners opposite each edge, one per face sharing the edge
+        let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for face in &self.faces {
+            for ((a, b), c) in edges(face).iter().zip([face[2], face[0], face[1]]) {
+                opposite.entry((*a, *b)).or_default().push(c);
+            }
+        }
+        let mut neighbors = vec![vec![]; self.vertices.len()];
+        let mut border_neighbors = vec![vec![]; self.vertices.len()];
+        for (&(a, b), corners) in &opposite {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            if corners.len() != 2 {
+                border_neighbors[a].push(b);
+                border_neighbors[b].push(a);
+            }
+        }
+
+        let mut stencils: Vec<Stencil> = (0..self.vertices.len())
+            .map(|v| {
+                let n = neighbors[v].len();
+                match border_neighbors[v].len() {
+                    0 if n > 0 => {
+                        let beta = if n == 3 {
+                            3. / 16.
+                        } else {
+                            3. / (8. * n as f32)
+                        };
+                        let mut weights = vec![(v, 1. - n as f32 * beta)];
+                        weights.extend(neighbors[v].iter().map(|&u| (u, beta)));
+                        weights
+                    }
+                    2 => vec![
+                        (v, 0.75),
+                        (border_neighbors[v][0], 0.125),
+                        (border_neighbors[v][1], 0.125),
+                    ],
+                    // corners where borders meet, and vertices no face uses
+                    _ => vec![(v, 1.)],
+                }
+            })
+            .collect();
+        let mut edge_vertices: HashMap<(usize, usize), usize> = HashMap::new();
+        for (&(a, b), corners) in &opposite {
+            let weights = match corners[..] {
+                [c, d] => vec![(a, 0.375), (b, 0.375), (c, 0.125), (d, 0.125)],
+                _ => vec![(a, 0.5), (b, 0.5)],
+            };
+            edge_vertices.insert((a, b), stencils.len());
+            stencils.push(weights);
+        }
+
+        let mut faces = Vec::with_capacity(4 * self.faces.len());
+        for face in &self.faces {
+            let [a, b, c] = *face;
+            let [ab, bc, ca] = edges(face).map(|edge| edge_vertices[&edge]);
+            faces.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+        }
+        (stencils, faces)
+    }
+
+    // Moves each vertex along its smooth normal (averaged over the faces around it, weighted by
+    // their area) by its offset, e.g. to raise terrain by the heights in a texture. Vertices that
+    // no face uses stay in place.
+    pub fn displace(&mut self, offsets: &[f32]) {
+        let mut normals = vec![vector!(0, 0, 0); self.vertices.len()];
+        for face in &self.faces {
+            let normal = face_normal(&self.vertices, face);
+            for &v in face {
+                normals[v] = normals[v] + normal;
+            }
+        }
+        for ((vertex, normal), &offset) in self.vertices.iter_mut().zip(normals).zip(offsets) {
+            if normal.magnitude() > 0. {
+                *vertex = *vertex + normal.norm() * offset;
+            }
+        }
+    }
+
+    pub fn vertex_normals(&self, crease_angle: f32) -> Vec<[Tuple; 3]> {
+        compute_vertex_normals(&self.vertices, &self.faces, crease_angle)
+    }
+
+    pub fn triangles(&self) -> Vec<Triangle> {
+        self.faces
+            .iter()
+            .map(|&[a, b, c]| Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c]))
+            .collect()
+    }
+
+    pub fn smooth_triangles(&self, crease_angle: f32) -> Vec<SmoothTriangle> {
+        smooth_triangles(&self.vertices, &self.faces, crease_angle)
+    }
+}
+
+// A mesh of only the vertices that the faces use, in the order they are first used; faces that
+// have collapsed into a line or a point are dropped
+fn compacted<I: Iterator<Item = [usize; 3]>>(vertices: &[Tuple], faces: I) -> TriangleMesh {
+    let mut new_indices: HashMap<usize, usize> = HashMap::new();
+    let mut mesh = TriangleMesh::default();
+    for [a, b, c] in faces {
+        if a == b || b == c || a == c {
+            continue;
+        }
+        let face = [a, b, c].map(|v| {
+            *new_indices.entry(v).or_insert_with(|| {
+                mesh.vertices.push(vertices[v]);
+                mesh.vertices.len() - 1
+            })
+        });
+        mesh.faces.push(face);
+    }
+    mesh
+}
+
+// weights should add up to 1
+fn weighted_sum(terms: &[(Tuple, f32)]) -> Tuple {
+    let mut sum = point!(0, 0, 0);
+    for &(p, weight) in terms {
+        sum.x += p.x * weight;
+        sum.y += p.y * weight;
+        sum.z += p.z * weight;
+    }
+    sum
+}
+
+// A vertex made by subdivision, as the indices of the vertices it averages and their weights
+type Stencil = Vec<(usize, f32)>;
+
+// the values of the vertices blended by each stencil
+fn blend(stencils: &[Stencil], values: &[Tuple]) -> Vec<Tuple> {
+    stencils
+        .iter()
+        .map(|weights| {
+            let terms: Vec<(Tuple, f32)> = weights.iter().map(|&(v, w)| (values[v], w)).collect();
+            weighted_sum(&terms)
+        })
+        .collect()
+}
+
+// not normalized; its length is twice the face's area
+fn face_normal(vertices: &[Tuple], &[a, b, c]: &[usize; 3]) -> Tuple {
+    (vertices[b] - vertices[a]).cross(vertices[c] - vertices[a])
+}
+
+// each as (smaller index, larger index)
+fn edges(&[a, b, c]: &[usize; 3]) -> [(usize, usize); 3] {
+    [(a, b), (b, c), (c, a)].map(|(from, to)| (from.min(to), from.max(to)))
+}
+
+// The sum of squared distances to a set of weighted planes, stored as the upper triangle of a
+// symmetric 4x4 matrix
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn of_plane(normal: Tuple, point: Tuple, weight: f64) -> Quadric {
+        let plane = [normal.x, normal.y, normal.z, -normal.dot(point)].map(|c| c as f64);
+        let mut entries = [0.; 10];
+        let mut i = 0;
+        for row in 0..4 {
+            for column in row..4 {
+                entries[i] = weight * plane[row] * plane[column];
+                i += 1;
+            }
+        }
+        Quadric(entries)
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (entry, other_entry) in self.0.iter_mut().zip(&other.0) {
+            *entry += other_entry;
+        }
+    }
+
+    fn error(&self, point: Tuple) -> f64 {
+        let p = [point.x, point.y, point.z, 1.].map(|c| c as f64);
+        let mut error = 0.;
+        let mut i = 0;
+        for row in 0..4 {
+            for column in row..4 {
+                let term = self.0[i] * p[row] * p[column];
+                // entries off of the diagonal appear twice in the full matrix
+                error += if row == column { term } else { 2. * term };
+                i += 1;
+            }
+        }
+        error
+    }
+}
+
+// Merging remove into keep, which moves to position; ordered so that the cheapest is popped first
+#[derive(Debug)]
+struct Collapse {
+    cost: f64,
+    keep: usize,
+    remove: usize,
+    versions: (u32, u32),
+    position: Tuple,
+}
+
+impl Collapse {
+    fn new(
+        keep: usize,
+        remove: usize,
+        positions: &[Tuple],
+        quadrics: &[Quadric],
+        versions: &[u32],
+    ) -> Collapse {
+        let mut quadric = quadrics[keep];
+        quadric.add(&quadrics[remove]);
+        let (a, b) = (positions[keep], positions[remove]);
+        let (cost, position) = [a, b, a + (b - a) * 0.5]
+            .iter()
+            .map(|&p| (quadric.error(p), p))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .unwrap();
+        Collapse {
+            cost,
+            keep,
+            remove,
+            versions: (versions[keep], versions[remove]),
+            position,
+        }
+    }
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, since BinaryHeap pops the largest
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+// For each vertex, the index of the vertex it is merged into: the first one within tolerance of
+// it, or itself. Vertices are found through a grid of tolerance-sized cells, so this takes about
+// linear time. A tolerance of 0 merges only identical vertices.
+pub fn weld_vertices(vertices: &[Tuple], tolerance: f32) -> Vec<usize> {
+    let cell_of = |p: Tuple| -> [i64; 3] {
+        if tolerance > 0. {
+            [p.x, p.y, p.z].map(|c| (c / tolerance).floor() as i64)
+        } else {
+            [p.x, p.y, p.z].map(|c| c.to_bits() as i64)
+        }
+    };
+    let reach = if tolerance > 0. { 1 } else { 0 };
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut merged_into = Vec::with_capacity(vertices.len());
+    for (index, &vertex) in vertices.iter().enumerate() {
+        let [x, y, z] = cell_of(vertex);
+        let mut found = None;
+        'search: for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    let neighbors = match cells.get(&[x + dx, y + dy, z + dz]) {
+                        Some(neighbors) => neighbors,
+                        None => continue,
+                    };
+                    found = neighbors
+                        .iter()
+                        .copied()
+                        .find(|&other| (vertices[other] - vertex).magnitude() <= tolerance);
+                    if found.is_some() {
+                        break 'search;
+                    }
+                }
+            }
+        }
+        match found {
+            Some(other) => merged_into.push(other),
+            None => {
+                cells.entry([x, y, z]).or_default().push(index);
+                merged_into.push(index);
+            }
+        }
+    }
+    merged_into
+}
+
+// Normals for each corner of each face (given as indices into vertices) of a triangle mesh, for
+// smooth shading meshes that don't come with their own. Each corner's normal averages the normals
+// of the faces around its vertex, weighted by their area; faces meeting at more than crease_angle
+// (in radians) are not averaged, so that e.g. the edges of a cube stay sharp. A crease angle of
+// PI smooths everything.
+pub fn compute_vertex_normals(
+    vertices: &[Tuple],
+    faces: &[[usize; 3]],
+    crease_angle: f32,
+) -> Vec<[Tuple; 3]> {
+    // the cross product's length is twice the triangle's area, which weights the average
+    let face_normals: Vec<Tuple> = faces
+        .iter()
+        .map(|&[a, b, c]| (vertices[b] - vertices[a]).cross(vertices[c] - vertices[a]))
+        .collect();
+    let mut faces_at_vertex = vec![vec![]; vertices.len()];
+    for (index, face) in faces.iter().enumerate() {
+        for &vertex in face {
+            faces_at_vertex[vertex].push(index);
+        }
+    }
+    // a little leeway so that faces exactly at the crease angle are smoothed
+    let min_cos = crease_angle.cos() - 1e-5;
+    let is_smooth = |own: Tuple, other: Tuple| {
+        let lengths = own.magnitude() * other.magnitude();
+        // degenerate faces have no direction of their own and are smoothed with anything
+        lengths == 0. || own.dot(other) / lengths >= min_cos
+    };
+
+    faces
+        .iter()
+        .zip(&face_normals)
+        .map(|(face, &own)| {
+            let mut corners = [own; 3];
+            for (corner, &vertex) in corners.iter_mut().zip(face) {
+                let mut sum = vector!(0, 0, 0);
+                for &other in &faces_at_vertex[vertex] {
+                    if is_smooth(own, face_normals[other]) {
+                        sum = sum + face_normals[other];
+                    }
+                }
+                if sum.magnitude() > 0. {
+                    *corner = sum.norm();
+                }
+            }
+            corners
+        })
+        .collect()
+}
+
+// Smooth-shaded triangles for the given faces, with normals from compute_vertex_normals
+pub fn smooth_triangles(
+    vertices: &[Tuple],
+    faces: &[[usize; 3]],
+    crease_angle: f32,
+) -> Vec<SmoothTriangle> {
+    faces
+        .iter()
+        .zip(compute_vertex_normals(vertices, faces, crease_angle))
+        .map(|(&[a, b, c], [n1, n2, n3])| {
+            SmoothTriangle::new(vertices[a], vertices[b], vertices[c], n1, n2, n3)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding_box::BoundingBox;
+    use crate::transformations::{scaling, translation};
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    // two faces of a roof meeting at a right angle along the z axis
+    fn roof() -> (Vec<Tuple>, Vec<[usize; 3]>) {
+        let vertices = vec![
+            point!(-1, 0, 0),
+            point!(0, 1, 0),
+            point!(0, 1, 1),
+            point!(1, 0, 0),
+        ];
+        let faces = vec![[0, 2, 1], [1, 2, 3]];
+        (vertices, faces)
+    }
+
+    #[test]
+    fn flat_faces_keep_face_normal() {
+        let vertices = vec![
+            point!(0, 0, 0),
+            point!(1, 0, 0),
+            point!(1, 0, 1),
+            point!(0, 0, 1),
+        ];
+        let normals = compute_vertex_normals(&vertices, &[[0, 2, 1], [0, 3, 2]], PI);
+        for face in normals {
+            for normal in &face {
+                assert_abs_diff_eq!(*normal, vector!(0, 1, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn normals_at_creases() {
+        let (vertices, faces) = roof();
+        let ridge = vector!(0, 1, 0);
+        let left = vector!(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0);
+        let right = vector!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0);
+        let test_data = vec![
+            ("smooth", PI, [left, ridge, ridge], [ridge, ridge, right]),
+            (
+                "right angle",
+                PI / 2.,
+                [left, ridge, ridge],
+                [ridge, ridge, right],
+            ),
+            ("sharp", PI / 4., [left; 3], [right; 3]),
+        ];
+        for (name, crease_angle, expected_left, expected_right) in test_data {
+            println!("Case {}", name);
+            let normals = compute_vertex_normals(&vertices, &faces, crease_angle);
+            for i in 0..3 {
+                assert_abs_diff_eq!(normals[0][i], expected_left[i]);
+                assert_abs_diff_eq!(normals[1][i], expected_right[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn larger_faces_weigh_more() {
+        // the same roof, but with the right side twice as long
+        let mut vertices = roof().0;
+        vertices[3] = point!(2, -1, 0);
+        let normals = compute_vertex_normals(&vertices, &roof().1, PI);
+        let ridge = normals[0][1];
+        assert!(ridge.x > 0.);
+        assert_abs_diff_eq!(ridge, vector!(1, 3, 0).norm());
+    }
+
+    #[test]
+    fn welding_merges_nearby_vertices() {
+        let vertices = vec![
+            point!(0, 0, 0),
+            point!(1, 0, 0),
+            point!(0.001, 0, 0),
+            point!(1, 0.0005, 0),
+            point!(0.5, 0, 0),
+        ];
+        let test_data = vec![
+            ("exact", 0., vec![0, 1, 2, 3, 4]),
+            ("close", 0.01, vec![0, 1, 0, 1, 4]),
+            ("far", 0.6, vec![0, 1, 0, 1, 0]),
+        ];
+        for (name, tolerance, expected) in test_data {
+            assert_eq!(
+                weld_vertices(&vertices, tolerance),
+                expected,
+                "Case {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn welding_triangle_soup() {
+        let (a, b, c, d) = (
+            point!(0, 0, 0),
+            point!(1, 0, 0),
+            point!(0, 1, 0),
+            point!(1, 1, 0),
+        );
+        let mesh = TriangleMesh::from_triangles(&[[a, b, c], [c, b, d], [a, a, d]]);
+        assert_eq!(mesh.vertices.len(), 9);
+        let welded = mesh.weld(0.);
+        // the degenerate triangle is dropped
+        assert_eq!(welded.vertices, vec![a, b, c, d]);
+        assert_eq!(welded.faces, vec![[0, 1, 2], [2, 1, 3]]);
+        // once welded, the shared edge can be smoothed
+        let normals = welded.vertex_normals(PI);
+        assert_abs_diff_eq!(normals[1][0], vector!(0, 0, 1));
+        assert_eq!(welded.triangles().len(), 2);
+    }
+
+    // an n by n grid of squares on the xz plane, each split into two triangles, with its height
+    // given by the function
+    fn grid<F: Fn(f32, f32) -> f32>(n: usize, height: F) -> TriangleMesh {
+        let mut vertices = vec![];
+        for i in 0..=n {
+            for j in 0..=n {
+                let (x, z) = (i as f32 / n as f32, j as f32 / n as f32);
+                vertices.push(point!(x, height(x, z), z));
+            }
+        }
+        let index = |i: usize, j: usize| i * (n + 1) + j;
+        let mut faces = vec![];
+        for i in 0..n {
+            for j in 0..n {
+                faces.push([index(i, j), index(i, j + 1), index(i + 1, j)]);
+                faces.push([index(i + 1, j), index(i, j + 1), index(i + 1, j + 1)]);
+            }
+        }
+        TriangleMesh::new(vertices, faces)
+    }
+
+    #[test]
+    fn simplifying_flat_mesh_keeps_its_shape() {
+        let mesh = grid(8, |_, _| 0.);
+        assert_eq!(mesh.faces.len(), 128);
+        let simplified = mesh.simplify(10);
+        assert!(simplified.faces.len() <= 10, "{}", simplified.faces.len());
+        assert!(simplified.vertices.len() < mesh.vertices.len());
+        let mut area = 0.;
+        for face in &simplified.faces {
+            let normal = face_normal(&simplified.vertices, face);
+            // still flat and facing the same way
+            assert!(normal.y > 0.);
+            assert_abs_diff_eq!(normal.norm(), vector!(0, 1, 0));
+            area += normal.magnitude() / 2.;
+        }
+        // the borders stayed in place
+        assert_abs_diff_eq!(area, 1., epsilon = 0.0001);
+    }
+
+    #[test]
+    fn simplifying_keeps_detail_where_it_matters() {
+        // flat except for a sharp ridge along x = 0.5
+        let mesh = grid(8, |x, _| if x == 0.5 { 0.5 } else { 0. });
+        let simplified = mesh.simplify(32);
+        assert!(simplified.faces.len() <= 32);
+        let ridge_height = simplified.vertices.iter().map(|v| v.y).fold(0., f32::max);
+        assert_abs_diff_eq!(ridge_height, 0.5);
+    }
+
+    #[test]
+    fn simplifying_to_more_faces_changes_nothing() {
+        let mesh = grid(2, |x, z| x * z);
+        assert_eq!(mesh.simplify(100), mesh);
+    }
+
+    fn tetrahedron() -> TriangleMesh {
+        TriangleMesh::new(
+            vec![
+                point!(1, 1, 1),
+                point!(1, -1, -1),
+                point!(-1, 1, -1),
+                point!(-1, -1, 1),
+            ],
+            vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]],
+        )
+    }
+
+    #[test]
+    fn subdividing_closed_mesh() {
+        let test_data = vec![(1, 16, 10), (2, 64, 34)];
+        for (iterations, faces, vertices) in test_data {
+            let subdivided = tetrahedron().subdivide(iterations);
+            assert_eq!(subdivided.faces.len(), faces, "Case {}", iterations);
+            assert_eq!(subdivided.vertices.len(), vertices, "Case {}", iterations);
+        }
+        let once = tetrahedron().subdivide(1);
+        // each corner has three neighbors: 1 - 3 * 3/16 of itself and 3/16 of each neighbor
+        assert_abs_diff_eq!(once.vertices[0], point!(0.25, 0.25, 0.25));
+        // each edge: 3/8 of its ends and 1/8 of the two opposite corners
+        let edge = once.faces[0][1];
+        assert_abs_diff_eq!(once.vertices[edge], point!(0.5, 0, 0));
+        // still closed and facing outwards
+        for face in &once.faces {
+            let center = weighted_sum(&[
+                (once.vertices[face[0]], 1. / 3.),
+                (once.vertices[face[1]], 1. / 3.),
+                (once.vertices[face[2]], 1. / 3.),
+            ]);
+            assert!(face_normal(&once.vertices, face).dot(center - point!(0, 0, 0)) > 0.);
+        }
+        assert_eq!(tetrahedron().subdivide(0), tetrahedron());
+    }
+
+    #[test]
+    fn subdividing_keeps_borders_and_flat_areas() {
+        let mesh = grid(2, |_, _| 0.);
+        let subdivided = mesh.subdivide(2);
+        assert_eq!(subdivided.faces.len(), 8 * 16);
+        let mut bounds = BoundingBox::empty();
+        for v in &subdivided.vertices {
+            assert_eq!(v.y, 0.);
+            bounds.add_point(*v);
+        }
+        // the borders are smoothed along themselves, which rounds off the grid's corners a bit
+        assert!(bounds.min.x >= 0. && bounds.max.x <= 1.);
+        assert_abs_diff_eq!(bounds.max.x, 1.);
+    }
+
+    #[test]
+    fn subdividing_interpolates_values_like_positions() {
+        let mesh = grid(2, |x, z| x * z);
+        let values: Vec<Tuple> = mesh.vertices.iter().map(|v| point!(v.z, v.x, 0)).collect();
+        let (subdivided, values) = mesh.subdivide_with_values(2, &values);
+        assert_eq!(values.len(), subdivided.vertices.len());
+        for (vertex, value) in subdivided.vertices.iter().zip(values) {
+            assert_abs_diff_eq!(value, point!(vertex.z, vertex.x, 0));
+        }
+    }
+
+    #[test]
+    fn displacing_along_normals() {
+        let mut mesh = grid(2, |_, _| 0.);
+        let offsets: Vec<f32> = (0..mesh.vertices.len()).map(|v| v as f32 / 10.).collect();
+        let before = mesh.clone();
+        mesh.displace(&offsets);
+        for (v, (moved, original)) in mesh.vertices.iter().zip(before.vertices).enumerate() {
+            assert_abs_diff_eq!(*moved, original + vector!(0, offsets[v], 0));
+        }
+    }
+
+    #[test]
+    fn baking_transformation_into_vertices() {
+        let (vertices, faces) = roof();
+        let mut mesh = TriangleMesh::new(vertices, faces);
+        mesh.bake_transform(&(translation(1., 0., 0.) * &scaling(2., 2., 2.)));
+        assert_eq!(mesh.vertices[0], point!(-1, 0, 0));
+        assert_eq!(mesh.vertices[2], point!(1, 2, 2));
+    }
+
+    #[test]
+    fn smooth_triangles_use_computed_normals() {
+        let (vertices, faces) = roof();
+        let triangles = smooth_triangles(&vertices, &faces, PI);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1].base.p3, point!(1, 0, 0));
+        assert_abs_diff_eq!(triangles[1].n1, vector!(0, 1, 0));
+        assert_abs_diff_eq!(triangles[1].n3, vector!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+    }
+}
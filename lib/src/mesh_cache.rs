@@ -0,0 +1,368 @@
+use crate::obj_parser::{
+    parse_obj_faces, IndexedTriangle, ObjParseResults, ParseError, ParseOptions, ParseWarning,
+    ParsedFaces,
+};
+use crate::tuple::Tuple;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Identifies a mesh cache file and the version of its layout
+const MESH_CACHE_MAGIC: &[u8; 8] = b"RTCMESH1";
+
+// What cached faces were made from; if any of it changes, the cache is stale
+#[derive(Clone, Debug, PartialEq)]
+struct CacheKey {
+    source_len: u64,
+    source_modified_nanos: u128,
+    // the Debug form of the options
+    options: String,
+}
+
+impl CacheKey {
+    fn new(source: &Path, options: &ParseOptions) -> io::Result<CacheKey> {
+        let metadata = fs::metadata(source)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(CacheKey {
+            source_len: metadata.len(),
+            source_modified_nanos: modified.as_nanos(),
+            options: format!("{:?}", options),
+        })
+    }
+}
+
+// Parses the OBJ file at path with the given options, or, if neither the file nor the options
+// have changed since the last time, loads the faces parsed then from the cache file next to it
+// (see cache_path), which is much faster for large models. The cache is (re)written after
+// parsing. Caches that can't be read or written only mean parsing again.
+pub fn parse_obj_file(path: &Path, options: &ParseOptions) -> Result<ObjParseResults, ParseError> {
+    // the texture can't be checked for changes, so displaced models are always parsed again
+    if options.displacement.is_some() {
+        return Ok(parse_obj_faces(File::open(path)?, options)?.into());
+    }
+    let key = CacheKey::new(path, options)?;
+    let cache = cache_path(path);
+    if let Ok(parsed) = load(&cache, &key) {
+        return Ok(parsed.into());
+    }
+    let parsed = parse_obj_faces(File::open(path)?, options)?;
+    save(&cache, &key, &parsed).ok();
+    Ok(parsed.into())
+}
+
+// e.g. dragon.obj.rtcache for dragon.obj
+pub fn cache_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".rtcache");
+    path.with_file_name(file_name)
+}
+
+// Write to a temporary file first and then move it into place, so that being killed in the middle
+// of saving never leaves behind a corrupt cache.
+fn save(path: &Path, key: &CacheKey, parsed: &ParsedFaces) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    write_to(BufWriter::new(File::create(&temp_path)?), key, parsed)?;
+    fs::rename(&temp_path, path)
+}
+
+fn load(path: &Path, key: &CacheKey) -> io::Result<ParsedFaces> {
+    let (cached_key, parsed) = read_from(BufReader::new(File::open(path)?))?;
+    if &cached_key != key {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Stale cache"));
+    }
+    Ok(parsed)
+}
+
+// Layout (all numbers little-endian, counts and indices as u32, strings as their length and then
+// their UTF-8 bytes): magic; the key's source length as u64, modification time as u128 and
+// options; the number of ignored lines; the warnings, each as line, column and message; the
+// vertices and then the normals, each as a count followed by x, y and z as f32; the groups, each
+// as its name and its triangles, which are three vertex indices, a byte telling whether normal
+// indices follow, and then three normal indices if so.
+fn write_to<W: Write>(mut writer: W, key: &CacheKey, parsed: &ParsedFaces) -> io::Result<()> {
+    writer.write_all(MESH_CACHE_MAGIC)?;
+    writer.write_all(&key.source_len.to_le_bytes())?;
+    writer.write_all(&key.source_modified_nanos.to_le_bytes())?;
+    write_str(&mut writer, &key.options)?;
+
+    write_u32(&mut writer, parsed.num_ignored_lines)?;
+    write_u32(&mut writer, parsed.warnings.len())?;
+    for warning in &parsed.warnings {
+        write_u32(&mut writer, warning.line)?;
+        write_u32(&mut writer, warning.column)?;
+        write_str(&mut writer, &warning.message)?;
+    }
+    for tuples in &[&parsed.vertices, &parsed.normals] {
+        write_u32(&mut writer, tuples.len())?;
+        for t in tuples.iter() {
+            for coordinate in &[t.x, t.y, t.z] {
+                writer.write_all(&coordinate.to_le_bytes())?;
+            }
+        }
+    }
+    write_u32(&mut writer, parsed.groups.len())?;
+    for (name, triangles) in &parsed.groups {
+        write_str(&mut writer, name)?;
+        write_u32(&mut writer, triangles.len())?;
+        for triangle in triangles {
+            for &index in &triangle.vertices {
+                write_u32(&mut writer, index)?;
+            }
+            writer.write_all(&[triangle.normals.is_some() as u8])?;
+            for &index in triangle.normals.iter().flatten() {
+                write_u32(&mut writer, index)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn read_from<R: Read>(mut reader: R) -> io::Result<(CacheKey, ParsedFaces)> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MESH_CACHE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a mesh cache file",
+        ));
+    }
+    let mut source_len = [0u8; 8];
+    reader.read_exact(&mut source_len)?;
+    let mut source_modified_nanos = [0u8; 16];
+    reader.read_exact(&mut source_modified_nanos)?;
+    let key = CacheKey {
+        source_len: u64::from_le_bytes(source_len),
+        source_modified_nanos: u128::from_le_bytes(source_modified_nanos),
+        options: read_string(&mut reader)?,
+    };
+
+    let num_ignored_lines = read_u32(&mut reader)?;
+    let mut warnings = vec![];
+    for _ in 0..read_u32(&mut reader)? {
+        warnings.push(ParseWarning {
+            line: read_u32(&mut reader)?,
+            column: read_u32(&mut reader)?,
+            message: read_string(&mut reader)?,
+        });
+    }
+    let vertices = read_tuples(&mut reader, 1.)?;
+    let normals = read_tuples(&mut reader, 0.)?;
+    let mut groups = HashMap::new();
+    for _ in 0..read_u32(&mut reader)? {
+        let name = read_string(&mut reader)?;
+        let mut triangles = vec![];
+        for _ in 0..read_u32(&mut reader)? {
+            let vertices = read_indices(&mut reader, vertices.len())?;
+            let mut has_normals = [0u8];
+            reader.read_exact(&mut has_normals)?;
+            let normals = match has_normals[0] {
+                0 => None,
+                _ => Some(read_indices(&mut reader, normals.len())?),
+            };
+            triangles.push(IndexedTriangle { vertices, normals });
+        }
+        groups.insert(name, triangles);
+    }
+    let parsed = ParsedFaces {
+        num_ignored_lines,
+        warnings,
+        vertices,
+        normals,
+        groups,
+    };
+    Ok((key, parsed))
+}
+
+fn write_u32<W: Write>(writer: &mut W, n: usize) -> io::Result<()> {
+    writer.write_all(&(n as u32).to_le_bytes())
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    write_u32(writer, s.len())?;
+    writer.write_all(s.as_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+// Lengths and counts come from the file, so buffers grow as data actually arrives instead of being
+// allocated up front; a corrupt length then fails with UnexpectedEof rather than a huge allocation.
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_u32(reader)?;
+    let mut bytes = vec![];
+    reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// w is 1 for points and 0 for vectors
+fn read_tuples<R: Read>(reader: &mut R, w: f32) -> io::Result<Vec<Tuple>> {
+    let mut tuples = vec![];
+    for _ in 0..read_u32(reader)? {
+        let x = read_f32(reader)?;
+        let y = read_f32(reader)?;
+        let z = read_f32(reader)?;
+        let tuple = Tuple::try_new(x, y, z, w)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        tuples.push(tuple);
+    }
+    Ok(tuples)
+}
+
+// checked, so that a corrupt cache is an error rather than a panic later
+fn read_indices<R: Read>(reader: &mut R, len: usize) -> io::Result<[usize; 3]> {
+    let mut indices = [0; 3];
+    for index in &mut indices {
+        *index = read_u32(reader)?;
+        if *index >= len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Index out of range in mesh cache",
+            ));
+        }
+    }
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::f32::consts::PI;
+
+    const TEXT: &str = "
+        v -1 0 0
+        v 0 1 0
+        v 0 1 1
+        v 1 0 0
+        vn 0 0 1
+        vn 0 1 0
+        vn 1 0 0
+        vn 1 1 1
+        usemtl shiny
+        f 1 3 2
+        g Right
+        f 2/1/2 3/1/3 4/1/4";
+
+    fn test_key() -> CacheKey {
+        CacheKey {
+            source_len: 123,
+            source_modified_nanos: 1_600_000_000_123_456_789,
+            options: "options".to_string(),
+        }
+    }
+
+    #[test]
+    fn cache_survives_round_trip() {
+        let options = ParseOptions {
+            smoothing_crease_angle: Some(PI),
+            ..ParseOptions::default()
+        };
+        let parsed = parse_obj_faces(TEXT.as_bytes(), &options).unwrap();
+        let mut bytes = vec![];
+        write_to(&mut bytes, &test_key(), &parsed).unwrap();
+        let (key, read) = read_from(bytes.as_slice()).unwrap();
+        assert_eq!(key, test_key());
+        assert_eq!(read, parsed);
+        assert_eq!(read.warnings.len(), 1);
+        assert!(read.groups["Right"][0].normals.is_some());
+    }
+
+    #[test]
+    fn reading_corrupt_caches_fails() {
+        let parsed = parse_obj_faces(TEXT.as_bytes(), &ParseOptions::default()).unwrap();
+        let mut bytes = vec![];
+        write_to(&mut bytes, &test_key(), &parsed).unwrap();
+
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() - 1);
+        let error = read_from(truncated.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let error = read_from(&b"v 1 2 3\nv 4 5 6"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // huge lengths and counts are not trusted to allocate
+        let mut huge_string = bytes.clone();
+        huge_string[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = read_from(huge_string.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let mut huge_count = MESH_CACHE_MAGIC.to_vec();
+        // source length and modification time, then empty options, no ignored lines and no warnings
+        huge_count.extend_from_slice(&[0u8; 8 + 16 + 4 + 4 + 4]);
+        huge_count.extend_from_slice(&u32::MAX.to_le_bytes());
+        let error = read_from(huge_count.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let mut nan_vertex = huge_count.clone();
+        let count = nan_vertex.len() - 4;
+        nan_vertex[count..].copy_from_slice(&1u32.to_le_bytes());
+        for value in &[f32::NAN, 0., 0.] {
+            nan_vertex.extend_from_slice(&value.to_le_bytes());
+        }
+        let error = read_from(nan_vertex.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // the last index of the last triangle
+        let mut out_of_range = bytes;
+        let last = out_of_range.len() - 4;
+        out_of_range[last..].copy_from_slice(&99u32.to_le_bytes());
+        let error = read_from(out_of_range.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn cache_is_used_until_source_or_options_change() {
+        let dir = env::temp_dir().join(format!("rtc_mesh_cache_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("roof.obj");
+        fs::write(&source, TEXT).unwrap();
+        let cache = cache_path(&source);
+        assert_eq!(cache, dir.join("roof.obj.rtcache"));
+
+        let options = ParseOptions::default();
+        let parsed = parse_obj_file(&source, &options).unwrap();
+        assert_eq!(parsed.get_group("Right").unwrap().get_children().len(), 1);
+        let key = CacheKey::new(&source, &options).unwrap();
+        assert!(load(&cache, &key).is_ok());
+
+        // a cache with other contents but the right key is trusted
+        let mut fake = parse_obj_faces(TEXT.as_bytes(), &options).unwrap();
+        fake.groups.remove("Right");
+        save(&cache, &key, &fake).unwrap();
+        let loaded = parse_obj_file(&source, &options).unwrap();
+        assert!(loaded.get_group("Right").is_none());
+
+        // but not with other options
+        let smoothed = ParseOptions {
+            smoothing_crease_angle: Some(PI),
+            ..ParseOptions::default()
+        };
+        let reparsed = parse_obj_file(&source, &smoothed).unwrap();
+        assert!(reparsed.get_group("Right").is_some());
+        assert!(load(&cache, &key).is_err());
+        assert!(load(&cache, &CacheKey::new(&source, &smoothed).unwrap()).is_ok());
+
+        // or when the source changes
+        fs::write(&source, format!("{}\nf 1 2 3", TEXT)).unwrap();
+        let reparsed = parse_obj_file(&source, &smoothed).unwrap();
+        assert_eq!(reparsed.get_group("Right").unwrap().get_children().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
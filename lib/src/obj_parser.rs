@@ -1,21 +1,56 @@
 use crate::bounding_box::BoundingBox;
+use crate::mesh::{compute_vertex_normals, weld_vertices, TriangleMesh};
+use crate::pattern::uv::UVPattern;
 use crate::shape::group::GroupShape;
+use crate::shape::polygon::triangulate;
 use crate::shape::shape::Shape;
 use crate::shape::smooth_triangle::SmoothTriangle;
 use crate::shape::triangle::Triangle;
+use crate::stats::{self, Phase};
 use crate::tuple::Tuple;
 use std::collections::hash_map::HashMap;
+use std::f32::consts::PI;
 use std::fmt::{self, Display, Formatter};
 use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
 
 pub struct ObjParseResults {
     num_ignored_lines: usize,
     vertices: Vec<Tuple>,
     normals: Vec<Tuple>,
     groups: Option<HashMap<String, GroupShape>>,
+    warnings: Vec<ParseWarning>,
+}
+
+// A problem in the input that did not stop parsing, such as an unsupported statement
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseWarning {
+    // 1-based
+    pub line: usize,
+    // 1-based, counted in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
 }
 
 impl ObjParseResults {
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    pub fn num_ignored_lines(&self) -> usize {
+        self.num_ignored_lines
+    }
+
     pub fn get_default_group(&self) -> Option<&GroupShape> {
         match &self.groups {
             Some(groups) => groups.get(""),
@@ -55,7 +90,62 @@ impl ObjParseResults {
     }
 }
 
-// TODO: proper parsing errors should also contain the line and column number
+// Choices made while turning the parsed faces into shapes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    // give faces without normals of their own smooth normals from compute_vertex_normals, with
+    // this crease angle in radians; None to leave them flat
+    pub smoothing_crease_angle: Option<f32>,
+    // merge vertices closer than this (after scaling the model to -1..1) before computing
+    // normals, for files that repeat each vertex for every face that uses it; None to keep them
+    pub weld_tolerance: Option<f32>,
+    // simplify the model to at most this many triangles (see TriangleMesh::simplify), taking from
+    // each group in proportion to its size; normals from the file no longer fit the simplified
+    // faces and are dropped. Best combined with welding.
+    pub max_triangles: Option<usize>,
+    // rounds of Loop subdivision to smooth each group with (see TriangleMesh::subdivide), after
+    // any simplification. Subdivided groups are smooth shaded, with smoothing_crease_angle if it
+    // is given and otherwise with no creases; normals from the file are dropped.
+    pub subdivisions: u32,
+    // move the vertices along their smooth normals by heights read from a texture, after any
+    // subdivision, so that e.g. a few faces of terrain get detailed relief. Only vertices with
+    // texture coordinates (from vt statements) move. Displaced models are smooth shaded like
+    // subdivided ones.
+    pub displacement: Option<Displacement>,
+}
+
+// Heights for ParseOptions::displacement: the brightness (average of the channels) of the texture
+// at each vertex's texture coordinates, mapped from [0, 1] to [low, high] like ScalarPattern.
+// Heights are in the units of the model after it has been scaled to -1..1.
+#[derive(Clone, Debug)]
+pub struct Displacement {
+    pub texture: Arc<dyn UVPattern>,
+    pub low: f32,
+    pub high: f32,
+}
+
+impl Displacement {
+    pub fn new(texture: Arc<dyn UVPattern>, low: f32, high: f32) -> Self {
+        Displacement { texture, low, high }
+    }
+
+    pub fn height_at(&self, u: f32, v: f32) -> f32 {
+        let c = self.texture.color_at(u, v);
+        let brightness = ((c.r + c.g + c.b) / 3.).clamp(0., 1.);
+        self.low + (self.high - self.low) * brightness
+    }
+}
+
+// textures are compared by identity, like ScalarPattern
+impl PartialEq for Displacement {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.texture, &other.texture)
+            && self.low == other.low
+            && self.high == other.high
+    }
+}
+
+// Error messages contain the 1-based line and column of the problem
 #[derive(Debug)]
 pub enum ParseError {
     IoError(io::Error),
@@ -64,6 +154,7 @@ pub enum ParseError {
     MalformedVertex(String),
     MalformedFace(String),
     MalformedNormal(String),
+    MalformedTextureCoordinates(String),
     MalformedGroupDeclaration(String),
     UnexpectedSymbol(String),
 }
@@ -91,128 +182,441 @@ impl Display for ParseError {
             ParseError::MalformedVertex(ref s) => f.write_str(s),
             ParseError::MalformedFace(ref s) => f.write_str(s),
             ParseError::MalformedNormal(ref s) => f.write_str(s),
+            ParseError::MalformedTextureCoordinates(ref s) => f.write_str(s),
             ParseError::MalformedGroupDeclaration(ref s) => f.write_str(s),
             ParseError::UnexpectedSymbol(ref s) => f.write_str(s),
         }
     }
 }
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::IoError(e) => Some(e),
+            ParseError::ParseFloatError(e) => Some(e),
+            ParseError::ParseIntError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Statements that are valid OBJ but not supported; lines containing them are skipped
+const UNSUPPORTED_STATEMENTS: &[&str] = &[
+    "vp", "o", "s", "l", "p", "mtllib", "usemtl", "cstype", "deg", "curv", "surf",
+];
 
 pub fn parse_obj<T: Read>(reader: T) -> Result<ObjParseResults, ParseError> {
+    parse_obj_with_options(reader, &ParseOptions::default())
+}
+
+pub fn parse_obj_with_options<T: Read>(
+    reader: T,
+    options: &ParseOptions,
+) -> Result<ObjParseResults, ParseError> {
+    parse_obj_faces(reader, options).map(ObjParseResults::from)
+}
+
+// Everything parsed from an OBJ file, with faces still as indices rather than shapes; this is
+// what mesh caches store
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ParsedFaces {
+    pub(crate) num_ignored_lines: usize,
+    pub(crate) warnings: Vec<ParseWarning>,
+    pub(crate) vertices: Vec<Tuple>,
+    pub(crate) normals: Vec<Tuple>,
+    pub(crate) groups: HashMap<String, Vec<IndexedTriangle>>,
+}
+
+pub(crate) fn parse_obj_faces<T: Read>(
+    reader: T,
+    options: &ParseOptions,
+) -> Result<ParsedFaces, ParseError> {
+    let _timer = stats::time_phase(Phase::ObjParsing);
     let buf_reader = BufReader::new(reader);
     let mut num_ignored_lines = 0;
+    let mut warnings = vec![];
     // add one dummy point to simplify processing; OBJ files use 1-based indexing
     let mut vertices = vec![point!(0, 0, 0)];
-    let mut normals = vec![point!(0, 0, 0)];
-    let mut groups: HashMap<String, GroupShape> = HashMap::new();
-    let mut current_group: Option<&mut GroupShape> = None;
+    let mut normals = vec![vector!(0, 0, 0)];
+    // u and v in x and y; z is 1 to tell them apart from the 0 of vertices without any
+    let mut texture_coords = vec![point!(0, 0, 0)];
+    // the texture coordinates of each vertex, from the first face corner that gives it any
+    let mut vertex_texture_coords = vec![];
+    // shapes are only created at the end, once normals can be computed from all of the faces
+    let mut groups: HashMap<String, Vec<IndexedTriangle>> = HashMap::new();
+    let mut current_group: Option<String> = None;
     let mut normalization_finished = false;
     for (index, line) in buf_reader.lines().enumerate() {
+        let line_number = index + 1;
         let line = line?;
-        let line = line.trim();
-        let mut elements = line.split_whitespace();
+        let mut elements = tokenize(&line).into_iter();
         match elements.next() {
             // parse a vertex line: v f32 f32 f32
-            Some("v") => {
+            Some(Token { text: "v", column }) => {
                 if normalization_finished {
                     return Err(ParseError::UnexpectedSymbol(format!(
-                        "Found vertex at line {}; vertices must all be specified before any faces are specified (so that they \
-                            may be normalized before any faces are created)", index)));
+                        "Found vertex at line {}, column {}; vertices must all be specified before any faces are specified (so that they \
+                            may be normalized before any faces are created)", line_number, column)));
                 }
-                let coordinates = elements
-                    .map(|x| x.parse::<f32>())
-                    .collect::<Result<Vec<f32>, std::num::ParseFloatError>>()?;
+                let coordinates = parse_coordinates(elements, line_number)
+                    .map_err(ParseError::MalformedVertex)?;
                 if coordinates.len() != 3 {
                     return Err(ParseError::MalformedVertex(format!(
-                        "Wrong number of coordinates in vertex at line {}; expected 3, found {}",
-                        index,
+                        "Wrong number of coordinates in vertex at line {}, column {}; expected 3, found {}",
+                        line_number,
+                        column,
                         coordinates.len()
                     )));
                 } else {
-                    vertices.push(point!(coordinates[0], coordinates[1], coordinates[2]))
+                    vertices.push(
+                        Tuple::try_new(coordinates[0], coordinates[1], coordinates[2], 1.)
+                            .map_err(|e| {
+                                ParseError::MalformedVertex(format!(
+                                    "Invalid vertex at line {}, column {}: {}",
+                                    line_number, column, e
+                                ))
+                            })?,
+                    )
                 }
             }
             // parse a normal line: vn f32 f32 f32
-            Some("vn") => {
-                let coordinates = elements
-                    .map(|x| x.parse::<f32>())
-                    .collect::<Result<Vec<f32>, std::num::ParseFloatError>>()?;
+            Some(Token { text: "vn", column }) => {
+                let coordinates = parse_coordinates(elements, line_number)
+                    .map_err(ParseError::MalformedNormal)?;
                 if coordinates.len() != 3 {
                     return Err(ParseError::MalformedNormal(format!(
-                        "Wrong number of coordinates in normal vector at line {}; expected 3, found {}",
-                        index,
+                        "Wrong number of coordinates in normal vector at line {}, column {}; expected 3, found {}",
+                        line_number,
+                        column,
+                        coordinates.len()
+                    )));
+                } else {
+                    normals.push(
+                        Tuple::try_new(coordinates[0], coordinates[1], coordinates[2], 0.)
+                            .map_err(|e| {
+                                ParseError::MalformedNormal(format!(
+                                    "Invalid normal vector at line {}, column {}: {}",
+                                    line_number, column, e
+                                ))
+                            })?,
+                    )
+                }
+            }
+            // parse texture coordinates: vt f32 [f32 [f32]]; the optional depth is ignored
+            Some(Token { text: "vt", column }) => {
+                let coordinates = parse_coordinates(elements, line_number)
+                    .map_err(ParseError::MalformedTextureCoordinates)?;
+                if coordinates.is_empty() || coordinates.len() > 3 {
+                    return Err(ParseError::MalformedTextureCoordinates(format!(
+                        "Wrong number of texture coordinates at line {}, column {}; expected 1 to 3, found {}",
+                        line_number,
+                        column,
                         coordinates.len()
                     )));
                 } else {
-                    normals.push(vector!(coordinates[0], coordinates[1], coordinates[2]))
+                    let v = coordinates.get(1).copied().unwrap_or(0.);
+                    texture_coords.push(Tuple::try_new(coordinates[0], v, 1., 1.).map_err(|e| {
+                        ParseError::MalformedTextureCoordinates(format!(
+                            "Invalid texture coordinates at line {}, column {}: {}",
+                            line_number, column, e
+                        ))
+                    })?)
                 }
             }
             // parse a triangle line: vf usize usize usize
-            // Next: set flag that no more vertices may be read. Normalize all vertices, update tests. Then try making a scene with an OBJ file!
-            Some("f") => {
+            Some(Token { text: "f", column }) => {
                 if !normalization_finished {
                     normalize_vertices(&mut vertices);
                     normalization_finished = true;
+                    vertex_texture_coords = vec![point!(0, 0, 0); vertices.len()];
                 }
 
                 // TODO: throw useful error if normal is specified for some but not all faces in spec
                 let face_specs = elements
-                    .map(parse_face)
+                    .map(|token| parse_face(token, line_number, vertices.len(), normals.len()))
                     .collect::<Result<Vec<FaceParseResults>, ParseError>>()?;
+                // texture coordinates are only needed for displacement, so faces referring to
+                // missing ones are still accepted
+                for spec in &face_specs {
+                    let coords = spec.texture.and_then(|t| texture_coords.get(t));
+                    if let Some(&coords) = coords {
+                        if vertex_texture_coords[spec.vertex].z == 0. {
+                            vertex_texture_coords[spec.vertex] = coords;
+                        }
+                    }
+                }
                 if face_specs.len() < 3 {
                     return Err(ParseError::MalformedFace(format!(
-                        "Not enough vertices to form a face at line {}; expected 3, found {}",
-                        index,
+                        "Not enough vertices to form a face at line {}, column {}; expected 3, found {}",
+                        line_number,
+                        column,
                         face_specs.len()
                     )));
                 } else {
-                    // current_group = current_group.get_or_insert_with(||{});
-                    match current_group {
-                        None => {
-                            // the default group. We use the empty string because it will be impossible to
-                            // accidentally override while parsing the OBJ file.
-                            groups.insert("".into(), GroupShape::new());
-                            current_group = groups.get_mut("");
-                        }
-                        _ => {}
-                    }
-                    for triangle in fan_triangulation(&vertices, &normals, &face_specs) {
-                        current_group = current_group.map(|g| {
-                            g.add_child(triangle);
-                            g
-                        });
-                    }
+                    let group_name = current_group.get_or_insert_with(|| {
+                        // the default group. We use the empty string because it will be impossible to
+                        // accidentally override while parsing the OBJ file.
+                        groups.insert("".into(), vec![]);
+                        "".into()
+                    });
+                    groups
+                        .get_mut(group_name.as_str())
+                        .unwrap()
+                        .extend(triangulate_face(&vertices, &face_specs));
                 }
             }
             // parse a group declaration: g GroupName
-            Some("g") => match elements.next() {
+            Some(Token { text: "g", column }) => match elements.next() {
                 Some(name) => {
-                    groups.insert(name.to_string(), GroupShape::new());
-                    current_group = groups.get_mut(&name.to_string());
+                    groups.insert(name.text.to_string(), vec![]);
+                    current_group = Some(name.text.to_string());
                 }
                 None => {
                     return Err(ParseError::MalformedGroupDeclaration(format!(
-                        "Missing group name on line {}",
-                        index
+                        "Missing group name at line {}, column {}",
+                        line_number,
+                        column + 1
                     )));
                 }
             },
-            // as-yet unknown command
-            Some(_) => {}
+            // comment
+            Some(token) if token.text.starts_with('#') => {}
+            Some(token) => {
+                num_ignored_lines += 1;
+                let message = if UNSUPPORTED_STATEMENTS.contains(&token.text) {
+                    format!("Unsupported statement '{}' ignored", token.text)
+                } else {
+                    format!("Unknown statement '{}' ignored", token.text)
+                };
+                warnings.push(ParseWarning {
+                    line: line_number,
+                    column: token.column,
+                    message,
+                });
+            }
             // blank line
             None => {}
         };
-
-        num_ignored_lines += 1;
     }
     if !normalization_finished {
         normalize_vertices(&mut vertices);
+        vertex_texture_coords = vec![point!(0, 0, 0); vertices.len()];
     }
-    Ok(ObjParseResults {
+    let mut parsed = ParsedFaces {
         num_ignored_lines,
+        warnings,
         vertices,
         normals,
-        groups: Some(groups),
-    })
+        groups,
+    };
+    process_faces(&mut parsed, vertex_texture_coords, options);
+    Ok(parsed)
+}
+
+// A triangle of a face, as indices into the parsed vertices and normals
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IndexedTriangle {
+    pub(crate) vertices: [usize; 3],
+    pub(crate) normals: Option<[usize; 3]>,
+}
+
+// Welds, simplifies, subdivides, displaces and smooths the faces as the options ask. Simplified
+// and subdivided groups add vertices of their own, and computed normals are added to the normals.
+// texture_coords holds each vertex's texture coordinates as from parse_obj_faces.
+fn process_faces(parsed: &mut ParsedFaces, mut texture_coords: Vec<Tuple>, options: &ParseOptions) {
+    let ParsedFaces {
+        vertices,
+        normals,
+        groups,
+        ..
+    } = parsed;
+    if let Some(tolerance) = options.weld_tolerance {
+        let merged_into = weld_vertices(vertices, tolerance);
+        for (vertex, &into) in merged_into.iter().enumerate() {
+            if texture_coords[into].z == 0. {
+                texture_coords[into] = texture_coords[vertex];
+            }
+        }
+        for triangles in groups.values_mut() {
+            for triangle in triangles.iter_mut() {
+                triangle.vertices = triangle.vertices.map(|v| merged_into[v]);
+            }
+            triangles.retain(|t| {
+                let [a, b, c] = t.vertices;
+                a != b && b != c && a != c
+            });
+        }
+    }
+    let total: usize = groups.values().map(Vec::len).sum();
+    match options.max_triangles {
+        Some(max_triangles) if total > max_triangles => {
+            for triangles in groups.values_mut() {
+                let target = triangles.len() * max_triangles / total;
+                reshape_group(triangles, vertices, &mut texture_coords, |mesh, _| {
+                    (mesh.simplify(target), vec![])
+                });
+            }
+        }
+        _ => {}
+    }
+    if options.subdivisions > 0 {
+        for triangles in groups.values_mut() {
+            reshape_group(triangles, vertices, &mut texture_coords, |mesh, coords| {
+                mesh.subdivide_with_values(options.subdivisions, coords)
+            });
+        }
+    }
+    if let Some(displacement) = &options.displacement {
+        // blending coordinates with the missing ones of other vertices leaves z below 1
+        let offsets: Vec<f32> = texture_coords
+            .iter()
+            .map(|c| {
+                if (c.z - 1.).abs() < 1e-3 {
+                    displacement.height_at(c.x, c.y)
+                } else {
+                    0.
+                }
+            })
+            .collect();
+        let faces = groups.values().flatten().map(|t| t.vertices).collect();
+        let mut mesh = TriangleMesh::new(std::mem::take(vertices), faces);
+        mesh.displace(&offsets);
+        *vertices = mesh.vertices;
+        // normals from the file no longer fit
+        for triangle in groups.values_mut().flatten() {
+            triangle.normals = None;
+        }
+    }
+    let smoothing_crease_angle = match options.smoothing_crease_angle {
+        None if options.subdivisions > 0 || options.displacement.is_some() => Some(PI),
+        angle => angle,
+    };
+    if let Some(angle) = smoothing_crease_angle {
+        // normals are computed for all of the groups together, so that they are smooth across
+        // the groups' borders
+        let mut without_normals: Vec<&mut IndexedTriangle> = groups
+            .values_mut()
+            .flatten()
+            .filter(|t| t.normals.is_none())
+            .collect();
+        let faces: Vec<[usize; 3]> = without_normals.iter().map(|t| t.vertices).collect();
+        for (triangle, corner_normals) in without_normals
+            .iter_mut()
+            .zip(compute_vertex_normals(vertices, &faces, angle))
+        {
+            let first = normals.len();
+            normals.extend(corner_normals);
+            triangle.normals = Some([first, first + 1, first + 2]);
+        }
+    }
+}
+
+impl From<ParsedFaces> for ObjParseResults {
+    fn from(parsed: ParsedFaces) -> Self {
+        let (vertices, normals) = (&parsed.vertices, &parsed.normals);
+        let groups = parsed
+            .groups
+            .into_iter()
+            .map(|(name, triangles)| {
+                let mut group = GroupShape::new();
+                for triangle in triangles {
+                    let [p1, p2, p3] = triangle.vertices.map(|i| vertices[i]);
+                    let shape: Box<dyn Shape> = match triangle.normals {
+                        Some(indices) => {
+                            let [n1, n2, n3] = indices.map(|i| normals[i]);
+                            Box::new(SmoothTriangle::new(p1, p2, p3, n1, n2, n3))
+                        }
+                        None => Box::new(Triangle::new(p1, p2, p3)),
+                    };
+                    group.add_child(shape);
+                }
+                (name, group)
+            })
+            .collect();
+        ObjParseResults {
+            num_ignored_lines: parsed.num_ignored_lines,
+            vertices: parsed.vertices,
+            normals: parsed.normals,
+            groups: Some(groups),
+            warnings: parsed.warnings,
+        }
+    }
+}
+
+// Replaces the triangles with the faces of a mesh made from them, adding its vertices. reshape is
+// also given the texture coordinates of the vertices, and returns those of the new ones or nothing
+// if they have none.
+fn reshape_group<F: FnOnce(TriangleMesh, &[Tuple]) -> (TriangleMesh, Vec<Tuple>)>(
+    triangles: &mut Vec<IndexedTriangle>,
+    vertices: &mut Vec<Tuple>,
+    texture_coords: &mut Vec<Tuple>,
+    reshape: F,
+) {
+    let faces = triangles.iter().map(|t| t.vertices).collect();
+    let (reshaped, reshaped_coords) =
+        reshape(TriangleMesh::new(vertices.clone(), faces), texture_coords);
+    let offset = vertices.len();
+    vertices.extend(reshaped.vertices);
+    if reshaped_coords.is_empty() {
+        texture_coords.resize(vertices.len(), point!(0, 0, 0));
+    } else {
+        texture_coords.extend(reshaped_coords);
+    }
+    *triangles = reshaped
+        .faces
+        .into_iter()
+        .map(|face| IndexedTriangle {
+            vertices: face.map(|v| v + offset),
+            normals: None,
+        })
+        .collect();
+}
+
+// Whitespace-separated piece of a line, with its 1-based column
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (column, (byte_index, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((byte_index, column + 1)),
+            (true, Some((start_byte, start_column))) => {
+                tokens.push(Token {
+                    text: &line[start_byte..byte_index],
+                    column: start_column,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((start_byte, start_column)) = start {
+        tokens.push(Token {
+            text: &line[start_byte..],
+            column: start_column,
+        });
+    }
+    tokens
+}
+
+// Returns an error message suitable for the caller's error variant
+fn parse_coordinates<'a, I: Iterator<Item = Token<'a>>>(
+    tokens: I,
+    line_number: usize,
+) -> Result<Vec<f32>, String> {
+    tokens
+        .map(|token| {
+            token.text.parse::<f32>().map_err(|e| {
+                format!(
+                    "Invalid coordinate '{}' at line {}, column {}: {}",
+                    token.text, line_number, token.column, e
+                )
+            })
+        })
+        .collect()
 }
 
 struct FaceParseResults {
@@ -221,8 +625,21 @@ struct FaceParseResults {
     normal: Option<usize>,
 }
 
-fn parse_face(face_string: &str) -> Result<FaceParseResults, ParseError> {
-    let elements = face_string
+// num_vertices and num_normals include the dummy element at index 0
+fn parse_face(
+    token: Token,
+    line_number: usize,
+    num_vertices: usize,
+    num_normals: usize,
+) -> Result<FaceParseResults, ParseError> {
+    let malformed = |message: String| {
+        ParseError::MalformedFace(format!(
+            "{} at line {}, column {}",
+            message, line_number, token.column
+        ))
+    };
+    let elements = token
+        .text
         .split('/')
         .map(|x| {
             if x.is_empty() {
@@ -232,17 +649,35 @@ fn parse_face(face_string: &str) -> Result<FaceParseResults, ParseError> {
             }
         })
         .map(Option::transpose)
-        .collect::<Result<Vec<Option<usize>>, std::num::ParseIntError>>()?;
+        .collect::<Result<Vec<Option<usize>>, std::num::ParseIntError>>()
+        .map_err(|e| malformed(format!("Invalid index in '{}': {}", token.text, e)))?;
     match elements[0] {
-        Some(vertex) => Ok(FaceParseResults {
-            vertex,
-            // get() returns an Option<&Option<usize>> here, unfortunately
-            texture: elements.get(1).map(|x| *x).flatten(),
-            normal: elements.get(2).map(|x| *x).flatten(),
-        }),
-        None => Err(ParseError::MalformedFace(
-            "Missing vertex index".to_string(),
-        )),
+        Some(vertex) => {
+            if vertex == 0 || vertex >= num_vertices {
+                return Err(malformed(format!(
+                    "Vertex index {} out of range 1..={}",
+                    vertex,
+                    num_vertices - 1
+                )));
+            }
+            let texture = elements.get(1).copied().flatten();
+            let normal = elements.get(2).copied().flatten();
+            if let Some(normal) = normal {
+                if normal == 0 || normal >= num_normals {
+                    return Err(malformed(format!(
+                        "Normal index {} out of range 1..={}",
+                        normal,
+                        num_normals - 1
+                    )));
+                }
+            }
+            Ok(FaceParseResults {
+                vertex,
+                texture,
+                normal,
+            })
+        }
+        None => Err(malformed("Missing vertex index".to_string())),
     }
 }
 
@@ -263,39 +698,38 @@ fn normalize_vertices(vertices: &mut Vec<Tuple>) {
     }
 }
 
-// Assumptons: chosen_vertices describes a convex polygon (interior angles all < PI/2).
-fn fan_triangulation(
+// Cut the face into triangles; it may be concave, but should be planar and not cross itself
+fn triangulate_face(
     all_vertices: &[Tuple],
-    all_normals: &[Tuple],
     face_specs: &[FaceParseResults],
-) -> Vec<Box<dyn Shape>> {
+) -> Vec<IndexedTriangle> {
     debug_assert!(face_specs.len() > 2);
-    let mut triangles: Vec<Box<dyn Shape>> = vec![];
     let using_smooth_triangles = face_specs[0].normal.is_some();
+    let corners: Vec<Tuple> = face_specs
+        .iter()
+        .map(|spec| all_vertices[spec.vertex])
+        .collect();
 
-    // TODO: try replacing this with a fancy windowing function
-    for index in 1..face_specs.len() - 1 {
-        let v1 = all_vertices[face_specs[0].vertex];
-        let v2 = all_vertices[face_specs[index].vertex];
-        let v3 = all_vertices[face_specs[index + 1].vertex];
-
-        let tri: Box<dyn Shape> = if using_smooth_triangles {
-            let n1 = all_normals[face_specs[0].vertex];
-            let n2 = all_normals[face_specs[index].vertex];
-            let n3 = all_normals[face_specs[index + 1].vertex];
-            Box::new(SmoothTriangle::new(v1, v2, v3, n1, n2, n3))
-        } else {
-            Box::new(Triangle::new(v1, v2, v3))
-        };
-        triangles.push(tri);
-    }
-    triangles
+    triangulate(&corners)
+        .into_iter()
+        .map(|corner_indices| IndexedTriangle {
+            vertices: corner_indices.map(|i| face_specs[i].vertex),
+            normals: if using_smooth_triangles {
+                Some(corner_indices.map(|i| face_specs[i].vertex))
+            } else {
+                None
+            },
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color::Color;
+    use crate::ray::Ray;
     use crate::shape::smooth_triangle::SmoothTriangle;
+    use std::f32::consts::FRAC_1_SQRT_2;
     use std::fs::File;
     use std::path::PathBuf;
 
@@ -311,6 +745,95 @@ mod tests {
         assert_eq!(results.num_ignored_lines, 5);
     }
 
+    #[test]
+    fn unknown_and_unsupported_statements_produce_warnings() {
+        let text = "# a comment is not a warning
+v 1 2 3
+  usemtl shiny
+bogus statement";
+        let results = parse_obj(text.as_bytes()).unwrap();
+        assert_eq!(results.num_ignored_lines(), 2);
+        assert_eq!(
+            results.warnings(),
+            &[
+                ParseWarning {
+                    line: 3,
+                    column: 3,
+                    message: "Unsupported statement 'usemtl' ignored".to_string()
+                },
+                ParseWarning {
+                    line: 4,
+                    column: 1,
+                    message: "Unknown statement 'bogus' ignored".to_string()
+                }
+            ]
+        );
+        assert_eq!(
+            results.warnings()[0].to_string(),
+            "line 3, column 3: Unsupported statement 'usemtl' ignored"
+        );
+    }
+
+    #[test]
+    fn errors_report_one_based_line_and_column() {
+        let test_data = vec![
+            (
+                "invalid coordinate",
+                "v 1 2 3\nv 1 x 3",
+                "Invalid coordinate 'x' at line 2, column 5",
+            ),
+            ("too few coordinates", "\n\n  vn 1 2", "expected 3, found 2"),
+            (
+                "NaN vertex",
+                "v nan 0 0",
+                "Invalid vertex at line 1, column 1: x cannot be NaN",
+            ),
+            (
+                "NaN normal",
+                "vn 0 0 NaN",
+                "Invalid normal vector at line 1, column 1: z cannot be NaN",
+            ),
+            (
+                "NaN texture coordinates",
+                "vt 0 nan",
+                "Invalid texture coordinates at line 1, column 1: y cannot be NaN",
+            ),
+            (
+                "vertex index out of range",
+                "v 1 2 3\nv 1 2 4\nv 1 3 3\nf 1 2   7",
+                "Vertex index 7 out of range 1..=3 at line 4, column 9",
+            ),
+            (
+                "too many texture coordinates",
+                "vt 0.5 0.5 0 1",
+                "expected 1 to 3, found 4",
+            ),
+            (
+                "missing group name",
+                "g",
+                "Missing group name at line 1, column 2",
+            ),
+        ];
+        for (name, text, expected_message) in test_data {
+            let message = match parse_obj(text.as_bytes()) {
+                Err(e) => e.to_string(),
+                Ok(_) => panic!("Case {} should fail", name),
+            };
+            assert!(
+                message.contains(expected_message),
+                "Case {}: {}",
+                name,
+                message
+            );
+        }
+        match parse_obj("\n\n  vn 1 2".as_bytes()) {
+            Err(ParseError::MalformedNormal(message)) => {
+                assert!(message.contains("at line 3, column 3"), "{}", message)
+            }
+            _ => panic!("Expected MalformedNormal"),
+        }
+    }
+
     #[test]
     fn vertex_records() {
         let text = "v -1 1 0
@@ -399,6 +922,35 @@ mod tests {
         assert_eq!(t3.p3, results.vertices[5]);
     }
 
+    #[test]
+    fn triangulating_concave_polygons() {
+        // an L; the corner at (0, 0) is the concave one after normalization
+        let text = "
+        v -1 -1 0
+        v 1 -1 0
+        v 1 0 0
+        v 0 0 0
+        v 0 1 0
+        v -1 1 0
+
+        f 1 2 3 4 5 6
+        ";
+
+        let results = parse_obj(text.as_bytes()).unwrap();
+        let g_children = results.get_default_group().unwrap().get_children();
+        assert_eq!(g_children.len(), 4);
+        let through_notch = Ray::new(point!(0.5, 0.5, -1), vector!(0, 0, 1));
+        let through_arm = Ray::new(point!(0.5, -0.5, -1), vector!(0, 0, 1));
+        let hits = |r: Ray| {
+            g_children
+                .iter()
+                .filter(|t| !t.local_intersect(r).is_empty())
+                .count()
+        };
+        assert_eq!(hits(through_notch), 0);
+        assert_eq!(hits(through_arm), 1);
+    }
+
     fn parse_obj_test_file(file_name: &str) -> ObjParseResults {
         let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "resources/test", file_name]
             .iter()
@@ -532,4 +1084,192 @@ mod tests {
             assert_eq!(triangle.n3, results.normals[3], "{}", name);
         }
     }
+
+    #[test]
+    fn smoothing_faces_without_normals() {
+        let text = "
+            v -1 0 0
+            v 0 1 0
+            v 0 1 1
+            v 1 0 0
+
+            f 1 3 2
+            g Right
+            f 2 3 4";
+        let options = ParseOptions {
+            smoothing_crease_angle: Some(PI),
+            ..ParseOptions::default()
+        };
+        let results = parse_obj_with_options(text.as_bytes(), &options).unwrap();
+        let left = results.get_default_group().unwrap().get_children()[0]
+            .downcast_ref::<SmoothTriangle>()
+            .unwrap();
+        let right = results.get_group("Right").unwrap().get_children()[0]
+            .downcast_ref::<SmoothTriangle>()
+            .unwrap();
+        // the ridge is smooth even though the faces are in different groups
+        assert_abs_diff_eq!(left.n1, vector!(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+        assert_abs_diff_eq!(left.n3, vector!(0, 1, 0));
+        assert_abs_diff_eq!(right.n1, vector!(0, 1, 0));
+        assert_abs_diff_eq!(right.n3, vector!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+
+        let results = parse_obj(text.as_bytes()).unwrap();
+        let flat = &results.get_group("Right").unwrap().get_children()[0];
+        assert!(flat.downcast_ref::<Triangle>().is_some());
+    }
+
+    #[test]
+    fn welding_duplicated_vertices() {
+        // each face has its own copies of the ridge's vertices, plus a sliver left over from a
+        // sloppy export
+        let text = "
+            v -1 0 0
+            v 0 1 0
+            v 0 1 1
+            v 0 1 0
+            v 0 1 1
+            v 1 0 0
+            v 0 1.0001 1
+
+            f 1 3 2
+            f 4 5 6
+            f 3 5 7";
+        let smoothed = |weld_tolerance| {
+            let options = ParseOptions {
+                smoothing_crease_angle: Some(PI),
+                weld_tolerance,
+                ..ParseOptions::default()
+            };
+            let results = parse_obj_with_options(text.as_bytes(), &options).unwrap();
+            let children = results.get_default_group().unwrap().get_children();
+            let triangles: Vec<SmoothTriangle> = children
+                .iter()
+                .map(|c| c.downcast_ref::<SmoothTriangle>().unwrap().clone())
+                .collect();
+            triangles
+        };
+        let unwelded = smoothed(None);
+        assert_eq!(unwelded.len(), 3);
+        assert_abs_diff_eq!(unwelded[0].n3, vector!(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+
+        let welded = smoothed(Some(0.001));
+        assert_eq!(welded.len(), 2);
+        assert_abs_diff_eq!(welded[0].n3, vector!(0, 1, 0));
+        assert_abs_diff_eq!(welded[1].n1, vector!(0, 1, 0));
+    }
+
+    #[test]
+    fn simplifying_while_parsing() {
+        // a flat 3x3 grid of squares, each cut into two triangles
+        let mut text = String::new();
+        for x in 0..4 {
+            for z in 0..4 {
+                text.push_str(&format!("v {} 0 {}\n", x, z));
+            }
+        }
+        for x in 0..3 {
+            for z in 0..3 {
+                let corner = x * 4 + z + 1;
+                text.push_str(&format!(
+                    "f {} {} {} {}\n",
+                    corner,
+                    corner + 1,
+                    corner + 5,
+                    corner + 4
+                ));
+            }
+        }
+        let options = ParseOptions {
+            max_triangles: Some(6),
+            ..ParseOptions::default()
+        };
+        let full = parse_obj(text.as_bytes()).unwrap();
+        assert_eq!(full.get_default_group().unwrap().get_children().len(), 18);
+        let simplified = parse_obj_with_options(text.as_bytes(), &options).unwrap();
+        let children = simplified.get_default_group().unwrap().get_children();
+        assert!(
+            !children.is_empty() && children.len() <= 6,
+            "{}",
+            children.len()
+        );
+        // the grid still covers the same square
+        let bounds = simplified.get_default_group().unwrap().bounding_box();
+        assert_eq!(bounds.min, point!(-1, 0, -1));
+        assert_eq!(bounds.max, point!(1, 0, 1));
+    }
+
+    #[test]
+    fn subdividing_while_parsing() {
+        let text = "
+            v 1 1 1
+            v 1 -1 -1
+            v -1 1 -1
+            v -1 -1 1
+            f 1 2 3
+            f 1 4 2
+            f 1 3 4
+            f 2 4 3";
+        let options = ParseOptions {
+            weld_tolerance: Some(0.),
+            subdivisions: 2,
+            ..ParseOptions::default()
+        };
+        let results = parse_obj_with_options(text.as_bytes(), &options).unwrap();
+        let children = results.get_default_group().unwrap().get_children();
+        assert_eq!(children.len(), 64);
+        // smoothly shaded, with normals pointing outwards
+        for child in children {
+            let triangle = child.downcast_ref::<SmoothTriangle>().unwrap();
+            assert!(triangle.n1.dot(triangle.base.p1 - point!(0, 0, 0)) > 0.);
+        }
+    }
+
+    // gray as bright as u
+    #[derive(Debug, Clone)]
+    struct Ramp;
+
+    impl UVPattern for Ramp {
+        fn color_at(&self, u: f32, _v: f32) -> Color {
+            color!(u, u, u)
+        }
+    }
+
+    #[test]
+    fn displacing_vertices_from_texture() {
+        // a square facing +y, with u going from 0 to 1 along x
+        let text = "
+            v -1 0 -1
+            v 1 0 -1
+            v 1 0 1
+            v -1 0 1
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            f 1/1 3/3 2/2
+            f 1/1 4/4 3/3";
+        let options = ParseOptions {
+            subdivisions: 2,
+            displacement: Some(Displacement::new(Arc::new(Ramp), 0., 0.1)),
+            ..ParseOptions::default()
+        };
+        let results = parse_obj_with_options(text.as_bytes(), &options).unwrap();
+        let children = results.get_default_group().unwrap().get_children();
+        assert_eq!(children.len(), 32);
+        for child in children {
+            let triangle = child.downcast_ref::<SmoothTriangle>().unwrap();
+            // subdivision moves u along with x, so the height still follows x
+            for p in [triangle.base.p1, triangle.base.p2, triangle.base.p3] {
+                assert_abs_diff_eq!(p.y, 0.05 * (p.x + 1.), epsilon = 1e-5);
+            }
+        }
+        // and without texture coordinates, nothing moves
+        let without_coordinates = text.replace("/1", "").replace("/2", "").replace("/3", "");
+        let without_coordinates = without_coordinates.replace("/4", "");
+        let results = parse_obj_with_options(without_coordinates.as_bytes(), &options).unwrap();
+        for child in results.get_default_group().unwrap().get_children() {
+            let triangle = child.downcast_ref::<SmoothTriangle>().unwrap();
+            assert_eq!(triangle.base.p1.y, 0.);
+        }
+    }
 }
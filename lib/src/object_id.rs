@@ -34,3 +34,38 @@ impl ObjectId {
         self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::shape::Shape;
+    use crate::shape::sphere::Sphere;
+    use std::collections::HashSet;
+    use std::thread;
+
+    #[test]
+    fn ids_are_unique_across_threads() {
+        let ids: Vec<usize> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut ids = vec![];
+                        for _ in 0..1000 {
+                            let id = ObjectId::default();
+                            ids.push(id.clone().get_id());
+                            ids.push(id.get_id());
+                            ids.push(Sphere::new().get_unique_id());
+                        }
+                        ids
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        assert_eq!(ids.len(), 24000);
+        assert_eq!(ids.into_iter().collect::<HashSet<_>>().len(), 24000);
+    }
+}
@@ -0,0 +1,193 @@
+// Patterns for combining other patterns by their alpha (coverage): decals, worn paint and so on
+use crate::color::Color;
+use crate::pattern::pattern::{component_point, BasePattern, Pattern};
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// Draws top over bottom; bottom shows through wherever top is not opaque
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    base: BasePattern,
+    top: Box<dyn Pattern>,
+    bottom: Box<dyn Pattern>,
+}
+
+impl Overlay {
+    pub fn new(top: Box<dyn Pattern>, bottom: Box<dyn Pattern>) -> Overlay {
+        Overlay {
+            base: BasePattern::new(),
+            top,
+            bottom,
+        }
+    }
+
+    fn alphas(&self, pattern_point: Tuple) -> (f32, f32) {
+        (
+            self.top
+                .alpha_at_world(component_point(self.top.as_ref(), pattern_point)),
+            self.bottom
+                .alpha_at_world(component_point(self.bottom.as_ref(), pattern_point)),
+        )
+    }
+}
+
+impl Pattern for Overlay {
+    fn get_base(&self) -> &BasePattern {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BasePattern {
+        &mut self.base
+    }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        self.top.hash_structure(hasher);
+        self.bottom.hash_structure(hasher);
+    }
+    fn color_at_world(&self, pattern_point: Tuple) -> Color {
+        let (top_alpha, bottom_alpha) = self.alphas(pattern_point);
+        let top = self
+            .top
+            .color_at_world(component_point(self.top.as_ref(), pattern_point));
+        if top_alpha >= 1. {
+            return top;
+        }
+        let bottom = self
+            .bottom
+            .color_at_world(component_point(self.bottom.as_ref(), pattern_point));
+        let alpha = top_alpha + bottom_alpha * (1. - top_alpha);
+        if alpha <= 0. {
+            return bottom;
+        }
+        // colors are not premultiplied by alpha, so weight each by its coverage
+        (top * top_alpha + bottom * (bottom_alpha * (1. - top_alpha))) / alpha
+    }
+    fn alpha_at_world(&self, pattern_point: Tuple) -> f32 {
+        let (top_alpha, bottom_alpha) = self.alphas(pattern_point);
+        top_alpha + bottom_alpha * (1. - top_alpha)
+    }
+}
+
+// pattern with its alpha taken from the brightness (average of the channels) of mask; for example,
+// noise as a mask gives worn paint
+#[derive(Clone, Debug)]
+pub struct AlphaMask {
+    base: BasePattern,
+    pattern: Box<dyn Pattern>,
+    mask: Box<dyn Pattern>,
+}
+
+impl AlphaMask {
+    pub fn new(pattern: Box<dyn Pattern>, mask: Box<dyn Pattern>) -> AlphaMask {
+        AlphaMask {
+            base: BasePattern::new(),
+            pattern,
+            mask,
+        }
+    }
+}
+
+impl Pattern for AlphaMask {
+    fn get_base(&self) -> &BasePattern {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BasePattern {
+        &mut self.base
+    }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        self.pattern.hash_structure(hasher);
+        self.mask.hash_structure(hasher);
+    }
+    fn color_at_world(&self, pattern_point: Tuple) -> Color {
+        self.pattern
+            .color_at_world(component_point(self.pattern.as_ref(), pattern_point))
+    }
+    fn alpha_at_world(&self, pattern_point: Tuple) -> f32 {
+        let mask_point = component_point(self.mask.as_ref(), pattern_point);
+        let c = self.mask.color_at_world(mask_point);
+        let brightness = ((c.r + c.g + c.b) / 3.).clamp(0., 1.);
+        brightness
+            * self
+                .pattern
+                .alpha_at_world(component_point(self.pattern.as_ref(), pattern_point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{black, white};
+    use crate::pattern::stripes::Stripes;
+    use crate::shape::sphere::Sphere;
+    use crate::transformations::scaling;
+
+    fn solid(c: Color) -> Box<dyn Pattern> {
+        Box::new(Stripes::new(c, c))
+    }
+
+    // white where x is in [0, 1) mod 2, black elsewhere
+    fn stripe_mask() -> Box<dyn Pattern> {
+        Box::new(Stripes::new(white(), black()))
+    }
+
+    #[test]
+    fn opaque_patterns_cover_underlying_color() {
+        let pattern = Stripes::new(white(), black());
+        let shape = Sphere::new();
+        assert_eq!(
+            pattern.color_over(point!(0.5, 0, 0), &shape, color!(1, 0, 0)),
+            white()
+        );
+    }
+
+    #[test]
+    fn masked_pattern_shows_underlying_color_where_mask_is_dark() {
+        let pattern = AlphaMask::new(solid(color!(0, 0, 1)), stripe_mask());
+        let shape = Sphere::new();
+        let underneath = color!(1, 0, 0);
+        let test_data = vec![
+            ("covered", point!(0.5, 0, 0), color!(0, 0, 1)),
+            ("uncovered", point!(1.5, 0, 0), underneath),
+        ];
+        for (name, p, expected) in test_data {
+            assert_eq!(
+                pattern.color_over(p, &shape, underneath),
+                expected,
+                "Case {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn partial_alpha_blends_colors() {
+        let mut mask = Stripes::new(color!(0.25, 0.25, 0.25), black());
+        mask.set_transformation(scaling(10., 10., 10.));
+        let pattern = AlphaMask::new(solid(white()), Box::new(mask));
+        let shape = Sphere::new();
+        assert_abs_diff_eq!(
+            pattern.color_over(point!(0.5, 0, 0), &shape, black()),
+            color!(0.25, 0.25, 0.25)
+        );
+    }
+
+    #[test]
+    fn overlay_draws_top_over_bottom() {
+        let decal = AlphaMask::new(solid(color!(0, 1, 0)), stripe_mask());
+        let pattern = Overlay::new(Box::new(decal), solid(color!(1, 0, 0)));
+        assert_eq!(pattern.color_at_world(point!(0.5, 0, 0)), color!(0, 1, 0));
+        assert_eq!(pattern.color_at_world(point!(1.5, 0, 0)), color!(1, 0, 0));
+        assert_eq!(pattern.alpha_at_world(point!(1.5, 0, 0)), 1.);
+    }
+
+    #[test]
+    fn overlay_of_transparent_patterns_is_transparent() {
+        let top = AlphaMask::new(solid(color!(0, 1, 0)), solid(black()));
+        let bottom = AlphaMask::new(solid(color!(1, 0, 0)), solid(black()));
+        let pattern = Overlay::new(Box::new(top), Box::new(bottom));
+        let shape = Sphere::new();
+        assert_eq!(pattern.alpha_at_world(point!(0, 0, 0)), 0.);
+        assert_eq!(
+            pattern.color_over(point!(0, 0, 0), &shape, color!(0, 0, 1)),
+            color!(0, 0, 1)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use crate::color::Color;
+use crate::constants::gray;
+use crate::fingerprint::{hash_color, hash_f32};
+use crate::pattern::pattern::BasePattern;
+use crate::pattern::pattern::Pattern;
+use crate::pattern::uv::UVPattern;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+fn default_brick_color() -> Color {
+    color!(0.6, 0.2, 0.1)
+}
+
+// Whether coordinate falls in the mortar between bricks of the given size along one axis. The
+// mortar is centered on the seams, so half of it lies on either side of each brick.
+fn is_mortar(coordinate: f32, brick_size: f32, mortar_width: f32) -> bool {
+    let offset = coordinate.rem_euclid(brick_size);
+    let half_mortar = mortar_width / 2.;
+    offset < half_mortar || offset >= brick_size - half_mortar
+}
+
+// Bricks laid in rows along y, running along x and z. Every other row is shifted along x and z by
+// row_offset (a fraction of a brick), giving the usual running bond.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Brick {
+    base: BasePattern,
+    pub brick: Color,
+    pub mortar: Color,
+    pub width: f32,
+    pub height: f32,
+    pub depth: f32,
+    pub mortar_width: f32,
+    pub row_offset: f32,
+}
+
+impl Brick {
+    pub fn new(brick: Color, mortar: Color) -> Brick {
+        Brick {
+            base: BasePattern::new(),
+            brick,
+            mortar,
+            width: 1.,
+            height: 0.5,
+            depth: 1.,
+            mortar_width: 0.05,
+            row_offset: 0.5,
+        }
+    }
+}
+
+impl Default for Brick {
+    fn default() -> Self {
+        Self::new(default_brick_color(), gray())
+    }
+}
+
+impl Pattern for Brick {
+    fn get_base(&self) -> &BasePattern {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BasePattern {
+        &mut self.base
+    }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.brick);
+        hash_color(hasher, &self.mortar);
+        for &value in &[
+            self.width,
+            self.height,
+            self.depth,
+            self.mortar_width,
+            self.row_offset,
+        ] {
+            hash_f32(hasher, value);
+        }
+    }
+    fn color_at_world(&self, world_point: Tuple) -> Color {
+        let row = (world_point.y / self.height).floor();
+        let shift = row * self.row_offset;
+        if is_mortar(world_point.y, self.height, self.mortar_width)
+            || is_mortar(
+                world_point.x + shift * self.width,
+                self.width,
+                self.mortar_width,
+            )
+            || is_mortar(
+                world_point.z + shift * self.depth,
+                self.depth,
+                self.mortar_width,
+            )
+        {
+            self.mortar
+        } else {
+            self.brick
+        }
+    }
+}
+
+// Bricks in UV space: columns bricks across u and rows bricks up v. mortar_width is in UV units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UVBrick {
+    pub brick: Color,
+    pub mortar: Color,
+    pub columns: f32,
+    pub rows: f32,
+    pub mortar_width: f32,
+    pub row_offset: f32,
+}
+
+impl UVBrick {
+    pub fn new(columns: f32, rows: f32, brick: Color, mortar: Color) -> Self {
+        UVBrick {
+            brick,
+            mortar,
+            columns,
+            rows,
+            mortar_width: 0.01,
+            row_offset: 0.5,
+        }
+    }
+}
+
+impl Default for UVBrick {
+    fn default() -> Self {
+        Self::new(4., 8., default_brick_color(), gray())
+    }
+}
+
+impl UVPattern for UVBrick {
+    fn color_at(&self, u: f32, v: f32) -> Color {
+        let (brick_width, brick_height) = (1. / self.columns, 1. / self.rows);
+        let row = (v * self.rows).floor();
+        let u = u + row * self.row_offset * brick_width;
+        if is_mortar(v, brick_height, self.mortar_width)
+            || is_mortar(u, brick_width, self.mortar_width)
+        {
+            self.mortar
+        } else {
+            self.brick
+        }
+    }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.brick);
+        hash_color(hasher, &self.mortar);
+        for &value in &[self.columns, self.rows, self.mortar_width, self.row_offset] {
+            hash_f32(hasher, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{black, white};
+
+    #[test]
+    fn brick_has_mortar_along_seams() {
+        let pattern = Brick::new(white(), black());
+        let test_data = vec![
+            ("brick", point!(0.5, 0.25, 0.5), white()),
+            ("bed joint", point!(0.5, 0.51, 0.5), black()),
+            ("head joint", point!(0.99, 0.25, 0.5), black()),
+            ("joint in z", point!(0.5, 0.25, 0.01), black()),
+            ("negative coordinates", point!(-0.25, -0.25, -0.25), white()),
+        ];
+        for (name, p, expected) in test_data {
+            assert_eq!(pattern.color_at_world(p), expected, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn brick_rows_are_offset() {
+        let mut pattern = Brick::new(white(), black());
+        // a head joint in the first row is the middle of a brick in the second
+        assert_eq!(pattern.color_at_world(point!(1, 0.25, 0.25)), black());
+        assert_eq!(pattern.color_at_world(point!(1, 0.75, 0.25)), white());
+
+        pattern.row_offset = 0.;
+        assert_eq!(pattern.color_at_world(point!(1, 0.75, 0.25)), black());
+    }
+
+    #[test]
+    fn uv_brick() {
+        let pattern = UVBrick::new(2., 2., white(), black());
+        let test_data = vec![
+            ("brick", 0.25, 0.25, white()),
+            ("bed joint", 0.25, 0.5, black()),
+            ("head joint", 0.5, 0.25, black()),
+            ("offset row", 0.5, 0.75, white()),
+            ("edge", 0., 0.25, black()),
+        ];
+        for (name, u, v, expected) in test_data {
+            assert_eq!(pattern.color_at(u, v), expected, "Case {}", name);
+        }
+    }
+}
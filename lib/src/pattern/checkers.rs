@@ -1,15 +1,24 @@
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::white;
+use crate::fingerprint::{hash_color, hash_f32};
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// Coordinates this close to a whole number are treated as that number. Surfaces lying exactly on a
+// cell boundary (such as a floor at y = 0 or y = 1) are computed with slight errors on either side
+// of it, which would otherwise give a noisy mix of both colors.
+pub const DEFAULT_SNAP_EPSILON: f32 = 1e-4;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Checkers {
     base: BasePattern,
-    a: Color,
-    b: Color,
+    pub a: Color,
+    pub b: Color,
+    // 0 disables snapping
+    pub snap_epsilon: f32,
 }
 
 impl Checkers {
@@ -18,6 +27,16 @@ impl Checkers {
             base: BasePattern::new(),
             a,
             b,
+            snap_epsilon: DEFAULT_SNAP_EPSILON,
+        }
+    }
+
+    fn snap(&self, value: f32) -> f32 {
+        let rounded = value.round();
+        if (value - rounded).abs() < self.snap_epsilon {
+            rounded
+        } else {
+            value
         }
     }
 }
@@ -35,10 +54,19 @@ impl Pattern for Checkers {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+        hash_f32(hasher, self.snap_epsilon);
+    }
     fn color_at_world(&self, world_point: Tuple) -> Color {
+        let (x, y, z) = (
+            self.snap(world_point.x),
+            self.snap(world_point.y),
+            self.snap(world_point.z),
+        );
         // TODO: is any kind of overflow possible here?
-        if (world_point.x.abs() + world_point.y.abs() + world_point.z.abs()).floor() as i32 % 2 == 0
-        {
+        if (x.abs() + y.abs() + z.abs()).floor() as i32 % 2 == 0 {
             self.a
         } else {
             self.b
@@ -73,4 +101,21 @@ mod tests {
         assert_eq!(pattern.color_at_world(point!(0, 0, 0.99)), white());
         assert_eq!(pattern.color_at_world(point!(0, 0, 1.01)), black());
     }
+
+    #[test]
+    fn checkers_snap_coordinates_near_cell_boundaries() {
+        let mut pattern = Checkers::default();
+        // a floor at y = 1, computed slightly above or below it
+        let test_data = vec![
+            ("below", point!(0, 0.99999, 0), black()),
+            ("on", point!(0, 1, 0), black()),
+            ("above", point!(0, 1.00001, 0), black()),
+        ];
+        for (name, p, expected) in &test_data {
+            assert_eq!(pattern.color_at_world(*p), *expected, "Case {}", name);
+        }
+
+        pattern.snap_epsilon = 0.;
+        assert_eq!(pattern.color_at_world(point!(0, 0.99999, 0)), white());
+    }
 }
@@ -1,26 +1,47 @@
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::white;
+use crate::fingerprint::{hash_color, hash_tuple};
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
+use crate::pattern::stripes::unit_direction;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Gradient {
     base: BasePattern,
     a: Color,
+    b: Color,
     distance: Color,
+    // unit vector; the colors are blended along it
+    direction: Tuple,
 }
 
 impl Gradient {
     pub fn new(a: Color, b: Color) -> Gradient {
+        Self::along(a, b, vector!(1, 0, 0))
+    }
+
+    // Gradient repeating along direction (which need not be normalized) instead of the x axis
+    pub fn along(a: Color, b: Color, direction: Tuple) -> Gradient {
         let distance = b - a;
         Gradient {
             base: BasePattern::new(),
             a,
+            b,
             distance,
+            direction: unit_direction(direction),
         }
     }
+
+    pub fn direction(&self) -> Tuple {
+        self.direction
+    }
+
+    pub fn colors(&self) -> (Color, Color) {
+        (self.a, self.b)
+    }
 }
 
 impl Pattern for Gradient {
@@ -30,8 +51,14 @@ impl Pattern for Gradient {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+        hash_tuple(hasher, &self.direction);
+    }
     fn color_at_world(&self, world_point: Tuple) -> Color {
-        let fraction = world_point.x - world_point.x.floor();
+        let position = world_point.dot(self.direction);
+        let fraction = position - position.floor();
         self.a + (self.distance * fraction)
     }
 }
@@ -63,4 +90,19 @@ mod tests {
             color!(0.25, 0.25, 0.25)
         );
     }
+
+    #[test]
+    fn gradient_along_direction() {
+        let pattern = Gradient::along(white(), black(), vector!(0, -3, 0));
+        assert_eq!(pattern.direction(), vector!(0, -1, 0));
+        assert_eq!(pattern.color_at_world(point!(7, 0, 7)), white());
+        assert_eq!(
+            pattern.color_at_world(point!(0, -0.25, 0)),
+            color!(0.75, 0.75, 0.75)
+        );
+        assert_eq!(
+            pattern.color_at_world(point!(0, 0.25, 0)),
+            color!(0.25, 0.25, 0.25)
+        );
+    }
 }
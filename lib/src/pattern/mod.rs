@@ -1,7 +1,11 @@
+pub mod alpha;
+pub mod brick;
 pub mod checkers;
 pub mod gradient;
 pub mod pattern;
 pub mod rings;
+pub mod scalar;
 pub mod sine_2d;
 pub mod stripes;
 pub mod uv;
+pub mod worley;
@@ -1,15 +1,31 @@
 use crate::color::Color;
-use crate::matrix::Matrix;
+use crate::fingerprint::hash_matrix;
+use crate::matrix::{Matrix, SingularMatrixError};
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
+use downcast_rs::DowncastSync;
 use dyn_clone::DynClone;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 
-pub trait Pattern: Debug + DynClone {
+pub trait Pattern: Debug + DynClone + DowncastSync {
     // tthe BasePattern that the wrapping instance is delegating to
     fn get_base(&self) -> &BasePattern;
     fn get_base_mut(&mut self) -> &mut BasePattern;
     fn color_at_world(&self, object_point: Tuple) -> Color;
+    // How much of the surface the pattern covers at the point, from 0 (transparent) to 1 (opaque).
+    // Whatever is underneath shows through the uncovered part; see color_over.
+    fn alpha_at_world(&self, _object_point: Tuple) -> f32 {
+        1.
+    }
+    // The color averaged over an area about footprint wide around the point, to avoid aliasing
+    // when a detailed pattern is seen from far away. Only textures filter; others ignore footprint.
+    fn color_at_world_filtered(&self, object_point: Tuple, _footprint: f32) -> Color {
+        self.color_at_world(object_point)
+    }
+    // Feed the pattern's own parameters (e.g. its colors or component patterns, but not its
+    // transformation) into hasher. Patterns with such parameters must override this.
+    fn hash_parameters(&self, _hasher: &mut dyn Hasher) {}
 
     // don't override these
     fn color_at_object(&self, world_point: Tuple, object: &dyn Shape) -> Color {
@@ -17,19 +33,82 @@ pub trait Pattern: Debug + DynClone {
         let pattern_point = self.transformation_inverse() * &object_point;
         self.color_at_world(pattern_point)
     }
+    // color_at_object blended over underneath according to the pattern's alpha
+    fn color_over(&self, world_point: Tuple, object: &dyn Shape, underneath: Color) -> Color {
+        self.color_over_filtered(world_point, object, underneath, 0.)
+    }
+    // color_over, filtered over footprint (a width in world space; see color_at_world_filtered)
+    fn color_over_filtered(
+        &self,
+        world_point: Tuple,
+        object: &dyn Shape,
+        underneath: Color,
+        footprint: f32,
+    ) -> Color {
+        let object_point = object.transformation_inverse() * world_point;
+        let pattern_point = self.transformation_inverse() * object_point;
+        let alpha = self.alpha_at_world(pattern_point);
+        if alpha <= 0. {
+            return underneath;
+        }
+        let color = if footprint > 0. {
+            let to_pattern = self.transformation_inverse() * object.transformation_inverse();
+            self.color_at_world_filtered(pattern_point, footprint * average_scale(&to_pattern))
+        } else {
+            self.color_at_world(pattern_point)
+        };
+        if alpha >= 1. {
+            color
+        } else {
+            color * alpha + underneath * (1. - alpha)
+        }
+    }
     fn set_transformation(&mut self, t: Matrix) {
         self.get_base_mut().set_transformation(t)
     }
+    // Like set_transformation, but returns an error instead of panicking if t is not invertible
+    fn try_set_transformation(&mut self, t: Matrix) -> Result<(), SingularMatrixError> {
+        if t.try_inverse().is_none() {
+            return Err(SingularMatrixError { matrix: t });
+        }
+        self.set_transformation(t);
+        Ok(())
+    }
+    fn transformation(&self) -> &Matrix {
+        self.get_base().transformation()
+    }
     fn transformation_inverse(&self) -> &Matrix {
         self.get_base().transformation_inverse()
     }
+    // Feed everything that determines how the pattern looks into hasher: its type, parameters and
+    // transformation (see Shape::hash_structure)
+    fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        self.as_any().type_id().hash(&mut &mut *hasher);
+        self.hash_parameters(hasher);
+        hash_matrix(hasher, self.transformation());
+    }
 }
 
+impl_downcast!(sync Pattern);
 dyn_clone::clone_trait_object!(Pattern);
 
+// Converts a point in a combining pattern's space into the space of one of its component patterns
+pub fn component_point(component: &dyn Pattern, pattern_point: Tuple) -> Tuple {
+    component.transformation_inverse() * pattern_point
+}
+
+// roughly how much t stretches lengths, for carrying a footprint from one space into another
+pub(crate) fn average_scale(t: &Matrix) -> f32 {
+    ((t * vector!(1, 0, 0)).magnitude()
+        + (t * vector!(0, 1, 0)).magnitude()
+        + (t * vector!(0, 0, 1)).magnitude())
+        / 3.
+}
+
 // Other pattern implementations should delegate to this one where these defaults are acceptable.
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct BasePattern {
+    t: Matrix,
     t_inverse: Matrix,
 }
 
@@ -50,7 +129,15 @@ impl Pattern for BasePattern {
     }
 
     fn set_transformation(&mut self, t: Matrix) {
-        self.t_inverse = t.inverse();
+        self.t_inverse = match t.try_inverse() {
+            Some(inverse) => inverse,
+            None => panic!("{}", SingularMatrixError { matrix: t }),
+        };
+        self.t = t;
+    }
+
+    fn transformation(&self) -> &Matrix {
+        &self.t
     }
 
     fn transformation_inverse(&self) -> &Matrix {
@@ -90,11 +177,29 @@ impl Pattern for TestPattern {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::canvas::Canvas;
+    use crate::constants::{black, red, white};
+    use crate::fingerprint::StructureRecorder;
     use crate::material::Material;
+    use crate::pattern::alpha::Overlay;
+    use crate::pattern::checkers::Checkers;
+    use crate::pattern::stripes::Stripes;
+    use crate::pattern::uv::{PlanarMap, TextureMap, UVImage};
     use crate::shape::sphere::Sphere;
     use crate::transformations::scaling;
     use crate::transformations::translation;
 
+    #[test]
+    fn degenerate_pattern_transformation_is_rejected() {
+        let mut test_pattern = TestPattern::new();
+        assert!(test_pattern
+            .try_set_transformation(scaling(0.0, 1.0, 1.0))
+            .is_err());
+        assert!(test_pattern
+            .try_set_transformation(scaling(2.0, 1.0, 1.0))
+            .is_ok());
+    }
+
     #[test]
     fn pattern_with_object_transformation() {
         let object = Sphere::build(scaling(2.0, 2.0, 2.0), Material::default());
@@ -120,4 +225,76 @@ mod tests {
         let c = test_pattern.color_at_object(point!(2.5, 3, 3.5), &object);
         assert_eq!(c, color!(0.75, 0.5, 0.25));
     }
+
+    fn structure(pattern: &dyn Pattern) -> Vec<u8> {
+        let mut recorder = StructureRecorder::default();
+        pattern.hash_structure(&mut recorder);
+        recorder.bytes
+    }
+
+    fn image(pixel: Color) -> Box<dyn Pattern> {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.write_pixel(1, 1, pixel);
+        Box::new(TextureMap::new(
+            Box::new(UVImage::new(canvas)),
+            Box::new(PlanarMap),
+        ))
+    }
+
+    #[test]
+    fn patterns_are_hashed_by_structure() {
+        let mut scaled = Stripes::new(white(), black());
+        scaled.set_transformation(scaling(2., 2., 2.));
+        let overlay = |top: Color| -> Box<dyn Pattern> {
+            Box::new(Overlay::new(
+                Box::new(Stripes::new(top, black())),
+                Box::new(Checkers::new(white(), black())),
+            ))
+        };
+        type Case = (&'static str, Box<dyn Pattern>, Box<dyn Pattern>, bool);
+        let test_data: Vec<Case> = vec![
+            (
+                "identical patterns",
+                Box::new(Stripes::new(white(), black())),
+                Box::new(Stripes::new(white(), black())),
+                true,
+            ),
+            (
+                "different colors",
+                Box::new(Stripes::new(white(), black())),
+                Box::new(Stripes::new(red(), black())),
+                false,
+            ),
+            (
+                "different transformations",
+                Box::new(Stripes::new(white(), black())),
+                Box::new(scaled),
+                false,
+            ),
+            (
+                "different pattern types",
+                Box::new(Stripes::new(white(), black())),
+                Box::new(Checkers::new(white(), black())),
+                false,
+            ),
+            (
+                "identical components",
+                overlay(white()),
+                overlay(white()),
+                true,
+            ),
+            (
+                "different components",
+                overlay(white()),
+                overlay(red()),
+                false,
+            ),
+            ("identical images", image(white()), image(white()), true),
+            ("different images", image(white()), image(red()), false),
+        ];
+        for (name, p1, p2, expected) in test_data {
+            println!("Case {}", name);
+            assert_eq!(structure(p1.as_ref()) == structure(p2.as_ref()), expected);
+        }
+    }
 }
@@ -1,15 +1,17 @@
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::white;
+use crate::fingerprint::hash_color;
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Rings {
     base: BasePattern,
-    a: Color,
-    b: Color,
+    pub a: Color,
+    pub b: Color,
 }
 
 impl Rings {
@@ -35,6 +37,10 @@ impl Pattern for Rings {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+    }
     fn color_at_world(&self, world_point: Tuple) -> Color {
         // TODO: is any kind of overflow possible here?
         if (world_point.x.powi(2) + world_point.z.powi(2))
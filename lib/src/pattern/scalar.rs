@@ -0,0 +1,68 @@
+// Patterns that vary a number instead of a color, for modulating material properties such as
+// reflective or shininess over a surface (e.g. a checkerboard of glossy and matte tiles)
+use crate::fingerprint::hash_f32;
+use crate::pattern::pattern::Pattern;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+// Maps the brightness (average of the channels) of a color pattern from [0, 1] to [low, high]
+#[derive(Clone, Debug)]
+pub struct ScalarPattern {
+    pattern: Arc<dyn Pattern>,
+    pub low: f32,
+    pub high: f32,
+}
+
+impl ScalarPattern {
+    pub fn new(pattern: Arc<dyn Pattern>, low: f32, high: f32) -> ScalarPattern {
+        ScalarPattern { pattern, low, high }
+    }
+
+    pub fn pattern(&self) -> &dyn Pattern {
+        self.pattern.as_ref()
+    }
+
+    pub fn value_at_object(&self, world_point: Tuple, object: &dyn Shape) -> f32 {
+        let c = self.pattern.color_at_object(world_point, object);
+        let brightness = ((c.r + c.g + c.b) / 3.).clamp(0., 1.);
+        self.low + (self.high - self.low) * brightness
+    }
+
+    // see Pattern::hash_structure
+    pub fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        self.pattern.hash_structure(hasher);
+        hash_f32(hasher, self.low);
+        hash_f32(hasher, self.high);
+    }
+}
+
+// patterns are compared by identity, like Material::pattern
+impl PartialEq for ScalarPattern {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.pattern, &other.pattern)
+            && self.low == other.low
+            && self.high == other.high
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{black, white};
+    use crate::pattern::checkers::Checkers;
+    use crate::shape::sphere::Sphere;
+    use crate::transformations::scaling;
+
+    #[test]
+    fn scalar_pattern_maps_brightness_to_range() {
+        let pattern = ScalarPattern::new(Arc::new(Checkers::new(white(), black())), 0.2, 0.8);
+        let mut shape = Sphere::new();
+        shape.set_transformation(scaling(2., 2., 2.));
+        // white
+        assert_abs_diff_eq!(pattern.value_at_object(point!(0.5, 0, 0), &shape), 0.8);
+        // black
+        assert_abs_diff_eq!(pattern.value_at_object(point!(2.5, 0, 0), &shape), 0.2);
+    }
+}
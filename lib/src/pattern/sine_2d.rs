@@ -1,14 +1,17 @@
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::white;
+use crate::fingerprint::hash_color;
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Sine2D {
     base: BasePattern,
     a: Color,
+    b: Color,
     distance: Color,
 }
 
@@ -18,9 +21,14 @@ impl Sine2D {
         Sine2D {
             base: BasePattern::new(),
             a,
+            b,
             distance,
         }
     }
+
+    pub fn colors(&self) -> (Color, Color) {
+        (self.a, self.b)
+    }
 }
 
 impl Default for Sine2D {
@@ -36,6 +44,10 @@ impl Pattern for Sine2D {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+    }
     fn color_at_world(&self, world_point: Tuple) -> Color {
         let cosine = (world_point.x + world_point.z).cos();
         // cosine is in [1, -1], but we need a fraction in [0, 1]
@@ -1,26 +1,50 @@
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::white;
+use crate::fingerprint::{hash_color, hash_tuple};
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
 use crate::tuple::Tuple;
 use std::fmt::Debug;
+use std::hash::Hasher;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Stripes {
     pub a: Color,
     pub b: Color,
     base: BasePattern,
+    // unit vector; the stripes alternate along it
+    direction: Tuple,
 }
 
 impl Stripes {
     pub fn new(a: Color, b: Color) -> Stripes {
+        Self::along(a, b, vector!(1, 0, 0))
+    }
+
+    // Stripes alternating along direction (which need not be normalized) instead of the x axis
+    pub fn along(a: Color, b: Color, direction: Tuple) -> Stripes {
         Stripes {
             a,
             b,
             base: BasePattern::new(),
+            direction: unit_direction(direction),
         }
     }
+
+    pub fn direction(&self) -> Tuple {
+        self.direction
+    }
+}
+
+// normalizes the direction of a pattern
+pub(crate) fn unit_direction(direction: Tuple) -> Tuple {
+    assert!(
+        direction.is_vector() && direction.magnitude() > 0.,
+        "Pattern direction must be a non-zero vector: {:?}",
+        direction
+    );
+    direction.norm()
 }
 
 impl Default for Stripes {
@@ -36,8 +60,13 @@ impl Pattern for Stripes {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+        hash_tuple(hasher, &self.direction);
+    }
     fn color_at_world(&self, world_point: Tuple) -> Color {
-        if world_point.x.floor() as i32 % 2 == 0 {
+        if world_point.dot(self.direction).floor() as i32 % 2 == 0 {
             self.a
         } else {
             self.b
@@ -82,4 +111,30 @@ mod tests {
         assert_eq!(pattern.color_at_world(point!(-1, 0, 0)), black());
         assert_eq!(pattern.color_at_world(point!(-1.1, 0, 0)), white());
     }
+
+    #[test]
+    fn stripes_along_direction() {
+        let pattern = Stripes::along(white(), black(), vector!(0, 0, 2));
+        assert_eq!(pattern.direction(), vector!(0, 0, 1));
+        let test_data = vec![
+            ("constant in x", point!(5, 0, 0.5), white()),
+            ("constant in y", point!(0, 5, 0.5), white()),
+            ("alternates in z", point!(0, 0, 1.5), black()),
+            ("negative z", point!(0, 0, -0.5), black()),
+        ];
+        for (name, p, expected) in test_data {
+            assert_eq!(pattern.color_at_world(p), expected, "Case {}", name);
+        }
+
+        let diagonal = Stripes::along(white(), black(), vector!(1, 1, 0));
+        assert_eq!(diagonal.color_at_world(point!(0.5, 0.5, 0)), white());
+        assert_eq!(diagonal.color_at_world(point!(1, 1, 0)), black());
+        assert_eq!(diagonal.color_at_world(point!(1, -1, 0)), white());
+    }
+
+    #[test]
+    #[should_panic(expected = "Pattern direction must be a non-zero vector")]
+    fn stripes_direction_must_be_nonzero() {
+        Stripes::along(white(), black(), vector!(0, 0, 0));
+    }
 }
@@ -2,17 +2,32 @@ use crate::canvas::Canvas;
 use crate::color::Color;
 use crate::constants::black;
 use crate::constants::{blue, brown, cyan, green, purple, red, white, yellow};
+use crate::fingerprint::{hash_color, hash_f32};
 use crate::pattern::pattern::BasePattern;
 use crate::pattern::pattern::Pattern;
 use crate::tuple::Tuple;
 use dyn_clone::DynClone;
 use std::f32::consts::{FRAC_1_PI, PI};
 use std::fmt::{Debug, Formatter, Result};
+use std::hash::Hasher;
+use std::sync::Arc;
 
 const FRAC_1_2PI: f32 = 1. / (2. * PI);
+const UV_FOOTPRINT_STEP: f32 = 1e-3;
 
-pub trait UVPattern: Debug + DynClone {
+pub trait UVPattern: Debug + DynClone + Send + Sync {
     fn color_at(&self, u: f32, v: f32) -> Color;
+    // the color averaged over an area about footprint wide in UV space
+    fn color_at_filtered(&self, u: f32, v: f32, _footprint: f32) -> Color {
+        self.color_at(u, v)
+    }
+    // Feed the pattern's own parameters into hasher. UV patterns with parameters must override this.
+    fn hash_parameters(&self, _hasher: &mut dyn Hasher) {}
+    // UV patterns aren't Any, so they are told apart by their type name (see Pattern::hash_structure)
+    fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        hasher.write(std::any::type_name::<Self>().as_bytes());
+        self.hash_parameters(hasher);
+    }
 }
 
 dyn_clone::clone_trait_object!(UVPattern);
@@ -53,10 +68,61 @@ impl UVPattern for UVCheckers {
             self.b
         }
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+        hash_f32(hasher, self.width);
+        hash_f32(hasher, self.height);
+    }
 }
 
-pub trait UVMapping: Debug + DynClone {
+pub trait UVMapping: Debug + DynClone + Send + Sync {
+    // u and v in [0, 1]; mappings that would go outside of that range repeat
     fn point_to_uv(&self, p: Tuple) -> (f32, f32);
+    // u and v before repeating, so that a WrapMode can be applied instead
+    fn point_to_raw_uv(&self, p: Tuple) -> (f32, f32) {
+        self.point_to_uv(p)
+    }
+    // mappings have no parameters, so the type is all that distinguishes them
+    fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        hasher.write(std::any::type_name::<Self>().as_bytes());
+    }
+}
+
+// How a TextureMap treats u and v outside of [0, 1]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WrapMode {
+    // tile the texture
+    #[default]
+    Repeat,
+    // stretch the texture's edges outwards
+    Clamp,
+    // tile the texture, flipping every other copy
+    Mirror,
+    // use the given color outside of the texture, e.g. for decals
+    Border(Color),
+}
+
+impl WrapMode {
+    // None if the coordinate is outside of the texture and the border should be used
+    fn wrap(&self, coordinate: f32) -> Option<f32> {
+        match self {
+            WrapMode::Repeat => Some(coordinate.rem_euclid(1.)),
+            // stop just short of 1, which is already the start of the next tile for e.g. checkers
+            WrapMode::Clamp => Some(coordinate.clamp(0., 1. - f32::EPSILON)),
+            WrapMode::Mirror => {
+                let t = coordinate.rem_euclid(2.);
+                Some(if t > 1. { 2. - t } else { t })
+            }
+            WrapMode::Border(_) => {
+                if (0. ..=1.).contains(&coordinate) {
+                    Some(coordinate)
+                } else {
+                    None
+                }
+            }
+        }
+    }
 }
 
 dyn_clone::clone_trait_object!(UVMapping);
@@ -66,6 +132,7 @@ pub struct TextureMap {
     base: BasePattern,
     uv_pattern: Box<dyn UVPattern>,
     uv_mapping: Box<dyn UVMapping>,
+    pub wrap: WrapMode,
 }
 
 impl TextureMap {
@@ -74,8 +141,54 @@ impl TextureMap {
             base: BasePattern::new(),
             uv_pattern,
             uv_mapping,
+            wrap: WrapMode::default(),
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    // None if the point is outside of the texture and the border should be used
+    fn uv_at(&self, point: Tuple) -> Option<(f32, f32)> {
+        if self.wrap == WrapMode::Repeat {
+            return Some(self.uv_mapping.point_to_uv(point));
+        }
+        let (u, v) = self.uv_mapping.point_to_raw_uv(point);
+        Some((self.wrap.wrap(u)?, self.wrap.wrap(v)?))
+    }
+
+    fn border_color(&self) -> Color {
+        match self.wrap {
+            WrapMode::Border(color) => color,
+            _ => unreachable!("only the border wrap mode leaves the texture"),
         }
     }
+
+    // Estimates how far footprint (in pattern space) reaches in UV space around point, from how
+    // quickly u and v change when the point moves a small step along each axis
+    fn uv_footprint(&self, point: Tuple, footprint: f32) -> f32 {
+        let (u, v) = self.uv_mapping.point_to_uv(point);
+        // the step is small, so a large difference means it crossed the seam of the texture
+        let wrapped = |d: f32| {
+            let d = d.abs().rem_euclid(1.);
+            d.min(1. - d)
+        };
+        [
+            vector!(UV_FOOTPRINT_STEP, 0, 0),
+            vector!(0, UV_FOOTPRINT_STEP, 0),
+            vector!(0, 0, UV_FOOTPRINT_STEP),
+        ]
+        .iter()
+        .map(|&offset| {
+            let (u2, v2) = self.uv_mapping.point_to_uv(point + offset);
+            wrapped(u2 - u).max(wrapped(v2 - v))
+        })
+        .fold(0., f32::max)
+            * footprint
+            / UV_FOOTPRINT_STEP
+    }
 }
 
 impl Pattern for TextureMap {
@@ -85,10 +198,34 @@ impl Pattern for TextureMap {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        self.uv_pattern.hash_structure(hasher);
+        self.uv_mapping.hash_structure(hasher);
+        match self.wrap {
+            WrapMode::Repeat => hasher.write_u8(0),
+            WrapMode::Clamp => hasher.write_u8(1),
+            WrapMode::Mirror => hasher.write_u8(2),
+            WrapMode::Border(color) => {
+                hasher.write_u8(3);
+                hash_color(hasher, &color);
+            }
+        }
+    }
     // color value will allow client to test that world_point was transformed
     fn color_at_world(&self, world_point: Tuple) -> Color {
-        let (u, v) = self.uv_mapping.point_to_uv(world_point);
-        self.uv_pattern.color_at(u, v)
+        match self.uv_at(world_point) {
+            Some((u, v)) => self.uv_pattern.color_at(u, v),
+            None => self.border_color(),
+        }
+    }
+    fn color_at_world_filtered(&self, world_point: Tuple, footprint: f32) -> Color {
+        match self.uv_at(world_point) {
+            Some((u, v)) => {
+                let uv_footprint = self.uv_footprint(world_point, footprint);
+                self.uv_pattern.color_at_filtered(u, v, uv_footprint)
+            }
+            None => self.border_color(),
+        }
     }
 }
 
@@ -173,6 +310,11 @@ impl UVPattern for AlignCheck {
 
         self.main
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        for c in &[self.main, self.ul, self.ur, self.bl, self.br] {
+            hash_color(hasher, c);
+        }
+    }
 }
 
 impl Default for AlignCheck {
@@ -197,6 +339,9 @@ impl UVMapping for PlanarMap {
     fn point_to_uv(&self, p: Tuple) -> (f32, f32) {
         (p.x.rem_euclid(1.), p.z.rem_euclid(1.))
     }
+    fn point_to_raw_uv(&self, p: Tuple) -> (f32, f32) {
+        (p.x, p.z)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -209,6 +354,9 @@ impl UVMapping for CylindricalMap {
 
         return (u, v);
     }
+    fn point_to_raw_uv(&self, p: Tuple) -> (f32, f32) {
+        (calculate_u_from_azimuth(p), p.y * FRAC_1_2PI)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -251,6 +399,11 @@ impl Pattern for CubicMap {
     fn get_base_mut(&mut self) -> &mut BasePattern {
         &mut self.base
     }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        for uv_pattern in &self.uv_patterns {
+            uv_pattern.hash_structure(hasher);
+        }
+    }
 
     // color value will allow client to test that world_point was transformed
     fn color_at_world(&self, world_point: Tuple) -> Color {
@@ -343,36 +496,104 @@ pub fn get_align_check_cubic_map_pattern() -> CubicMap {
     pattern
 }
 
+// Clones share the same canvas and mipmaps
 #[derive(Clone)]
 pub struct UVImage {
-    canvas: Canvas,
+    canvas: Arc<Canvas>,
+    // successively halved copies of canvas, down to 1x1; empty unless with_mipmaps was called
+    mipmaps: Arc<Vec<Canvas>>,
 }
 impl UVImage {
-    pub fn new(canvas: Canvas) -> Self {
-        Self { canvas }
+    // accepts either a Canvas or an Arc<Canvas> already shared with other patterns
+    pub fn new(canvas: impl Into<Arc<Canvas>>) -> Self {
+        Self {
+            canvas: canvas.into(),
+            mipmaps: Arc::new(vec![]),
+        }
+    }
+
+    // Generates the mipmaps used by color_at_filtered, so that far-away textures don't sparkle
+    pub fn with_mipmaps(mut self) -> Self {
+        let mut mipmaps: Vec<Canvas> = vec![];
+        loop {
+            let previous = mipmaps.last().unwrap_or(&self.canvas);
+            if previous.width == 1 && previous.height == 1 {
+                break;
+            }
+            let next = previous.downsampled();
+            mipmaps.push(next);
+        }
+        self.mipmaps = Arc::new(mipmaps);
+        self
+    }
+
+    pub fn mipmap_levels(&self) -> usize {
+        self.mipmaps.len()
+    }
+
+    // level 0 is the full-size image
+    fn level(&self, level: usize) -> &Canvas {
+        if level == 0 {
+            &self.canvas
+        } else {
+            &self.mipmaps[level - 1]
+        }
     }
 }
 
+// the pixel of canvas nearest to (u, v)
+fn sample_nearest(canvas: &Canvas, u: f32, v: f32) -> Color {
+    // flip v over so it matches the image layout, with y at the top
+    let v = 1. - v;
+
+    let x = u * (canvas.width - 1) as f32;
+    let y = v * (canvas.height - 1) as f32;
+
+    // be sure and round x and y to the nearest whole number
+    canvas.pixel_at(x.round() as usize, y.round() as usize)
+}
+
 impl Debug for UVImage {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         // TODO: print abbreviate canvas representation (too big to print all of it as debug representation)
         f.debug_struct("UVImage")
             .field("width", &self.canvas.width)
             .field("height", &self.canvas.height)
+            .field("mipmap_levels", &self.mipmaps.len())
             .finish()
     }
 }
 
 impl UVPattern for UVImage {
     fn color_at(&self, u: f32, v: f32) -> Color {
-        // flip v over so it matches the image layout, with y at the top
-        let v = 1. - v;
-
-        let x = u * (self.canvas.width - 1) as f32;
-        let y = v * (self.canvas.height - 1) as f32;
-
-        // be sure and round x and y to the nearest whole number
-        self.canvas.pixel_at(x.round() as usize, y.round() as usize)
+        sample_nearest(&self.canvas, u, v)
+    }
+    // Picks the mipmap levels whose pixels are about footprint wide and blends between them
+    fn color_at_filtered(&self, u: f32, v: f32, footprint: f32) -> Color {
+        if self.mipmaps.is_empty() || footprint <= 0. {
+            return self.color_at(u, v);
+        }
+        let pixels = footprint * self.canvas.width.max(self.canvas.height) as f32;
+        let level = pixels.log2().clamp(0., self.mipmaps.len() as f32);
+        let lower = level.floor() as usize;
+        let fraction = level - lower as f32;
+        let color = sample_nearest(self.level(lower), u, v);
+        if fraction == 0. {
+            color
+        } else {
+            color * (1. - fraction) + sample_nearest(self.level(lower + 1), u, v) * fraction
+        }
+    }
+    // the mipmaps are generated from the canvas, so only whether there are any matters
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hasher.write_usize(self.canvas.width);
+        hasher.write_usize(self.canvas.height);
+        for y in 0..self.canvas.height {
+            for x in 0..self.canvas.width {
+                hash_color(hasher, &self.canvas.pixel_at(x, y));
+            }
+        }
+        hasher.write_usize(self.mipmaps.len());
     }
 }
 
@@ -668,4 +889,116 @@ mod tests {
             assert_eq!(color, expected_color, "Case {}", name);
         }
     }
+
+    #[test]
+    fn cloned_uv_image_shares_canvas() {
+        let pattern = UVImage::new(Canvas::new(10, 10));
+        let copy = pattern.clone();
+        assert!(Arc::ptr_eq(&pattern.canvas, &copy.canvas));
+    }
+
+    #[test]
+    fn texture_map_wrap_modes() {
+        let checkers = UVCheckers::new(2., 2., white(), black());
+        let test_data = vec![
+            (
+                "repeat inside",
+                WrapMode::Repeat,
+                point!(0.25, 0, 0.25),
+                white(),
+            ),
+            (
+                "repeat outside",
+                WrapMode::Repeat,
+                point!(1.75, 0, 0.25),
+                black(),
+            ),
+            ("clamp below", WrapMode::Clamp, point!(-3, 0, 0.25), white()),
+            ("clamp above", WrapMode::Clamp, point!(0.25, 0, 5), black()),
+            ("mirror", WrapMode::Mirror, point!(1.75, 0, 0.25), white()),
+            (
+                "mirror negative",
+                WrapMode::Mirror,
+                point!(-0.75, 0, 0.25),
+                black(),
+            ),
+            (
+                "border inside",
+                WrapMode::Border(color!(1, 0, 0)),
+                point!(0.75, 0, 0.25),
+                black(),
+            ),
+            (
+                "border outside",
+                WrapMode::Border(color!(1, 0, 0)),
+                point!(1.25, 0, 0.25),
+                color!(1, 0, 0),
+            ),
+        ];
+        for (name, wrap, p, expected) in test_data {
+            let pattern = TextureMap::new(Box::new(checkers), Box::new(PlanarMap)).with_wrap(wrap);
+            assert_eq!(pattern.color_at_world(p), expected, "Case {}", name);
+        }
+    }
+
+    fn pixel_checkers(size: usize) -> Canvas {
+        let mut canvas = Canvas::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                if (x + y) % 2 == 0 {
+                    canvas.write_pixel(x, y, white());
+                }
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    fn uv_image_mipmaps_halve_down_to_one_pixel() {
+        assert_eq!(UVImage::new(pixel_checkers(4)).mipmap_levels(), 0);
+        let image = UVImage::new(pixel_checkers(4)).with_mipmaps();
+        assert_eq!(image.mipmap_levels(), 2);
+        assert_eq!(image.level(2).pixel_at(0, 0), color!(0.5, 0.5, 0.5));
+        assert_eq!(
+            UVImage::new(Canvas::new(1, 1))
+                .with_mipmaps()
+                .mipmap_levels(),
+            0
+        );
+    }
+
+    #[test]
+    fn uv_image_filtering_selects_mipmap_level() {
+        let image = UVImage::new(pixel_checkers(4)).with_mipmaps();
+        let test_data = vec![
+            ("no footprint", 0., black()),
+            ("smaller than a pixel", 0.1, black()),
+            // one pixel of the 1x1 level covers the whole image
+            ("whole image", 1., color!(0.5, 0.5, 0.5)),
+            ("larger than the image", 10., color!(0.5, 0.5, 0.5)),
+        ];
+        for (name, footprint, expected) in test_data {
+            assert_eq!(
+                image.color_at_filtered(0., 0., footprint),
+                expected,
+                "Case {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn texture_map_filters_far_away_texture() {
+        let image = UVImage::new(pixel_checkers(4)).with_mipmaps();
+        let pattern = TextureMap::new(Box::new(image), Box::new(PlanarMap));
+        let p = point!(0.1, 0, 0.1);
+        assert_eq!(
+            pattern.color_at_world_filtered(p, 0.),
+            pattern.color_at_world(p)
+        );
+        assert_abs_diff_eq!(
+            pattern.color_at_world_filtered(p, 1.),
+            color!(0.5, 0.5, 0.5)
+        );
+    }
 }
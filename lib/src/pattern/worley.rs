@@ -0,0 +1,190 @@
+use crate::color::Color;
+use crate::constants::black;
+use crate::constants::white;
+use crate::fingerprint::hash_color;
+use crate::pattern::pattern::BasePattern;
+use crate::pattern::pattern::Pattern;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// Which distances to the nearest feature points determine the color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorleyMetric {
+    // distance to the nearest point; round cells (scales, bubbles)
+    F1,
+    // distance to the second nearest point; cells with sharp, bulging edges (hammered metal)
+    F2,
+    // difference of the two; thin lines along the cell borders (cracked mud)
+    F2MinusF1,
+}
+
+// Worley (cellular) noise: each unit cell of space contains one randomly placed feature point,
+// and the color is a blend of a and b depending on the distances to the nearest feature points.
+// Deterministic for a given seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Worley {
+    base: BasePattern,
+    a: Color,
+    b: Color,
+    distance: Color,
+    pub metric: WorleyMetric,
+    pub seed: u32,
+}
+
+impl Worley {
+    pub fn new(a: Color, b: Color, metric: WorleyMetric) -> Worley {
+        Worley {
+            base: BasePattern::new(),
+            a,
+            b,
+            distance: b - a,
+            metric,
+            seed: 0,
+        }
+    }
+
+    pub fn colors(&self) -> (Color, Color) {
+        (self.a, self.b)
+    }
+
+    // Distances from p to the nearest and second nearest feature points. The closest points
+    // are always in the 3x3x3 block of cells around p's cell.
+    fn nearest_distances(&self, p: Tuple) -> (f32, f32) {
+        let (cell_x, cell_y, cell_z) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let mut f1 = f32::INFINITY;
+        let mut f2 = f32::INFINITY;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let x = cell_x + dx as f32;
+                    let y = cell_y + dy as f32;
+                    let z = cell_z + dz as f32;
+                    let feature = self.feature_point(x as i32, y as i32, z as i32);
+                    let distance = (point!(x, y, z) + feature - p).magnitude();
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    // offset of the feature point in the given cell from the cell's minimum corner
+    fn feature_point(&self, x: i32, y: i32, z: i32) -> Tuple {
+        let h = hash(x as u32, y as u32, z as u32, self.seed);
+        vector!(
+            unit_fraction(h),
+            unit_fraction(hash(h, 1, 0, 0)),
+            unit_fraction(hash(h, 2, 0, 0))
+        )
+    }
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new(white(), black(), WorleyMetric::F1)
+    }
+}
+
+// mixes the inputs into a well-distributed 32-bit value
+fn hash(x: u32, y: u32, z: u32, seed: u32) -> u32 {
+    let mut h = seed
+        ^ x.wrapping_mul(0x8da6_b343)
+        ^ y.wrapping_mul(0xd816_3841)
+        ^ z.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+// maps a hash to [0, 1)
+fn unit_fraction(h: u32) -> f32 {
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+impl Pattern for Worley {
+    fn get_base(&self) -> &BasePattern {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BasePattern {
+        &mut self.base
+    }
+    fn hash_parameters(&self, hasher: &mut dyn Hasher) {
+        hash_color(hasher, &self.a);
+        hash_color(hasher, &self.b);
+        hasher.write_u8(self.metric as u8);
+        hasher.write_u32(self.seed);
+    }
+    fn color_at_world(&self, world_point: Tuple) -> Color {
+        let (f1, f2) = self.nearest_distances(world_point);
+        let value = match self.metric {
+            WorleyMetric::F1 => f1,
+            WorleyMetric::F2 => f2,
+            WorleyMetric::F2MinusF1 => f2 - f1,
+        };
+        // distances are almost always within [0, 1]
+        self.a + (self.distance * value.min(1.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feature_points_lie_within_their_cells() {
+        let pattern = Worley::default();
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    let f = pattern.feature_point(x, y, z);
+                    for &c in &[f.x, f.y, f.z] {
+                        assert!((0. ..1.).contains(&c), "{:?} in cell {} {} {}", f, x, y, z);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pattern_is_a_at_feature_points() {
+        let pattern = Worley::default();
+        let p = point!(2, -1, 5) + pattern.feature_point(2, -1, 5);
+        assert_abs_diff_eq!(pattern.color_at_world(p), white(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn metrics_are_ordered() {
+        let points = vec![
+            point!(0.5, 0.5, 0.5),
+            point!(-3.2, 7.7, 0.1),
+            point!(10.25, -4, 2.5),
+        ];
+        for p in points {
+            let mut pattern = Worley::default();
+            let f1 = 1. - pattern.color_at_world(p).r;
+            pattern.metric = WorleyMetric::F2;
+            let f2 = 1. - pattern.color_at_world(p).r;
+            pattern.metric = WorleyMetric::F2MinusF1;
+            let difference = 1. - pattern.color_at_world(p).r;
+            assert!(f1 <= f2, "{:?}", p);
+            assert_abs_diff_eq!(difference, f2 - f1, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn seed_changes_feature_points() {
+        let mut pattern = Worley::default();
+        let p = point!(0.3, 0.6, 0.9);
+        let unseeded = pattern.color_at_world(p);
+        pattern.seed = 42;
+        assert_eq!(pattern.color_at_world(p), pattern.color_at_world(p));
+        assert_ne!(pattern.color_at_world(p), unseeded);
+    }
+}
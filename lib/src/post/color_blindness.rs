@@ -0,0 +1,112 @@
+use crate::color::Color;
+use crate::post::filter::ColorFilter;
+
+// Shows a render as a person with a color vision deficiency would see it, to check that colors
+// which carry meaning (e.g. the faces of an alignment cube) can still be told apart. Uses the
+// complete (severity 1) simulations of Machado, Oliveira and Fernandes (2009).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBlindness {
+    // no red cones
+    Protanopia,
+    // no green cones
+    Deuteranopia,
+    // no blue cones
+    Tritanopia,
+    // no color vision at all
+    Achromatopsia,
+}
+
+impl ColorBlindness {
+    fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+            // every channel becomes the luminance
+            ColorBlindness::Achromatopsia => [[0.2126, 0.7152, 0.0722]; 3],
+        }
+    }
+}
+
+impl ColorFilter for ColorBlindness {
+    fn apply(&self, color: Color) -> Color {
+        let m = self.matrix();
+        let row = |r: [f32; 3]| r[0] * color.r + r[1] * color.g + r[2] * color.b;
+        color!(row(m[0]), row(m[1]), row(m[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{blue, cyan, green, red, yellow};
+
+    fn distance(a: Color, b: Color) -> f32 {
+        let d = a - b;
+        (d.r * d.r + d.g * d.g + d.b * d.b).sqrt()
+    }
+
+    #[test]
+    fn grays_are_unchanged() {
+        for deficiency in &[
+            ColorBlindness::Protanopia,
+            ColorBlindness::Deuteranopia,
+            ColorBlindness::Tritanopia,
+            ColorBlindness::Achromatopsia,
+        ] {
+            for &gray in &[color!(0, 0, 0), color!(0.5, 0.5, 0.5), color!(1, 1, 1)] {
+                assert_abs_diff_eq!(deficiency.apply(gray), gray, epsilon = 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn confused_colors_look_alike() {
+        // name, deficiency, colors that become hard to tell apart, colors that stay distinct
+        let test_data = vec![
+            (
+                "protanopia",
+                ColorBlindness::Protanopia,
+                (green(), yellow()),
+                (blue(), yellow()),
+            ),
+            (
+                "deuteranopia",
+                ColorBlindness::Deuteranopia,
+                (red(), green()),
+                (blue(), yellow()),
+            ),
+            (
+                "tritanopia",
+                ColorBlindness::Tritanopia,
+                (green(), cyan()),
+                (red(), green()),
+            ),
+        ];
+        for (name, deficiency, (a, b), (c, d)) in test_data {
+            let confused = distance(deficiency.apply(a), deficiency.apply(b));
+            let distinct = distance(deficiency.apply(c), deficiency.apply(d));
+            assert!(confused < distance(a, b) * 0.5, "Case {}", name);
+            assert!(distinct > confused, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn achromatopsia_sees_luminance() {
+        let c = color!(0.2, 0.6, 0.9);
+        let l = c.luminance();
+        assert_abs_diff_eq!(ColorBlindness::Achromatopsia.apply(c), color!(l, l, l));
+    }
+}
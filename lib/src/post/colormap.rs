@@ -0,0 +1,89 @@
+use crate::color::Color;
+
+// viridis at evenly spaced points from 0 to 1 (after matplotlib's); perceptually uniform and
+// readable by the color blind, so that it shows magnitudes without exaggerating any range
+const VIRIDIS: [Color; 9] = [
+    Color {
+        r: 0.267,
+        g: 0.005,
+        b: 0.329,
+    },
+    Color {
+        r: 0.283,
+        g: 0.141,
+        b: 0.458,
+    },
+    Color {
+        r: 0.254,
+        g: 0.265,
+        b: 0.530,
+    },
+    Color {
+        r: 0.207,
+        g: 0.372,
+        b: 0.553,
+    },
+    Color {
+        r: 0.164,
+        g: 0.471,
+        b: 0.558,
+    },
+    Color {
+        r: 0.128,
+        g: 0.567,
+        b: 0.551,
+    },
+    Color {
+        r: 0.135,
+        g: 0.659,
+        b: 0.518,
+    },
+    Color {
+        r: 0.267,
+        g: 0.749,
+        b: 0.441,
+    },
+    Color {
+        r: 0.993,
+        g: 0.906,
+        b: 0.144,
+    },
+];
+
+// Dark purple for 0 through blue and green to yellow for 1; values outside of 0 to 1 are clamped
+pub fn viridis(value: f32) -> Color {
+    let position = value.clamp(0., 1.) * (VIRIDIS.len() - 1) as f32;
+    let index = (position as usize).min(VIRIDIS.len() - 2);
+    let fraction = position - index as f32;
+    VIRIDIS[index] * (1. - fraction) + VIRIDIS[index + 1] * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viridis_interpolates_and_clamps() {
+        let test_data = vec![
+            ("Start", 0., VIRIDIS[0]),
+            ("End", 1., VIRIDIS[8]),
+            ("Below range", -3., VIRIDIS[0]),
+            ("Above range", 7., VIRIDIS[8]),
+            ("Stop", 0.5, VIRIDIS[4]),
+            ("Between stops", 0.0625, color!(0.275, 0.073, 0.3935)),
+        ];
+        for (name, value, expected) in test_data {
+            println!("Case {}", name);
+            assert_abs_diff_eq!(viridis(value), expected);
+        }
+    }
+
+    #[test]
+    fn viridis_brightens_steadily() {
+        let luminance = |c: Color| 0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b;
+        let samples: Vec<f32> = (0..=20)
+            .map(|i| luminance(viridis(i as f32 / 20.)))
+            .collect();
+        assert!(samples.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::post::filter::ColorFilter;
+
+// the share of pixels that automatic exposure lets become brighter than white
+const CLIPPED_FRACTION: f32 = 0.01;
+const HISTOGRAM_BINS: usize = 4096;
+
+// Brightens or darkens the whole image, like a camera's exposure setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub multiplier: f32,
+}
+
+impl Exposure {
+    // An exposure that makes all but the brightest 1% of the canvas's pixels fit below white, so
+    // that scenes come out well exposed however strong their lights are. Leaves black canvases
+    // alone.
+    pub fn automatic(canvas: &Canvas) -> Exposure {
+        let bright = canvas
+            .histogram(HISTOGRAM_BINS)
+            .percentile(1. - CLIPPED_FRACTION);
+        let multiplier = if bright > 0. { 1. / bright } else { 1. };
+        Exposure { multiplier }
+    }
+
+    // the number of stops (doublings of brightness) that this exposure adds
+    pub fn stops(&self) -> f32 {
+        self.multiplier.log2()
+    }
+}
+
+impl ColorFilter for Exposure {
+    fn apply(&self, color: Color) -> Color {
+        color * self.multiplier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn automatic_exposure_brings_bright_pixels_to_white() {
+        let mut canvas = Canvas::new(100, 1);
+        for x in 0..100 {
+            canvas.write_pixel(x, 0, color!(4, 4, 4) * (x as f32 / 100.));
+        }
+        // a single very bright pixel is allowed to clip; it also widens the histogram's bins, which
+        // limits how precisely the exposure is found
+        canvas.write_pixel(99, 0, color!(1000, 1000, 1000));
+        let exposure = Exposure::automatic(&canvas);
+        assert_abs_diff_eq!(exposure.multiplier, 0.25, epsilon = 0.02);
+        assert_abs_diff_eq!(exposure.stops(), -2., epsilon = 0.1);
+
+        canvas.apply_filter(&exposure);
+        assert_abs_diff_eq!(
+            canvas.pixel_at(50, 0),
+            color!(0.5, 0.5, 0.5),
+            epsilon = 0.03
+        );
+    }
+
+    #[test]
+    fn black_canvas_is_left_alone() {
+        let exposure = Exposure::automatic(&Canvas::new(3, 3));
+        assert_eq!(exposure.multiplier, 1.);
+    }
+}
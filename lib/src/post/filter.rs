@@ -0,0 +1,57 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use std::fmt::Debug;
+
+// A change to the colors of a finished render, made to each pixel independently of the others
+pub trait ColorFilter: Debug {
+    fn apply(&self, color: Color) -> Color;
+}
+
+// a pipeline of filters, applied in order
+impl ColorFilter for Vec<Box<dyn ColorFilter>> {
+    fn apply(&self, color: Color) -> Color {
+        self.iter().fold(color, |c, filter| filter.apply(c))
+    }
+}
+
+impl Canvas {
+    pub fn apply_filter(&mut self, filter: &dyn ColorFilter) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = filter.apply(self.pixel_at(x, y));
+                self.write_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::post::grading::Contrast;
+
+    #[derive(Debug)]
+    struct Invert;
+
+    impl ColorFilter for Invert {
+        fn apply(&self, color: Color) -> Color {
+            color!(1, 1, 1) - color
+        }
+    }
+
+    #[test]
+    fn filters_are_applied_in_order_to_every_pixel() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, color!(0.25, 0.5, 1));
+        let pipeline: Vec<Box<dyn ColorFilter>> = vec![
+            Box::new(Invert),
+            Box::new(Contrast {
+                amount: 2.,
+                pivot: 0.5,
+            }),
+        ];
+        canvas.apply_filter(&pipeline);
+        assert_abs_diff_eq!(canvas.pixel_at(0, 0), color!(1, 0.5, -0.5));
+        assert_abs_diff_eq!(canvas.pixel_at(1, 0), color!(1.5, 1.5, 1.5));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::color::Color;
+use crate::post::filter::ColorFilter;
+
+// Spreads the channels away from pivot (amount > 1) or squeezes them towards it (amount < 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contrast {
+    pub amount: f32,
+    pub pivot: f32,
+}
+
+impl ColorFilter for Contrast {
+    fn apply(&self, color: Color) -> Color {
+        let channel = |c: f32| (c - self.pivot) * self.amount + self.pivot;
+        color!(channel(color.r), channel(color.g), channel(color.b))
+    }
+}
+
+// The usual three-way color corrector: lift raises the shadows, gain scales the highlights and
+// gamma bends the midtones (above 1 brightens them). Each is given per channel, so that e.g.
+// shadows can be tinted blue while highlights are warmed. Negative channels are treated as 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiftGammaGain {
+    pub lift: Color,
+    pub gamma: Color,
+    pub gain: Color,
+}
+
+impl Default for LiftGammaGain {
+    // changes nothing
+    fn default() -> Self {
+        LiftGammaGain {
+            lift: color!(0, 0, 0),
+            gamma: color!(1, 1, 1),
+            gain: color!(1, 1, 1),
+        }
+    }
+}
+
+impl ColorFilter for LiftGammaGain {
+    fn apply(&self, color: Color) -> Color {
+        let channel = |c: f32, lift: f32, gamma: f32, gain: f32| {
+            let c = c.max(0.);
+            (gain * (c + lift * (1. - c))).max(0.).powf(1. / gamma)
+        };
+        color!(
+            channel(color.r, self.lift.r, self.gamma.r, self.gain.r),
+            channel(color.g, self.lift.g, self.gamma.g, self.gain.g),
+            channel(color.b, self.lift.b, self.gamma.b, self.gain.b)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contrast_spreads_channels_from_pivot() {
+        let test_data = vec![
+            ("more", 2., color!(0.3, 0.5, 0.8), color!(0.1, 0.5, 1.1)),
+            ("less", 0.5, color!(0.3, 0.5, 0.8), color!(0.4, 0.5, 0.65)),
+            ("none", 1., color!(0.3, 0.5, 0.8), color!(0.3, 0.5, 0.8)),
+        ];
+        for (name, amount, color, expected) in test_data {
+            let contrast = Contrast { amount, pivot: 0.5 };
+            println!("Case {}", name);
+            assert_abs_diff_eq!(contrast.apply(color), expected);
+        }
+    }
+
+    #[test]
+    fn lift_gamma_gain() {
+        let grey = color!(0.25, 0.25, 0.25);
+        assert_abs_diff_eq!(LiftGammaGain::default().apply(grey), grey);
+
+        let lift = LiftGammaGain {
+            lift: color!(0.2, 0, 0),
+            ..LiftGammaGain::default()
+        };
+        // black is raised by the full lift, white not at all
+        assert_abs_diff_eq!(lift.apply(color!(0, 0, 0)), color!(0.2, 0, 0));
+        assert_abs_diff_eq!(lift.apply(color!(1, 1, 1)), color!(1, 1, 1));
+
+        let gamma = LiftGammaGain {
+            gamma: color!(2, 2, 2),
+            ..LiftGammaGain::default()
+        };
+        assert_abs_diff_eq!(gamma.apply(grey), color!(0.5, 0.5, 0.5));
+
+        let gain = LiftGammaGain {
+            gain: color!(2, 1, 0.5),
+            ..LiftGammaGain::default()
+        };
+        assert_abs_diff_eq!(gain.apply(grey), color!(0.5, 0.25, 0.125));
+        assert_abs_diff_eq!(gain.apply(color!(-1, 0, 0)), color!(0, 0, 0));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::canvas::ParseError;
+use crate::color::Color;
+use crate::post::filter::ColorFilter;
+use std::io::{BufRead, BufReader, Read};
+
+// A 3D lookup table, which maps each color to another one and can describe almost any color grade.
+// Looks are usually made in a color grading tool and exported in the .cube format (see from_cube).
+// Colors between the table's entries are interpolated, and colors outside of its domain are
+// clamped to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3D {
+    // entries per axis
+    size: usize,
+    domain_min: Color,
+    domain_max: Color,
+    // red changes fastest, then green, then blue
+    table: Vec<Color>,
+}
+
+impl Lut3D {
+    // a table which maps every color to itself
+    pub fn identity(size: usize) -> Lut3D {
+        assert!(
+            size >= 2,
+            "A lookup table needs at least 2 entries per axis"
+        );
+        let step = |i: usize| i as f32 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size.pow(3));
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push(color!(step(r), step(g), step(b)));
+                }
+            }
+        }
+        Lut3D {
+            size,
+            domain_min: color!(0, 0, 0),
+            domain_max: color!(1, 1, 1),
+            table,
+        }
+    }
+
+    // Reads a table in the Adobe/Resolve .cube format: a LUT_3D_SIZE line, optional DOMAIN_MIN and
+    // DOMAIN_MAX lines, and then one "r g b" line per entry. TITLE lines and # comments are ignored.
+    pub fn from_cube<T: Read>(reader: T) -> Result<Lut3D, ParseError> {
+        let mut size = None;
+        let mut domain_min = color!(0, 0, 0);
+        let mut domain_max = color!(1, 1, 1);
+        let mut table = vec![];
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line_number = index + 1;
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                None => continue,
+                Some(token) if token.starts_with('#') => continue,
+                Some(token) => token,
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let value = tokens.next().and_then(|t| t.parse::<usize>().ok());
+                    // the number of entries must be addressable; whether the file actually has
+                    // that many is checked once they have all been read
+                    match value.and_then(|s| Some((s, s.checked_pow(3)?))) {
+                        Some((s, entries)) if s >= 2 => size = Some((s, entries)),
+                        _ => {
+                            return Err(ParseError::MalformedDimensionHeader(format!(
+                                "Expected a size of at least 2 at line {}; found {}",
+                                line_number, line
+                            )))
+                        }
+                    }
+                }
+                "DOMAIN_MIN" => domain_min = parse_color(tokens, line_number, &line)?,
+                "DOMAIN_MAX" => domain_max = parse_color(tokens, line_number, &line)?,
+                "LUT_1D_SIZE" => {
+                    return Err(ParseError::IncorrectFormat(format!(
+                        "Only 3D lookup tables are supported; found {} at line {}",
+                        line, line_number
+                    )))
+                }
+                _ => {
+                    let values = std::iter::once(keyword).chain(tokens);
+                    table.push(parse_color(values, line_number, &line)?);
+                }
+            }
+        }
+        let (size, entries) = size.ok_or_else(|| {
+            ParseError::TruncatedHeader("File ended before LUT_3D_SIZE was specified".to_string())
+        })?;
+        if table.len() != entries {
+            return Err(ParseError::IncorrectFormat(format!(
+                "Expected {} entries for a table of size {}; found {}",
+                entries,
+                size,
+                table.len()
+            )));
+        }
+        Ok(Lut3D {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> Color {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+}
+
+fn parse_color<'a>(
+    mut tokens: impl Iterator<Item = &'a str>,
+    line_number: usize,
+    line: &str,
+) -> Result<Color, ParseError> {
+    let mut channels = [0.; 3];
+    for channel in channels.iter_mut() {
+        *channel = match tokens.next().map(str::parse::<f32>) {
+            Some(Ok(value)) => value,
+            _ => {
+                return Err(ParseError::IncorrectFormat(format!(
+                    "Expected three numbers at line {}; found {}",
+                    line_number, line
+                )))
+            }
+        };
+    }
+    Color::try_new(channels[0], channels[1], channels[2]).map_err(|e| {
+        ParseError::IncorrectFormat(format!("Invalid color at line {}: {}", line_number, e))
+    })
+}
+
+impl ColorFilter for Lut3D {
+    // trilinear interpolation between the 8 entries surrounding the color
+    fn apply(&self, color: Color) -> Color {
+        let last = (self.size - 1) as f32;
+        // the position of the color along one axis of the table, split into the index of the
+        // entry below it and the fraction of the way to the next entry
+        let position = |c: f32, min: f32, max: f32| {
+            let p = ((c - min) / (max - min)).clamp(0., 1.) * last;
+            let below = (p.floor() as usize).min(self.size - 2);
+            (below, p - below as f32)
+        };
+        let (r, fr) = position(color.r, self.domain_min.r, self.domain_max.r);
+        let (g, fg) = position(color.g, self.domain_min.g, self.domain_max.g);
+        let (b, fb) = position(color.b, self.domain_min.b, self.domain_max.b);
+        let mix = |a: Color, b: Color, t: f32| a * (1. - t) + b * t;
+        let along_r = |g, b| mix(self.entry(r, g, b), self.entry(r + 1, g, b), fr);
+        let along_g = |b| mix(along_r(g, b), along_r(g + 1, b), fg);
+        mix(along_g(b), along_g(b + 1), fb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // swaps red and blue
+    const SWAP_CUBE: &str = "TITLE \"swap\"
+# made by hand
+LUT_3D_SIZE 2
+
+0 0 0
+0 0 1
+0 1 0
+0 1 1
+1 0 0
+1 0 1
+1 1 0
+1 1 1
+";
+
+    #[test]
+    fn identity_table_changes_nothing() {
+        let lut = Lut3D::identity(5);
+        for &c in &[color!(0, 0, 0), color!(0.1, 0.5, 0.93), color!(1, 1, 1)] {
+            assert_abs_diff_eq!(lut.apply(c), c);
+        }
+    }
+
+    #[test]
+    fn cube_file_is_parsed_and_interpolated() {
+        let lut = Lut3D::from_cube(SWAP_CUBE.as_bytes()).unwrap();
+        assert_abs_diff_eq!(lut.apply(color!(1, 0, 0)), color!(0, 0, 1));
+        assert_abs_diff_eq!(lut.apply(color!(0.2, 0.5, 0.9)), color!(0.9, 0.5, 0.2));
+        // clamped to the domain
+        assert_abs_diff_eq!(lut.apply(color!(2, -1, 0)), color!(0, 0, 1));
+    }
+
+    #[test]
+    fn cube_domain_is_used() {
+        let cube = "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n".to_string()
+            + &"0 0 0\n".repeat(7)
+            + "1 1 1\n";
+        let lut = Lut3D::from_cube(cube.as_bytes()).unwrap();
+        assert_abs_diff_eq!(lut.apply(color!(2, 2, 2)), color!(1, 1, 1));
+        assert_abs_diff_eq!(lut.apply(color!(1, 2, 2)), color!(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn malformed_cube_files_are_rejected() {
+        let test_data = vec![
+            ("no size", "0 0 0\n"),
+            ("bad size", "LUT_3D_SIZE one\n"),
+            ("1D", "LUT_1D_SIZE 2\n0 0 0\n1 1 1\n"),
+            ("too few entries", "LUT_3D_SIZE 2\n0 0 0\n"),
+            ("bad entry", "LUT_3D_SIZE 2\n0 zero 0\n"),
+            ("NaN entry", "LUT_3D_SIZE 2\n0 nan 0\n"),
+            ("huge size", "LUT_3D_SIZE 3000000\n"),
+            ("overflowing size", "LUT_3D_SIZE 18446744073709551615\n"),
+        ];
+        for (name, cube) in test_data {
+            assert!(Lut3D::from_cube(cube.as_bytes()).is_err(), "Case {}", name);
+        }
+        match Lut3D::from_cube("LUT_3D_SIZE 3000000\n".as_bytes()) {
+            Err(ParseError::MalformedDimensionHeader(_)) => (),
+            other => panic!(
+                "Expected malformed dimension error; got {:?}",
+                other.is_ok()
+            ),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod color_blindness;
+pub mod colormap;
+pub mod exposure;
+pub mod filter;
+pub mod grading;
+pub mod lut;
+pub mod overlay;
+pub mod white_balance;
@@ -0,0 +1,517 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+// Drawing on top of a finished render, e.g. to burn the scene name, sample count and render time
+// into a frame. Everything is clipped to the canvas, so text and shapes may run off its edges.
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+// glyphs are separated by a blank column and lines by a blank row
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+// A tiny 5x7 bitmap font covering digits, capital letters and common punctuation; lower case
+// letters are drawn as capitals and anything else as a question mark. Each row is a bit mask whose
+// highest of 5 bits is the leftmost pixel. Sorted by character for binary search.
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 52] = [
+    (
+        ' ',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '!',
+        [
+            0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+        ],
+    ),
+    (
+        '#',
+        [
+            0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+        ],
+    ),
+    (
+        '%',
+        [
+            0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+        ],
+    ),
+    (
+        '\'',
+        [
+            0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '(',
+        [
+            0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+        ],
+    ),
+    (
+        ')',
+        [
+            0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '+',
+        [
+            0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+        ],
+    ),
+    (
+        ',',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '-',
+        [
+            0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '.',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+        ],
+    ),
+    (
+        '/',
+        [
+            0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+        ],
+    ),
+    (
+        '0',
+        [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '1',
+        [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        '2',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+    ),
+    (
+        '3',
+        [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '4',
+        [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+    ),
+    (
+        '5',
+        [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '6',
+        [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '7',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+    ),
+    (
+        '8',
+        [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '9',
+        [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+    ),
+    (
+        ':',
+        [
+            0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+        ],
+    ),
+    (
+        '=',
+        [
+            0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '?',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+        ],
+    ),
+    (
+        'A',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'B',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+    ),
+    (
+        'C',
+        [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'D',
+        [
+            0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+        ],
+    ),
+    (
+        'E',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'F',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'G',
+        [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+    ),
+    (
+        'H',
+        [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'I',
+        [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        'J',
+        [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+    ),
+    (
+        'K',
+        [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'L',
+        [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'M',
+        [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'N',
+        [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'O',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'P',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'Q',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+    ),
+    (
+        'R',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'S',
+        [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+    ),
+    (
+        'T',
+        [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'U',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'V',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+    ),
+    (
+        'W',
+        [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+    ),
+    (
+        'X',
+        [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'Y',
+        [
+            0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'Z',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        '_',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+        ],
+    ),
+];
+
+fn glyph(c: char) -> &'static [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    let index = GLYPHS
+        .binary_search_by_key(&c, |(g, _)| *g)
+        .or_else(|_| GLYPHS.binary_search_by_key(&'?', |(g, _)| *g))
+        .unwrap();
+    &GLYPHS[index].1
+}
+
+impl Canvas {
+    fn write_pixel_clipped(&mut self, x: isize, y: isize, color: Color) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.write_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    // Bresenham's line from (x0, y0) to (x1, y1), including both ends
+    pub fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.write_pixel_clipped(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    // the outline of the width x height rectangle whose top left corner is (x, y)
+    pub fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (right, bottom) = (x + width as isize - 1, y + height as isize - 1);
+        self.draw_line(x, y, right, y, color);
+        self.draw_line(x, bottom, right, bottom, color);
+        self.draw_line(x, y, x, bottom, color);
+        self.draw_line(right, y, right, bottom, color);
+    }
+
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        for row in y..y + height as isize {
+            for column in x..x + width as isize {
+                self.write_pixel_clipped(column, row, color);
+            }
+        }
+    }
+
+    // Draw text in the built-in bitmap font with its top left corner at (x, y); '\n' starts a new
+    // line. Only the glyphs' pixels are written, so put a fill_rect of text_size behind the text
+    // to make it readable on a busy render.
+    pub fn draw_text(&mut self, x: isize, y: isize, text: &str, color: Color) {
+        for (line_number, line) in text.lines().enumerate() {
+            let top = y + (line_number * LINE_HEIGHT) as isize;
+            for (column, c) in line.chars().enumerate() {
+                let left = x + (column * ADVANCE) as isize;
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    for bit in 0..GLYPH_WIDTH {
+                        if bits & (1 << (GLYPH_WIDTH - 1 - bit)) != 0 {
+                            self.write_pixel_clipped(
+                                left + bit as isize,
+                                top + row as isize,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The width and height in pixels that draw_text covers with text
+pub fn text_size(text: &str) -> (usize, usize) {
+    let lines = text.lines().count();
+    let longest = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    if lines == 0 || longest == 0 {
+        return (0, 0);
+    }
+    (longest * ADVANCE - 1, lines * LINE_HEIGHT - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit_pixels(canvas: &Canvas) -> Vec<(usize, usize)> {
+        let mut lit = vec![];
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                if canvas.pixel_at(x, y) != color!(0, 0, 0) {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let test_data = vec![
+            (
+                "Horizontal",
+                (1, 2, 4, 2),
+                vec![(1, 2), (2, 2), (3, 2), (4, 2)],
+            ),
+            (
+                "Vertical, upwards",
+                (3, 3, 3, 1),
+                vec![(3, 1), (3, 2), (3, 3)],
+            ),
+            ("Diagonal", (0, 0, 2, 2), vec![(0, 0), (1, 1), (2, 2)]),
+            ("Single point", (2, 2, 2, 2), vec![(2, 2)]),
+            ("Clipped", (-2, 0, 1, 0), vec![(0, 0), (1, 0)]),
+        ];
+        for (name, (x0, y0, x1, y1), expected) in test_data {
+            let mut canvas = Canvas::new(5, 5);
+            canvas.draw_line(x0, y0, x1, y1, color!(1, 1, 1));
+            let mut lit: Vec<(usize, usize)> = lit_pixels(&canvas);
+            lit.sort_by_key(|(x, y)| (*x, *y));
+            assert_eq!(lit, expected, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn rect_outline_and_fill() {
+        let mut canvas = Canvas::new(6, 6);
+        canvas.draw_rect(1, 1, 4, 3, color!(1, 0, 0));
+        assert_eq!(lit_pixels(&canvas).len(), 10);
+        assert_eq!(canvas.pixel_at(2, 2), color!(0, 0, 0));
+
+        canvas.fill_rect(4, 4, 5, 5, color!(0, 1, 0));
+        assert_eq!(canvas.pixel_at(5, 5), color!(0, 1, 0));
+        assert_eq!(lit_pixels(&canvas).len(), 14);
+    }
+
+    #[test]
+    fn draw_text_renders_glyphs() {
+        let (width, height) = text_size("1-\nab");
+        assert_eq!((width, height), (11, 15));
+        let mut canvas = Canvas::new(width, height);
+        canvas.draw_text(0, 0, "1-\nab", color!(1, 1, 1));
+        // the 1 has a stem in its third column and a foot across the bottom row
+        assert_eq!(canvas.pixel_at(2, 3), color!(1, 1, 1));
+        assert_eq!(canvas.pixel_at(0, 3), color!(0, 0, 0));
+        assert_eq!(canvas.pixel_at(1, 6), color!(1, 1, 1));
+        // the dash is the middle row of the second glyph
+        assert_eq!(canvas.pixel_at(6, 3), color!(1, 1, 1));
+        assert_eq!(canvas.pixel_at(6, 2), color!(0, 0, 0));
+        // lower case is drawn as upper case: A's crossbar on the second line
+        assert_eq!(canvas.pixel_at(2, 8 + 4), color!(1, 1, 1));
+        // the blank column between glyphs stays dark
+        assert!((0..height).all(|y| canvas.pixel_at(5, y) == color!(0, 0, 0)));
+    }
+
+    #[test]
+    fn unknown_characters_are_drawn_as_question_marks() {
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_eq!(glyph('z'), glyph('Z'));
+    }
+
+    #[test]
+    fn glyphs_are_sorted() {
+        assert!(GLYPHS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn text_off_the_canvas_is_clipped() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw_text(-3, -3, "TEXT", color!(1, 1, 1));
+        canvas.draw_text(2, 2, "TEXT", color!(1, 1, 1));
+        assert!(!lit_pixels(&canvas).is_empty());
+    }
+}
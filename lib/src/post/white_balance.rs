@@ -0,0 +1,68 @@
+use crate::color::Color;
+use crate::post::filter::ColorFilter;
+
+// keeps channels that the illuminant lacks almost entirely from being multiplied without bound
+const MIN_ILLUMINANT_CHANNEL: f32 = 0.01;
+
+// Removes the color cast of a scene's lighting: surfaces that are white under the given illuminant
+// become neutral gray of the same luminance, as a camera's white balance setting does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WhiteBalance {
+    // multiplies each channel
+    gains: Color,
+}
+
+impl WhiteBalance {
+    // the color of the light to correct for, e.g. the intensity of a colored light source
+    pub fn for_illuminant(illuminant: Color) -> WhiteBalance {
+        let luminance = illuminant.luminance();
+        let gain = |c: f32| luminance / c.max(MIN_ILLUMINANT_CHANNEL);
+        WhiteBalance {
+            gains: color!(gain(illuminant.r), gain(illuminant.g), gain(illuminant.b)),
+        }
+    }
+
+    // the temperature of the light to correct for, in Kelvin (see Color::from_temperature)
+    pub fn for_temperature(kelvin: f32) -> WhiteBalance {
+        WhiteBalance::for_illuminant(Color::from_temperature(kelvin))
+    }
+}
+
+impl ColorFilter for WhiteBalance {
+    fn apply(&self, color: Color) -> Color {
+        color * self.gains
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn illuminant_becomes_neutral() {
+        let illuminant = color!(1, 0.8, 0.5);
+        let balance = WhiteBalance::for_illuminant(illuminant);
+        let balanced = balance.apply(illuminant);
+        assert_abs_diff_eq!(balanced.r, balanced.g);
+        assert_abs_diff_eq!(balanced.g, balanced.b);
+        assert_abs_diff_eq!(balanced.luminance(), illuminant.luminance());
+        // a red surface under the light stays red
+        let surface = balance.apply(illuminant * color!(1, 0.2, 0.2));
+        assert!(surface.r > 4. * surface.g);
+    }
+
+    #[test]
+    fn warm_light_is_cooled() {
+        let balance = WhiteBalance::for_temperature(2700.);
+        let lit = Color::from_temperature(2700.) * color!(0.5, 0.5, 0.5);
+        let balanced = balance.apply(lit);
+        assert_abs_diff_eq!(balanced.r, balanced.b, epsilon = 0.0001);
+        assert_eq!(WhiteBalance::for_temperature(6600.).apply(lit), lit);
+    }
+
+    #[test]
+    fn missing_channels_are_not_amplified_without_bound() {
+        let balance = WhiteBalance::for_illuminant(color!(1, 1, 0));
+        assert!(balance.apply(color!(0, 0, 1)).b.is_finite());
+    }
+}
@@ -0,0 +1,37 @@
+// The types, traits, functions and macros needed for most scenes, so that a binary can start with
+// `use ray_tracer_challenge::prelude::*;` instead of importing each one from its module. Less
+// common pieces, such as individual patterns or UV maps, still come from their own modules.
+pub use crate::camera::{Aperture, Camera};
+pub use crate::canvas::Canvas;
+pub use crate::color::Color;
+pub use crate::constants::{
+    black, blue, brown, cyan, glass, gray, green, metal, purple, red, white, yellow,
+    REFRACTION_AIR, REFRACTION_DIAMOND, REFRACTION_GLASS, REFRACTION_VACCUM, REFRACTION_WATER,
+};
+pub use crate::light::{light::Light, point_light::PointLight, rectangle_light::RectangleLight};
+pub use crate::material::Material;
+pub use crate::matrix::{identity_4x4, Matrix};
+pub use crate::pattern::{
+    checkers::Checkers, gradient::Gradient, pattern::Pattern, rings::Rings, stripes::Stripes,
+};
+pub use crate::render_settings::{RenderSettings, ShadingMode};
+pub use crate::shape::{
+    cone::Cone,
+    csg::{CSGOperator, CSG},
+    cube::Cube,
+    cylinder::Cylinder,
+    group::GroupShape,
+    plane::Plane,
+    shape::{Shape, ShapeBuilder},
+    shape_enum::ShapeEnum,
+    smooth_triangle::SmoothTriangle,
+    sphere::Sphere,
+    triangle::Triangle,
+};
+pub use crate::transformations::{
+    orient_towards, reflection_xy, reflection_xz, reflection_yz, rotation_x, rotation_y,
+    rotation_z, scaling, shearing, shearing_angles, translation, view_transform,
+};
+pub use crate::tuple::Tuple;
+pub use crate::world::World;
+pub use crate::{color, point, scene, vector};
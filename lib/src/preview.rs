@@ -0,0 +1,23 @@
+use crate::canvas::Canvas;
+use crate::checkpoint::Tile;
+use std::path::PathBuf;
+
+// What the viewer of a preview asked for, e.g. by pressing a key in its window
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreviewAction {
+    Continue,
+    // write the canvas as it is now to the given path as a PPM, then continue
+    Save(PathBuf),
+    // stop rendering and discard the canvas
+    Abort,
+}
+
+// Somewhere to show a render while Camera::render_progressive is still working on it, such as a
+// window. The canvas fills in tile by tile with one sample per pixel, and every later pass
+// averages another sample into every pixel, so the image starts out rough and keeps improving.
+pub trait Preview {
+    // Show canvas, of which tile has just been updated
+    fn show(&mut self, canvas: &Canvas, tile: Tile);
+    // Called after each tile is shown
+    fn poll(&mut self) -> PreviewAction;
+}
@@ -0,0 +1,13 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::world::World;
+
+// Somewhere other than the CPU renderer to trace the primary rays of a render, such as a GPU
+// compute shader (see the gpu module, behind the gpu feature), for fast interactive previews. A
+// backend only has to handle the simple cases, like plain Phong materials lit by a point light;
+// Camera::render_with_backend traces every pixel that it leaves out on the CPU as usual.
+pub trait PrimaryRayBackend {
+    // The color of each pixel of camera's view of world, row by row, or None for pixels that
+    // should be traced on the CPU
+    fn trace(&mut self, camera: &Camera, world: &World) -> Vec<Option<Color>>;
+}
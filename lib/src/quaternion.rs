@@ -0,0 +1,202 @@
+use crate::matrix::Matrix;
+use crate::tuple::Tuple;
+use approx::AbsDiffEq;
+
+// Unit quaternion representing a rotation. Used for animation, where interpolating rotations
+// as quaternions avoids the gimbal lock and uneven speed of interpolating Euler angles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1., 0., 0., 0.)
+    }
+
+    // Rotation of angle radians around axis, which need not be normalized
+    pub fn from_axis_angle(axis: Tuple, angle: f32) -> Quaternion {
+        let axis = axis.norm();
+        let half_sine = (angle / 2.).sin();
+        Quaternion::new(
+            (angle / 2.).cos(),
+            axis.x * half_sine,
+            axis.y * half_sine,
+            axis.z * half_sine,
+        )
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn norm(&self) -> Quaternion {
+        let magnitude = self.magnitude();
+        Quaternion::new(
+            self.w / magnitude,
+            self.x / magnitude,
+            self.y / magnitude,
+            self.z / magnitude,
+        )
+    }
+
+    // Hamilton product; the resulting rotation applies other first, then self
+    pub fn multiply(&self, other: &Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        )
+    }
+
+    // Spherical linear interpolation: rotates at constant speed along the shortest arc from self
+    // (t = 0) to other (t = 1)
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut other = *other;
+        let mut cos_theta = self.dot(&other);
+        // q and -q are the same rotation; pick the one that's closer to take the short way around
+        if cos_theta < 0. {
+            other = Quaternion::new(-other.w, -other.x, -other.y, -other.z);
+            cos_theta = -cos_theta;
+        }
+        let (from_weight, to_weight) = if cos_theta > 0.9995 {
+            // nearly identical rotations; fall back to linear interpolation to avoid dividing by ~0
+            (1. - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1. - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+        Quaternion::new(
+            from_weight * self.w + to_weight * other.w,
+            from_weight * self.x + to_weight * other.x,
+            from_weight * self.y + to_weight * other.y,
+            from_weight * self.z + to_weight * other.z,
+        )
+        .norm()
+    }
+
+    pub fn to_matrix(&self) -> Matrix {
+        let Quaternion { w, x, y, z } = self.norm();
+        matrix!(
+            [
+                1. - 2. * (y * y + z * z),
+                2. * (x * y - w * z),
+                2. * (x * z + w * y),
+                0
+            ],
+            [
+                2. * (x * y + w * z),
+                1. - 2. * (x * x + z * z),
+                2. * (y * z - w * x),
+                0
+            ],
+            [
+                2. * (x * z - w * y),
+                2. * (y * z + w * x),
+                1. - 2. * (x * x + y * y),
+                0
+            ],
+            [0, 0, 0, 1]
+        )
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+impl AbsDiffEq for Quaternion {
+    type Epsilon = f32;
+
+    fn default_epsilon() -> Self::Epsilon {
+        f32::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self::Epsilon) -> bool {
+        f32::abs_diff_eq(&self.w, &other.w, epsilon)
+            && f32::abs_diff_eq(&self.x, &other.x, epsilon)
+            && f32::abs_diff_eq(&self.y, &other.y, epsilon)
+            && f32::abs_diff_eq(&self.z, &other.z, epsilon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::identity_4x4;
+    use crate::transformations::{rotation_x, rotation_y, rotation_z};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn axis_angle_quaternion_matches_rotation_matrices() {
+        let test_data = vec![
+            ("x", vector!(1, 0, 0), rotation_x(PI / 3.)),
+            ("y", vector!(0, 1, 0), rotation_y(PI / 3.)),
+            ("z", vector!(0, 0, 1), rotation_z(PI / 3.)),
+        ];
+        for (name, axis, expected) in test_data {
+            println!("Case {}", name);
+            let q = Quaternion::from_axis_angle(axis, PI / 3.);
+            assert_abs_diff_eq!(q.to_matrix(), expected, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn identity_quaternion_is_identity_matrix() {
+        assert_abs_diff_eq!(Quaternion::identity().to_matrix(), identity_4x4());
+    }
+
+    #[test]
+    fn multiplying_quaternions_composes_rotations() {
+        let qx = Quaternion::from_axis_angle(vector!(1, 0, 0), PI / 2.);
+        let qy = Quaternion::from_axis_angle(vector!(0, 1, 0), PI / 4.);
+        assert_abs_diff_eq!(
+            qy.multiply(&qx).to_matrix(),
+            rotation_y(PI / 4.) * rotation_x(PI / 2.),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn slerp_halfway_rotates_half_the_angle() {
+        let from = Quaternion::identity();
+        let to = Quaternion::from_axis_angle(vector!(0, 1, 0), PI / 2.);
+        assert_abs_diff_eq!(
+            from.slerp(&to, 0.5),
+            Quaternion::from_axis_angle(vector!(0, 1, 0), PI / 4.),
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(from.slerp(&to, 0.), from, epsilon = 1e-6);
+        assert_abs_diff_eq!(from.slerp(&to, 1.), to, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn slerp_takes_shortest_path() {
+        let from = Quaternion::from_axis_angle(vector!(0, 0, 1), 0.1);
+        let to = Quaternion::from_axis_angle(vector!(0, 0, 1), 2. * PI - 0.1);
+        // going the short way around passes through the identity rotation
+        assert_abs_diff_eq!(
+            from.slerp(&to, 0.5).to_matrix(),
+            identity_4x4(),
+            epsilon = 1e-5
+        );
+    }
+}
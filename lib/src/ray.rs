@@ -7,6 +7,14 @@ pub struct Ray {
     pub direction: Tuple,
     // 1/x, 1/y, 1/z cached for faster intersection calculations later
     pub direction_inverses: Tuple,
+    // The ray stands for a narrow cone of rays, such as the ones passing through one pixel; these
+    // estimate its width for filtering textures (see width_at). Both are 0 for a single ray.
+    pub footprint: f32,
+    pub spread: f32,
+    // Whether the ray was cast from the camera, rather than toward a light or bounced off or
+    // through a surface. Only these rays may skip the back faces of closed meshes, since the
+    // others can start inside them.
+    pub primary: bool,
 }
 
 impl Ray {
@@ -18,16 +26,42 @@ impl Ray {
             origin,
             direction,
             direction_inverses,
+            footprint: 0.,
+            spread: 0.,
+            primary: false,
         }
     }
+    pub fn as_primary(mut self) -> Self {
+        self.primary = true;
+        self
+    }
+    // The cone is footprint wide at the origin and widens by spread for each unit of distance
+    pub fn with_cone(mut self, footprint: f32, spread: f32) -> Self {
+        self.footprint = footprint;
+        self.spread = spread;
+        self
+    }
+    // width of the ray's cone at the given distance
+    pub fn width_at(&self, distance: f32) -> f32 {
+        self.footprint + self.spread * distance.abs()
+    }
     pub fn position(&self, distance: f32) -> Tuple {
         self.origin + self.direction * distance
     }
     pub fn transform(&self, transform_matrix: &Matrix) -> Ray {
-        Self::new(
-            transform_matrix * &self.origin,
-            transform_matrix * &self.direction,
-        )
+        let mut transformed = *self;
+        transformed.transform_mut(transform_matrix);
+        transformed
+    }
+    // Transform this ray in place; cheaper than transform when the original is no longer needed
+    pub fn transform_mut(&mut self, transform_matrix: &Matrix) {
+        self.origin.transform_mut(transform_matrix);
+        self.direction.transform_mut(transform_matrix);
+        self.direction_inverses = vector!(
+            1. / self.direction.x,
+            1. / self.direction.y,
+            1. / self.direction.z
+        );
     }
     // derivation: think of a rhombus shape sitting on point on the surface, with the
     // bottom left and right sides being the incoming and reflected vectors and
@@ -137,6 +171,16 @@ mod tests {
         assert_eq!(r2.direction, vector!(0, 3, 0));
     }
 
+    #[test]
+    fn ray_transform_in_place() {
+        let mut r = Ray::new(point!(1, 2, 3), vector!(0, 1, 0));
+        let m = translation(3.0, 4.0, 5.0) * scaling(2.0, 3.0, 4.0);
+        let expected = r.transform(&m);
+        r.transform_mut(&m);
+        assert_eq!(r, expected);
+        assert_eq!(r, Ray::new(point!(5, 10, 17), vector!(0, 3, 0)));
+    }
+
     #[test]
     fn reflect_vector_approaching_at_45_degrees() {
         let v = vector!(1, -1, 0);
@@ -0,0 +1,180 @@
+use crate::constants::{
+    DEFAULT_RAY_RECURSION_DEPTH, SELF_INTERSECTION_AVOIDANCE_EPSILON,
+    SELF_INTERSECTION_RELATIVE_EPSILON,
+};
+use crate::world::RemainingBounces;
+
+// a hundredth of the way from black to white
+pub const DEFAULT_ADAPTIVE_TOLERANCE: f32 = 0.01;
+
+// What is drawn for each pixel. Everything but Full is a debugging aid. Wireframe, Normals and
+// Depth are for checking imported meshes, smooth normals or where objects were placed; they ignore
+// lights and materials, trace no secondary rays and show rays that hit nothing as black.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadingMode {
+    // lights, materials, shadows, reflections and refractions
+    Full,
+    // surfaces in gray, brighter where they face the camera, with the parts of triangles within
+    // edge_width of an edge (in barycentric coordinates, so relative to the triangle's size) in
+    // white
+    Wireframe { edge_width: f32 },
+    // the surface normal as a color, from -1 to 1 in each of x, y and z mapped to 0 to 1 in red,
+    // green and blue; smooth triangles show their interpolated normals, and normals pointing away
+    // from the camera are not flipped
+    Normals,
+    // distance to the hit, from white at the camera fading to black at max_distance
+    Depth { max_distance: f32 },
+    // How many ray-bounding box and ray-primitive tests the pixel took, including for its shadow
+    // and secondary rays, from dark purple for none through blue and green to yellow for max_tests
+    // or more (see post::colormap::viridis); shows where bounding boxes fail to cull, e.g. because
+    // a group needs to be divided. render_progressive shows the Full shading instead.
+    Heatmap { max_tests: u32 },
+}
+
+// Everything that controls how a scene is rendered, as opposed to what is in it; passed to
+// Camera::render. The presets trade quality for speed: draft is for quickly checking the
+// composition of a scene, medium for checking lighting and materials, and final_ for the finished
+// image. Fields can be adjusted from there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    // multiplies the width and height of the camera's image
+    pub resolution_scale: f32,
+    // rays traced through different parts of each pixel and averaged, to smooth jagged edges
+    pub samples_per_pixel: u32,
+    // Adaptive sampling: pixels whose samples still disagree, e.g. at edges or in soft shadows,
+    // get further batches of samples_per_pixel samples, up to this many in total, until the
+    // standard error of their average (in the channel where it is largest) is at most
+    // adaptive_tolerance. Smooth areas stop after the first batch. None to always take
+    // samples_per_pixel samples.
+    pub max_samples_per_pixel: Option<u32>,
+    pub adaptive_tolerance: f32,
+    // how many times a ray may be reflected, and how many times it may be refracted
+    pub max_reflections: i16,
+    pub max_refractions: i16,
+    // the most points that area lights check for shadows; None to check every cell of the light
+    pub soft_shadow_samples: Option<u32>,
+    // reflections and refractions that would contribute less than this fraction of a pixel's
+    // color are not traced
+    pub min_contribution: f32,
+    // after this many reflections and refractions, further bounces are traced at random, with a
+    // probability equal to their contribution, and brightened to make up for the ones that are
+    // not; chains of bounces then end without the bias of max_reflections/max_refractions, which
+    // can be raised. None to trace every bounce up to those limits.
+    pub russian_roulette_depth: Option<i16>,
+    // the brightest that a single reflected or refracted sample may be, so that rare bright bounces
+    // (e.g. weighted up by Russian roulette) don't leave single blown-out pixels; clamping loses
+    // some energy, so None to leave samples as they are
+    pub max_secondary_brightness: Option<f32>,
+    // whether objects cast shadows at all
+    pub shadows: bool,
+    // How far above (or below) a surface secondary rays start, so that they do not hit the surface
+    // they start from due to floating point error: the larger of self_intersection_epsilon and
+    // self_intersection_relative_epsilon times the largest coordinate of the hit point or ray
+    // origin. The relative part keeps very large scenes free of acne; scenes of microscopic
+    // objects should lower self_intersection_epsilon to avoid gaps where surfaces touch.
+    pub self_intersection_epsilon: f32,
+    pub self_intersection_relative_epsilon: f32,
+    // Shadow queries towards the same light from points in the same cube of this size reuse the
+    // first one's answer, which saves casting shadow rays for neighboring pixels, antialiasing
+    // samples and bounces that shade nearly the same point, at the cost of shadow edges becoming
+    // blocky at this scale. The cache is emptied at the start of every row (or tile), so the
+    // image still doesn't depend on the number of threads. None to cast every shadow ray.
+    pub shadow_cache_cell_size: Option<f32>,
+    pub shading: ShadingMode,
+    // number of threads that Camera::render splits the image's rows between; the image is the
+    // same for any number
+    pub render_threads: usize,
+    // picks the sample positions and other random choices made while rendering; images with
+    // different seeds differ only in their noise
+    pub seed: u32,
+}
+
+impl RenderSettings {
+    pub fn max_bounces(&self) -> RemainingBounces {
+        RemainingBounces::new(self.max_reflections, self.max_refractions)
+    }
+
+    pub fn draft() -> Self {
+        RenderSettings {
+            resolution_scale: 0.25,
+            samples_per_pixel: 1,
+            max_reflections: 1,
+            max_refractions: 1,
+            soft_shadow_samples: Some(1),
+            min_contribution: 0.1,
+            shadows: false,
+            ..RenderSettings::default()
+        }
+    }
+
+    pub fn medium() -> Self {
+        RenderSettings {
+            resolution_scale: 0.5,
+            samples_per_pixel: 4,
+            max_reflections: 2,
+            max_refractions: 3,
+            soft_shadow_samples: Some(4),
+            min_contribution: 0.01,
+            shadows: true,
+            ..RenderSettings::default()
+        }
+    }
+
+    // final is a reserved word
+    pub fn final_() -> Self {
+        RenderSettings {
+            samples_per_pixel: 16,
+            ..RenderSettings::default()
+        }
+    }
+}
+
+// one sample per pixel at full resolution, with every shadow
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            resolution_scale: 1.,
+            samples_per_pixel: 1,
+            max_samples_per_pixel: None,
+            adaptive_tolerance: DEFAULT_ADAPTIVE_TOLERANCE,
+            max_reflections: DEFAULT_RAY_RECURSION_DEPTH,
+            max_refractions: DEFAULT_RAY_RECURSION_DEPTH,
+            soft_shadow_samples: None,
+            min_contribution: 0.,
+            russian_roulette_depth: None,
+            max_secondary_brightness: None,
+            shadows: true,
+            self_intersection_epsilon: SELF_INTERSECTION_AVOIDANCE_EPSILON,
+            self_intersection_relative_epsilon: SELF_INTERSECTION_RELATIVE_EPSILON,
+            shadow_cache_cell_size: None,
+            shading: ShadingMode::Full,
+            render_threads: 1,
+            seed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_increase_in_quality() {
+        let presets = [
+            RenderSettings::draft(),
+            RenderSettings::medium(),
+            RenderSettings::final_(),
+        ];
+        for pair in presets.windows(2) {
+            let (lower, higher) = (pair[0], pair[1]);
+            assert!(lower.resolution_scale < higher.resolution_scale);
+            assert!(lower.samples_per_pixel < higher.samples_per_pixel);
+            assert!(lower.max_reflections < higher.max_reflections);
+            assert!(lower.max_refractions < higher.max_refractions);
+            let samples = |s: RenderSettings| s.soft_shadow_samples.unwrap_or(u32::MAX);
+            assert!(samples(lower) < samples(higher));
+            assert!(lower.min_contribution > higher.min_contribution);
+            assert!(lower.shadows <= higher.shadows);
+        }
+    }
+}
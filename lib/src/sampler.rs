@@ -0,0 +1,162 @@
+// Low-discrepancy numbers for the random choices made while rendering a pixel, such as where in
+// the pixel each ray goes and which point of an area light it checks for shadows. The samples of
+// a pixel take their numbers from Halton sequences, which cover [0, 1) far more evenly than
+// independent random numbers, so noise fades much faster as samples are added. Every pixel's
+// sequences are shifted by their own pseudo-random offset (Cranley-Patterson rotation) so that
+// neighboring pixels don't make the same choices, which would show up as patterns instead of
+// noise.
+//
+// The camera calls start_sample before tracing each sample, and the code tracing it draws numbers
+// with next_1d, each from the next dimension (that is, the next prime base) of the sequence.
+// Every number depends only on the seed, pixel, sample index and dimension, so a pixel comes out
+// the same no matter which thread renders it or what was rendered before it.
+use std::cell::Cell;
+
+// bases of the dimensions that use Halton sequences; higher bases need more samples than any
+// pixel gets before their points spread out, so further dimensions use hashed values instead
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+#[derive(Clone, Copy, Default)]
+struct SampleState {
+    seed: u32,
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u32,
+}
+
+thread_local! {
+    static CURRENT: Cell<SampleState> = Cell::new(SampleState::default());
+}
+
+// Start drawing numbers for the index-th sample of the pixel at (x, y) on this thread. Different
+// seeds give different, but equally well spread, numbers.
+pub fn start_sample(seed: u32, x: u32, y: u32, index: u32) {
+    CURRENT.with(|s| {
+        s.set(SampleState {
+            seed,
+            x,
+            y,
+            index,
+            dimension: 0,
+        })
+    });
+}
+
+// The next number in [0, 1) for the current sample. Outside of a render this continues the
+// numbers of pixel (0, 0)'s first sample.
+pub fn next_1d() -> f32 {
+    CURRENT.with(|s| {
+        let mut state = s.get();
+        let value = sample(state.seed, state.x, state.y, state.index, state.dimension);
+        state.dimension += 1;
+        s.set(state);
+        value
+    })
+}
+
+// The given dimension of the index-th sample of the pixel at (x, y), in [0, 1)
+pub fn sample(seed: u32, x: u32, y: u32, index: u32, dimension: u32) -> f32 {
+    let value = match PRIMES.get(dimension as usize) {
+        Some(&base) => radical_inverse(base, index),
+        None => hash_to_unit(&[seed, x, y, index, dimension]),
+    };
+    let offset = hash_to_unit(&[seed, x, y, dimension]);
+    let rotated = value + offset;
+    if rotated >= 1. {
+        rotated - 1.
+    } else {
+        rotated
+    }
+}
+
+// The digits of index in the given base, mirrored around the decimal point: the index-th element
+// of the Halton sequence for base. E.g. in base 2, 1, 2, 3, 4... become 0.1, 0.01, 0.11, 0.001...
+// (0.5, 0.25, 0.75, 0.125...), each filling the largest gap left by the ones before.
+pub fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inverse_base = 1. / base as f64;
+    let mut digit_value = inverse_base;
+    let mut result = 0.;
+    while index > 0 {
+        result += (index % base) as f64 * digit_value;
+        index /= base;
+        digit_value *= inverse_base;
+    }
+    // rounding to f32 could reach 1 for indices close to a power of the base
+    (result as f32).min(1. - f32::EPSILON / 2.)
+}
+
+// a pseudo-random number in [0, 1) determined by values (SplitMix64's mixing function)
+fn hash_to_unit(values: &[u32]) -> f32 {
+    let mut hash: u64 = 0x9e37_79b9_7f4a_7c15;
+    for &value in values {
+        hash ^= value as u64;
+        hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+    }
+    // the top 24 bits, which is all that an f32 below 1 can hold
+    (hash >> 40) as f32 / (1 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        let test_data = vec![
+            ("base 2", 2, vec![0., 0.5, 0.25, 0.75, 0.125]),
+            ("base 3", 3, vec![0., 1. / 3., 2. / 3., 1. / 9., 4. / 9.]),
+        ];
+        for (name, base, expected) in test_data {
+            for (index, value) in expected.into_iter().enumerate() {
+                let actual = radical_inverse(base, index as u32);
+                assert!(
+                    (actual - value).abs() < 1e-6,
+                    "Case {}: {} != {}",
+                    name,
+                    actual,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn samples_of_each_pixel_are_stratified() {
+        // n^2 samples of the dimension with base n put one point in each 1/n^2 of [0, 1)
+        for &(dimension, bin_count) in &[(0, 16), (1, 9), (2, 25)] {
+            let mut bins = vec![0; bin_count];
+            for index in 0..bin_count as u32 {
+                let value = sample(0, 3, 7, index, dimension);
+                assert!((0. ..1.).contains(&value));
+                bins[(value * bin_count as f32) as usize] += 1;
+            }
+            assert_eq!(bins, vec![1; bin_count], "Case {}", dimension);
+        }
+    }
+
+    #[test]
+    fn pixels_and_seeds_are_scrambled_differently() {
+        assert_ne!(sample(0, 0, 0, 1, 0), sample(0, 1, 0, 1, 0));
+        assert_ne!(sample(0, 0, 0, 1, 0), sample(0, 0, 1, 1, 0));
+        assert_ne!(sample(0, 0, 0, 1, 0), sample(1, 0, 0, 1, 0));
+        // but the same pixel always gets the same numbers
+        assert_eq!(sample(9, 5, 2, 3, 40), sample(9, 5, 2, 3, 40));
+    }
+
+    #[test]
+    fn next_1d_moves_through_dimensions() {
+        start_sample(1, 2, 3, 4);
+        let drawn: Vec<f32> = (0..3).map(|_| next_1d()).collect();
+        let expected: Vec<f32> = (0..3).map(|d| sample(1, 2, 3, 4, d)).collect();
+        assert_eq!(drawn, expected);
+        start_sample(1, 2, 3, 4);
+        assert_eq!(next_1d(), expected[0]);
+    }
+}
@@ -0,0 +1,963 @@
+// Saving and loading scenes as JSON. A World is converted to a SceneDescription, a plain-data
+// mirror of the scene that serde can (de)serialize, and a SceneDescription can be built back into
+// a World. Shapes, patterns and lights are stored as enums tagged with their "type".
+//
+// Group children are stored with their transformation relative to the group, the same way they
+// are passed to GroupShape::add_child. Shapes that cannot be described (e.g. texture-mapped
+// patterns or test shapes) cause an Unsupported error instead of being silently dropped.
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::light::light::Light;
+use crate::light::point_light::PointLight;
+use crate::light::rectangle_light::RectangleLight;
+use crate::material::Material;
+use crate::matrix::{identity_4x4, Matrix, SingularMatrixError};
+use crate::pattern::checkers::{Checkers, DEFAULT_SNAP_EPSILON};
+use crate::pattern::gradient::Gradient;
+use crate::pattern::pattern::Pattern;
+use crate::pattern::rings::Rings;
+use crate::pattern::sine_2d::Sine2D;
+use crate::pattern::stripes::Stripes;
+use crate::shape::cone::Cone;
+use crate::shape::csg::{CSGOperator, CSG};
+use crate::shape::cube::Cube;
+use crate::shape::cylinder::Cylinder;
+use crate::shape::group::GroupShape;
+use crate::shape::plane::Plane;
+use crate::shape::shape::Shape;
+use crate::shape::smooth_triangle::SmoothTriangle;
+use crate::shape::sphere::Sphere;
+use crate::shape::triangle::Triangle;
+use crate::tuple::Tuple;
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    // the scene contains something that cannot be saved
+    Unsupported(String),
+    // the scene file describes something that cannot be built, e.g. a degenerate transformation
+    Invalid(String),
+    Json(serde_json::Error),
+    IoError(io::Error),
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> SceneError {
+        SceneError::Json(err)
+    }
+}
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> SceneError {
+        SceneError::IoError(err)
+    }
+}
+impl From<SingularMatrixError> for SceneError {
+    fn from(err: SingularMatrixError) -> SceneError {
+        SceneError::Invalid(err.to_string())
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SceneError::Unsupported(what) => write!(f, "Cannot save {} to a scene file", what),
+            SceneError::Invalid(what) => write!(f, "Invalid scene file: {}", what),
+            SceneError::Json(err) => write!(f, "Invalid scene file: {}", err),
+            SceneError::IoError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Unsupported(_) | SceneError::Invalid(_) => None,
+            SceneError::Json(err) => Some(err),
+            SceneError::IoError(err) => Some(err),
+        }
+    }
+}
+
+// A world and the camera to view it with
+pub struct Scene {
+    pub world: World,
+    pub camera: Option<Camera>,
+}
+
+// Declares a Scene in Rust rather than JSON, so that mistakes are caught by the compiler:
+//
+// scene! {
+//     camera: {
+//         width: 100, height: 50, field_of_view: PI / 3.,
+//         from: point!(0, 1.5, -5), to: point!(0, 1, 0), up: vector!(0, 1, 0),
+//     },
+//     light: PointLight::new(point!(-10, 10, -10), white()),
+//     objects: [
+//         Plane,
+//         Sphere { transform: translation(0., 1., 0.), material: glass() },
+//         Cube { transform: scaling(0.5, 0.5, 0.5), shadow: false },
+//     ],
+// }
+//
+// The camera is optional. Each object is a shape type, created with its new(), and optionally
+// the transform, material and shadow (whether it casts one) to set on it. The shape types must be
+// imported where the macro is used.
+#[macro_export]
+macro_rules! scene {
+    (
+        $(camera: {
+            width: $width:expr,
+            height: $height:expr,
+            field_of_view: $field_of_view:expr,
+            from: $from:expr,
+            to: $to:expr,
+            up: $up:expr $(,)?
+        },)?
+        light: $light:expr,
+        objects: [$($shape:ident $({$($field:ident: $value:expr),* $(,)?})?),* $(,)?] $(,)?
+    ) => {{
+        #[allow(unused_imports)]
+        use $crate::shape::shape::Shape as _;
+        let camera: Option<$crate::camera::Camera> = $crate::scene!(
+            @camera $($width, $height, $field_of_view, $from, $to, $up)?
+        );
+        let mut world = $crate::world::World::new();
+        world.light = Some(Box::new($light));
+        $(
+            #[allow(unused_mut)]
+            let mut shape = $shape::new();
+            $($($crate::scene!(@set shape, $field, $value);)*)?
+            world.objects.push(Box::new(shape));
+        )*
+        $crate::scene_file::Scene { world, camera }
+    }};
+    (@camera) => {
+        None
+    };
+    (@camera $width:expr, $height:expr, $field_of_view:expr, $from:expr, $to:expr, $up:expr) => {
+        Some(
+            $crate::camera::Camera::builder()
+                .size($width, $height)
+                .fov($field_of_view)
+                .look_from($from)
+                .look_at($to)
+                .up($up)
+                .build(),
+        )
+    };
+    (@set $shape:ident, transform, $value:expr) => {
+        $shape.set_transformation($value)
+    };
+    (@set $shape:ident, material, $value:expr) => {
+        $shape.set_material($value)
+    };
+    (@set $shape:ident, shadow, $value:expr) => {
+        $shape.set_casts_shadow($value)
+    };
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightDescription>,
+    #[serde(default)]
+    pub objects: Vec<ShapeDescription>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub width: u32,
+    pub height: u32,
+    // in radians
+    pub field_of_view: f32,
+    #[serde(default = "identity_4x4")]
+    pub transform: Matrix,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightDescription {
+    Point {
+        position: Tuple,
+        intensity: Color,
+        #[serde(default = "default_casts_shadow")]
+        cast_shadows: bool,
+    },
+    // always sampled with random jitter when loaded
+    Rectangle {
+        intensity: Color,
+        corner: Tuple,
+        // full edges of the light, not of a single cell
+        u_vec: Tuple,
+        u_steps: i32,
+        v_vec: Tuple,
+        v_steps: i32,
+        #[serde(default = "default_casts_shadow")]
+        cast_shadows: bool,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub color: Color,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    pub reflective: f32,
+    pub transparency: f32,
+    pub refractive_index: f32,
+    pub dispersion: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<PatternDescription>,
+}
+
+impl Default for MaterialDescription {
+    fn default() -> Self {
+        describe_material(&Material::default()).unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PatternDescription {
+    Stripes {
+        a: Color,
+        b: Color,
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+        #[serde(default = "default_pattern_direction")]
+        direction: Tuple,
+    },
+    Gradient {
+        a: Color,
+        b: Color,
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+        #[serde(default = "default_pattern_direction")]
+        direction: Tuple,
+    },
+    Rings {
+        a: Color,
+        b: Color,
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+    },
+    Checkers {
+        a: Color,
+        b: Color,
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+        #[serde(default = "default_snap_epsilon")]
+        snap_epsilon: f32,
+    },
+    Sine2d {
+        a: Color,
+        b: Color,
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+    },
+}
+
+// Properties shared by all non-group shapes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShapeProperties {
+    #[serde(default = "identity_4x4")]
+    pub transform: Matrix,
+    #[serde(default)]
+    pub material: MaterialDescription,
+    #[serde(default = "default_casts_shadow")]
+    pub casts_shadow: bool,
+}
+
+fn default_casts_shadow() -> bool {
+    true
+}
+
+fn default_pattern_direction() -> Tuple {
+    vector!(1, 0, 0)
+}
+
+fn default_snap_epsilon() -> f32 {
+    DEFAULT_SNAP_EPSILON
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDescription {
+    Sphere {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+    },
+    Plane {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+    },
+    Cube {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+    },
+    // a missing minimum_y or maximum_y means the shape is infinitely long in that direction
+    Cylinder {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum_y: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum_y: Option<f32>,
+        #[serde(default)]
+        closed: bool,
+    },
+    Cone {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum_y: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum_y: Option<f32>,
+        #[serde(default)]
+        closed: bool,
+    },
+    Triangle {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+    },
+    SmoothTriangle {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        n1: Tuple,
+        n2: Tuple,
+        n3: Tuple,
+    },
+    Group {
+        #[serde(default = "identity_4x4")]
+        transform: Matrix,
+        children: Vec<ShapeDescription>,
+    },
+    Csg {
+        #[serde(flatten)]
+        properties: ShapeProperties,
+        operator: CSGOperator,
+        left: Box<ShapeDescription>,
+        right: Box<ShapeDescription>,
+    },
+}
+
+impl SceneDescription {
+    pub fn from_world(world: &World, camera: Option<&Camera>) -> Result<Self, SceneError> {
+        let light = match &world.light {
+            Some(light) => Some(
+                light
+                    .description()
+                    .ok_or_else(|| SceneError::Unsupported("light".to_string()))?,
+            ),
+            None => None,
+        };
+        let objects = world
+            .objects
+            .iter()
+            .map(|o| describe_shape(o.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SceneDescription {
+            camera: camera.map(|c| CameraDescription {
+                width: c.width(),
+                height: c.height(),
+                field_of_view: c.field_of_view(),
+                transform: c.transformation().clone(),
+            }),
+            light,
+            objects,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, SceneError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SceneError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    // Fails if the description can't be built, e.g. because of a degenerate transformation
+    pub fn build(&self) -> Result<Scene, SceneError> {
+        let mut world = World::new();
+        world.light = self.light.as_ref().map(build_light);
+        world.objects = self
+            .objects
+            .iter()
+            .map(build_shape)
+            .collect::<Result<Vec<_>, _>>()?;
+        let camera = match &self.camera {
+            Some(c) => {
+                let transform = build_transform(&c.transform)?;
+                if transform.try_inverse().is_none() {
+                    return Err(SingularMatrixError { matrix: transform }.into());
+                }
+                Some(Camera::new(c.width, c.height, c.field_of_view, transform))
+            }
+            None => None,
+        };
+        Ok(Scene { world, camera })
+    }
+}
+
+pub fn save_scene(path: &Path, world: &World, camera: Option<&Camera>) -> Result<(), SceneError> {
+    let json = SceneDescription::from_world(world, camera)?.to_json()?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let json = fs::read_to_string(path)?;
+    SceneDescription::from_json(&json)?.build()
+}
+
+fn describe_material(m: &Material) -> Result<MaterialDescription, SceneError> {
+    if m.has_scalar_patterns() {
+        return Err(SceneError::Unsupported(
+            "materials with scalar patterns".to_string(),
+        ));
+    }
+    Ok(MaterialDescription {
+        color: m.color,
+        ambient: m.ambient,
+        diffuse: m.diffuse,
+        specular: m.specular,
+        shininess: m.shininess,
+        reflective: m.reflective,
+        transparency: m.transparency,
+        refractive_index: m.refractive_index,
+        dispersion: m.dispersion,
+        pattern: match &m.pattern {
+            Some(p) => Some(describe_pattern(p.as_ref())?),
+            None => None,
+        },
+    })
+}
+
+// Matrices are only checked for being square when they are deserialized
+fn build_transform(m: &Matrix) -> Result<Matrix, SceneError> {
+    if m.size() != 4 {
+        return Err(SceneError::Invalid(format!(
+            "Transformations must be 4x4 matrices; found one of size {}",
+            m.size()
+        )));
+    }
+    Ok(m.clone())
+}
+
+fn build_direction(direction: &Tuple) -> Result<Tuple, SceneError> {
+    if !(direction.is_vector() && direction.magnitude() > 0.) {
+        return Err(SceneError::Invalid(format!(
+            "Pattern direction must be a non-zero vector; found {:?}",
+            direction
+        )));
+    }
+    Ok(*direction)
+}
+
+fn build_material(m: &MaterialDescription) -> Result<Material, SceneError> {
+    Ok(Material {
+        color: m.color,
+        ambient: m.ambient,
+        diffuse: m.diffuse,
+        specular: m.specular,
+        shininess: m.shininess,
+        reflective: m.reflective,
+        transparency: m.transparency,
+        refractive_index: m.refractive_index,
+        dispersion: m.dispersion,
+        pattern: match &m.pattern {
+            Some(p) => Some(build_pattern(p)?),
+            None => None,
+        },
+        ..Material::default()
+    })
+}
+
+fn describe_pattern(p: &dyn Pattern) -> Result<PatternDescription, SceneError> {
+    let transform = p.transformation().clone();
+    if let Some(p) = p.downcast_ref::<Stripes>() {
+        Ok(PatternDescription::Stripes {
+            a: p.a,
+            b: p.b,
+            transform,
+            direction: p.direction(),
+        })
+    } else if let Some(p) = p.downcast_ref::<Gradient>() {
+        let (a, b) = p.colors();
+        Ok(PatternDescription::Gradient {
+            a,
+            b,
+            transform,
+            direction: p.direction(),
+        })
+    } else if let Some(p) = p.downcast_ref::<Rings>() {
+        Ok(PatternDescription::Rings {
+            a: p.a,
+            b: p.b,
+            transform,
+        })
+    } else if let Some(p) = p.downcast_ref::<Checkers>() {
+        Ok(PatternDescription::Checkers {
+            a: p.a,
+            b: p.b,
+            transform,
+            snap_epsilon: p.snap_epsilon,
+        })
+    } else if let Some(p) = p.downcast_ref::<Sine2D>() {
+        let (a, b) = p.colors();
+        Ok(PatternDescription::Sine2d { a, b, transform })
+    } else {
+        Err(SceneError::Unsupported(format!("pattern {:?}", p)))
+    }
+}
+
+fn build_pattern(p: &PatternDescription) -> Result<Arc<dyn Pattern>, SceneError> {
+    let (mut pattern, transform): (Box<dyn Pattern>, &Matrix) = match p {
+        PatternDescription::Stripes {
+            a,
+            b,
+            transform,
+            direction,
+        } => (
+            Box::new(Stripes::along(*a, *b, build_direction(direction)?)),
+            transform,
+        ),
+        PatternDescription::Gradient {
+            a,
+            b,
+            transform,
+            direction,
+        } => (
+            Box::new(Gradient::along(*a, *b, build_direction(direction)?)),
+            transform,
+        ),
+        PatternDescription::Rings { a, b, transform } => (Box::new(Rings::new(*a, *b)), transform),
+        PatternDescription::Checkers {
+            a,
+            b,
+            transform,
+            snap_epsilon,
+        } => {
+            let mut checkers = Checkers::new(*a, *b);
+            checkers.snap_epsilon = *snap_epsilon;
+            (Box::new(checkers), transform)
+        }
+        PatternDescription::Sine2d { a, b, transform } => {
+            (Box::new(Sine2D::new(*a, *b)), transform)
+        }
+    };
+    pattern.try_set_transformation(build_transform(transform)?)?;
+    Ok(Arc::from(pattern))
+}
+
+fn build_light(l: &LightDescription) -> Box<dyn Light> {
+    match l {
+        LightDescription::Point {
+            position,
+            intensity,
+            cast_shadows,
+        } => {
+            let mut light = PointLight::new(*position, *intensity);
+            light.cast_shadows = *cast_shadows;
+            Box::new(light)
+        }
+        LightDescription::Rectangle {
+            intensity,
+            corner,
+            u_vec,
+            u_steps,
+            v_vec,
+            v_steps,
+            cast_shadows,
+        } => {
+            let mut light = RectangleLight::new(
+                *intensity, *corner, *u_vec, *u_steps, *v_vec, *v_steps, None,
+            );
+            light.cast_shadows = *cast_shadows;
+            Box::new(light)
+        }
+    }
+}
+
+fn describe_shape(s: &dyn Shape) -> Result<ShapeDescription, SceneError> {
+    if let Some(g) = s.downcast_ref::<GroupShape>() {
+        let children = g
+            .get_children()
+            .iter()
+            .map(|c| describe_shape(c.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(ShapeDescription::Group {
+            transform: s.transformation().clone(),
+            children,
+        });
+    }
+    let properties = ShapeProperties {
+        transform: s.transformation().clone(),
+        material: describe_material(s.material())?,
+        casts_shadow: s.casts_shadow(),
+    };
+    let finite = |y: f32| if y.is_finite() { Some(y) } else { None };
+    let description = if s.downcast_ref::<Sphere>().is_some() {
+        ShapeDescription::Sphere { properties }
+    } else if s.downcast_ref::<Plane>().is_some() {
+        ShapeDescription::Plane { properties }
+    } else if s.downcast_ref::<Cube>().is_some() {
+        ShapeDescription::Cube { properties }
+    } else if let Some(c) = s.downcast_ref::<Cylinder>() {
+        ShapeDescription::Cylinder {
+            properties,
+            minimum_y: finite(c.minimum_y),
+            maximum_y: finite(c.maximum_y),
+            closed: c.closed,
+        }
+    } else if let Some(c) = s.downcast_ref::<Cone>() {
+        ShapeDescription::Cone {
+            properties,
+            minimum_y: finite(c.minimum_y),
+            maximum_y: finite(c.maximum_y),
+            closed: c.closed,
+        }
+    } else if let Some(t) = s.downcast_ref::<Triangle>() {
+        ShapeDescription::Triangle {
+            properties,
+            p1: t.p1,
+            p2: t.p2,
+            p3: t.p3,
+        }
+    } else if let Some(t) = s.downcast_ref::<SmoothTriangle>() {
+        ShapeDescription::SmoothTriangle {
+            properties,
+            p1: t.base.p1,
+            p2: t.base.p2,
+            p3: t.base.p3,
+            n1: t.n1,
+            n2: t.n2,
+            n3: t.n3,
+        }
+    } else if let Some(c) = s.downcast_ref::<CSG>() {
+        let (left, right) = c.children();
+        ShapeDescription::Csg {
+            properties,
+            operator: c.operator(),
+            left: Box::new(describe_shape(left)?),
+            right: Box::new(describe_shape(right)?),
+        }
+    } else {
+        return Err(SceneError::Unsupported(format!("shape {:?}", s)));
+    };
+    Ok(description)
+}
+
+fn build_shape(s: &ShapeDescription) -> Result<Box<dyn Shape>, SceneError> {
+    let (mut shape, properties): (Box<dyn Shape>, &ShapeProperties) = match s {
+        ShapeDescription::Group {
+            transform,
+            children,
+        } => {
+            let mut g = GroupShape::new();
+            for child in children {
+                g.add_child(build_shape(child)?);
+            }
+            g.try_set_transformation(build_transform(transform)?)?;
+            return Ok(Box::new(g));
+        }
+        ShapeDescription::Sphere { properties } => (Box::new(Sphere::new()), properties),
+        ShapeDescription::Plane { properties } => (Box::new(Plane::new()), properties),
+        ShapeDescription::Cube { properties } => (Box::new(Cube::new()), properties),
+        ShapeDescription::Cylinder {
+            properties,
+            minimum_y,
+            maximum_y,
+            closed,
+        } => {
+            let mut c = Cylinder::new();
+            c.minimum_y = minimum_y.unwrap_or(f32::NEG_INFINITY);
+            c.maximum_y = maximum_y.unwrap_or(f32::INFINITY);
+            c.closed = *closed;
+            (Box::new(c), properties)
+        }
+        ShapeDescription::Cone {
+            properties,
+            minimum_y,
+            maximum_y,
+            closed,
+        } => {
+            let mut c = Cone::new();
+            c.minimum_y = minimum_y.unwrap_or(f32::NEG_INFINITY);
+            c.maximum_y = maximum_y.unwrap_or(f32::INFINITY);
+            c.closed = *closed;
+            (Box::new(c), properties)
+        }
+        ShapeDescription::Triangle {
+            properties,
+            p1,
+            p2,
+            p3,
+        } => (Box::new(Triangle::new(*p1, *p2, *p3)), properties),
+        ShapeDescription::SmoothTriangle {
+            properties,
+            p1,
+            p2,
+            p3,
+            n1,
+            n2,
+            n3,
+        } => (
+            Box::new(SmoothTriangle::new(*p1, *p2, *p3, *n1, *n2, *n3)),
+            properties,
+        ),
+        ShapeDescription::Csg {
+            properties,
+            operator,
+            left,
+            right,
+        } => (
+            Box::new(CSG::new(*operator, build_shape(left)?, build_shape(right)?)),
+            properties,
+        ),
+    };
+    shape.try_set_transformation(build_transform(&properties.transform)?)?;
+    shape.set_material(build_material(&properties.material)?);
+    shape.set_casts_shadow(properties.casts_shadow);
+    Ok(shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::white;
+    use crate::render_settings::RenderSettings;
+    use crate::transformations::{rotation_y, scaling, translation, view_transform};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn scene_macro_builds_world_and_camera() {
+        let glass = Material::builder().transparency(1.).build();
+        let scene = scene! {
+            camera: {
+                width: 20,
+                height: 10,
+                field_of_view: PI / 3.,
+                from: point!(0, 1.5, -5),
+                to: point!(0, 1, 0),
+                up: vector!(0, 1, 0),
+            },
+            light: PointLight::new(point!(-10, 10, -10), white()),
+            objects: [
+                Plane,
+                Sphere { transform: translation(0., 1., 0.), material: glass.clone() },
+                Cube { transform: scaling(0.5, 0.5, 0.5), shadow: false },
+            ],
+        };
+
+        let camera = scene.camera.unwrap();
+        assert_eq!((camera.width(), camera.height()), (20, 10));
+        assert_eq!(
+            camera.transformation(),
+            &view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0))
+        );
+        let world = scene.world;
+        assert_eq!(world.light.unwrap().position(), point!(-10, 10, -10));
+        assert_eq!(world.objects.len(), 3);
+        assert!(world.objects[0].is::<Plane>());
+        assert_eq!(world.objects[1].transformation(), &translation(0., 1., 0.));
+        assert_eq!(world.objects[1].material(), &glass);
+        assert!(world.objects[2].is::<Cube>());
+        assert!(!world.objects[2].casts_shadow());
+    }
+
+    #[test]
+    fn scene_macro_camera_is_optional() {
+        let scene = scene! {
+            light: PointLight::new(point!(0, 0, 0), white()),
+            objects: [Sphere],
+        };
+        assert!(scene.camera.is_none());
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    fn test_world() -> World {
+        let mut stripes = Stripes::new(color!(1, 0, 0), color!(0, 0, 1));
+        stripes.set_transformation(scaling(0.25, 1., 1.));
+        let floor_material = Material::builder()
+            .pattern(Arc::new(stripes))
+            .reflective(0.5)
+            .build();
+
+        let mut cylinder = Cylinder::build(translation(2., 0., 0.), Material::default());
+        cylinder.maximum_y = 2.;
+        cylinder.closed = true;
+
+        let mut group = GroupShape::new();
+        group.add_child(Box::new(Sphere::build(
+            translation(0., 1., 0.),
+            Material::default(),
+        )));
+        group.add_child(Box::new(Triangle::new(
+            point!(0, 1, 0),
+            point!(-1, 0, 0),
+            point!(1, 0, 0),
+        )));
+        group.set_transformation(rotation_y(PI / 2.));
+
+        let csg = CSG::new(
+            CSGOperator::Difference(),
+            Box::new(Cube::new()),
+            Box::new(Sphere::build(scaling(1.3, 1.3, 1.3), Material::default())),
+        );
+
+        let mut world = World::new();
+        world.light = Some(Box::new(PointLight::new(point!(-10, 10, -10), white())));
+        world.objects = vec![
+            Box::new(Plane::build(identity_4x4(), floor_material)),
+            Box::new(cylinder),
+            Box::new(group),
+            Box::new(csg),
+        ];
+        world
+    }
+
+    #[test]
+    fn world_survives_round_trip_through_json() {
+        let mut world = test_world();
+        let camera = Camera::new(
+            20,
+            10,
+            PI / 3.,
+            view_transform(point!(0, 1.5, -5), point!(0, 1, 0), vector!(0, 1, 0)),
+        );
+        let description = SceneDescription::from_world(&world, Some(&camera)).unwrap();
+        let json = description.to_json().unwrap();
+        let loaded = SceneDescription::from_json(&json).unwrap();
+        assert_eq!(loaded, description);
+
+        let mut scene = loaded.build().unwrap();
+        assert_eq!(scene.world.objects.len(), world.objects.len());
+        for (original, loaded) in world.objects.iter().zip(scene.world.objects.iter()) {
+            assert_eq!(original.fingerprint(), loaded.fingerprint());
+        }
+        let loaded_camera = scene.camera.unwrap();
+        assert_eq!(loaded_camera.width(), 20);
+        assert_eq!(loaded_camera.transformation(), camera.transformation());
+        assert_eq!(
+            camera.render(&mut world, RenderSettings::default()).to_ppm(),
+            loaded_camera
+                .render(&mut scene.world, RenderSettings::default())
+                .to_ppm()
+        );
+    }
+
+    #[test]
+    fn infinite_cylinder_bounds_are_omitted() {
+        let mut world = World::new();
+        world.objects.push(Box::new(Cylinder::new()));
+        let json = SceneDescription::from_world(&world, None)
+            .unwrap()
+            .to_json()
+            .unwrap();
+        assert!(!json.contains("null"));
+        let scene = SceneDescription::from_json(&json).unwrap().build().unwrap();
+        let cylinder = scene.world.objects[0].downcast_ref::<Cylinder>().unwrap();
+        assert_eq!(cylinder.minimum_y, f32::NEG_INFINITY);
+        assert_eq!(cylinder.maximum_y, f32::INFINITY);
+    }
+
+    #[test]
+    fn hand_written_scene_uses_defaults() {
+        let json = r#"{
+            "light": {"type": "point", "position": {"x": 0, "y": 5, "z": 0, "w": 1},
+                      "intensity": {"r": 1, "g": 1, "b": 1}},
+            "objects": [{"type": "sphere", "material": {"color": "red"}}]
+        }"#;
+        let scene = SceneDescription::from_json(json).unwrap().build().unwrap();
+        assert!(scene.camera.is_none());
+        let sphere = &scene.world.objects[0];
+        assert_eq!(*sphere.transformation(), identity_4x4());
+        assert_eq!(sphere.material().color, color!(1, 0, 0));
+        assert_eq!(sphere.material().diffuse, Material::default().diffuse);
+        assert!(sphere.casts_shadow());
+        assert!(scene.world.light.unwrap().casts_shadows());
+    }
+
+    #[test]
+    fn unsupported_patterns_are_reported() {
+        let mut world = World::new();
+        let material = Material::builder()
+            .pattern(Arc::new(crate::pattern::pattern::TestPattern::new()))
+            .build();
+        world
+            .objects
+            .push(Box::new(Sphere::build(identity_4x4(), material)));
+        match SceneDescription::from_world(&world, None) {
+            Err(SceneError::Unsupported(message)) => assert!(message.contains("TestPattern")),
+            _ => panic!("Expected Unsupported error"),
+        }
+    }
+
+    #[test]
+    fn invalid_scenes_are_reported() {
+        let sphere = |transform: &str| {
+            format!(
+                r#"{{"objects": [{{"type": "sphere", "transform": {}}}]}}"#,
+                transform
+            )
+        };
+        let stripes = r#"{"objects": [{"type": "sphere", "material": {"pattern": {
+            "type": "stripes", "a": "white", "b": "black",
+            "direction": {"x": 0, "y": 0, "z": 0, "w": 0}}}}]}"#;
+        let test_data = vec![
+            (
+                "singular transform",
+                sphere("[[0,0,0,0],[0,1,0,0],[0,0,1,0],[0,0,0,1]]"),
+                "not invertible",
+            ),
+            (
+                "3x3 transform",
+                sphere("[[1,0,0],[0,1,0],[0,0,1]]"),
+                "size 3",
+            ),
+            (
+                "singular group transform",
+                r#"{"objects": [{"type": "group", "children": [],
+                    "transform": [[0,0,0,0],[0,0,0,0],[0,0,0,0],[0,0,0,0]]}]}"#
+                    .to_string(),
+                "not invertible",
+            ),
+            (
+                "singular camera transform",
+                r#"{"camera": {"width": 2, "height": 2, "field_of_view": 1,
+                    "transform": [[0,0,0,0],[0,0,0,0],[0,0,0,0],[0,0,0,0]]}}"#
+                    .to_string(),
+                "not invertible",
+            ),
+            ("zero pattern direction", stripes.to_string(), "non-zero"),
+        ];
+        for (name, json, expected) in test_data {
+            println!("Case {}", name);
+            match SceneDescription::from_json(&json).unwrap().build() {
+                Err(SceneError::Invalid(message)) => {
+                    assert!(message.contains(expected), "{}", message)
+                }
+                Err(e) => panic!("Expected Invalid error; got {}", e),
+                Ok(_) => panic!("Expected Invalid error"),
+            }
+        }
+    }
+}
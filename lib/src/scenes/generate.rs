@@ -0,0 +1,234 @@
+// Parameterized stress scenes for measuring performance. All randomness comes from the given
+// seed, so the same parameters always produce the same scene.
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::constants::{glass, white};
+use crate::light::point_light::PointLight;
+use crate::material::Material;
+use crate::shape::cube::Cube;
+use crate::shape::group::GroupShape;
+use crate::shape::plane::Plane;
+use crate::shape::shape::Shape;
+use crate::shape::sphere::Sphere;
+use crate::shape::triangle::Triangle;
+use crate::transformations::{rotation_y, scaling, translation, view_transform};
+use crate::tuple::Tuple;
+use crate::world::World;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+
+// Objects in the generated scenes are scattered over [-SCENE_EXTENT, SCENE_EXTENT] in x and z
+pub const SCENE_EXTENT: f32 = 10.;
+
+// Triangles around the cone of each generated tree; the trunk adds 11 more: 8 sides and 3 on top
+const TREE_SEGMENTS: usize = 12;
+
+// Camera looking down at the generated scenes from the front
+pub fn benchmark_camera(width_pixels: u32, height_pixels: u32) -> Camera {
+    Camera::new(
+        width_pixels,
+        height_pixels,
+        PI / 3.,
+        view_transform(
+            point!(0, SCENE_EXTENT, -2. * SCENE_EXTENT),
+            point!(0, 0, 0),
+            vector!(0, 1, 0),
+        ),
+    )
+}
+
+fn benchmark_world() -> World {
+    let mut world = World::new();
+    world.light = Some(Box::new(PointLight::new(
+        point!(-SCENE_EXTENT, 2. * SCENE_EXTENT, -SCENE_EXTENT),
+        white(),
+    )));
+    let floor_material = Material::builder()
+        .color(color!(0.8, 0.8, 0.8))
+        .specular(0.)
+        .build();
+    world.objects.push(Box::new(Plane::build(
+        translation(0., 0., 0.),
+        floor_material,
+    )));
+    world
+}
+
+fn random_color(rng: &mut StdRng) -> Color {
+    color!(
+        rng.gen_range(0.1, 1.),
+        rng.gen_range(0.1, 1.),
+        rng.gen_range(0.1, 1.)
+    )
+}
+
+// count spheres of random size, color and reflectivity resting on a floor plane
+pub fn random_spheres(count: usize, seed: u64) -> World {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = benchmark_world();
+    for _ in 0..count {
+        let radius = rng.gen_range(0.1, 1.);
+        let x = rng.gen_range(-SCENE_EXTENT, SCENE_EXTENT);
+        let z = rng.gen_range(-SCENE_EXTENT, SCENE_EXTENT);
+        let material = Material::builder()
+            .color(random_color(&mut rng))
+            .reflective(rng.gen_range(0., 0.5))
+            .build();
+        world.objects.push(Box::new(Sphere::build(
+            translation(x, radius, z) * scaling(radius, radius, radius),
+            material,
+        )));
+    }
+    world
+}
+
+// columns x rows glass cubes evenly spaced on a floor plane; stresses refraction
+pub fn glass_cube_grid(columns: usize, rows: usize) -> World {
+    let mut world = benchmark_world();
+    let spacing_x = 2. * SCENE_EXTENT / columns.max(1) as f32;
+    let spacing_z = 2. * SCENE_EXTENT / rows.max(1) as f32;
+    let half_size = 0.35 * spacing_x.min(spacing_z);
+    for column in 0..columns {
+        for row in 0..rows {
+            let x = -SCENE_EXTENT + (column as f32 + 0.5) * spacing_x;
+            let z = -SCENE_EXTENT + (row as f32 + 0.5) * spacing_z;
+            world.objects.push(Box::new(Cube::build(
+                translation(x, half_size, z) * scaling(half_size, half_size, half_size),
+                glass(),
+            )));
+        }
+    }
+    world
+}
+
+// Simple low-poly tree: a cone of triangles on top of a capped square trunk, 1 unit tall and standing
+// on the origin
+pub fn tree_mesh() -> GroupShape {
+    let mut tree = GroupShape::new();
+    let apex = point!(0, 1, 0);
+    let crown_base = 0.3;
+    let crown_radius = 0.3;
+    for i in 0..TREE_SEGMENTS {
+        let angle1 = 2. * PI * i as f32 / TREE_SEGMENTS as f32;
+        let angle2 = 2. * PI * (i + 1) as f32 / TREE_SEGMENTS as f32;
+        let p1 = point!(
+            crown_radius * angle1.cos(),
+            crown_base,
+            crown_radius * angle1.sin()
+        );
+        let p2 = point!(
+            crown_radius * angle2.cos(),
+            crown_base,
+            crown_radius * angle2.sin()
+        );
+        tree.add_child(Box::new(Triangle::new(apex, p1, p2)));
+    }
+    let trunk_radius = 0.05;
+    let corners = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)];
+    for i in 0..corners.len() {
+        let (x1, z1) = corners[i];
+        let (x2, z2) = corners[(i + 1) % corners.len()];
+        let bottom1 = point!(x1 * trunk_radius, 0, z1 * trunk_radius);
+        let bottom2 = point!(x2 * trunk_radius, 0, z2 * trunk_radius);
+        let top1 = point!(x1 * trunk_radius, crown_base, z1 * trunk_radius);
+        let top2 = point!(x2 * trunk_radius, crown_base, z2 * trunk_radius);
+        tree.add_child(Box::new(Triangle::new(bottom1, bottom2, top2)));
+        tree.add_child(Box::new(Triangle::new(bottom1, top2, top1)));
+    }
+    // cap the top of the trunk, split so that no edge runs along the trunk's axis
+    let top = |x: f32, z: f32| point!(x * trunk_radius, crown_base, z * trunk_radius);
+    let middle = top(0., -1.);
+    tree.add_child(Box::new(Triangle::new(top(-1., -1.), middle, top(-1., 1.))));
+    tree.add_child(Box::new(Triangle::new(middle, top(1., -1.), top(1., 1.))));
+    tree.add_child(Box::new(Triangle::new(middle, top(1., 1.), top(-1., 1.))));
+    tree
+}
+
+// count copies of tree_mesh with random position, rotation, size and color. Each tree is a
+// divided group, so this stresses bounding box culling and triangle intersection.
+pub fn mesh_forest(count: usize, seed: u64) -> World {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = benchmark_world();
+    let mut forest = GroupShape::new();
+    let prototype = tree_mesh();
+    for _ in 0..count {
+        let height = rng.gen_range(0.5, 2.);
+        let x = rng.gen_range(-SCENE_EXTENT, SCENE_EXTENT);
+        let z = rng.gen_range(-SCENE_EXTENT, SCENE_EXTENT);
+        let mut tree = prototype.clone();
+        tree.set_transformation(
+            translation(x, 0., z)
+                * rotation_y(rng.gen_range(0., 2. * PI))
+                * scaling(height, height, height),
+        );
+        tree.set_material(
+            Material::builder()
+                .color(random_color(&mut rng))
+                .specular(0.1)
+                .build(),
+        );
+        forest.add_child(Box::new(tree));
+    }
+    forest.divide(4);
+    world.objects.push(Box::new(forest));
+    world
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn random_spheres_has_requested_number_of_spheres_plus_floor() {
+        let world = random_spheres(25, 7);
+        assert_eq!(world.objects.len(), 26);
+    }
+
+    #[test]
+    fn same_seed_generates_same_scene() {
+        let world1 = random_spheres(10, 42);
+        let world2 = random_spheres(10, 42);
+        let world3 = random_spheres(10, 43);
+        for (s1, s2) in world1.objects.iter().zip(world2.objects.iter()) {
+            assert_eq!(s1.transformation(), s2.transformation());
+            assert_eq!(s1.material(), s2.material());
+        }
+        assert_ne!(
+            world1.objects[1].transformation(),
+            world3.objects[1].transformation()
+        );
+    }
+
+    #[test]
+    fn glass_cube_grid_has_one_cube_per_cell() {
+        let world = glass_cube_grid(4, 3);
+        assert_eq!(world.objects.len(), 13);
+        assert_eq!(world.objects[1].material().transparency, 1.);
+    }
+
+    #[test]
+    fn tree_mesh_is_unit_height() {
+        let tree = tree_mesh();
+        assert_eq!(tree.get_children().len(), TREE_SEGMENTS + 11);
+        let bounds = tree.bounding_box();
+        assert_eq!(bounds.min.y, 0.);
+        assert_eq!(bounds.max.y, 1.);
+    }
+
+    #[test]
+    fn mesh_forest_tree_can_be_hit() {
+        let world = mesh_forest(1, 3);
+        assert_eq!(world.objects.len(), 2);
+        let forest = world.objects[1].parent_space_bounding_box();
+        let above_tree = point!(
+            (forest.min.x + forest.max.x) / 2.,
+            forest.max.y + 1.,
+            (forest.min.z + forest.max.z) / 2.
+        );
+        let xs = world.intersect(Ray::new(above_tree, vector!(0, -1, 0)));
+        // crown, trunk and floor
+        assert!(xs.len() > 2);
+    }
+}
@@ -1,12 +1,13 @@
 use crate::bounding_box::BoundingBox;
 use crate::intersection::Intersection;
 use crate::material::Material;
-use crate::matrix::Matrix;
+use crate::matrix::{identity_4x4, Matrix, SingularMatrixError};
 use crate::object_id::ObjectId;
 use crate::ray::Ray;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 // Other shape implementations should delegate to this one where these defaults are acceptable.
 #[derive(Debug, Clone)]
@@ -16,13 +17,28 @@ pub struct BaseShape {
     t: Matrix,
     t_inverse: Matrix,
     t_inverse_transpose: Matrix,
-    m: Material,
+    // whether t is the identity, in which case rays and normals need no transforming
+    identity: bool,
+    // shared, since groups assign the same material to all of their children
+    m: Arc<Material>,
 }
 
 impl BaseShape {
     pub fn new() -> Self {
         Default::default()
     }
+
+    // for callers that already know the inverse of t
+    pub(crate) fn set_transformation_and_inverse(&mut self, t: Matrix, t_inverse: Matrix) {
+        self.t_inverse_transpose = t_inverse.transpose();
+        self.t_inverse = t_inverse;
+        self.t = t;
+        self.identity = self.t == identity_4x4();
+    }
+
+    pub(crate) fn has_identity_transformation(&self) -> bool {
+        self.identity
+    }
 }
 
 impl Default for BaseShape {
@@ -34,7 +50,8 @@ impl Default for BaseShape {
             t: Matrix::default(),
             t_inverse: Matrix::default(),
             t_inverse_transpose: Matrix::default(),
-            m: Material::default(),
+            identity: true,
+            m: Arc::new(Material::default()),
         }
     }
 }
@@ -54,14 +71,24 @@ impl Shape for BaseShape {
         &self.t
     }
     fn set_transformation(&mut self, t: Matrix) {
+        self.t_inverse = match t.try_inverse() {
+            Some(inverse) => inverse,
+            None => panic!("{}", SingularMatrixError { matrix: t }),
+        };
+        self.t_inverse_transpose = self.t_inverse.transpose();
         self.t = t;
-        self.t_inverse = self.t.inverse();
-        self.t_inverse_transpose = self.t.inverse().transpose();
+        self.identity = self.t == identity_4x4();
     }
     fn material(&self) -> &Material {
         &self.m
     }
     fn set_material(&mut self, m: Material) {
+        self.m = Arc::new(m);
+    }
+    fn shared_material(&self) -> &Arc<Material> {
+        &self.m
+    }
+    fn set_shared_material(&mut self, m: Arc<Material>) {
         self.m = m;
     }
     fn casts_shadow(&self) -> bool {
@@ -82,7 +109,7 @@ impl Shape for BaseShape {
     fn local_intersect(&self, _object_ray: Ray) -> Vec<Intersection> {
         unimplemented!()
     }
-    fn local_norm_at(&self, _object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
         unimplemented!()
     }
 
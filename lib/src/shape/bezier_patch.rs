@@ -0,0 +1,481 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_tuple;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::obj_parser::ParseError;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::group::GroupShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Read};
+
+// Rows and columns of the grid of triangles used to find starting points for Newton's method
+const TESSELLATION: usize = 8;
+// Newton's method stops when the ray and the surface are this close
+const CLOSE_ENOUGH: f32 = 0.000_01;
+const MAX_NEWTON_STEPS: usize = 10;
+// how far outside of [0, 1] u and v may drift before a solution is rejected
+const UV_TOLERANCE: f32 = 0.000_1;
+
+// A bicubic Bézier patch, defined by a 4x4 grid of control points. u runs along the rows of the
+// grid and v down its columns. Rays are intersected with a coarse triangulation of the patch first;
+// Newton's method then moves each hit onto the exact surface, whose normal is known exactly.
+#[derive(Debug, Clone)]
+pub struct BezierPatch {
+    base: BaseShape,
+    control_points: [Tuple; 16],
+    // control points as vectors from the origin, which can be weighted and summed
+    offsets: [Tuple; 16],
+    // the surface sampled at (TESSELLATION + 1)^2 evenly spaced (u, v), row by row
+    samples: Vec<Tuple>,
+}
+
+// The cubic Bernstein polynomials at t, and their derivatives
+fn bernstein(t: f32) -> ([f32; 4], [f32; 4]) {
+    let s = 1. - t;
+    (
+        [s * s * s, 3. * t * s * s, 3. * t * t * s, t * t * t],
+        [
+            -3. * s * s,
+            3. * s * s - 6. * t * s,
+            6. * t * s - 3. * t * t,
+            3. * t * t,
+        ],
+    )
+}
+
+impl BezierPatch {
+    // control_points are given row by row
+    pub fn new(control_points: [Tuple; 16]) -> Self {
+        let mut offsets = [vector!(0, 0, 0); 16];
+        for (offset, p) in offsets.iter_mut().zip(control_points.iter()) {
+            *offset = vector!(p.x, p.y, p.z);
+        }
+        let mut patch = BezierPatch {
+            base: BaseShape::new(),
+            control_points,
+            offsets,
+            samples: vec![],
+        };
+        let mut samples = Vec::with_capacity((TESSELLATION + 1).pow(2));
+        for row in 0..=TESSELLATION {
+            for column in 0..=TESSELLATION {
+                let (u, v) = (
+                    column as f32 / TESSELLATION as f32,
+                    row as f32 / TESSELLATION as f32,
+                );
+                samples.push(patch.point_at(u, v));
+            }
+        }
+        patch.samples = samples;
+        patch
+    }
+
+    pub fn build(control_points: [Tuple; 16], transform: Matrix, material: Material) -> Self {
+        let mut s = BezierPatch::new(control_points);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    pub fn control_points(&self) -> &[Tuple; 16] {
+        &self.control_points
+    }
+
+    // the point on the surface at (u, v) and the surface's derivatives along u and v there
+    fn evaluate(&self, u: f32, v: f32) -> (Tuple, Tuple, Tuple) {
+        let (bu, dbu) = bernstein(u);
+        let (bv, dbv) = bernstein(v);
+        let mut p = vector!(0, 0, 0);
+        let mut du = vector!(0, 0, 0);
+        let mut dv = vector!(0, 0, 0);
+        for row in 0..4 {
+            for column in 0..4 {
+                let offset = self.offsets[row * 4 + column];
+                p = p + offset * (bu[column] * bv[row]);
+                du = du + offset * (dbu[column] * bv[row]);
+                dv = dv + offset * (bu[column] * dbv[row]);
+            }
+        }
+        (point!(p.x, p.y, p.z), du, dv)
+    }
+
+    pub fn point_at(&self, u: f32, v: f32) -> Tuple {
+        self.evaluate(u, v).0
+    }
+
+    // Refines a guess at where the ray hits the surface by solving
+    // P(u, v) - (origin + t * direction) = 0 with Newton's method
+    fn refine(&self, ray: &Ray, mut u: f32, mut v: f32, mut t: f32) -> Option<(f32, f32, f32)> {
+        for _ in 0..MAX_NEWTON_STEPS {
+            let (p, du, dv) = self.evaluate(u, v);
+            let error = p - ray.position(t);
+            if error.magnitude() < CLOSE_ENOUGH {
+                let in_range = |x: f32| (-UV_TOLERANCE..=1. + UV_TOLERANCE).contains(&x);
+                return if in_range(u) && in_range(v) {
+                    Some((u.clamp(0., 1.), v.clamp(0., 1.), t))
+                } else {
+                    None
+                };
+            }
+            // solve [du dv -direction] * step = error with Cramer's rule
+            let minus_direction = -ray.direction;
+            let determinant = du.dot(dv.cross(minus_direction));
+            if determinant.abs() < f32::EPSILON {
+                return None;
+            }
+            u -= error.dot(dv.cross(minus_direction)) / determinant;
+            v -= du.dot(error.cross(minus_direction)) / determinant;
+            t -= du.dot(dv.cross(error)) / determinant;
+        }
+        None
+    }
+
+    // Candidate hits from the triangulated patch as (u, v, t)
+    fn tessellated_hits(&self, ray: &Ray) -> Vec<(f32, f32, f32)> {
+        let mut hits = vec![];
+        let step = 1. / TESSELLATION as f32;
+        let sample = |row: usize, column: usize| self.samples[row * (TESSELLATION + 1) + column];
+        for row in 0..TESSELLATION {
+            for column in 0..TESSELLATION {
+                let (u0, v0) = (column as f32 * step, row as f32 * step);
+                let p00 = sample(row, column);
+                let p10 = sample(row, column + 1);
+                let p01 = sample(row + 1, column);
+                let p11 = sample(row + 1, column + 1);
+                // two triangles per cell; (a, b) are the barycentric coordinates of the hit
+                if let Some((a, b, t)) = intersect_triangle(ray, p00, p10, p01) {
+                    hits.push((u0 + a * step, v0 + b * step, t));
+                }
+                if let Some((a, b, t)) = intersect_triangle(ray, p11, p01, p10) {
+                    hits.push((u0 + (1. - a) * step, v0 + (1. - b) * step, t));
+                }
+            }
+        }
+        hits
+    }
+}
+
+// Möller–Trumbore intersection with the triangle p1, p2, p3: the distance and the barycentric
+// coordinates of the hit along p1->p2 and p1->p3
+fn intersect_triangle(ray: &Ray, p1: Tuple, p2: Tuple, p3: Tuple) -> Option<(f32, f32, f32)> {
+    let e1 = p2 - p1;
+    let e2 = p3 - p1;
+    let dir_cross_e2 = ray.direction.cross(e2);
+    let determinant = e1.dot(dir_cross_e2);
+    if determinant.abs() < 0.000_000_1 {
+        return None;
+    }
+    let f = 1. / determinant;
+    let p1_to_origin = ray.origin - p1;
+    let a = f * p1_to_origin.dot(dir_cross_e2);
+    if !(0. ..=1.).contains(&a) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(e1);
+    let b = f * ray.direction.dot(origin_cross_e1);
+    if b < 0. || a + b > 1. {
+        return None;
+    }
+    Some((a, b, f * e2.dot(origin_cross_e1)))
+}
+
+impl Shape for BezierPatch {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    // the hits' u and v are the coordinates of the hit on the patch
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        if !self.bounding_box().intersects(object_ray) {
+            return vec![];
+        }
+        let mut intersections: Vec<Intersection> = vec![];
+        for (u, v, t) in self.tessellated_hits(&object_ray) {
+            if let Some((u, v, t)) = self.refine(&object_ray, u, v, t) {
+                // neighboring triangles often lead to the same hit
+                let is_duplicate = intersections
+                    .iter()
+                    .any(|i| (i.distance - t).abs() < CLOSE_ENOUGH * 10.);
+                if !is_duplicate {
+                    intersections.push(Intersection::new_with_uv(t, self, u, v));
+                }
+            }
+        }
+        Intersection::sort(&mut intersections);
+        intersections
+    }
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        unreachable!(
+            "Use local_norm_at_hit; the normal is found from the hit's u and v on the patch"
+        )
+    }
+
+    fn local_norm_at_hit(&self, _object_point: Tuple, hit: &Intersection) -> Tuple {
+        let (_, du, dv) = self.evaluate(hit.u, hit.v);
+        let normal = du.cross(dv);
+        if normal.magnitude() > f32::EPSILON {
+            return normal.norm();
+        }
+        // Some patches (like the top of the teapot's lid) collapse a whole edge to one point,
+        // where one derivative is 0; use the normal from just inside of the patch instead.
+        let nudge = |x: f32| x + (0.5 - x) * 0.001;
+        let (_, du, dv) = self.evaluate(nudge(hit.u), nudge(hit.v));
+        du.cross(dv).norm()
+    }
+
+    // the patch lies inside of the convex hull of its control points
+    fn bounding_box(&self) -> BoundingBox {
+        let mut b = BoundingBox::empty();
+        for p in self.control_points.iter() {
+            b.add_point(*p);
+        }
+        b
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        for p in self.control_points.iter() {
+            hash_tuple(hasher, p);
+        }
+    }
+}
+
+// Reads patches in the format of Newell's teapot data: the number of patches, then one line per
+// patch with the 1-based indices of its 16 control points, then the number of vertices, then one
+// line per vertex. Values are separated by commas and/or whitespace.
+pub fn parse_bezier_patches<T: Read>(reader: T) -> Result<GroupShape, ParseError> {
+    let mut lines = vec![];
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push((index + 1, line));
+        }
+    }
+    let mut lines = lines.into_iter();
+    let mut next_line = |description: &str| {
+        lines.next().ok_or_else(|| {
+            ParseError::UnexpectedSymbol(format!("File ended before the {} was given", description))
+        })
+    };
+    let values = |line: &str| -> Vec<String> {
+        line.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+            .collect()
+    };
+
+    let (_, count) = next_line("number of patches")?;
+    // the counts come from the file, so the lists grow only as their lines are actually read
+    let patch_count = count.trim().parse::<usize>()?;
+    let mut patches = vec![];
+    for _ in 0..patch_count {
+        let (line_number, line) = next_line("patch")?;
+        let indices = values(&line)
+            .iter()
+            .map(|value| value.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()?;
+        if indices.len() != 16 {
+            return Err(ParseError::MalformedFace(format!(
+                "Expected 16 control point indices at line {}; found {}",
+                line_number,
+                indices.len()
+            )));
+        }
+        patches.push((line_number, indices));
+    }
+
+    let (_, count) = next_line("number of vertices")?;
+    let vertex_count = count.trim().parse::<usize>()?;
+    let mut vertices = vec![];
+    for _ in 0..vertex_count {
+        let (line_number, line) = next_line("vertex")?;
+        let coordinates = values(&line)
+            .iter()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()?;
+        if coordinates.len() != 3 {
+            return Err(ParseError::MalformedVertex(format!(
+                "Expected 3 coordinates at line {}; found {}",
+                line_number,
+                coordinates.len()
+            )));
+        }
+        let vertex =
+            Tuple::try_new(coordinates[0], coordinates[1], coordinates[2], 1.).map_err(|e| {
+                ParseError::MalformedVertex(format!(
+                    "Invalid vertex at line {}: {}",
+                    line_number, e
+                ))
+            })?;
+        vertices.push(vertex);
+    }
+
+    let mut group = GroupShape::new();
+    for (line_number, indices) in patches {
+        let mut control_points = [point!(0, 0, 0); 16];
+        for (control_point, &index) in control_points.iter_mut().zip(indices.iter()) {
+            *control_point = *index
+                .checked_sub(1)
+                .and_then(|index| vertices.get(index))
+                .ok_or_else(|| {
+                    ParseError::MalformedFace(format!(
+                        "Control point index {} at line {} does not refer to a vertex",
+                        index, line_number
+                    ))
+                })?;
+        }
+        group.add_child(Box::new(BezierPatch::new(control_points)));
+    }
+    Ok(group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a patch over x and z in [0, 3] with the given heights for the control points
+    fn patch_with_heights(heights: [f32; 16]) -> BezierPatch {
+        let mut control_points = [point!(0, 0, 0); 16];
+        for row in 0..4 {
+            for column in 0..4 {
+                control_points[row * 4 + column] =
+                    point!(column as f32, heights[row * 4 + column], row as f32);
+            }
+        }
+        BezierPatch::new(control_points)
+    }
+
+    fn bump() -> BezierPatch {
+        let mut heights = [0.; 16];
+        for &i in &[5, 6, 9, 10] {
+            heights[i] = 1.;
+        }
+        patch_with_heights(heights)
+    }
+
+    #[test]
+    fn ray_intersects_flat_patch() {
+        let patch = patch_with_heights([0.; 16]);
+        let xs = patch.local_intersect(Ray::new(point!(1.5, 5, 0.75), vector!(0, -1, 0)));
+        assert_eq!(xs.len(), 1);
+        assert_abs_diff_eq!(xs[0].distance, 5., epsilon = 0.0001);
+        assert_abs_diff_eq!(xs[0].u, 0.5, epsilon = 0.0001);
+        assert_abs_diff_eq!(xs[0].v, 0.25, epsilon = 0.0001);
+        let normal = patch.local_norm_at_hit(point!(1.5, 0, 0.75), &xs[0]);
+        assert_abs_diff_eq!(normal.y.abs(), 1.);
+    }
+
+    #[test]
+    fn ray_intersects_curved_patch_exactly() {
+        let patch = bump();
+        // the inner control points contribute (3/8)^2 each to the center of the patch
+        let xs = patch.local_intersect(Ray::new(point!(1.5, 5, 1.5), vector!(0, -1, 0)));
+        assert_eq!(xs.len(), 1);
+        assert_abs_diff_eq!(xs[0].distance, 5. - 0.5625, epsilon = 0.0001);
+        // the top of the bump is flat
+        let normal = patch.local_norm_at_hit(point!(1.5, 0.5625, 1.5), &xs[0]);
+        assert_abs_diff_eq!(normal, vector!(0, -1, 0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn ray_crosses_bump_twice() {
+        let patch = bump();
+        let xs = patch.local_intersect(Ray::new(point!(-1, 0.3, 1.5), vector!(1, 0, 0)));
+        assert_eq!(xs.len(), 2);
+        for i in &xs {
+            let p = patch.point_at(i.u, i.v);
+            assert_abs_diff_eq!(p.y, 0.3, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn ray_misses_patch() {
+        let patch = bump();
+        let xs = patch.local_intersect(Ray::new(point!(4, 5, 1.5), vector!(0, -1, 0)));
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn patch_bounding_box_contains_control_points() {
+        let b = bump().bounding_box();
+        assert_eq!(b.min, point!(0, 0, 0));
+        assert_eq!(b.max, point!(3, 1, 3));
+    }
+
+    #[test]
+    fn parse_patches() {
+        let data = "1
+            1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16
+            16
+            0,0,0
+            1,0,0
+            2,0,0
+            3,0,0
+            0,0,1
+            1,1,1
+            2,1,1
+            3,0,1
+            0,0,2
+            1,1,2
+            2,1,2
+            3,0,2
+            0,0,3
+            1,0,3
+            2,0,3
+            3,0,3";
+        let group = parse_bezier_patches(data.as_bytes()).unwrap();
+        let children = group.get_children();
+        assert_eq!(children.len(), 1);
+        let patch = children[0].downcast_ref::<BezierPatch>().unwrap();
+        assert_eq!(patch.control_points(), bump().control_points());
+    }
+
+    #[test]
+    fn parse_patches_with_bad_index() {
+        let data =
+            "1\n1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,17\n16\n".to_string() + &"0,0,0\n".repeat(16);
+        match parse_bezier_patches(data.as_bytes()) {
+            Err(ParseError::MalformedFace(message)) => assert!(message.contains("line 2")),
+            other => panic!("Expected malformed face error; got {:?}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn parse_patches_with_bad_counts_or_vertices() {
+        let patches = "1\n1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16\n";
+        let test_data = vec![
+            (
+                "huge patch count",
+                "100000000000000000\n".to_string(),
+                "patch",
+            ),
+            (
+                "huge vertex count",
+                patches.to_string() + "100000000000000000\n",
+                "vertex",
+            ),
+            (
+                "NaN vertex",
+                patches.to_string() + "16\n" + &"0,0,0\n".repeat(15) + "nan nan nan\n",
+                "line 19",
+            ),
+        ];
+        for (name, data, expected) in test_data {
+            println!("Case {}", name);
+            match parse_bezier_patches(data.as_bytes()) {
+                Err(ParseError::UnexpectedSymbol(message))
+                | Err(ParseError::MalformedVertex(message)) => {
+                    assert!(message.contains(expected), "{}", message)
+                }
+                other => panic!("Expected parse error; got {:?}", other.is_ok()),
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
 use crate::intersection::Intersection;
 use crate::material::Material;
 use crate::matrix::Matrix;
@@ -7,6 +8,7 @@ use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
 use std::f32;
+use std::hash::Hasher;
 
 // Base shape is a double-napped cone with tips meeting at the origin and extending vertically along the y axis.
 
@@ -29,6 +31,14 @@ impl Cone {
         s.set_material(material);
         s
     }
+
+    // Cut off below minimum_y and above maximum_y, optionally with caps on the ends
+    pub fn with_limits(mut self, minimum_y: f32, maximum_y: f32, closed: bool) -> Self {
+        self.minimum_y = minimum_y;
+        self.maximum_y = maximum_y;
+        self.closed = closed;
+        self
+    }
 }
 
 impl Default for Cone {
@@ -57,7 +67,7 @@ impl Shape for Cone {
     }
 
     // norms at the corners are the norms of one of the adjacent sides
-    fn local_norm_at(&self, object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
         let dist_square = object_point.x.powi(2) + object_point.z.powi(2);
         // TODO: why does this work? Shouldn't it be < y?
         if dist_square < 1.0 {
@@ -82,6 +92,12 @@ impl Shape for Cone {
             point!(limit, self.maximum_y, limit),
         );
     }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_f32(hasher, self.minimum_y);
+        hash_f32(hasher, self.maximum_y);
+        hasher.write_u8(self.closed as u8);
+    }
 }
 
 const CLOSE_TO_ZERO: f32 = 0.000_001;
@@ -177,9 +193,9 @@ impl Cone {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::utils::dummy_intersection;
+    use crate::transformations::{rotation_z, translation};
     use approx::AbsDiffEq;
-    use std::f32::consts::SQRT_2;
+    use std::f32::consts::{PI, SQRT_2};
 
     // #[test]
     // fn ray_misses_cone() {
@@ -271,7 +287,7 @@ mod tests {
             ("3", point!(-1, -1, 0), vector!(-1, 1, 0)),
         ];
         for (name, point, expected_normal) in test_data {
-            let normal = c.local_norm_at(point, &dummy_intersection(&c));
+            let normal = c.local_norm_at(point);
             assert_eq!(normal, expected_normal, "{}", name);
         }
     }
@@ -296,4 +312,37 @@ mod tests {
         assert_eq!(b.min, point!(-5, -5, -5));
         assert_eq!(b.max, point!(5, 3, 5));
     }
+
+    #[test]
+    fn half_bounded_cone_bounding_box() {
+        let mut c = Cone::new();
+        c.maximum_y = 2.;
+        let b = c.bounding_box();
+        assert_eq!(
+            b.min,
+            point!(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        );
+        assert_eq!(b.max, point!(f32::INFINITY, 2, f32::INFINITY));
+    }
+
+    #[test]
+    fn cone_bounding_box_in_parent_space() {
+        let mut c = Cone::new();
+        c.minimum_y = -1.;
+        c.maximum_y = 2.;
+        c.set_transformation(translation(1., 0., 0.));
+        let b = c.parent_space_bounding_box();
+        assert_eq!(b.min, point!(-1, -1, -2));
+        assert_eq!(b.max, point!(3, 2, 2));
+
+        // an unbounded cone stays unbounded instead of turning into NaN
+        let mut c = Cone::new();
+        c.set_transformation(translation(1., 0., 0.) * rotation_z(PI / 4.));
+        let b = c.parent_space_bounding_box();
+        assert_eq!(
+            b.min,
+            point!(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        );
+        assert_eq!(b.max, point!(f32::INFINITY, f32::INFINITY, f32::INFINITY));
+    }
 }
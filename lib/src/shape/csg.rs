@@ -1,13 +1,14 @@
 use crate::bounding_box::BoundingBox;
-use crate::intersection::Intersection;
+use crate::intersection::{Intersection, Intersections};
 use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
-use std::cell::RefCell;
-use std::cmp::Ordering::Equal;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CSGOperator {
     Union(),
     Intersection(),
@@ -20,7 +21,9 @@ pub struct CSG {
     op: CSGOperator,
     s1: Box<dyn Shape>,
     s2: Box<dyn Shape>,
-    cached_bounding_box: RefCell<Option<BoundingBox>>,
+    // in the CSG's object space, so unaffected by its own transformation; the children can't be
+    // changed after construction, so it never needs to be invalidated
+    cached_bounding_box: OnceLock<BoundingBox>,
 }
 
 impl CSG {
@@ -30,13 +33,21 @@ impl CSG {
             op,
             s1,
             s2,
-            cached_bounding_box: RefCell::new(None),
+            cached_bounding_box: OnceLock::new(),
         }
     }
 
+    pub fn operator(&self) -> CSGOperator {
+        self.op
+    }
+
+    pub fn children(&self) -> (&dyn Shape, &dyn Shape) {
+        (self.s1.as_ref(), self.s2.as_ref())
+    }
+
     fn filter_intersections<'a>(
         &self,
-        intersections: &Vec<Intersection<'a>>,
+        intersections: &[Intersection<'a>],
     ) -> Vec<Intersection<'a>> {
         // begin outside of both children
         let mut inside_s1 = false;
@@ -44,9 +55,12 @@ impl CSG {
         let mut filtered: Vec<Intersection> = vec![];
 
         for i in intersections {
-            let hit_s1 = self.s1.includes(i.object);
+            let hit_s1 = match i.instance {
+                Some(instance) => self.s1.includes(instance),
+                None => self.s1.includes(i.object),
+            };
             if CSG::intersection_allowed(self.op, hit_s1, inside_s1, inside_s2) {
-                filtered.push(*i);
+                filtered.push(i.clone());
             }
             if hit_s1 {
                 inside_s1 = !inside_s1;
@@ -75,7 +89,6 @@ impl CSG {
 }
 
 impl Shape for CSG {
-    // TODO: pass transformation down tree similar to in group
     fn get_base(&self) -> &BaseShape {
         &self.base
     }
@@ -85,25 +98,25 @@ impl Shape for CSG {
     }
 
     fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
-        let mut intersections = vec![];
-
         let b = self.bounding_box();
         if !b.intersects(object_ray) {
-            return intersections;
+            return vec![];
         }
 
-        for i in self.s1.as_ref().intersect(object_ray) {
-            intersections.push(i);
-        }
-        for i in self.s2.as_ref().intersect(object_ray) {
-            intersections.push(i);
-        }
-        intersections.sort_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap_or(Equal));
+        let mut intersections = Intersections::new();
+        intersections.merge(self.s1.as_ref().intersect(object_ray));
+        intersections.merge(self.s2.as_ref().intersect(object_ray));
 
-        self.filter_intersections(&intersections)
+        let mut filtered = self.filter_intersections(&intersections);
+        if !self.get_base().has_identity_transformation() {
+            for i in &mut filtered {
+                i.found_through(self.transformation_inverse());
+            }
+        }
+        filtered
     }
 
-    fn local_norm_at(&self, _object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
         // intersection objects will always point to s1 and s2, so no need to implement this
         unimplemented!()
     }
@@ -117,16 +130,20 @@ impl Shape for CSG {
     }
 
     fn bounding_box(&self) -> BoundingBox {
-        let mut cached_box = self.cached_bounding_box.borrow_mut();
-        cached_box.get_or_insert_with(|| {
+        *self.cached_bounding_box.get_or_init(|| {
             let mut b = BoundingBox::empty();
 
             b.add_bounding_box(self.s1.parent_space_bounding_box());
             b.add_bounding_box(self.s2.parent_space_bounding_box());
 
             b
-        });
-        cached_box.unwrap()
+        })
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        self.op.hash(&mut &mut *hasher);
+        self.s1.hash_structure(hasher);
+        self.s2.hash_structure(hasher);
     }
 
     fn divide(&mut self, threshold: usize) {
@@ -142,7 +159,7 @@ impl Clone for CSG {
             op: self.op,
             s1: self.s1.clone(),
             s2: self.s2.clone(),
-            cached_bounding_box: RefCell::new(None),
+            cached_bounding_box: OnceLock::new(),
         }
     }
 }
@@ -327,6 +344,19 @@ mod tests {
         assert_eq!(b.max, point!(3, 4, 5));
     }
 
+    #[test]
+    fn csg_cached_bounding_box_survives_transformation() {
+        let mut shape = CSG::new(
+            CSGOperator::Union(),
+            Box::new(Sphere::new()),
+            Box::new(Sphere::new()),
+        );
+        let before = shape.bounding_box();
+        shape.set_transformation(translation(5., 0., 0.));
+        assert_eq!(shape.bounding_box(), before);
+        assert_eq!(shape.parent_space_bounding_box().min, point!(4, -1, -1));
+    }
+
     #[test]
     fn ray_intersection_doesnt_test_children_if_bounding_box_is_missed() {
         let left = TestShape::new();
@@ -336,12 +366,12 @@ mod tests {
         shape.intersect(r);
 
         let test_shape1 = shape.s1.downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape1.saved_ray.borrow());
-        assert!(test_shape1.saved_ray.borrow().is_none());
+        println!("{:?}", test_shape1.saved_ray());
+        assert!(test_shape1.saved_ray().is_none());
 
         let test_shape2 = shape.s2.downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape2.saved_ray.borrow());
-        assert!(test_shape2.saved_ray.borrow().is_none());
+        println!("{:?}", test_shape2.saved_ray());
+        assert!(test_shape2.saved_ray().is_none());
     }
 
     #[test]
@@ -353,12 +383,12 @@ mod tests {
         shape.intersect(r);
 
         let test_shape1 = shape.s1.downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape1.saved_ray.borrow());
-        assert!(test_shape1.saved_ray.borrow().is_some());
+        println!("{:?}", test_shape1.saved_ray());
+        assert!(test_shape1.saved_ray().is_some());
 
         let test_shape2 = shape.s2.downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape2.saved_ray.borrow());
-        assert!(test_shape2.saved_ray.borrow().is_some());
+        println!("{:?}", test_shape2.saved_ray());
+        assert!(test_shape2.saved_ray().is_some());
     }
 
     #[test]
@@ -63,7 +63,7 @@ impl Shape for Cube {
     }
 
     // norms at the corners are the norms of one of the adjacent sides
-    fn local_norm_at(&self, object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
         let (x_abs, y_abs, z_abs) = (
             object_point.x.abs(),
             object_point.y.abs(),
@@ -131,7 +131,6 @@ pub fn aabb_intersection(object_ray: Ray, min: Tuple, max: Tuple) -> Option<(f32
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::utils::dummy_intersection;
     #[test]
     fn ray_intersects_cube() {
         let c = Cube::new();
@@ -220,12 +219,7 @@ mod tests {
             ),
         ];
         for (name, point, expected_normal) in test_data {
-            assert_eq!(
-                c.local_norm_at(point, &dummy_intersection(&c)),
-                expected_normal,
-                "{}",
-                name
-            );
+            assert_eq!(c.local_norm_at(point), expected_normal, "{}", name);
         }
     }
 }
@@ -1,4 +1,5 @@
 use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
 use crate::intersection::Intersection;
 use crate::material::Material;
 use crate::matrix::Matrix;
@@ -7,6 +8,7 @@ use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
 use std::f32;
+use std::hash::Hasher;
 
 // Base shape is parallel to the Y-axis and infinitely long, centered on world origin
 
@@ -29,6 +31,14 @@ impl Cylinder {
         s.set_material(material);
         s
     }
+
+    // Cut off below minimum_y and above maximum_y, optionally with caps on the ends
+    pub fn with_limits(mut self, minimum_y: f32, maximum_y: f32, closed: bool) -> Self {
+        self.minimum_y = minimum_y;
+        self.maximum_y = maximum_y;
+        self.closed = closed;
+        self
+    }
 }
 
 impl Default for Cylinder {
@@ -59,7 +69,7 @@ impl Shape for Cylinder {
     }
 
     // norms at the corners are the norms of one of the adjacent sides
-    fn local_norm_at(&self, object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
         let dist_square = object_point.x.powi(2) + object_point.z.powi(2);
         if dist_square < 1.0 {
             if object_point.y >= self.maximum_y - CLOSE_TO_ZERO {
@@ -77,6 +87,12 @@ impl Shape for Cylinder {
             max: point!(1, self.maximum_y, 1),
         }
     }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_f32(hasher, self.minimum_y);
+        hash_f32(hasher, self.maximum_y);
+        hasher.write_u8(self.closed as u8);
+    }
 }
 
 const CLOSE_TO_ZERO: f32 = 0.000_001;
@@ -154,7 +170,6 @@ impl Cylinder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::utils::dummy_intersection;
     use approx::AbsDiffEq;
 
     #[test]
@@ -334,7 +349,7 @@ mod tests {
             ("-x", point!(-1, 1, 0), vector!(-1, 0, 0)),
         ];
         for (name, point, expected_normal) in test_data {
-            let normal = c.local_norm_at(point, &dummy_intersection(&c));
+            let normal = c.local_norm_at(point);
             assert_eq!(normal, expected_normal, "{}", name);
         }
     }
@@ -357,7 +372,7 @@ mod tests {
             ("+y at top front", point!(0, 2, 0.5), vector!(0, 1, 0)),
         ];
         for (name, point, expected_normal) in test_data {
-            let normal = c.local_norm_at(point, &dummy_intersection(&c));
+            let normal = c.local_norm_at(point);
             assert_eq!(normal, expected_normal, "{}", name);
         }
     }
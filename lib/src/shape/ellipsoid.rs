@@ -0,0 +1,173 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A sphere stretched to the given radii along x, y and z, centered on the origin. This is the same
+// as scaling a sphere, but the stretch stays part of the shape, so the shape's transformation is
+// free for placing it and the bounding box hugs the surface.
+#[derive(Debug, Clone)]
+pub struct Ellipsoid {
+    base: BaseShape,
+    pub radius_x: f32,
+    pub radius_y: f32,
+    pub radius_z: f32,
+}
+
+impl Ellipsoid {
+    pub fn new(radius_x: f32, radius_y: f32, radius_z: f32) -> Self {
+        assert!(
+            radius_x > 0. && radius_y > 0. && radius_z > 0.,
+            "Ellipsoid radii must be positive: ({}, {}, {})",
+            radius_x,
+            radius_y,
+            radius_z
+        );
+        Ellipsoid {
+            base: BaseShape::new(),
+            radius_x,
+            radius_y,
+            radius_z,
+        }
+    }
+
+    pub fn build(radii: (f32, f32, f32), transform: Matrix, material: Material) -> Self {
+        let mut s = Ellipsoid::new(radii.0, radii.1, radii.2);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+}
+
+impl Default for Ellipsoid {
+    fn default() -> Self {
+        Ellipsoid::new(1., 1., 1.)
+    }
+}
+
+impl Shape for Ellipsoid {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    // Squash the ray into the space of a unit sphere. Distances along the ray are the same there,
+    // since the origin and direction are squashed alike.
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let origin = vector!(
+            object_ray.origin.x / self.radius_x,
+            object_ray.origin.y / self.radius_y,
+            object_ray.origin.z / self.radius_z
+        );
+        let direction = vector!(
+            object_ray.direction.x / self.radius_x,
+            object_ray.direction.y / self.radius_y,
+            object_ray.direction.z / self.radius_z
+        );
+        let a = direction.dot(direction);
+        let b = 2.0 * direction.dot(origin);
+        let c = origin.dot(origin) - 1.0;
+        let discriminant = b.powi(2) - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return vec![];
+        }
+
+        let two_a = 2.0 * a;
+        let discriminant_sqrt = discriminant.sqrt();
+        vec![
+            Intersection::new((-b - discriminant_sqrt) / two_a, self),
+            Intersection::new((-b + discriminant_sqrt) / two_a, self),
+        ]
+    }
+
+    // the gradient of (x/rx)^2 + (y/ry)^2 + (z/rz)^2; unlike the point itself, this stays
+    // perpendicular to the surface when the radii differ
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
+        vector!(
+            object_point.x / self.radius_x.powi(2),
+            object_point.y / self.radius_y.powi(2),
+            object_point.z / self.radius_z.powi(2)
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min: point!(-self.radius_x, -self.radius_y, -self.radius_z),
+            max: point!(self.radius_x, self.radius_y, self.radius_z),
+        }
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_f32(hasher, self.radius_x);
+        hash_f32(hasher, self.radius_y);
+        hash_f32(hasher, self.radius_z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::sphere::Sphere;
+    use crate::transformations::scaling;
+
+    #[test]
+    fn ray_intersects_ellipsoid() {
+        let e = Ellipsoid::new(3., 2., 1.);
+        let test_data = vec![
+            ("along x", point!(-5, 0, 0), vector!(1, 0, 0), vec![2., 8.]),
+            ("along y", point!(0, -5, 0), vector!(0, 1, 0), vec![3., 7.]),
+            ("along z", point!(0, 0, -5), vector!(0, 0, 1), vec![4., 6.]),
+            ("miss", point!(0, 2.5, -5), vector!(0, 0, 1), vec![]),
+        ];
+        for (name, origin, direction, expected) in test_data {
+            let xs = e.local_intersect(Ray::new(origin, direction));
+            let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+            assert_eq!(distances.len(), expected.len(), "Case {}", name);
+            for (distance, expected) in distances.iter().zip(expected) {
+                assert_abs_diff_eq!(*distance, expected, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn ellipsoid_matches_scaled_sphere() {
+        let e = Ellipsoid::new(3., 2., 1.);
+        let mut s = Sphere::new();
+        s.set_transformation(scaling(3., 2., 1.));
+        let r = Ray::new(point!(-4, 1, -3), vector!(1, 0.2, 1).norm());
+        let ellipsoid_hits = e.intersect(r);
+        let sphere_hits = s.intersect(r);
+        assert_eq!(ellipsoid_hits.len(), 2);
+        for (e_hit, s_hit) in ellipsoid_hits.iter().zip(sphere_hits.iter()) {
+            assert_abs_diff_eq!(e_hit.distance, s_hit.distance, epsilon = 0.0001);
+            let p = r.position(e_hit.distance);
+            assert_abs_diff_eq!(e.normal_at(&p), s.normal_at(&p), epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn ellipsoid_normals() {
+        let e = Ellipsoid::new(2., 1., 1.);
+        // at 45 degrees around the ellipse in xy
+        let p = point!(2. * 0.5f32.sqrt(), 0.5f32.sqrt(), 0);
+        let normal = e.local_norm_at(p).norm();
+        // perpendicular to the ellipse's tangent there, (-2, 1, 0)
+        assert_abs_diff_eq!(normal.dot(vector!(-2, 1, 0)), 0.);
+        assert!(normal.x > 0. && normal.y > 0.);
+    }
+
+    #[test]
+    fn ellipsoid_bounding_box() {
+        let b = Ellipsoid::new(3., 2., 1.).bounding_box();
+        assert_eq!(b.min, point!(-3, -2, -1));
+        assert_eq!(b.max, point!(3, 2, 1));
+    }
+}
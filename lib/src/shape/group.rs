@@ -1,22 +1,28 @@
-use crate::bounding_box::BoundingBox;
-use crate::intersection::Intersection;
+use crate::bounding_box::{BoundingBox, BoundingBoxPacket, BOX_PACKET_SIZE};
+use crate::intersection::{Intersection, Intersections};
 use crate::material::Material;
-use crate::matrix::Matrix;
 use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
+use crate::stats::{self, Phase};
 use crate::tuple::Tuple;
-use std::cell::RefCell;
+use std::hash::Hasher;
+use std::sync::{Arc, OnceLock};
 
-// instead of using BaseShape for the transform here, we propagate transforms to the children and then
-// locally always assume a transform of I, allowing children to do all actual ray transformations.
-// This leads to fewer multiplications and also allows us to avoid linking to parent groups, which
-// is a pain in the Rusty...
+// The group's transformation is kept in its BaseShape and applied to rays once for all of the
+// children, so changing it doesn't touch the children at all. Since children don't link to their
+// parent groups (which is a pain in the Rusty...), hits record the inverse transformations of the
+// groups they were found through instead (see Intersection::world_to_parent).
 #[derive(Debug, Default)]
 pub struct GroupShape {
     base: BaseShape,
     children: Vec<Box<dyn Shape>>,
-    cached_bounding_box: RefCell<Option<BoundingBox>>,
+    cached_bounding_box: OnceLock<BoundingBox>,
+    // parent-space bounding boxes of the children, in the same order
+    cached_child_boxes: OnceLock<Vec<BoundingBoxPacket>>,
+    // materials assigned to some of the children, in the same order; unlike the children's own
+    // materials, they are kept when the group's material is set
+    face_materials: Vec<Option<Arc<Material>>>,
 }
 
 impl GroupShape {
@@ -26,53 +32,177 @@ impl GroupShape {
 
     pub fn with_children(children: Vec<Box<dyn Shape>>) -> Self {
         let mut g = Self::default();
+        g.face_materials = vec![None; children.len()];
         g.children = children;
         g
     }
 
-    /// Note to clients: the children's transforms will have this group's transform baked in.
-    /// To get the child in its original form, call remove_child (not implemented)
+    /// The children's transforms are relative to this group's space
     pub fn get_children(&self) -> &Vec<Box<dyn Shape>> {
         &self.children
     }
 
-    pub fn add_child(&mut self, mut child: Box<dyn Shape>) {
-        // bake this group's transform into the child's existing transform
-        let old_child_transform = child.transformation().clone();
-        child.set_transformation(self.transformation() * &old_child_transform);
+    pub fn add_child(&mut self, child: Box<dyn Shape>) {
         self.children.push(child);
+        self.face_materials.push(None);
+        self.invalidate_bounding_box();
+    }
+
+    // Gives some of the children (by index), such as the faces of a mesh that use a different
+    // material than the rest, a material of their own. Hits on them (or on their descendants)
+    // carry it, and it is kept when the group's material is set later; a material assigned by an
+    // inner group takes precedence over one assigned by an outer group.
+    pub fn assign_material<I: IntoIterator<Item = usize>>(&mut self, children: I, m: Material) {
+        let m = Arc::new(m);
+        for child in children {
+            self.face_materials[child] = Some(Arc::clone(&m));
+        }
+    }
+
+    // Bakes the transformations of all of the shapes in the group that can be baked
+    // (see Shape::bake_transform), such as the triangles of a mesh, which speeds up intersecting
+    // static props. Group transformations are not baked, since a group transforms the ray only once
+    // for all of its children, and setting them later still moves the whole group. Returns whether
+    // every shape was baked.
+    pub fn bake_transforms(&mut self) -> bool {
+        let mut all_baked = true;
+        for child in &mut self.children {
+            all_baked &= child.bake_transform();
+        }
+        self.invalidate_bounding_box();
+        all_baked
+    }
+
+    // the material assigned to the child with the given index, if any
+    pub fn assigned_material(&self, child: usize) -> Option<&Material> {
+        self.face_materials[child].as_deref()
+    }
+
+    // must be called whenever the children are added, removed or moved
+    fn invalidate_bounding_box(&mut self) {
+        self.cached_bounding_box.take();
+        self.cached_child_boxes.take();
+    }
+
+    fn child_boxes(&self) -> &[BoundingBoxPacket] {
+        self.cached_child_boxes.get_or_init(|| {
+            let boxes: Vec<BoundingBox> = self
+                .children
+                .iter()
+                .map(|c| c.parent_space_bounding_box())
+                .collect();
+            BoundingBoxPacket::pack(&boxes)
+        })
+    }
+
+    // Intersect with a ray in the space that this group's transformation is relative to, skipping
+    // the group's own bounding box test if the caller has already done it
+    fn intersect_from_parent_space(
+        &self,
+        parent_ray: Ray,
+        check_bounds: bool,
+    ) -> Vec<Intersection<'_>> {
+        let transformed = !self.get_base().has_identity_transformation();
+        let object_ray = if transformed {
+            parent_ray.transform(self.transformation_inverse())
+        } else {
+            parent_ray
+        };
+        if check_bounds && !self.bounding_box().intersects(object_ray) {
+            return vec![];
+        }
+        let mut xs = self.intersect_children(object_ray).into_vec();
+        if transformed {
+            for x in &mut xs {
+                x.found_through(self.transformation_inverse());
+            }
+        }
+        xs
     }
 
-    // Meant ONLY to be used by divide, because returned left and right children will
-    // still have the group's transform baked into their own.
-    fn partition_children(&mut self) -> (Vec<Box<dyn Shape>>, Vec<Box<dyn Shape>>) {
-        let (left_bounds, right_bounds) = self.bounding_box().split();
+    // Intersect the children whose bounding boxes are hit, testing the boxes several at a time.
+    // Child groups are descended into directly, since their own boxes were already tested here.
+    fn intersect_children(&self, object_ray: Ray) -> Intersections<'_> {
+        let mut intersections = Intersections::new();
+        for ((packet, children), materials) in self
+            .child_boxes()
+            .iter()
+            .zip(self.children.chunks(BOX_PACKET_SIZE))
+            .zip(self.face_materials.chunks(BOX_PACKET_SIZE))
+        {
+            for ((c, hit), material) in children
+                .iter()
+                .zip(packet.intersects(object_ray).iter())
+                .zip(materials)
+            {
+                if !hit {
+                    continue;
+                }
+                let mut xs = match c.downcast_ref::<GroupShape>() {
+                    Some(group) => group.intersect_from_parent_space(object_ray, false),
+                    None => c.intersect(object_ray),
+                };
+                if let Some(m) = material {
+                    for x in &mut xs {
+                        x.material.get_or_insert(m);
+                    }
+                }
+                intersections.merge(xs);
+            }
+        }
+        intersections
+    }
+
+    // Meant ONLY to be used by divide. Children are returned with their assigned materials, and
+    // their transforms stay relative to this group's space.
+    fn partition_children(&mut self) -> (Vec<AssignedChild>, Vec<AssignedChild>) {
+        // unbounded children (such as planes or untruncated cones) can't go in either half, and
+        // splitting an infinite box would give NaN, so only the bounded children are split
+        let mut bounds = BoundingBox::empty();
+        for c in &self.children {
+            let child_bounds = c.as_ref().parent_space_bounding_box();
+            if child_bounds.is_finite() {
+                bounds.add_bounding_box(child_bounds);
+            }
+        }
+        if !bounds.is_finite() {
+            return (vec![], vec![]);
+        }
+        let (left_bounds, right_bounds) = bounds.split();
         let mut left = vec![];
         let mut right = vec![];
         let mut new_children = vec![];
-        for c in self.children.drain(..) {
+        let mut new_materials = vec![];
+        for (c, m) in self.children.drain(..).zip(self.face_materials.drain(..)) {
             let child_bounds = c.as_ref().parent_space_bounding_box();
             if left_bounds.contains_bounding_box(child_bounds) {
-                left.push(c);
+                left.push((c, m));
             } else if right_bounds.contains_bounding_box(child_bounds) {
-                right.push(c);
+                right.push((c, m));
             } else {
-                new_children.push(c)
+                new_children.push(c);
+                new_materials.push(m);
             }
         }
         self.children = new_children;
+        self.face_materials = new_materials;
         (left, right)
     }
 
-    // Meant ONLY to be used by divide because it does NOT push down this group's
-    // transformation (partition_children left the transformation baked in).
-    fn make_subgroup(&mut self, mut new_group_children: Vec<Box<dyn Shape>>) {
+    // Meant ONLY to be used by divide; the new subgroup has the identity transformation, since its
+    // children are already relative to this group's space.
+    fn make_subgroup(&mut self, mut new_group_children: Vec<AssignedChild>) {
         // don't bother wrapping a single shape in another group object
         if new_group_children.len() == 1 {
-            self.children.push(new_group_children.remove(0));
+            let (child, material) = new_group_children.remove(0);
+            self.children.push(child);
+            self.face_materials.push(material);
         } else {
-            let new_child = GroupShape::with_children(new_group_children);
+            let (children, materials) = new_group_children.into_iter().unzip();
+            let mut new_child = GroupShape::with_children(children);
+            new_child.face_materials = materials;
             self.children.push(Box::new(new_child));
+            self.face_materials.push(None);
         }
     }
 }
@@ -91,53 +221,40 @@ impl Shape for GroupShape {
             self.children.iter().any(|s| s.as_ref().includes(other))
         }
     }
-    // just pass the material on to the children
-    // TODO: could be very inefficient for large groups
+    // just pass the material on to the children; they all share the one copy
     fn set_material(&mut self, m: Material) {
+        self.set_shared_material(Arc::new(m));
+    }
+    fn set_shared_material(&mut self, m: Arc<Material>) {
         for child in &mut self.children.iter_mut() {
-            child.set_material(m.clone());
+            child.set_shared_material(Arc::clone(&m));
         }
     }
-    fn set_transformation(&mut self, t: Matrix) {
-        // loop over children and undo the previous transformation that was applied to them
-        // by multiplying their transform by the inverse of this group's transform. Then
-        // apply the new group transform.
-        if self.children.len() > 0 {
-            let child_transformer = &t * self.transformation_inverse();
-            for c in self.children.iter_mut() {
-                let old_child_transform = c.transformation().clone();
-                c.set_transformation(&child_transformer * &old_child_transform);
-            }
+    // hits refer to the children, so they need the flag too
+    fn set_casts_shadow(&mut self, casts_shadow: bool) {
+        self.base.set_casts_shadow(casts_shadow);
+        for child in &mut self.children.iter_mut() {
+            child.set_casts_shadow(casts_shadow);
         }
-        // important in case parent group needs to undo its own transform propagated to this group
-        self.get_base_mut().set_transformation(t);
     }
     fn intersect(&self, world_ray: Ray) -> Vec<Intersection> {
-        // skip world to local conversion for Group, since the transformation matrix is propagated to the children
-        self.local_intersect(world_ray)
+        // groups are not primitives, so unlike the default this doesn't count a primitive test
+        self.intersect_from_parent_space(world_ray, true)
     }
     fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
-        let mut intersections = vec![];
-
         let b = self.bounding_box();
         if !b.intersects(object_ray) {
-            return intersections;
+            return vec![];
         }
-
-        for c in &mut self.children.iter() {
-            for i in c.intersect(object_ray) {
-                intersections.push(i);
-            }
-        }
-        intersections
+        self.intersect_children(object_ray).into_vec()
     }
-    fn local_norm_at(&self, _object_point: Tuple, _hit: &Intersection) -> Tuple {
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
         unreachable!("Groups do not have normals. This method should never be called.")
     }
 
     fn bounding_box(&self) -> BoundingBox {
-        let mut cached_box = self.cached_bounding_box.borrow_mut();
-        cached_box.get_or_insert_with(|| {
+        *self.cached_bounding_box.get_or_init(|| {
             let mut b = BoundingBox::empty();
 
             for child in &mut self.children.iter() {
@@ -145,17 +262,32 @@ impl Shape for GroupShape {
                 b.add_bounding_box(child_box);
             }
             b
-        });
-        cached_box.unwrap()
+        })
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hasher.write_usize(self.children.len());
+        for (child, material) in self.children.iter().zip(&self.face_materials) {
+            child.hash_structure(hasher);
+            hasher.write_u8(material.is_some() as u8);
+            if let Some(m) = material {
+                m.hash_structure(hasher);
+            }
+        }
+    }
+
+    fn bake_transform(&mut self) -> bool {
+        self.bake_transforms()
     }
 
-    fn parent_space_bounding_box(&self) -> BoundingBox {
-        // transformation for self is always pushed down to children, so we can't use shape's default implementation here.
-        // TODO: put self.transformation in a separate field so that we don't have to override this here.
-        self.bounding_box()
+    fn set_backface_culling(&mut self, cull: bool) {
+        for child in &mut self.children {
+            child.set_backface_culling(cull);
+        }
     }
 
     fn divide(&mut self, threshold: usize) {
+        let _timer = stats::time_phase(Phase::BvhBuild);
         if threshold <= self.children.len() {
             let (left, right) = self.partition_children();
             if !left.is_empty() {
@@ -164,6 +296,7 @@ impl Shape for GroupShape {
             if !right.is_empty() {
                 self.make_subgroup(right);
             }
+            self.invalidate_bounding_box();
         }
 
         for child in &mut self.children.iter_mut() {
@@ -177,25 +310,35 @@ impl Clone for GroupShape {
         Self {
             base: self.base.clone(),
             children: self.children.clone(),
-            cached_bounding_box: RefCell::new(None),
+            cached_bounding_box: OnceLock::new(),
+            cached_child_boxes: OnceLock::new(),
+            face_materials: self.face_materials.clone(),
         }
     }
 }
 
+// a child with the material assigned to it, if any
+type AssignedChild = (Box<dyn Shape>, Option<Arc<Material>>);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color::Color;
+    use crate::matrix::{identity_4x4, Matrix};
+    use crate::pattern::pattern::TestPattern;
     use crate::shape::base_shape::BaseShape;
+    use crate::shape::cone::Cone;
     use crate::shape::cylinder::Cylinder;
+    use crate::shape::shape::ShapeBuilder;
     use crate::shape::sphere::Sphere;
     use crate::shape::test_shape::TestShape;
-    use crate::test::utils::dummy_intersection;
+    use crate::shape::triangle::Triangle;
     use crate::transformations::rotation_y;
     use crate::transformations::scaling;
     use crate::transformations::translation;
     use crate::tuple::Tuple;
     use std::cmp::Ordering::Equal;
-    use std::f32::consts::PI;
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
 
     #[test]
     fn add_child_to_group() {
@@ -224,6 +367,11 @@ mod tests {
         for c in g.get_children().iter() {
             assert_eq!(c.material().shininess, group_shininess);
         }
+        let children = g.get_children();
+        assert!(Arc::ptr_eq(
+            children[0].shared_material(),
+            children[2].shared_material()
+        ));
     }
 
     #[test]
@@ -271,14 +419,10 @@ mod tests {
         s.set_transformation(translation(5.0, 0.0, 0.0));
         g.add_child(Box::new(s));
 
+        // the child's own transformation is untouched
         assert_eq!(
             g.get_children()[0].transformation(),
-            &matrix!(
-                [2.0, 0.0, 0.0, 10.0],
-                [0.0, 2.0, 0.0, 0.0],
-                [0.0, 0.0, 2.0, 0.0],
-                [0.0, 0.0, 0.0, 1.0]
-            )
+            &translation(5.0, 0.0, 0.0)
         );
 
         let r = Ray::new(point!(10, 0, -10), vector!(0, 0, 1));
@@ -297,14 +441,10 @@ mod tests {
         g.set_transformation(scaling(2.0, 2.0, 2.0));
         let r = Ray::new(point!(10, 0, -10), vector!(0, 0, 1));
 
+        // the child's own transformation is untouched
         assert_eq!(
             g.get_children()[0].transformation(),
-            &matrix!(
-                [2.0, 0.0, 0.0, 10.0],
-                [0.0, 2.0, 0.0, 0.0],
-                [0.0, 0.0, 2.0, 0.0],
-                [0.0, 0.0, 0.0, 1.0]
-            )
+            &translation(5.0, 0.0, 0.0)
         );
 
         let xs = g.intersect(r);
@@ -324,14 +464,10 @@ mod tests {
         g.set_transformation(scaling(2.0, 2.0, 2.0));
         let r = Ray::new(point!(10, 0, -10), vector!(0, 0, 1));
 
+        // the child's own transformation is untouched
         assert_eq!(
             g.get_children()[0].transformation(),
-            &matrix!(
-                [2.0, 0.0, 0.0, 10.0],
-                [0.0, 2.0, 0.0, 0.0],
-                [0.0, 0.0, 2.0, 0.0],
-                [0.0, 0.0, 0.0, 1.0]
-            )
+            &translation(5.0, 0.0, 0.0)
         );
 
         let xs = g.intersect(r);
@@ -355,8 +491,11 @@ mod tests {
             .downcast_ref::<GroupShape>()
             .unwrap();
         let s = g2.get_children()[0].as_ref();
+        let mut i = Intersection::new(0., s);
+        i.found_through(g2.transformation_inverse());
+        i.found_through(g1.transformation_inverse());
 
-        let p = s.world_to_object_point(&point!(-2, 0, -10));
+        let p = s.world_to_object_point(&i.world_to_parent_point(&point!(-2, 0, -10)));
         assert_abs_diff_eq!(p, point!(5.0, 0.0, -0.66666657));
     }
 
@@ -383,9 +522,67 @@ mod tests {
             .downcast_ref::<GroupShape>()
             .unwrap();
         let s = g2.get_children()[0].as_ref();
+        let mut i = Intersection::new(0., s);
+        i.found_through(g2.transformation_inverse());
+        i.found_through(g1.transformation_inverse());
+
+        let n = i.normal_at(&world_point);
+        assert_abs_diff_eq!(
+            n,
+            vector!(0.2857036, 0.42854306, -0.8571606),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn retransforming_nested_groups_moves_children() {
+        let mut inner = GroupShape::new();
+        let mut s = Sphere::new();
+        s.set_transformation(translation(5.0, 0.0, 0.0));
+        inner.add_child(Box::new(s));
+        inner.set_transformation(scaling(1.0, 2.0, 3.0));
+        let mut outer = GroupShape::new();
+        outer.set_transformation(rotation_y(PI / 3.0));
+        outer.add_child(Box::new(inner));
+        outer.set_transformation(translation(1.0, 2.0, 3.0) * rotation_y(PI / 2.0));
+        outer.set_transformation(scaling(2.0, 2.0, 2.0));
+
+        let inner = outer.get_children()[0]
+            .as_ref()
+            .downcast_ref::<GroupShape>()
+            .unwrap();
+        let s = inner.get_children()[0].as_ref();
+        assert_eq!(s.transformation(), &translation(5.0, 0.0, 0.0));
 
-        let n = s.normal_at(&world_point, &dummy_intersection(&g1));
-        assert_abs_diff_eq!(n, vector!(0.2857036, 0.42854306, -0.8571606));
+        // the sphere ends up centered on (10, 0, 0), with radii (2, 4, 6)
+        let r = Ray::new(point!(10, 0, -20), vector!(0, 0, 1));
+        let xs = outer.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_abs_diff_eq!(xs[0].distance, 14., epsilon = 1e-4);
+        assert_abs_diff_eq!(xs[1].distance, 26., epsilon = 1e-4);
+        assert_abs_diff_eq!(
+            xs[0].normal_at(&r.position(xs[0].distance)),
+            vector!(0, 0, -1),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn children_share_group_material_pattern() {
+        let mut g = GroupShape::new();
+        g.add_child(Box::new(Sphere::new()));
+        g.add_child(Box::new(Sphere::new()));
+        g.set_material(
+            Material::builder()
+                .pattern(Arc::new(TestPattern::new()))
+                .build(),
+        );
+
+        let children = g.get_children();
+        let p1 = children[0].material().pattern.as_ref().unwrap();
+        let p2 = children[1].material().pattern.as_ref().unwrap();
+        assert!(Arc::ptr_eq(p1, p2));
+        assert_eq!(children[0].material(), children[1].material());
     }
 
     #[test]
@@ -409,7 +606,31 @@ mod tests {
     }
 
     #[test]
-    fn group_parent_space_bounding_box_ignores_passed_down_transformation() {
+    fn group_bounding_box_is_recomputed_after_changes() {
+        let mut shape = GroupShape::new();
+        shape.add_child(Box::new(Sphere::new()));
+        assert_eq!(shape.bounding_box().max, point!(1, 1, 1));
+
+        let mut s = Sphere::new();
+        s.set_transformation(translation(5., 0., 0.));
+        shape.add_child(Box::new(s));
+        assert_eq!(shape.bounding_box().max, point!(6, 1, 1));
+
+        shape.set_transformation(scaling(2., 2., 2.));
+        assert_eq!(shape.bounding_box().max, point!(6, 1, 1));
+        assert_eq!(shape.parent_space_bounding_box().max, point!(12, 2, 2));
+
+        let mut outer = GroupShape::new();
+        outer.add_child(Box::new(shape));
+        assert_eq!(outer.bounding_box().max, point!(12, 2, 2));
+        outer.set_transformation(translation(0., 1., 0.));
+        assert_eq!(outer.parent_space_bounding_box().max, point!(12, 3, 2));
+        let inner = outer.get_children()[0].as_ref();
+        assert_eq!(inner.bounding_box().max, point!(6, 1, 1));
+    }
+
+    #[test]
+    fn group_parent_space_bounding_box_applies_group_transformation() {
         let mut s = Sphere::new();
         s.set_transformation(scaling(2., 2., 2.));
 
@@ -423,9 +644,10 @@ mod tests {
         shape.add_child(Box::new(c));
         shape.set_transformation(scaling(0.5, 0.5, 0.5));
 
-        let b1 = shape.bounding_box();
-        let b2 = shape.parent_space_bounding_box();
-        assert_eq!(b1, b2);
+        assert_eq!(shape.bounding_box().max, point!(2, 2, 2));
+        let b = shape.parent_space_bounding_box();
+        assert_eq!(b.min, point!(-1, -1, -1));
+        assert_eq!(b.max, point!(1, 1, 1));
     }
 
     #[test]
@@ -437,8 +659,8 @@ mod tests {
         shape.intersect(r);
 
         let test_shape = shape.get_children()[0].downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape.saved_ray.borrow());
-        assert!(test_shape.saved_ray.borrow().is_none());
+        println!("{:?}", test_shape.saved_ray());
+        assert!(test_shape.saved_ray().is_none());
     }
 
     #[test]
@@ -450,8 +672,8 @@ mod tests {
         shape.intersect(r);
 
         let test_shape = shape.get_children()[0].downcast_ref::<TestShape>().unwrap();
-        println!("{:?}", test_shape.saved_ray.borrow());
-        assert!(test_shape.saved_ray.borrow().is_some());
+        println!("{:?}", test_shape.saved_ray());
+        assert!(test_shape.saved_ray().is_some());
     }
 
     #[test]
@@ -481,11 +703,11 @@ mod tests {
 
         // left should contain s1
         assert_eq!(left.len(), 1);
-        assert_eq!(left[0].get_unique_id(), s1_id);
+        assert_eq!(left[0].0.get_unique_id(), s1_id);
 
         // right should contain s2
         assert_eq!(right.len(), 1);
-        assert_eq!(right[0].get_unique_id(), s2_id);
+        assert_eq!(right[0].0.get_unique_id(), s2_id);
     }
 
     #[test]
@@ -497,7 +719,7 @@ mod tests {
         let s2_id = s2.get_unique_id();
 
         let mut g = GroupShape::new();
-        g.make_subgroup(vec![Box::new(s1), Box::new(s2)]);
+        g.make_subgroup(vec![(Box::new(s1), None), (Box::new(s2), None)]);
 
         let g_children = g.get_children();
         assert_eq!(g_children.len(), 1);
@@ -604,7 +826,7 @@ mod tests {
     }
 
     #[test]
-    fn divide_preserves_pushed_down_transformation() {
+    fn divide_preserves_transformations() {
         let mut s1 = Sphere::new();
         s1.set_transformation(translation(-2., 0., 0.));
 
@@ -620,27 +842,133 @@ mod tests {
         group.add_child(Box::new(s2));
         group.add_child(Box::new(s3));
 
-        // we expect the transformation to be passed down to the children; this
-        // should not change when the group is divided
+        // the children stay relative to the group, and the new subgroup adds no transformation
         group.divide(2);
 
+        assert_eq!(group.transformation(), &translation(1., 1., 0.));
         assert_eq!(
             group.get_children()[0].transformation(),
-            &translation(-1., 1., 0.),
+            &translation(-2., 0., 0.),
             "s1 transformation should be preserved during division"
         );
         let subgroup = group.get_children()[1]
             .downcast_ref::<GroupShape>()
             .unwrap();
+        assert_eq!(subgroup.transformation(), &identity_4x4());
         assert_eq!(
             subgroup.get_children()[0].transformation(),
-            &translation(3., 0., 0.),
+            &translation(2., -1., 0.),
             "s2 transformation should be preserved during division"
         );
         assert_eq!(
             subgroup.get_children()[1].transformation(),
-            &translation(3., 2., 0.),
+            &translation(2., 1., 0.),
             "s3 transformation should be preserved during division"
         );
     }
+
+    #[test]
+    fn divided_group_keeps_unbounded_cone() {
+        let mut cone = Cone::new();
+        cone.set_transformation(translation(5., 0., 0.));
+        let mut s1 = Sphere::new();
+        s1.set_transformation(translation(-2., 0., 0.));
+        let mut s2 = Sphere::new();
+        s2.set_transformation(translation(2., 0., 0.));
+
+        let mut g = GroupShape::new();
+        g.add_child(Box::new(cone));
+        g.add_child(Box::new(s1));
+        g.add_child(Box::new(s2));
+        g.divide(1);
+
+        // far from the spheres, where the cone is 10 wide
+        let r = Ray::new(point!(5, 10, -20), vector!(0, 0, 1));
+        let xs = g.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_abs_diff_eq!(xs[0].distance, 10.);
+    }
+
+    #[test]
+    fn baking_group_transformations() {
+        let mut inner = GroupShape::with_children(vec![Box::new(
+            Triangle::new(point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0))
+                .with_transform(scaling(1.5, 1.5, 1.5)),
+        )]);
+        inner.set_transformation(rotation_y(PI / 4.));
+        let mut g = GroupShape::with_children(vec![Box::new(inner)]);
+        g.set_transformation(translation(0., 0., 3.) * &scaling(2., 2., 2.));
+        let r = Ray::new(point!(0, 0.5, -5), vector!(0, 0, 1));
+        let before = g.intersect(r)[0].distance;
+
+        assert!(g.bake_transforms());
+        let inner = g.get_children()[0].downcast_ref::<GroupShape>().unwrap();
+        let t = &inner.get_children()[0];
+        assert_eq!(t.transformation(), &identity_4x4());
+        // the groups keep their transformations
+        assert_eq!(inner.transformation(), &rotation_y(PI / 4.));
+        let after = g.intersect(r);
+        assert_eq!(after.len(), 1);
+        assert_abs_diff_eq!(after[0].distance, before, epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            g.parent_space_bounding_box().max,
+            point!(3. * FRAC_1_SQRT_2, 3, 3. + 3. * FRAC_1_SQRT_2),
+            epsilon = 1e-5
+        );
+
+        // spheres have no vertices to move
+        g.add_child(Box::new(Sphere::new()));
+        assert!(!g.bake_transforms());
+    }
+
+    #[test]
+    fn hits_carry_assigned_materials() {
+        let spheres = (0..4).map(|i| -> Box<dyn Shape> {
+            Box::new(Sphere::build(
+                translation(3. * i as f32, 0., 0.),
+                Material::default(),
+            ))
+        });
+        let mut g = GroupShape::with_children(spheres.collect());
+        let red = Material::builder().color(color!(1, 0, 0)).build();
+        g.assign_material(vec![1, 3], red.clone());
+        // setting the group's material doesn't replace assigned materials
+        g.set_material(Material::builder().color(color!(0, 0, 1)).build());
+        assert_eq!(g.assigned_material(1), Some(&red));
+        assert_eq!(g.assigned_material(2), None);
+
+        let colors = |g: &GroupShape| {
+            let r = Ray::new(point!(-5, 0, 0), vector!(1, 0, 0));
+            let xs = g.intersect(r);
+            xs.iter()
+                .step_by(2)
+                .map(|x| x.material().color)
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            color!(0, 0, 1),
+            color!(1, 0, 0),
+            color!(0, 0, 1),
+            color!(1, 0, 0),
+        ];
+        assert_eq!(colors(&g), expected);
+        // and the assignments move along with the children when the group is divided
+        g.divide(1);
+        assert!(g.get_children()[0].downcast_ref::<GroupShape>().is_some());
+        assert_eq!(colors(&g), expected);
+
+        // inner assignments win
+        let green = Material::builder().color(color!(0, 1, 0)).build();
+        let mut outer = GroupShape::with_children(vec![Box::new(g)]);
+        outer.assign_material(vec![0], green);
+        assert_eq!(
+            colors(&outer),
+            vec![
+                color!(0, 1, 0),
+                color!(1, 0, 0),
+                color!(0, 1, 0),
+                color!(1, 0, 0)
+            ]
+        );
+    }
 }
@@ -0,0 +1,202 @@
+use crate::bounding_box::BoundingBox;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+// Another copy of a prototype shape with its own transformation and, optionally, its own material.
+// The prototype is shared rather than copied, so a large mesh can be parsed and divided once and
+// then placed in a scene many times. Hits on an instance refer to the prototype's shapes and
+// remember the instance they were found through (see Intersection::instance).
+//
+// Limitation: instances cannot be nested inside of other instances' prototypes.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    base: BaseShape,
+    prototype: Arc<dyn Shape>,
+    overrides_material: bool,
+}
+
+impl Instance {
+    pub fn new(prototype: Arc<dyn Shape>, transform: Matrix, material: Option<Material>) -> Self {
+        let mut instance = Instance {
+            base: BaseShape::new(),
+            prototype,
+            overrides_material: false,
+        };
+        instance.set_transformation(transform);
+        if let Some(m) = material {
+            instance.set_material(m);
+        }
+        instance
+    }
+
+    pub fn prototype(&self) -> &Arc<dyn Shape> {
+        &self.prototype
+    }
+
+    // the material used instead of the prototype's own, if any
+    pub fn material_override(&self) -> Option<&Material> {
+        if self.overrides_material {
+            Some(self.material())
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        self.get_unique_id() == other.get_unique_id()
+    }
+}
+
+impl Shape for Instance {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.set_shared_material(Arc::new(m));
+    }
+    fn set_shared_material(&mut self, m: Arc<Material>) {
+        self.base.set_shared_material(m);
+        self.overrides_material = true;
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.get_unique_id() == other.get_unique_id() || self.prototype.includes(other)
+    }
+
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let mut intersections = self.prototype.intersect(object_ray);
+        let transformed = !self.get_base().has_identity_transformation();
+        for i in &mut intersections {
+            debug_assert!(i.instance.is_none(), "Instances cannot be nested");
+            i.instance = Some(self);
+            if transformed {
+                i.found_through(self.transformation_inverse());
+            }
+        }
+        intersections
+    }
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        unreachable!("Hits refer to the prototype's shapes, never to the instance itself.")
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.prototype.parent_space_bounding_box()
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        self.prototype.hash_structure(hasher);
+        hasher.write_u8(self.overrides_material as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::matrix::identity_4x4;
+    use crate::shape::cube::Cube;
+    use crate::shape::group::GroupShape;
+    use crate::shape::sphere::Sphere;
+    use crate::transformations::{rotation_z, scaling, translation};
+    use std::f32::consts::PI;
+
+    fn shared_sphere() -> Arc<dyn Shape> {
+        Arc::new(Sphere::build(
+            translation(0., 1., 0.),
+            Material::builder().color(color!(1, 0, 0)).build(),
+        ))
+    }
+
+    #[test]
+    fn instances_transform_rays_into_prototype_space() {
+        let sphere = shared_sphere();
+        let test_data = vec![
+            ("identity", identity_4x4(), point!(0, 1, -5), vec![4., 6.]),
+            (
+                "translated",
+                translation(5., 0., 0.),
+                point!(5, 1, -5),
+                vec![4., 6.],
+            ),
+            (
+                "scaled",
+                scaling(2., 2., 2.),
+                point!(0, 2, -5),
+                vec![3., 7.],
+            ),
+            ("missed", translation(5., 0., 0.), point!(0, 1, -5), vec![]),
+        ];
+        for (name, transform, origin, expected) in test_data {
+            let instance = Instance::new(Arc::clone(&sphere), transform, None);
+            let xs = instance.intersect(Ray::new(origin, vector!(0, 0, 1)));
+            let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+            assert_eq!(distances, expected, "Case {}", name);
+            for i in &xs {
+                assert!(i.object.includes(sphere.as_ref()));
+                assert_eq!(i.instance, Some(&instance));
+            }
+        }
+    }
+
+    #[test]
+    fn instances_share_one_prototype() {
+        let sphere = shared_sphere();
+        let left = Instance::new(Arc::clone(&sphere), translation(-3., 0., 0.), None);
+        let right = Instance::new(Arc::clone(&sphere), translation(3., 0., 0.), None);
+        assert!(Arc::ptr_eq(left.prototype(), right.prototype()));
+        let g = GroupShape::with_children(vec![Box::new(left), Box::new(right)]);
+        let b = g.bounding_box();
+        assert_eq!(b.min, point!(-4, 0, -1));
+        assert_eq!(b.max, point!(4, 2, 1));
+        let xs = g.intersect(Ray::new(point!(-10, 1, 0), vector!(1, 0, 0)));
+        assert_eq!(xs.len(), 4);
+        assert!(xs[0].instance != xs[3].instance);
+    }
+
+    #[test]
+    fn normals_and_materials_of_instanced_hits() {
+        let sphere = shared_sphere();
+        let plain = Instance::new(Arc::clone(&sphere), rotation_z(PI / 2.), None);
+        let painted = Instance::new(
+            Arc::clone(&sphere),
+            translation(0., 0., 10.),
+            Some(Material::builder().color(color!(0, 0, 1)).build()),
+        );
+        assert!(plain.material_override().is_none());
+
+        // rotated so that the sphere sits at (-1, 0, 0)
+        let xs = plain.intersect(Ray::new(point!(-1, 5, 0), vector!(0, -1, 0)));
+        assert_abs_diff_eq!(xs[0].distance, 4.);
+        assert_abs_diff_eq!(xs[0].normal_at(&point!(-1, 1, 0)), vector!(0, 1, 0));
+        assert_eq!(xs[0].material().color, color!(1, 0, 0));
+
+        let xs = painted.intersect(Ray::new(point!(0, 1, 0), vector!(0, 0, 1)));
+        assert_abs_diff_eq!(xs[0].normal_at(&point!(0, 1, 9)), vector!(0, 0, -1));
+        assert_eq!(xs[0].material().color, color!(0, 0, 1));
+    }
+
+    #[test]
+    fn instance_fingerprint_includes_prototype() {
+        let sphere = shared_sphere();
+        let cube: Arc<dyn Shape> = Arc::new(Cube::new());
+        let a = Instance::new(Arc::clone(&sphere), identity_4x4(), None);
+        let b = Instance::new(Arc::clone(&sphere), identity_4x4(), None);
+        let c = Instance::new(cube, identity_4x4(), None);
+        assert!(a.structurally_eq(&b));
+        assert!(!a.structurally_eq(&c));
+    }
+}
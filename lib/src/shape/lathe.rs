@@ -0,0 +1,337 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A surface of revolution: the profile, a list of (radius, y) points, is revolved around the y
+// axis. Each pair of consecutive points sweeps out a slice of a cone (or a flat ring, if they have
+// the same y). The profile should be traced with the outside of the shape on its right, e.g. from
+// bottom to top for the outside of a vase; normals point that way.
+#[derive(Debug, Clone)]
+pub struct Lathe {
+    base: BaseShape,
+    profile: Vec<(f32, f32)>,
+    // interpolate normals between neighboring segments instead of showing the facets
+    pub smooth: bool,
+    bounds: BoundingBox,
+}
+
+impl Lathe {
+    pub fn new(profile: Vec<(f32, f32)>) -> Self {
+        assert!(
+            profile.len() >= 2,
+            "A lathe profile needs at least 2 points: {:?}",
+            profile
+        );
+        assert!(
+            profile.iter().all(|&(radius, _)| radius >= 0.),
+            "Lathe profile radii cannot be negative: {:?}",
+            profile
+        );
+        let max_radius = profile.iter().map(|&(radius, _)| radius).fold(0., f32::max);
+        let min_y = profile
+            .iter()
+            .map(|&(_, y)| y)
+            .fold(f32::INFINITY, f32::min);
+        let max_y = profile
+            .iter()
+            .map(|&(_, y)| y)
+            .fold(f32::NEG_INFINITY, f32::max);
+        Lathe {
+            base: BaseShape::new(),
+            profile,
+            smooth: false,
+            bounds: BoundingBox::with_bounds(
+                point!(-max_radius, min_y, -max_radius),
+                point!(max_radius, max_y, max_radius),
+            ),
+        }
+    }
+
+    pub fn build(profile: Vec<(f32, f32)>, transform: Matrix, material: Material) -> Self {
+        let mut s = Lathe::new(profile);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    pub fn profile(&self) -> &[(f32, f32)] {
+        &self.profile
+    }
+
+    fn segment_count(&self) -> usize {
+        self.profile.len() - 1
+    }
+
+    // unit normal of a segment in the (radius, y) plane, pointing to the right of the profile
+    fn segment_normal(&self, segment: usize) -> (f32, f32) {
+        let (r0, y0) = self.profile[segment];
+        let (r1, y1) = self.profile[segment + 1];
+        let (dr, dy) = (r1 - r0, y1 - y0);
+        let length = (dr * dr + dy * dy).sqrt();
+        (dy / length, -dr / length)
+    }
+
+    // normal at a profile point, averaged between the segments meeting there
+    fn vertex_normal(&self, vertex: usize) -> (f32, f32) {
+        let before = vertex.checked_sub(1).filter(|&s| !self.is_degenerate(s));
+        let after = Some(vertex)
+            .filter(|&s| s < self.segment_count())
+            .filter(|&s| !self.is_degenerate(s));
+        match (before, after) {
+            (Some(before), Some(after)) => {
+                let (r0, y0) = self.segment_normal(before);
+                let (r1, y1) = self.segment_normal(after);
+                let length = ((r0 + r1).powi(2) + (y0 + y1).powi(2)).sqrt();
+                if length < CLOSE_TO_ZERO {
+                    (r1, y1)
+                } else {
+                    ((r0 + r1) / length, (y0 + y1) / length)
+                }
+            }
+            (Some(segment), None) | (None, Some(segment)) => self.segment_normal(segment),
+            (None, None) => (0., 1.),
+        }
+    }
+
+    fn is_degenerate(&self, segment: usize) -> bool {
+        self.profile[segment] == self.profile[segment + 1]
+    }
+
+    // Where along the segment (0 at its first point, 1 at its second) the point lies
+    fn segment_fraction(&self, segment: usize, object_point: Tuple) -> f32 {
+        let (r0, y0) = self.profile[segment];
+        let (r1, y1) = self.profile[segment + 1];
+        if (y1 - y0).abs() > CLOSE_TO_ZERO {
+            (object_point.y - y0) / (y1 - y0)
+        } else {
+            let radius = (object_point.x.powi(2) + object_point.z.powi(2)).sqrt();
+            (radius - r0) / (r1 - r0)
+        }
+    }
+
+    // Segments share their end points; count a hit there only once, except at the profile's end
+    fn on_segment(&self, segment: usize, fraction: f32) -> bool {
+        fraction >= 0. && (fraction < 1. || (segment == self.segment_count() - 1 && fraction <= 1.))
+    }
+
+    fn intersect_segment<'a>(
+        &'a self,
+        segment: usize,
+        object_ray: &Ray,
+        intersections: &mut Vec<Intersection<'a>>,
+    ) {
+        let (r0, y0) = self.profile[segment];
+        let (r1, y1) = self.profile[segment + 1];
+        let (o, d) = (object_ray.origin, object_ray.direction);
+        let mut add = |distance: f32| {
+            let fraction = self.segment_fraction(segment, object_ray.position(distance));
+            if self.on_segment(segment, fraction) {
+                intersections.push(Intersection::new_with_uv(
+                    distance,
+                    self,
+                    segment as f32,
+                    fraction,
+                ));
+            }
+        };
+
+        if (y1 - y0).abs() <= CLOSE_TO_ZERO {
+            // a flat ring
+            if d.y.abs() > CLOSE_TO_ZERO {
+                add((y0 - o.y) / d.y);
+            }
+            return;
+        }
+
+        // the segment lies on the cone x^2 + z^2 = (slope * y + offset)^2
+        let slope = (r1 - r0) / (y1 - y0);
+        let offset = r0 - slope * y0;
+        let radius_at_origin = slope * o.y + offset;
+        let a = d.x.powi(2) + d.z.powi(2) - (slope * d.y).powi(2);
+        let b = 2. * (o.x * d.x + o.z * d.z - slope * d.y * radius_at_origin);
+        let c = o.x.powi(2) + o.z.powi(2) - radius_at_origin.powi(2);
+        if a.abs() < CLOSE_TO_ZERO {
+            // the ray is parallel to one of the lines on the cone, so it crosses it only once
+            if b.abs() > CLOSE_TO_ZERO {
+                add(-c / b);
+            }
+            return;
+        }
+        let discriminant = b.powi(2) - 4. * a * c;
+        if discriminant < 0. {
+            return;
+        }
+        let discriminant_sqrt = discriminant.sqrt();
+        add((-b - discriminant_sqrt) / (2. * a));
+        add((-b + discriminant_sqrt) / (2. * a));
+    }
+}
+
+const CLOSE_TO_ZERO: f32 = 0.000_001;
+
+impl Shape for Lathe {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        if !self.bounds.intersects(object_ray) {
+            return vec![];
+        }
+        let mut intersections = vec![];
+        for segment in 0..self.segment_count() {
+            if !self.is_degenerate(segment) {
+                self.intersect_segment(segment, &object_ray, &mut intersections);
+            }
+        }
+        Intersection::sort(&mut intersections);
+        intersections
+    }
+
+    // the hit's u is the index of the segment it hit and v how far along the segment it was
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        unreachable!(
+            "Use local_norm_at_hit; the hit's u records which segment of the profile was hit"
+        )
+    }
+
+    fn local_norm_at_hit(&self, object_point: Tuple, hit: &Intersection) -> Tuple {
+        let segment = (hit.u as usize).min(self.segment_count() - 1);
+        let (normal_r, normal_y) = if self.smooth {
+            let (r0, y0) = self.vertex_normal(segment);
+            let (r1, y1) = self.vertex_normal(segment + 1);
+            let t = self.segment_fraction(segment, object_point).clamp(0., 1.);
+            (r0 + (r1 - r0) * t, y0 + (y1 - y0) * t)
+        } else {
+            self.segment_normal(segment)
+        };
+        // revolve the normal to face away from the axis at the point
+        let radius = (object_point.x.powi(2) + object_point.z.powi(2)).sqrt();
+        if radius < CLOSE_TO_ZERO {
+            return vector!(0, normal_y.signum(), 0);
+        }
+        vector!(
+            object_point.x / radius * normal_r,
+            normal_y,
+            object_point.z / radius * normal_r
+        )
+        .norm()
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hasher.write_usize(self.profile.len());
+        for &(radius, y) in &self.profile {
+            hash_f32(hasher, radius);
+            hash_f32(hasher, y);
+        }
+        hasher.write_u8(self.smooth as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    // a closed cylinder of radius 1 from y = 0 to y = 1
+    fn closed_cylinder() -> Lathe {
+        Lathe::new(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)])
+    }
+
+    fn distances(xs: &[Intersection]) -> Vec<f32> {
+        xs.iter().map(|i| i.distance).collect()
+    }
+
+    #[test]
+    fn ray_intersects_lathe() {
+        let lathe = closed_cylinder();
+        let test_data = vec![
+            ("side", point!(0, 0.5, -5), vector!(0, 0, 1), vec![4., 6.]),
+            ("caps", point!(0.5, 5, 0), vector!(0, -1, 0), vec![4., 5.]),
+            (
+                "through a cap and a side",
+                point!(0, 1.5, 0),
+                vector!(0, -1, 1).norm(),
+                vec![FRAC_1_SQRT_2, 2. * FRAC_1_SQRT_2],
+            ),
+            ("miss", point!(2, 0.5, -5), vector!(0, 0, 1), vec![]),
+        ];
+        for (name, origin, direction, expected) in test_data {
+            let xs = lathe.local_intersect(Ray::new(origin, direction));
+            let found = distances(&xs);
+            assert_eq!(found.len(), expected.len(), "Case {}: {:?}", name, found);
+            for (found, expected) in found.iter().zip(expected.iter()) {
+                assert_abs_diff_eq!(found, expected, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn ray_intersects_cone_segment() {
+        // a cone with its point at the top
+        let lathe = Lathe::new(vec![(1., 0.), (0., 1.)]);
+        let xs = lathe.local_intersect(Ray::new(point!(0, 0.5, -5), vector!(0, 0, 1)));
+        assert_abs_diff_eq!(distances(&xs)[..], [4.5, 5.5][..]);
+        // the other half of the double cone is not part of the segment
+        let xs = lathe.local_intersect(Ray::new(point!(0, 1.5, -5), vector!(0, 0, 1)));
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn lathe_normals() {
+        let lathe = Lathe::new(vec![(0., 0.), (1., 0.), (1., 1.), (0., 2.)]);
+        let test_data = vec![
+            ("bottom", point!(0, 0, 0.5), 0., vector!(0, -1, 0)),
+            ("side", point!(0, 0.5, -1), 1., vector!(0, 0, -1)),
+            (
+                "cone",
+                point!(0.5, 1.5, 0),
+                2.,
+                vector!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0),
+            ),
+        ];
+        for (name, p, segment, expected) in test_data {
+            let xs = vec![Intersection::new_with_uv(1., &lathe, segment, 0.)];
+            let normal = lathe.local_norm_at_hit(p, &xs[0]);
+            assert_abs_diff_eq!(normal, expected);
+            assert!(normal.is_vector(), "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn smooth_lathe_normals_blend_at_vertices() {
+        let mut lathe = Lathe::new(vec![(1., 0.), (1., 1.), (0., 2.)]);
+        let hit = Intersection::new_with_uv(1., &lathe, 1., 0.);
+        assert_abs_diff_eq!(
+            lathe.local_norm_at_hit(point!(1, 1, 0), &hit),
+            vector!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0)
+        );
+
+        lathe.smooth = true;
+        let hit = Intersection::new_with_uv(1., &lathe, 1., 0.);
+        // halfway between the side's normal and the cone's
+        let expected = vector!(1. + FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0).norm();
+        assert_abs_diff_eq!(lathe.local_norm_at_hit(point!(1, 1, 0), &hit), expected);
+    }
+
+    #[test]
+    fn lathe_bounding_box() {
+        let lathe = Lathe::new(vec![(0.5, -1.), (2., 0.), (1., 3.)]);
+        let b = lathe.bounding_box();
+        assert_eq!(b.min, point!(-2, -1, -2));
+        assert_eq!(b.max, point!(2, 3, 2));
+    }
+}
@@ -1,12 +1,24 @@
 pub mod base_shape;
+pub mod bezier_patch;
 pub mod cone;
 pub mod csg;
 pub mod cube;
 pub mod cylinder;
+pub mod ellipsoid;
 pub mod group;
+pub mod instance;
+pub mod lathe;
 pub mod plane;
+pub mod polygon;
+pub mod prism;
+pub mod rounded_cube;
+pub mod rounded_cylinder;
+mod sdf;
 pub mod shape;
+pub mod shape_enum;
 pub mod smooth_triangle;
 pub mod sphere;
+pub mod sphere_cloud;
 mod test_shape;
+pub mod text;
 pub mod triangle;
@@ -0,0 +1,463 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_tuple;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A flat polygon with any number of corners, which may be concave (but should not cross itself).
+// The points should all lie in one plane. Intersections are found against the triangles that the
+// polygon is cut into.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    base: BaseShape,
+    points: Vec<Tuple>,
+    // points projected onto the two axes that the polygon is least squashed along
+    projected: Vec<(f32, f32)>,
+    axes: (usize, usize),
+    triangles: Vec<[usize; 3]>,
+    normal: Tuple,
+}
+
+impl Polygon {
+    pub fn new(points: Vec<Tuple>) -> Self {
+        assert!(
+            points.len() >= 3,
+            "A polygon needs at least 3 points: {:?}",
+            points
+        );
+        let normal = newell_normal(&points);
+        let axes = projection_axes(normal);
+        let projected = project(&points, axes);
+        let triangles = triangulate(&points);
+        Polygon {
+            base: BaseShape::new(),
+            points,
+            projected,
+            axes,
+            triangles,
+            // same winding convention as Triangle
+            normal: -normal.norm(),
+        }
+    }
+
+    pub fn build(points: Vec<Tuple>, transform: Matrix, material: Material) -> Self {
+        let mut s = Polygon::new(points);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    pub fn points(&self) -> &[Tuple] {
+        &self.points
+    }
+
+    // indices into points() of the corners of the triangles covering the polygon
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    fn contains(&self, p: (f32, f32)) -> bool {
+        self.triangles.iter().any(|&[a, b, c]| {
+            in_triangle(
+                p,
+                self.projected[a],
+                self.projected[b],
+                self.projected[c],
+                CLOSE_TO_ZERO,
+            )
+        })
+    }
+}
+
+const CLOSE_TO_ZERO: f32 = 0.000_001;
+
+// The normal of the plane that best fits the points, pointing towards the side from which they
+// wind counter-clockwise. Works for concave and slightly non-planar polygons.
+fn newell_normal(points: &[Tuple]) -> Tuple {
+    let mut normal = vector!(0, 0, 0);
+    for (i, p) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        normal.x += (p.y - next.y) * (p.z + next.z);
+        normal.y += (p.z - next.z) * (p.x + next.x);
+        normal.z += (p.x - next.x) * (p.y + next.y);
+    }
+    normal
+}
+
+// the two axes to keep when projecting onto a coordinate plane, chosen to drop the axis that the
+// normal points along most
+fn projection_axes(normal: Tuple) -> (usize, usize) {
+    let (x, y, z) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    if x >= y && x >= z {
+        (1, 2)
+    } else if y >= z {
+        (2, 0)
+    } else {
+        (0, 1)
+    }
+}
+
+fn coordinate(p: Tuple, axis: usize) -> f32 {
+    match axis {
+        0 => p.x,
+        1 => p.y,
+        _ => p.z,
+    }
+}
+
+fn project(points: &[Tuple], axes: (usize, usize)) -> Vec<(f32, f32)> {
+    points
+        .iter()
+        .map(|&p| (coordinate(p, axes.0), coordinate(p, axes.1)))
+        .collect()
+}
+
+// twice the signed area of the triangle; positive if a, b, c turn counter-clockwise
+fn turn(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// whether p is inside of the triangle abc, or within tolerance of its edges (for positive
+// tolerances) or its interior (for negative ones)
+fn in_triangle(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32), tolerance: f32) -> bool {
+    let area = turn(a, b, c);
+    if area.abs() < CLOSE_TO_ZERO {
+        return false;
+    }
+    let sign = area.signum();
+    let tolerance = tolerance * area.abs();
+    turn(a, b, p) * sign >= -tolerance
+        && turn(b, c, p) * sign >= -tolerance
+        && turn(c, a, p) * sign >= -tolerance
+}
+
+// Cuts a simple polygon into triangles by ear clipping, returning the indices of their corners in
+// the same winding order as the polygon. Convex polygons come out as a fan around the first point.
+pub fn triangulate(points: &[Tuple]) -> Vec<[usize; 3]> {
+    let projected = project(points, projection_axes(newell_normal(points)));
+    let winding = signed_area(&projected).signum();
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+    while remaining.len() > 3 {
+        let corners = |position: usize| {
+            let count = remaining.len();
+            (
+                remaining[(position + count - 1) % count],
+                remaining[position],
+                remaining[(position + 1) % count],
+            )
+        };
+        // Only corners that turn against the polygon's winding can poke into an ear, and they spoil
+        // it even if they only touch its edge. Copies of the ear's own corners (from the cuts made
+        // by merge_holes) don't count.
+        let reflex: Vec<(f32, f32)> = (0..remaining.len())
+            .filter(|&position| {
+                let (previous, current, next) = corners(position);
+                turn(projected[previous], projected[current], projected[next]) * winding
+                    < -CLOSE_TO_ZERO
+            })
+            .map(|position| projected[remaining[position]])
+            .collect();
+        let is_ear = |position: usize| {
+            let (previous, current, next) = corners(position);
+            let (a, b, c) = (projected[previous], projected[current], projected[next]);
+            turn(a, b, c) * winding > CLOSE_TO_ZERO
+                && reflex
+                    .iter()
+                    .filter(|&&p| p != a && p != b && p != c)
+                    .all(|&p| !in_triangle(p, a, b, c, CLOSE_TO_ZERO))
+        };
+        // start looking after the first point so that convex polygons are fanned from it; if the
+        // polygon is degenerate and there is no ear, cut off a corner anyway
+        let position = (1..remaining.len())
+            .chain(0..1)
+            .find(|&position| is_ear(position))
+            .unwrap_or(1);
+        let (previous, current, next) = corners(position);
+        triangles.push([previous, current, next]);
+        remaining.remove(position);
+    }
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    triangles
+}
+
+// twice the signed area of the polygon once projected onto the given axes
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let mut area = 0.;
+    for (i, &(x, y)) in points.iter().enumerate() {
+        let (next_x, next_y) = points[(i + 1) % points.len()];
+        area += x * next_y - next_x * y;
+    }
+    area
+}
+
+// whether the segments a-b and c-d cross somewhere other than at their ends
+fn segments_cross(a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)) -> bool {
+    turn(a, b, c) * turn(a, b, d) < 0. && turn(c, d, a) * turn(c, d, b) < 0.
+}
+
+// Joins holes into the outline with zero-width cuts, giving a single polygon that triangulate can
+// fill without covering the holes. The holes must lie inside of the outline without touching it or
+// each other; they may be given in either winding order.
+pub fn merge_holes(outline: &[Tuple], holes: &[Vec<Tuple>]) -> Vec<Tuple> {
+    let axes = projection_axes(newell_normal(outline));
+    let outline_winding = signed_area(&project(outline, axes)).signum();
+    // wind the holes against the outline, and cut to the rightmost holes first
+    let mut holes: Vec<Vec<Tuple>> = holes
+        .iter()
+        .filter(|hole| hole.len() >= 3)
+        .map(|hole| {
+            let mut hole = hole.clone();
+            if signed_area(&project(&hole, axes)).signum() == outline_winding {
+                hole.reverse();
+            }
+            hole
+        })
+        .collect();
+    let rightmost = |points: &[Tuple]| {
+        (0..points.len())
+            .max_by(|&a, &b| {
+                coordinate(points[a], axes.0)
+                    .partial_cmp(&coordinate(points[b], axes.0))
+                    .unwrap()
+            })
+            .unwrap()
+    };
+    holes.sort_by(|a, b| {
+        coordinate(b[rightmost(b)], axes.0)
+            .partial_cmp(&coordinate(a[rightmost(a)], axes.0))
+            .unwrap()
+    });
+
+    let mut merged = outline.to_vec();
+    for (i, hole) in holes.iter().enumerate() {
+        let start = rightmost(hole);
+        let from = (
+            coordinate(hole[start], axes.0),
+            coordinate(hole[start], axes.1),
+        );
+        let projected = project(&merged, axes);
+        let others: Vec<Vec<(f32, f32)>> =
+            holes[i..].iter().map(|hole| project(hole, axes)).collect();
+        let blocked = |to: (f32, f32)| {
+            std::iter::once(&projected)
+                .chain(others.iter())
+                .any(|points| {
+                    (0..points.len()).any(|j| {
+                        segments_cross(from, to, points[j], points[(j + 1) % points.len()])
+                    })
+                })
+        };
+        let distance = |m: &usize| {
+            let (x, y) = projected[*m];
+            (x - from.0).powi(2) + (y - from.1).powi(2)
+        };
+        let by_distance = |a: &usize, b: &usize| distance(a).partial_cmp(&distance(b)).unwrap();
+        // bridge to the closest corner that can be reached without crossing an edge
+        let target = (0..merged.len())
+            .filter(|&m| !blocked(projected[m]))
+            .min_by(by_distance)
+            .or_else(|| (0..merged.len()).min_by(by_distance))
+            .unwrap();
+        let mut bridged = merged[..=target].to_vec();
+        bridged.extend_from_slice(&hole[start..]);
+        bridged.extend_from_slice(&hole[..=start]);
+        bridged.extend_from_slice(&merged[target..]);
+        merged = bridged;
+    }
+    merged
+}
+
+impl Shape for Polygon {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let approach = self.normal.dot(object_ray.direction);
+        if approach.abs() < CLOSE_TO_ZERO {
+            return vec![];
+        }
+        let distance = self.normal.dot(self.points[0] - object_ray.origin) / approach;
+        let hit = object_ray.position(distance);
+        if self.contains((coordinate(hit, self.axes.0), coordinate(hit, self.axes.1))) {
+            vec![Intersection::new(distance, self)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        self.normal
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let mut b = BoundingBox::empty();
+        for p in &self.points {
+            b.add_point(*p);
+        }
+        b
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        for p in &self.points {
+            hash_tuple(hasher, p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::triangle::Triangle;
+
+    // an L in the xy plane; the corner at (1, 1) is the concave one
+    fn l_shape() -> Polygon {
+        Polygon::new(vec![
+            point!(0, 0, 0),
+            point!(2, 0, 0),
+            point!(2, 1, 0),
+            point!(1, 1, 0),
+            point!(1, 2, 0),
+            point!(0, 2, 0),
+        ])
+    }
+
+    #[test]
+    fn triangulating_convex_polygon_makes_fan() {
+        let square = vec![
+            point!(0, 0, 0),
+            point!(1, 0, 0),
+            point!(1, 0, 1),
+            point!(0, 0, 1),
+        ];
+        assert_eq!(triangulate(&square), vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn triangulating_concave_polygon() {
+        let l = l_shape();
+        let triangles = l.triangles();
+        assert_eq!(triangles.len(), 4);
+        // no triangle may cover the notch of the L
+        for &[a, b, c] in triangles {
+            let projected = &l.projected;
+            assert!(
+                !in_triangle((1.5, 1.5), projected[a], projected[b], projected[c], 0.),
+                "Triangle {:?} covers the notch",
+                [a, b, c]
+            );
+        }
+    }
+
+    #[test]
+    fn triangulating_polygon_with_corner_on_ear_edge() {
+        // an H; the inside corners of the crossbar sit on the edges of tempting ears
+        let h: Vec<Tuple> = [
+            (0, 3),
+            (1, 3),
+            (1, 2),
+            (3, 2),
+            (3, 3),
+            (4, 3),
+            (4, 0),
+            (3, 0),
+            (3, 1),
+            (1, 1),
+            (1, 0),
+            (0, 0),
+        ]
+        .iter()
+        .map(|&(x, y)| point!(x as f32, y as f32, 0))
+        .collect();
+        let projected = project(&h, (0, 1));
+        let area: f32 = triangulate(&h)
+            .iter()
+            .map(|&[a, b, c]| turn(projected[a], projected[b], projected[c]).abs() / 2.)
+            .sum();
+        assert_abs_diff_eq!(area, 8.);
+    }
+
+    #[test]
+    fn triangulating_polygon_with_hole() {
+        let square = |min: f32, max: f32| {
+            vec![
+                point!(min, min, 0),
+                point!(max, min, 0),
+                point!(max, max, 0),
+                point!(min, max, 0),
+            ]
+        };
+        // both squares wind the same way; the hole gets turned around
+        let merged = merge_holes(&square(0., 4.), &[square(1., 2.)]);
+        assert_eq!(merged.len(), 10);
+        let triangles = triangulate(&merged);
+        assert_eq!(triangles.len(), 8);
+        let projected = project(&merged, (0, 1));
+        let area: f32 = triangles
+            .iter()
+            .map(|&[a, b, c]| turn(projected[a], projected[b], projected[c]).abs() / 2.)
+            .sum();
+        assert_abs_diff_eq!(area, 16. - 1.);
+        for &[a, b, c] in &triangles {
+            assert!(!in_triangle(
+                (1.5, 1.5),
+                projected[a],
+                projected[b],
+                projected[c],
+                0.
+            ));
+        }
+    }
+
+    #[test]
+    fn ray_intersects_concave_polygon() {
+        let l = l_shape();
+        let test_data = vec![
+            ("lower arm", point!(1.5, 0.5, -2), Some(2.)),
+            ("upper arm", point!(0.5, 1.5, -2), Some(2.)),
+            ("corner", point!(0.5, 0.5, -2), Some(2.)),
+            ("notch", point!(1.5, 1.5, -2), None),
+            ("outside", point!(-0.5, 0.5, -2), None),
+        ];
+        for (name, origin, expected) in test_data {
+            let xs = l.local_intersect(Ray::new(origin, vector!(0, 0, 1)));
+            assert_eq!(xs.first().map(|i| i.distance), expected, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn parallel_ray_misses_polygon() {
+        let l = l_shape();
+        let xs = l.local_intersect(Ray::new(point!(0.5, 0.5, -1), vector!(1, 0, 0)));
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn polygon_normal_matches_triangle() {
+        let (p1, p2, p3) = (point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0));
+        let triangle = Triangle::new(p1, p2, p3);
+        let polygon = Polygon::new(vec![p1, p2, p3]);
+        assert_eq!(
+            polygon.local_norm_at(point!(0, 0.5, 0)),
+            triangle.local_norm_at(point!(0, 0.5, 0))
+        );
+    }
+
+    #[test]
+    fn polygon_bounding_box() {
+        let b = l_shape().bounding_box();
+        assert_eq!(b.min, point!(0, 0, 0));
+        assert_eq!(b.max, point!(2, 2, 0));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A convex polygon in the xz plane, extruded along y from minimum_y to maximum_y and closed with
+// flat caps. The outline is a list of (x, z) points and may be given in either winding order.
+#[derive(Debug, Clone)]
+pub struct Prism {
+    base: BaseShape,
+    outline: Vec<(f32, f32)>,
+    // outward unit normal (x, z) of each side and its distance from the y axis; the side starting
+    // at outline[i] is the plane normal . (x, z) = offset
+    sides: Vec<((f32, f32), f32)>,
+    pub minimum_y: f32,
+    pub maximum_y: f32,
+}
+
+impl Prism {
+    pub fn new(outline: Vec<(f32, f32)>) -> Self {
+        assert!(
+            outline.len() >= 3,
+            "A prism outline needs at least 3 points: {:?}",
+            outline
+        );
+        let edge = |i: usize| {
+            let (x0, z0) = outline[i];
+            let (x1, z1) = outline[(i + 1) % outline.len()];
+            (x1 - x0, z1 - z0)
+        };
+        // the sign of each corner's turn; all turns go the same way in a convex polygon
+        let turns: Vec<f32> = (0..outline.len())
+            .map(|i| {
+                let (dx0, dz0) = edge(i);
+                let (dx1, dz1) = edge((i + 1) % outline.len());
+                dx0 * dz1 - dz0 * dx1
+            })
+            .collect();
+        let counter_clockwise = turns.iter().sum::<f32>() > 0.;
+        assert!(
+            turns.iter().all(|&turn| if counter_clockwise {
+                turn >= -CLOSE_TO_ZERO
+            } else {
+                turn <= CLOSE_TO_ZERO
+            }),
+            "A prism outline must be convex: {:?}",
+            outline
+        );
+        let sides = (0..outline.len())
+            .map(|i| {
+                let (dx, dz) = edge(i);
+                let length = (dx * dx + dz * dz).sqrt();
+                assert!(
+                    length > CLOSE_TO_ZERO,
+                    "A prism outline cannot repeat a point: {:?}",
+                    outline
+                );
+                // turning counter-clockwise (from x towards z) means the inside is on the left
+                let normal = if counter_clockwise {
+                    (dz / length, -dx / length)
+                } else {
+                    (-dz / length, dx / length)
+                };
+                let (x, z) = outline[i];
+                (normal, normal.0 * x + normal.1 * z)
+            })
+            .collect();
+        Prism {
+            base: BaseShape::new(),
+            outline,
+            sides,
+            minimum_y: -1.,
+            maximum_y: 1.,
+        }
+    }
+
+    pub fn build(outline: Vec<(f32, f32)>, transform: Matrix, material: Material) -> Self {
+        let mut s = Prism::new(outline);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    // A right-angled triangular prism: its slanted side runs from (1, -1) to (-1, 1) in xz. Rotate it
+    // onto its side to get a ramp.
+    pub fn wedge() -> Self {
+        Prism::new(vec![(-1., -1.), (1., -1.), (-1., 1.)])
+    }
+
+    pub fn outline(&self) -> &[(f32, f32)] {
+        &self.outline
+    }
+
+    // every face of the prism as a plane (outward normal, offset): points inside of the prism
+    // satisfy normal . point <= offset for all of them
+    fn faces(&self) -> impl Iterator<Item = (Tuple, f32)> + '_ {
+        self.sides
+            .iter()
+            .map(|&((x, z), offset)| (vector!(x, 0, z), offset))
+            .chain(vec![
+                (vector!(0, 1, 0), self.maximum_y),
+                (vector!(0, -1, 0), -self.minimum_y),
+            ])
+    }
+}
+
+impl Default for Prism {
+    // a cube-sized square prism
+    fn default() -> Self {
+        Prism::new(vec![(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)])
+    }
+}
+
+const CLOSE_TO_ZERO: f32 = 0.000_001;
+
+impl Shape for Prism {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    // Clip the ray against each face's half-space: it enters the prism at the last face it crosses
+    // going in and leaves at the first one it crosses going out
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let mut enter = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        for (normal, offset) in self.faces() {
+            let approach = normal.dot(object_ray.direction);
+            let clearance = offset - normal.dot(object_ray.origin - point!(0, 0, 0));
+            if approach.abs() < CLOSE_TO_ZERO {
+                // parallel to this face; either always inside of it or always outside
+                if clearance < 0. {
+                    return vec![];
+                }
+                continue;
+            }
+            let distance = clearance / approach;
+            if approach < 0. {
+                enter = enter.max(distance);
+            } else {
+                exit = exit.min(distance);
+            }
+            if enter > exit {
+                return vec![];
+            }
+        }
+        vec![
+            Intersection::new(enter, self),
+            Intersection::new(exit, self),
+        ]
+    }
+
+    // the normal of the face that the point is closest to (or furthest outside of)
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
+        let from_origin = object_point - point!(0, 0, 0);
+        self.faces()
+            .map(|(normal, offset)| (normal, normal.dot(from_origin) - offset))
+            .fold((vector!(0, 1, 0), f32::NEG_INFINITY), |best, face| {
+                if face.1 > best.1 {
+                    face
+                } else {
+                    best
+                }
+            })
+            .0
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let mut b = BoundingBox::empty();
+        for &(x, z) in &self.outline {
+            b.add_point(point!(x, self.minimum_y, z));
+            b.add_point(point!(x, self.maximum_y, z));
+        }
+        b
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        for &(x, z) in &self.outline {
+            hash_f32(hasher, x);
+            hash_f32(hasher, z);
+        }
+        hash_f32(hasher, self.minimum_y);
+        hash_f32(hasher, self.maximum_y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::SQRT_2;
+
+    // a regular hexagon with corners 1 unit from the y axis
+    fn hexagon() -> Prism {
+        Prism::new(
+            (0..6)
+                .map(|i| {
+                    let angle = i as f32 * std::f32::consts::PI / 3.;
+                    (angle.cos(), angle.sin())
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn ray_intersects_prism() {
+        let hexagon = hexagon();
+        let wedge = Prism::wedge();
+        let apothem = 3f32.sqrt() / 2.;
+        let test_data = vec![
+            (
+                "hexagon side",
+                &hexagon,
+                point!(-5, 0, 0),
+                vector!(1, 0, 0),
+                vec![4., 6.],
+            ),
+            (
+                "hexagon caps",
+                &hexagon,
+                point!(0, 5, 0),
+                vector!(0, -1, 0),
+                vec![4., 6.],
+            ),
+            (
+                "hexagon flat sides",
+                &hexagon,
+                point!(0, 0.5, -5),
+                vector!(0, 0, 1),
+                vec![5. - apothem, 5. + apothem],
+            ),
+            (
+                "from inside",
+                &hexagon,
+                point!(0, 0, 0),
+                vector!(0, 0, 1),
+                vec![-apothem, apothem],
+            ),
+            (
+                "miss",
+                &hexagon,
+                point!(0, 1.5, -5),
+                vector!(0, 0, 1),
+                vec![],
+            ),
+            (
+                "wedge slope",
+                &wedge,
+                point!(5, 0, 5),
+                vector!(-1, 0, -1),
+                vec![5. * SQRT_2, 6. * SQRT_2],
+            ),
+            (
+                "beyond wedge slope",
+                &wedge,
+                point!(0.5, 0, 5),
+                vector!(0, 0, -1),
+                vec![5.5, 6.],
+            ),
+            (
+                "past wedge slope",
+                &wedge,
+                point!(5, 0, 5),
+                vector!(0, 0, -1),
+                vec![],
+            ),
+        ];
+        for (name, prism, origin, direction, expected) in test_data {
+            let direction = direction.norm();
+            let xs = prism.local_intersect(Ray::new(origin, direction));
+            let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+            assert_eq!(distances.len(), expected.len(), "Case {}", name);
+            for (distance, expected) in distances.iter().zip(expected) {
+                assert_abs_diff_eq!(*distance, expected, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn normals_on_prism() {
+        let wedge = Prism::wedge();
+        let slope = SQRT_2 / 2.;
+        let test_data = vec![
+            ("front", point!(0, 0, -1), vector!(0, 0, -1)),
+            ("left", point!(-1, 0.5, 0), vector!(-1, 0, 0)),
+            ("slope", point!(0, 0, 0), vector!(slope, 0, slope)),
+            ("top", point!(-0.5, 1, -0.5), vector!(0, 1, 0)),
+            ("bottom", point!(-0.5, -1, -0.5), vector!(0, -1, 0)),
+        ];
+        for (name, p, expected) in test_data {
+            let normal = wedge.local_norm_at(p);
+            assert_abs_diff_eq!(normal, expected);
+            assert_eq!(normal.w, 0., "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn winding_order_does_not_matter() {
+        let clockwise = Prism::new(vec![(-1., -1.), (-1., 1.), (1., -1.)]);
+        let r = Ray::new(point!(0.5, 0, 5), vector!(0, 0, -1));
+        let xs = clockwise.local_intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_abs_diff_eq!(xs[0].distance, 5.5);
+        assert_abs_diff_eq!(
+            clockwise.local_norm_at_hit(point!(0, 0, 0), &xs[0]),
+            Prism::wedge().local_norm_at_hit(point!(0, 0, 0), &xs[0])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "convex")]
+    fn concave_outline_is_rejected() {
+        Prism::new(vec![(0., 0.), (2., 0.), (1., 0.5), (2., 2.), (0., 2.)]);
+    }
+
+    #[test]
+    fn prism_bounding_box() {
+        let mut wedge = Prism::wedge();
+        wedge.maximum_y = 3.;
+        let b = wedge.bounding_box();
+        assert_eq!(b.min, point!(-1, -1, -1));
+        assert_eq!(b.max, point!(1, 3, 1));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::cube::Cube;
+use crate::shape::sdf::intersect_convex;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A cube with the same size as Cube, but with its edges and corners rounded off with the given
+// radius. A radius of 0 gives a regular cube and a radius of 1 gives a sphere.
+#[derive(Debug, Clone)]
+pub struct RoundedCube {
+    base: BaseShape,
+    pub edge_radius: f32,
+}
+
+impl RoundedCube {
+    pub fn new(edge_radius: f32) -> Self {
+        assert!(
+            (0. ..=1.).contains(&edge_radius),
+            "Rounded cube edge radius must be between 0 and 1: {}",
+            edge_radius
+        );
+        RoundedCube {
+            base: BaseShape::new(),
+            edge_radius,
+        }
+    }
+
+    pub fn build(edge_radius: f32, transform: Matrix, material: Material) -> Self {
+        let mut s = RoundedCube::new(edge_radius);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    // How far p is outside of the smaller cube whose surface, pushed out by edge_radius, is the
+    // rounded cube. Negative coordinates mean p is inside of that cube's slab along that axis.
+    fn outside_inner_cube(&self, p: Tuple) -> (f32, f32, f32) {
+        let inner = 1. - self.edge_radius;
+        (p.x.abs() - inner, p.y.abs() - inner, p.z.abs() - inner)
+    }
+
+    fn signed_distance(&self, p: Tuple) -> f32 {
+        let (x, y, z) = self.outside_inner_cube(p);
+        let outside = (x.max(0.).powi(2) + y.max(0.).powi(2) + z.max(0.).powi(2)).sqrt();
+        let inside = x.max(y).max(z).min(0.);
+        outside + inside - self.edge_radius
+    }
+}
+
+impl Default for RoundedCube {
+    fn default() -> Self {
+        RoundedCube::new(0.1)
+    }
+}
+
+impl Shape for RoundedCube {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        intersect_convex(
+            self,
+            |p| self.signed_distance(p),
+            object_ray,
+            Cube::min_point(),
+            Cube::max_point(),
+        )
+    }
+
+    // every surface point is edge_radius away from the inner cube, so the normal points straight
+    // away from the closest point on the inner cube
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
+        let (x, y, z) = self.outside_inner_cube(object_point);
+        let normal = vector!(
+            x.max(0.) * object_point.x.signum(),
+            y.max(0.) * object_point.y.signum(),
+            z.max(0.) * object_point.z.signum()
+        );
+        if normal.magnitude() > 0. {
+            normal
+        } else {
+            // only possible without rounding; use the closest face of the cube instead
+            Cube::new().local_norm_at(object_point)
+        }
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min: Cube::min_point(),
+            max: Cube::max_point(),
+        }
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_f32(hasher, self.edge_radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::SQRT_2;
+
+    #[test]
+    fn ray_intersects_rounded_cube() {
+        let c = RoundedCube::new(0.5);
+        let test_data = vec![
+            ("face", point!(5, 0.2, 0), vector!(-1, 0, 0), vec![4., 6.]),
+            (
+                "edge",
+                point!(5, 0, 5),
+                vector!(-1, 0, -1),
+                // the rounded edge is a quarter circle of radius 0.5 around (0.5, 0.5) in xz
+                vec![
+                    5. * SQRT_2 - (0.5 * SQRT_2 + 0.5),
+                    5. * SQRT_2 + (0.5 * SQRT_2 + 0.5),
+                ],
+            ),
+            (
+                "cut-off corner",
+                point!(0.95, 0.95, -5),
+                vector!(0, 0, 1),
+                vec![],
+            ),
+            ("miss", point!(2, 0, -5), vector!(0, 0, 1), vec![]),
+        ];
+        for (name, origin, direction, expected) in test_data {
+            let xs = c.local_intersect(Ray::new(origin, direction.norm()));
+            let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+            assert_eq!(distances.len(), expected.len(), "Case {}", name);
+            for (distance, expected) in distances.iter().zip(expected) {
+                assert_abs_diff_eq!(*distance, expected, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_cube_normals() {
+        let c = RoundedCube::new(0.5);
+        let test_data = vec![
+            ("face", point!(1, 0.2, 0.3), vector!(1, 0, 0)),
+            (
+                "edge",
+                point!(0.5 + 0.5 / SQRT_2, 0, -0.5 - 0.5 / SQRT_2),
+                vector!(1, 0, -1).norm(),
+            ),
+            (
+                "corner",
+                point!(-0.8, -0.8, -0.8),
+                vector!(-1, -1, -1).norm(),
+            ),
+        ];
+        for (name, p, expected) in test_data {
+            let normal = c.local_norm_at(p).norm();
+            assert_abs_diff_eq!(normal, expected, epsilon = 0.0001);
+            assert_eq!(normal.w, 0., "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn unrounded_cube_matches_cube() {
+        let c = RoundedCube::new(0.);
+        let r = Ray::new(point!(0.5, 5, 0.5), vector!(0, -1, 0));
+        let xs = c.local_intersect(r);
+        assert_abs_diff_eq!(xs[0].distance, 4., epsilon = 0.0001);
+        assert_eq!(
+            c.local_norm_at_hit(point!(0.5, 1, 0.5), &xs[0]),
+            vector!(0, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_f32;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::sdf::intersect_convex;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// A closed cylinder of radius 1 running from y = -1 to y = 1, with the rims where the caps meet
+// the sides rounded off with the given radius.
+#[derive(Debug, Clone)]
+pub struct RoundedCylinder {
+    base: BaseShape,
+    pub edge_radius: f32,
+}
+
+impl RoundedCylinder {
+    pub fn new(edge_radius: f32) -> Self {
+        assert!(
+            (0. ..=1.).contains(&edge_radius),
+            "Rounded cylinder edge radius must be between 0 and 1: {}",
+            edge_radius
+        );
+        RoundedCylinder {
+            base: BaseShape::new(),
+            edge_radius,
+        }
+    }
+
+    pub fn build(edge_radius: f32, transform: Matrix, material: Material) -> Self {
+        let mut s = RoundedCylinder::new(edge_radius);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    // How far p is outside of the smaller cylinder whose surface, pushed out by edge_radius, is the
+    // rounded cylinder: first away from the y axis, then past the caps.
+    fn outside_inner_cylinder(&self, p: Tuple) -> (f32, f32) {
+        let inner = 1. - self.edge_radius;
+        (
+            (p.x.powi(2) + p.z.powi(2)).sqrt() - inner,
+            p.y.abs() - inner,
+        )
+    }
+
+    fn signed_distance(&self, p: Tuple) -> f32 {
+        let (radial, vertical) = self.outside_inner_cylinder(p);
+        let outside = (radial.max(0.).powi(2) + vertical.max(0.).powi(2)).sqrt();
+        let inside = radial.max(vertical).min(0.);
+        outside + inside - self.edge_radius
+    }
+}
+
+impl Default for RoundedCylinder {
+    fn default() -> Self {
+        RoundedCylinder::new(0.1)
+    }
+}
+
+impl Shape for RoundedCylinder {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let b = self.bounding_box();
+        intersect_convex(self, |p| self.signed_distance(p), object_ray, b.min, b.max)
+    }
+
+    // the normal points straight away from the closest point on the inner cylinder
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
+        let (radial, vertical) = self.outside_inner_cylinder(object_point);
+        let distance_from_axis = (object_point.x.powi(2) + object_point.z.powi(2)).sqrt();
+        if radial <= 0. && vertical <= 0. {
+            // only possible without rounding; use the closest of the sides and caps instead
+            return if radial > vertical {
+                vector!(object_point.x, 0, object_point.z)
+            } else {
+                vector!(0, object_point.y.signum(), 0)
+            };
+        }
+        let outward = if radial > 0. {
+            radial / distance_from_axis
+        } else {
+            0.
+        };
+        vector!(
+            object_point.x * outward,
+            vertical.max(0.) * object_point.y.signum(),
+            object_point.z * outward
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min: point!(-1, -1, -1),
+            max: point!(1, 1, 1),
+        }
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_f32(hasher, self.edge_radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::SQRT_2;
+
+    #[test]
+    fn ray_intersects_rounded_cylinder() {
+        let c = RoundedCylinder::new(0.5);
+        let test_data = vec![
+            ("side", point!(5, 0, 0), vector!(-1, 0, 0), vec![4., 6.]),
+            ("cap", point!(0.3, 5, 0), vector!(0, -1, 0), vec![4., 6.]),
+            (
+                "rim",
+                point!(5, 5, 0),
+                vector!(-1, -1, 0),
+                // the rim is a circle of radius 0.5 around (0.5, 0.5) in xy
+                vec![4.5 * SQRT_2 - 0.5, 5.5 * SQRT_2 + 0.5],
+            ),
+            ("past rim", point!(0.95, 0.95, -5), vector!(0, 0, 1), vec![]),
+            ("miss", point!(1.5, 0, -5), vector!(0, 0, 1), vec![]),
+        ];
+        for (name, origin, direction, expected) in test_data {
+            let xs = c.local_intersect(Ray::new(origin, direction.norm()));
+            let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+            assert_eq!(distances.len(), expected.len(), "Case {}", name);
+            for (distance, expected) in distances.iter().zip(expected) {
+                assert_abs_diff_eq!(*distance, expected, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_cylinder_normals() {
+        let c = RoundedCylinder::new(0.5);
+        let rim = 0.5 + 0.5 / SQRT_2;
+        let test_data = vec![
+            ("side", point!(0, 0.2, -1), vector!(0, 0, -1)),
+            ("top", point!(0.2, 1, 0.2), vector!(0, 1, 0)),
+            ("bottom", point!(0, -1, 0), vector!(0, -1, 0)),
+            ("rim", point!(rim, -rim, 0), vector!(1, -1, 0).norm()),
+        ];
+        for (name, p, expected) in test_data {
+            let normal = c.local_norm_at(p).norm();
+            assert_abs_diff_eq!(normal, expected, epsilon = 0.0001);
+            assert_eq!(normal.w, 0., "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn unrounded_cylinder_normals() {
+        let c = RoundedCylinder::new(0.);
+        assert_eq!(c.local_norm_at(point!(1, 0.5, 0)), vector!(1, 0, 0));
+        assert_eq!(c.local_norm_at(point!(0.5, 1, 0)), vector!(0, 1, 0));
+    }
+}
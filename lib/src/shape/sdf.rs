@@ -0,0 +1,57 @@
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::shape::cube::aabb_intersection;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+
+// Helpers for shapes that are defined by a signed distance function: the distance from a point to
+// the shape's surface, negative inside of the shape.
+
+// how close a point on the ray must get to count as being on the surface
+const SURFACE_DISTANCE: f32 = 0.000_01;
+const MAX_STEPS: usize = 256;
+
+// Sphere-traces from one distance along the ray towards another, stepping forward by the distance
+// to the surface each time. Returns the distance of the first surface point reached, if any.
+fn trace<F: Fn(Tuple) -> f32>(sdf: &F, ray: &Ray, from: f32, to: f32) -> Option<f32> {
+    let speed = ray.direction.magnitude();
+    let heading = (to - from).signum();
+    let mut distance = from;
+    for _ in 0..MAX_STEPS {
+        let to_surface = sdf(ray.position(distance));
+        if to_surface < SURFACE_DISTANCE {
+            return Some(distance);
+        }
+        distance += heading * to_surface / speed;
+        if (to - distance) * heading < 0. {
+            return None;
+        }
+    }
+    None
+}
+
+// Intersections of the ray with a convex shape given by sdf and contained in the box between min
+// and max: one where the ray enters the shape, found by tracing forward from where it enters the
+// box, and one where it leaves, found by tracing backward from where it leaves the box.
+pub fn intersect_convex<'a, F: Fn(Tuple) -> f32>(
+    shape: &'a dyn Shape,
+    sdf: F,
+    ray: Ray,
+    min: Tuple,
+    max: Tuple,
+) -> Vec<Intersection<'a>> {
+    let (enter_box, exit_box) = match aabb_intersection(ray, min, max) {
+        Some(distances) => distances,
+        None => return vec![],
+    };
+    match trace(&sdf, &ray, enter_box, exit_box) {
+        Some(enter) => {
+            let exit = trace(&sdf, &ray, exit_box, enter).unwrap_or(enter);
+            vec![
+                Intersection::new(enter, shape),
+                Intersection::new(exit, shape),
+            ]
+        }
+        None => vec![],
+    }
+}
@@ -1,27 +1,40 @@
 use crate::bounding_box::BoundingBox;
+use crate::fingerprint::{hash_matrix, StructureRecorder};
 use crate::intersection::Intersection;
 use crate::material::Material;
-use crate::matrix::Matrix;
+use crate::matrix::{Matrix, SingularMatrixError};
 use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
+use crate::stats::{self, Counter};
 use crate::tuple::Tuple;
-use downcast_rs::Downcast;
+use downcast_rs::DowncastSync;
 use dyn_clone::DynClone;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::sync::Arc;
 
-// TODO: update to DowncastSync later when parallelizing
-pub trait Shape: Debug + DynClone + Downcast {
+// Shapes are Sync so that a world can be intersected from several threads at once
+pub trait Shape: Debug + DynClone + DowncastSync {
     // tthe BaseShape that the wrapping instance is delegating to
     fn get_base(&self) -> &BaseShape;
     fn get_base_mut(&mut self) -> &mut BaseShape;
 
     fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection>;
-    fn local_norm_at(&self, object_point: Tuple, hit: &Intersection) -> Tuple;
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple;
+    // Override for shapes whose normals depend on more of the hit than where it is, such as the
+    // u and v of a smooth triangle; their local_norm_at is never called by the renderer.
+    fn local_norm_at_hit(&self, object_point: Tuple, _hit: &Intersection) -> Tuple {
+        self.local_norm_at(object_point)
+    }
 
     fn bounding_box(&self) -> BoundingBox;
 
+    // Feed the shape's own parameters (e.g. a triangle's points or a group's children, but not
+    // its transformation or material) into hasher. Shapes with such parameters must override this.
+    fn hash_geometry(&self, _hasher: &mut dyn Hasher) {}
+
     // The rest of these should not be overridden by Shape implementers
 
     fn get_unique_id(&self) -> usize {
@@ -30,6 +43,15 @@ pub trait Shape: Debug + DynClone + Downcast {
     fn transformation(&self) -> &Matrix {
         self.get_base().transformation()
     }
+    // Like set_transformation, but returns an error instead of panicking if t is degenerate
+    // (not invertible), e.g. because it scales the shape to nothing along some axis
+    fn try_set_transformation(&mut self, t: Matrix) -> Result<(), SingularMatrixError> {
+        if t.try_inverse().is_none() {
+            return Err(SingularMatrixError { matrix: t });
+        }
+        self.set_transformation(t);
+        Ok(())
+    }
     fn set_transformation(&mut self, t: Matrix) {
         self.get_base_mut().set_transformation(t)
     }
@@ -39,6 +61,13 @@ pub trait Shape: Debug + DynClone + Downcast {
     fn set_material(&mut self, m: Material) {
         self.get_base_mut().set_material(m)
     }
+    fn shared_material(&self) -> &Arc<Material> {
+        self.get_base().shared_material()
+    }
+    // Use a material that may also be used by other shapes; only the pointer is copied
+    fn set_shared_material(&mut self, m: Arc<Material>) {
+        self.get_base_mut().set_shared_material(m)
+    }
     fn casts_shadow(&self) -> bool {
         self.get_base().casts_shadow()
     }
@@ -58,14 +87,19 @@ pub trait Shape: Debug + DynClone + Downcast {
         self.transformation_inverse() * world_point
     }
     fn world_to_object_ray(&self, world_ray: &Ray) -> Ray {
-        world_ray.transform(&self.transformation_inverse())
+        world_ray.transform(self.transformation_inverse())
     }
 
     // When intersecting the shape with a ray, all shapes need to first convert the
     //ray into object space, transforming it by the inverse of the shape’s transformation
     //matrix.
     fn intersect(&self, world_ray: Ray) -> Vec<Intersection> {
-        let object_ray = self.world_to_object_ray(&world_ray);
+        stats::count(Counter::RayPrimitiveTests);
+        // the ray was passed by value, so it can be converted to object space in place
+        let mut object_ray = world_ray;
+        if !self.get_base().has_identity_transformation() {
+            object_ray.transform_mut(self.transformation_inverse());
+        }
         self.local_intersect(object_ray)
     }
 
@@ -127,7 +161,11 @@ pub trait Shape: Debug + DynClone + Downcast {
         // This means we can simplify the first 4 cases into one: just take the inverse transpose
         // of the transform. Transposing the inverse of the rotation will give the original rotation
         // again, and transposing the scaling will not affect the normal's direction at all:
-        let mut world_normal = self.transformation_inverse_transpose() * object_normal;
+        let mut world_normal = if self.get_base().has_identity_transformation() {
+            *object_normal
+        } else {
+            self.transformation_inverse_transpose() * object_normal
+        };
 
         // That leaves translation. Translation does not affect vectors at all, so conceptually it
         // can be ignored. Multiplication by translation does not affect the 3x3 x-y-z components of
@@ -145,12 +183,25 @@ pub trait Shape: Debug + DynClone + Downcast {
         world_normal.norm()
     }
 
-    fn normal_at(&self, world_point: &Tuple, hit: &Intersection) -> Tuple {
+    fn normal_at(&self, world_point: &Tuple) -> Tuple {
         // When computing the normal vector, all shapes need to first convert the point to
         // object space, multiplying it by the inverse of the shape’s transformation matrix.
-        let object_point = self.world_to_object_point(&world_point);
-        let object_normal = self.local_norm_at(object_point, hit);
-        self.normal_to_world(&object_normal)
+        let object_point = if self.get_base().has_identity_transformation() {
+            *world_point
+        } else {
+            self.world_to_object_point(world_point)
+        };
+        self.normal_to_world(&self.local_norm_at(object_point))
+    }
+
+    // like normal_at, for shapes that need the hit (see local_norm_at_hit)
+    fn normal_at_hit(&self, world_point: &Tuple, hit: &Intersection) -> Tuple {
+        let object_point = if self.get_base().has_identity_transformation() {
+            *world_point
+        } else {
+            self.world_to_object_point(world_point)
+        };
+        self.normal_to_world(&self.local_norm_at_hit(object_point, hit))
     }
 
     // should only be overridden by GroupShape and CSG
@@ -165,12 +216,77 @@ pub trait Shape: Debug + DynClone + Downcast {
 
     // no-op for shapes that do not combine other shapes
     fn divide(&mut self, _threshold: usize) {}
+
+    // Moves the shape's transformation into its geometry and resets the transformation to the
+    // identity, so that rays no longer need to be transformed to intersect it; the geometry can't
+    // be told apart from the transformation afterwards. Only shapes defined by points, such as
+    // triangles, can do this; others are left alone and return false.
+    fn bake_transform(&mut self) -> bool {
+        false
+    }
+
+    // Whether camera rays should skip the back side of the shape, which is never seen from outside
+    // a closed mesh. No-op for shapes that are not made of triangles.
+    fn set_backface_culling(&mut self, _cull: bool) {}
+
+    // Feed everything that determines how the shape renders into hasher: its type, geometry,
+    // transformation and material, but not its unique ID
+    fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        self.as_any().type_id().hash(&mut &mut *hasher);
+        self.hash_geometry(hasher);
+        hash_matrix(hasher, self.transformation());
+        self.material().hash_structure(hasher);
+        hasher.write_u8(self.casts_shadow() as u8);
+    }
+
+    // Hash of the shape's structure; shapes that are structurally equal (even if they are
+    // different objects) have the same fingerprint. Only stable within a single run.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_structure(&mut hasher);
+        hasher.finish()
+    }
+
+    // Whether the shapes would render identically, regardless of their identity. Use == to
+    // check whether they are the same object.
+    fn structurally_eq(&self, other: &dyn Shape) -> bool {
+        let mut mine = StructureRecorder::default();
+        self.hash_structure(&mut mine);
+        let mut theirs = StructureRecorder::default();
+        other.hash_structure(&mut theirs);
+        mine.bytes == theirs.bytes
+    }
 }
 
-// TODO: add 'sync' keyword when parallelizing
-impl_downcast!(Shape);
+impl_downcast!(sync Shape);
 dyn_clone::clone_trait_object!(Shape);
 
+// Chainable setters shared by every shape, for building one in a single expression, e.g.
+// Cube::new().with_transform(scaling(2., 1., 1.)).with_material(glass()).with_shadow(false)
+pub trait ShapeBuilder: Shape + Sized {
+    fn with_transform(mut self, transform: Matrix) -> Self {
+        self.set_transformation(transform);
+        self
+    }
+
+    fn with_material(mut self, material: Material) -> Self {
+        self.set_material(material);
+        self
+    }
+
+    fn with_shared_material(mut self, material: Arc<Material>) -> Self {
+        self.set_shared_material(material);
+        self
+    }
+
+    fn with_shadow(mut self, casts_shadow: bool) -> Self {
+        self.set_casts_shadow(casts_shadow);
+        self
+    }
+}
+
+impl<T: Shape + Sized> ShapeBuilder for T {}
+
 impl PartialEq for dyn Shape {
     fn eq(&self, other: &Self) -> bool {
         self.get_unique_id() == other.get_unique_id()
@@ -188,14 +304,27 @@ impl Hash for dyn Shape {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color::Color;
+    use crate::constants::white;
+    use crate::light::point_light::PointLight;
+    use crate::matrix::identity_4x4;
+    use crate::pattern::pattern::Pattern;
+    use crate::pattern::stripes::Stripes;
+    use crate::shape::cone::Cone;
+    use crate::shape::cube::Cube;
+    use crate::shape::cylinder::Cylinder;
     use crate::shape::group::GroupShape;
+    use crate::shape::plane::Plane;
     use crate::shape::sphere::Sphere;
     use crate::shape::test_shape::TestShape;
-    use crate::test::utils::dummy_intersection;
+    use crate::shape::triangle::Triangle;
+    use crate::test::strategies;
     use crate::transformations::rotation_y;
     use crate::transformations::rotation_z;
     use crate::transformations::scaling;
     use crate::transformations::translation;
+    use crate::world::World;
+    use proptest::prelude::*;
     use std::f32::consts::FRAC_1_SQRT_2;
     use std::f32::consts::PI;
 
@@ -206,7 +335,7 @@ mod tests {
         s.set_transformation(scaling(2.0, 2.0, 2.0));
         s.intersect(r);
         assert_eq!(
-            s.saved_ray.into_inner().unwrap(),
+            s.saved_ray().unwrap(),
             Ray::new(point!(0, 0, -2.5), vector!(0, 0, 0.5))
         );
     }
@@ -218,7 +347,7 @@ mod tests {
         s.set_transformation(translation(5.0, 0.0, 0.0));
         s.intersect(r);
         assert_eq!(
-            s.saved_ray.into_inner().unwrap(),
+            s.saved_ray().unwrap(),
             Ray::new(point!(-5, 0, -5), vector!(0, 0, 1))
         );
     }
@@ -227,7 +356,7 @@ mod tests {
     fn normal_on_translated_shape() {
         let mut s = TestShape::new();
         s.set_transformation(translation(0.0, 1.0, 0.0));
-        let n = s.normal_at(&point!(0, 1.70711, -0.70711), &dummy_intersection(&s));
+        let n = s.normal_at(&point!(0, 1.70711, -0.70711));
         assert_abs_diff_eq!(n, vector!(0.0, 0.600_000_1, -0.799_999_95));
     }
 
@@ -235,17 +364,14 @@ mod tests {
     fn normal_on_transformed_shape() {
         let mut s = TestShape::new();
         s.set_transformation(scaling(1.0, 0.5, 1.0) * rotation_z(PI / 5.0));
-        let n = s.normal_at(
-            &point!(0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
-            &dummy_intersection(&s),
-        );
+        let n = s.normal_at(&point!(0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
         assert_abs_diff_eq!(n, vector!(-0.083_526_63, 0.932_529_6, -0.351_300_3));
     }
 
     #[test]
     fn normal_is_normalized_vector() {
         let s = TestShape::new();
-        let n = s.normal_at(&point!(1, 5, 10), &dummy_intersection(&s));
+        let n = s.normal_at(&point!(1, 5, 10));
         assert_abs_diff_eq!(n, n.norm());
     }
 
@@ -255,7 +381,6 @@ mod tests {
         let g1_transform = rotation_y(PI / 2.0);
         let g2_transform = scaling(1.0, 2.0, 3.0);
         let s_transform = translation(5.0, 0.0, 0.0);
-        let object_normal = vector!(frac_1_sqrt_3, frac_1_sqrt_3, frac_1_sqrt_3);
 
         let mut s = Sphere::new();
         s.set_transformation(s_transform.clone());
@@ -268,11 +393,49 @@ mod tests {
         g1.add_child(Box::new(g2));
 
         // lost ownership of these, so we have to dig them out again for testing...
-        let g2 = g1.get_children()[0].as_ref();
-        let s = g2.downcast_ref::<GroupShape>().unwrap().get_children()[0].as_ref();
+        let g2 = g1.get_children()[0].downcast_ref::<GroupShape>().unwrap();
+        let s = g2.get_children()[0].as_ref();
 
-        let n = s.normal_to_world(&object_normal);
-        assert_abs_diff_eq!(n, vector!(0.28571427, 0.42857143, -0.85714287));
+        // the groups' transformations are applied by the intersections found through them
+        let mut i = Intersection::new(0., s);
+        i.found_through(g2.transformation_inverse());
+        i.found_through(g1.transformation_inverse());
+        // the point on the unit sphere where the normal is (1/sqrt(3), 1/sqrt(3), 1/sqrt(3))
+        let object_point = point!(frac_1_sqrt_3, frac_1_sqrt_3, frac_1_sqrt_3);
+        let world_point =
+            g1.transformation() * (g2.transformation() * (s.transformation() * object_point));
+
+        let n = i.normal_at(&world_point);
+        assert_abs_diff_eq!(
+            n,
+            vector!(0.28571427, 0.42857143, -0.85714287),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn degenerate_transformation_is_rejected() {
+        let mut s = Sphere::new();
+        s.set_transformation(translation(1., 0., 0.));
+        let result = s.try_set_transformation(scaling(0., 0., 0.));
+        assert_eq!(
+            result,
+            Err(SingularMatrixError {
+                matrix: scaling(0., 0., 0.)
+            })
+        );
+        assert_eq!(s.transformation(), &translation(1., 0., 0.));
+
+        let mut g = GroupShape::with_children(vec![Box::new(Sphere::new())]);
+        assert!(g.try_set_transformation(scaling(1., 0., 1.)).is_err());
+        assert!(g.try_set_transformation(scaling(1., 2., 1.)).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "not invertible")]
+    fn setting_degenerate_transformation_panics_with_explanation() {
+        let mut s = Sphere::new();
+        s.set_transformation(scaling(0., 0., 0.));
     }
 
     #[test]
@@ -283,4 +446,184 @@ mod tests {
         assert_eq!(b.min, point!(0.5, -5, 1));
         assert_eq!(b.max, point!(1.5, -1, 9.));
     }
+
+    fn patterned_material() -> Material {
+        let mut stripes = Stripes::new(color!(1, 0, 0), color!(0, 0, 1));
+        stripes.set_transformation(scaling(0.5, 1., 1.));
+        Material::builder().pattern(Arc::new(stripes)).build()
+    }
+
+    fn group_of(transform: Matrix) -> GroupShape {
+        let mut g = GroupShape::new();
+        g.add_child(Box::new(Sphere::build(transform, Material::default())));
+        g.add_child(Box::new(Triangle::new(
+            point!(0, 1, 0),
+            point!(-1, 0, 0),
+            point!(1, 0, 0),
+        )));
+        g
+    }
+
+    #[test]
+    fn structural_equality_and_fingerprints() {
+        type Case = (&'static str, Box<dyn Shape>, Box<dyn Shape>, bool);
+        let test_data: Vec<Case> = vec![
+            (
+                "identical spheres",
+                Box::new(Sphere::build(translation(1., 2., 3.), patterned_material())),
+                Box::new(Sphere::build(translation(1., 2., 3.), patterned_material())),
+                true,
+            ),
+            (
+                "different transformations",
+                Box::new(Sphere::build(translation(1., 2., 3.), Material::default())),
+                Box::new(Sphere::build(translation(1., 2., 4.), Material::default())),
+                false,
+            ),
+            (
+                "different materials",
+                Box::new(Sphere::build(identity_4x4(), Material::default())),
+                Box::new(Sphere::build(identity_4x4(), patterned_material())),
+                false,
+            ),
+            (
+                "different shape types",
+                Box::new(Sphere::new()),
+                Box::new(Cube::new()),
+                false,
+            ),
+            (
+                "different geometry",
+                Box::new(Triangle::new(
+                    point!(0, 1, 0),
+                    point!(-1, 0, 0),
+                    point!(1, 0, 0),
+                )),
+                Box::new(Triangle::new(
+                    point!(0, 2, 0),
+                    point!(-1, 0, 0),
+                    point!(1, 0, 0),
+                )),
+                false,
+            ),
+            (
+                "identical groups",
+                Box::new(group_of(scaling(2., 2., 2.))),
+                Box::new(group_of(scaling(2., 2., 2.))),
+                true,
+            ),
+            (
+                "groups with different children",
+                Box::new(group_of(scaling(2., 2., 2.))),
+                Box::new(group_of(scaling(3., 3., 3.))),
+                false,
+            ),
+        ];
+        for (name, s1, s2, expected) in test_data {
+            println!("Case {}", name);
+            // distinct objects are never ==, even if they are structurally equal
+            assert!(*s1 != *s2);
+            assert!(s1.structurally_eq(s1.as_ref()));
+            assert_eq!(s1.structurally_eq(s2.as_ref()), expected);
+            assert_eq!(s1.fingerprint() == s2.fingerprint(), expected);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn normal_to_world_returns_unit_vectors(
+            t in strategies::transform(),
+            object_normal in strategies::nonzero_vector(),
+        ) {
+            let mut s = TestShape::new();
+            s.set_transformation(t);
+            let n = s.normal_to_world(&object_normal.norm());
+            assert!(n.is_vector());
+            assert_abs_diff_eq!(n.magnitude(), 1., epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn every_shape_has_chainable_setters() {
+        let m = Material::builder().ambient(0.5).build();
+        let t = translation(1., 2., 3.);
+        let shapes: Vec<(&str, Box<dyn Shape>)> = vec![
+            (
+                "sphere",
+                Box::new(
+                    Sphere::new()
+                        .with_transform(t.clone())
+                        .with_material(m.clone())
+                        .with_shadow(false),
+                ),
+            ),
+            (
+                "cube",
+                Box::new(
+                    Cube::new()
+                        .with_shadow(false)
+                        .with_transform(t.clone())
+                        .with_material(m.clone()),
+                ),
+            ),
+            (
+                "plane",
+                Box::new(
+                    Plane::new()
+                        .with_material(m.clone())
+                        .with_shadow(false)
+                        .with_transform(t.clone()),
+                ),
+            ),
+            (
+                "cylinder",
+                Box::new(
+                    Cylinder::new()
+                        .with_limits(-1., 1., true)
+                        .with_transform(t.clone())
+                        .with_shared_material(Arc::new(m.clone()))
+                        .with_shadow(false),
+                ),
+            ),
+            (
+                "cone",
+                Box::new(
+                    Cone::new()
+                        .with_limits(-1., 0., false)
+                        .with_transform(t.clone())
+                        .with_material(m.clone())
+                        .with_shadow(false),
+                ),
+            ),
+            (
+                "group",
+                Box::new(
+                    GroupShape::with_children(vec![Box::new(Sphere::new())])
+                        .with_transform(t.clone())
+                        .with_material(m.clone())
+                        .with_shadow(false),
+                ),
+            ),
+        ];
+        for (name, shape) in shapes {
+            assert_eq!(shape.transformation(), &t, "Case {}", name);
+            // groups pass their material on to their children
+            let material = match shape.downcast_ref::<GroupShape>() {
+                Some(group) => group.get_children()[0].material(),
+                None => shape.material(),
+            };
+            assert_eq!(material, &m, "Case {}", name);
+            assert!(!shape.casts_shadow(), "Case {}", name);
+
+            // the shape sits between the light and the origin, but doesn't shade it
+            let mut world = World::new();
+            world.objects.push(shape);
+            world.light = Some(Box::new(PointLight::new(point!(1, 10, 3), white())));
+            assert!(
+                !world.is_shadowed(point!(1, 10, 3), point!(1, -10, 3)),
+                "Case {}",
+                name
+            );
+        }
+    }
 }
@@ -0,0 +1,244 @@
+use crate::bounding_box::BoundingBox;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::cone::Cone;
+use crate::shape::cube::Cube;
+use crate::shape::cylinder::Cylinder;
+use crate::shape::group::GroupShape;
+use crate::shape::plane::Plane;
+use crate::shape::shape::Shape;
+use crate::shape::smooth_triangle::SmoothTriangle;
+use crate::shape::sphere::Sphere;
+use crate::shape::triangle::Triangle;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+// The built-in shapes as a closed set, so that code holding them directly (e.g. in a Vec<ShapeEnum>)
+// calls their methods through a match instead of a vtable, which lets the compiler inline the
+// intersection code. Meshes loaded from OBJ files are groups of (smooth) triangles. Any other shape
+// can still be wrapped in Other and goes through dynamic dispatch as before. ShapeEnum is itself a
+// Shape, so it can also be boxed and added to a world or group; the intersections it returns point
+// at the wrapped shape, not the wrapper. Whether the match beats the vtable depends on the shapes
+// and the CPU; compare with the "shape dispatch" benchmark before switching.
+#[derive(Debug, Clone)]
+pub enum ShapeEnum {
+    Sphere(Sphere),
+    Plane(Plane),
+    Cube(Cube),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Triangle(Triangle),
+    SmoothTriangle(SmoothTriangle),
+    Group(GroupShape),
+    Other(Box<dyn Shape>),
+}
+
+// Run $body with $s bound to the wrapped shape, whatever its type
+macro_rules! dispatch {
+    ($self:expr, $s:ident => $body:expr) => {
+        match $self {
+            ShapeEnum::Sphere($s) => $body,
+            ShapeEnum::Plane($s) => $body,
+            ShapeEnum::Cube($s) => $body,
+            ShapeEnum::Cylinder($s) => $body,
+            ShapeEnum::Cone($s) => $body,
+            ShapeEnum::Triangle($s) => $body,
+            ShapeEnum::SmoothTriangle($s) => $body,
+            ShapeEnum::Group($s) => $body,
+            ShapeEnum::Other($s) => $body,
+        }
+    };
+}
+
+impl ShapeEnum {
+    pub fn as_shape(&self) -> &dyn Shape {
+        dispatch!(self, s => s.as_ref_shape())
+    }
+}
+
+// Lets dispatch! produce a &dyn Shape from both concrete shapes and Box<dyn Shape>
+trait AsRefShape {
+    fn as_ref_shape(&self) -> &dyn Shape;
+}
+
+impl<T: Shape> AsRefShape for T {
+    fn as_ref_shape(&self) -> &dyn Shape {
+        self
+    }
+}
+
+impl AsRefShape for Box<dyn Shape> {
+    fn as_ref_shape(&self) -> &dyn Shape {
+        self.as_ref()
+    }
+}
+
+// Every method that some shape overrides is forwarded, so that the wrapped shape behaves exactly
+// as it would on its own
+impl Shape for ShapeEnum {
+    fn get_base(&self) -> &BaseShape {
+        dispatch!(self, s => s.get_base())
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        dispatch!(self, s => s.get_base_mut())
+    }
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        dispatch!(self, s => s.local_intersect(object_ray))
+    }
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
+        dispatch!(self, s => s.local_norm_at(object_point))
+    }
+    fn local_norm_at_hit(&self, object_point: Tuple, hit: &Intersection) -> Tuple {
+        dispatch!(self, s => s.local_norm_at_hit(object_point, hit))
+    }
+    fn bounding_box(&self) -> BoundingBox {
+        dispatch!(self, s => s.bounding_box())
+    }
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        dispatch!(self, s => s.hash_geometry(hasher))
+    }
+    fn transformation(&self) -> &Matrix {
+        dispatch!(self, s => s.transformation())
+    }
+    fn set_transformation(&mut self, t: Matrix) {
+        dispatch!(self, s => s.set_transformation(t))
+    }
+    fn material(&self) -> &Material {
+        dispatch!(self, s => s.material())
+    }
+    fn set_material(&mut self, m: Material) {
+        dispatch!(self, s => s.set_material(m))
+    }
+    fn shared_material(&self) -> &Arc<Material> {
+        dispatch!(self, s => s.shared_material())
+    }
+    fn set_shared_material(&mut self, m: Arc<Material>) {
+        dispatch!(self, s => s.set_shared_material(m))
+    }
+    fn casts_shadow(&self) -> bool {
+        dispatch!(self, s => s.casts_shadow())
+    }
+    fn set_casts_shadow(&mut self, casts_shadow: bool) {
+        dispatch!(self, s => s.set_casts_shadow(casts_shadow))
+    }
+    fn transformation_inverse(&self) -> &Matrix {
+        dispatch!(self, s => s.transformation_inverse())
+    }
+    fn transformation_inverse_transpose(&self) -> &Matrix {
+        dispatch!(self, s => s.transformation_inverse_transpose())
+    }
+    fn intersect(&self, world_ray: Ray) -> Vec<Intersection> {
+        dispatch!(self, s => s.intersect(world_ray))
+    }
+    fn normal_at(&self, world_point: &Tuple) -> Tuple {
+        dispatch!(self, s => s.normal_at(world_point))
+    }
+    fn normal_at_hit(&self, world_point: &Tuple, hit: &Intersection) -> Tuple {
+        dispatch!(self, s => s.normal_at_hit(world_point, hit))
+    }
+    fn includes(&self, other: &dyn Shape) -> bool {
+        dispatch!(self, s => s.includes(other))
+    }
+    fn parent_space_bounding_box(&self) -> BoundingBox {
+        dispatch!(self, s => s.parent_space_bounding_box())
+    }
+    fn divide(&mut self, threshold: usize) {
+        dispatch!(self, s => s.divide(threshold))
+    }
+    fn bake_transform(&mut self) -> bool {
+        dispatch!(self, s => s.bake_transform())
+    }
+    fn set_backface_culling(&mut self, cull: bool) {
+        dispatch!(self, s => s.set_backface_culling(cull))
+    }
+    // the wrapper is not part of the structure; an enum-wrapped sphere has the same fingerprint as
+    // a bare one
+    fn hash_structure(&self, hasher: &mut dyn Hasher) {
+        dispatch!(self, s => s.hash_structure(hasher))
+    }
+}
+
+macro_rules! impl_from_shape {
+    ($($variant:ident($shape:ty)),*) => {
+        $(
+            impl From<$shape> for ShapeEnum {
+                fn from(s: $shape) -> Self {
+                    ShapeEnum::$variant(s)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_shape!(
+    Sphere(Sphere),
+    Plane(Plane),
+    Cube(Cube),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Triangle(Triangle),
+    SmoothTriangle(SmoothTriangle),
+    Group(GroupShape),
+    Other(Box<dyn Shape>)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::shape::ShapeBuilder;
+    use crate::transformations::{scaling, translation};
+
+    fn shapes() -> Vec<ShapeEnum> {
+        vec![
+            Sphere::new().with_transform(translation(0., 0., 5.)).into(),
+            Plane::new().with_transform(translation(0., 0., 10.)).into(),
+            Cube::new().with_transform(scaling(2., 2., 2.)).into(),
+            Triangle::new(point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0)).into(),
+            GroupShape::with_children(vec![Box::new(Sphere::new())]).into(),
+            ShapeEnum::Other(Box::new(Cylinder::new())),
+        ]
+    }
+
+    #[test]
+    fn intersections_match_dynamic_dispatch() {
+        let ray = Ray::new(point!(0, 0.5, -5), vector!(0, 0, 1));
+        for (i, shape) in shapes().into_iter().enumerate() {
+            let static_xs: Vec<(f32, usize)> = shape
+                .intersect(ray)
+                .iter()
+                .map(|x| (x.distance, x.object.get_unique_id()))
+                .collect();
+            let boxed: Box<dyn Shape> = Box::new(shape.clone());
+            let dynamic_xs: Vec<(f32, usize)> = shape
+                .as_shape()
+                .intersect(ray)
+                .iter()
+                .map(|x| (x.distance, x.object.get_unique_id()))
+                .collect();
+            let boxed_xs: Vec<f32> = boxed.intersect(ray).iter().map(|x| x.distance).collect();
+            assert_eq!(static_xs, dynamic_xs, "Case {}", i);
+            assert_eq!(
+                static_xs.iter().map(|x| x.0).collect::<Vec<f32>>(),
+                boxed_xs,
+                "Case {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn wrapped_shape_keeps_identity_and_structure() {
+        let sphere = Sphere::new().with_transform(translation(1., 2., 3.));
+        let id = sphere.get_unique_id();
+        let fingerprint = sphere.fingerprint();
+        let wrapped: ShapeEnum = sphere.into();
+        assert_eq!(wrapped.get_unique_id(), id);
+        assert_eq!(wrapped.fingerprint(), fingerprint);
+        assert_eq!(wrapped.as_shape().get_unique_id(), id);
+        assert_eq!(wrapped.transformation(), &translation(1., 2., 3.));
+    }
+}
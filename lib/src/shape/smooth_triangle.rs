@@ -1,10 +1,12 @@
 use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_tuple;
 use crate::intersection::Intersection;
 use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::shape::triangle::Triangle;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
 
 #[derive(Debug, Clone)]
 pub struct SmoothTriangle {
@@ -40,7 +42,30 @@ impl Shape for SmoothTriangle {
         self.base.local_intersect(object_ray)
     }
 
-    fn local_norm_at(&self, _object_point: Tuple, hit: &Intersection) -> Tuple {
+    fn set_backface_culling(&mut self, cull: bool) {
+        self.base.set_backface_culling(cull);
+    }
+
+    fn bake_transform(&mut self) -> bool {
+        // normals are transformed as in normal_to_world, but only need to be normalized there
+        let normal_transform = self.transformation_inverse_transpose();
+        let [n1, n2, n3] = [self.n1, self.n2, self.n3].map(|n| {
+            let mut transformed = normal_transform * n;
+            transformed.w = 0.;
+            transformed
+        });
+        self.n1 = n1;
+        self.n2 = n2;
+        self.n3 = n3;
+        self.base = self.base.baked();
+        true
+    }
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        unreachable!("Use local_norm_at_hit; the normal is interpolated from the barycentric coordinates of the hit")
+    }
+
+    fn local_norm_at_hit(&self, _object_point: Tuple, hit: &Intersection) -> Tuple {
         // TODO: explain the math here. And why is the normal the same everywhere?
         self.n2 * hit.u + self.n3 * hit.v + self.n1 * (1. - hit.u - hit.v)
     }
@@ -49,12 +74,22 @@ impl Shape for SmoothTriangle {
         // TODO: this is totally wrong, but the text doesn't give the code for the smooth triangle case
         self.base.bounding_box()
     }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        self.base.hash_geometry(hasher);
+        hash_tuple(hasher, &self.n1);
+        hash_tuple(hasher, &self.n2);
+        hash_tuple(hasher, &self.n3);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transformations::rotation_z;
+    use crate::transformations::scaling;
     use crate::world::precompute_values;
+    use std::f32::consts::PI;
 
     fn default_smooth_triangle() -> SmoothTriangle {
         SmoothTriangle::new(
@@ -85,15 +120,15 @@ mod tests {
         let t = default_smooth_triangle();
         let r = Ray::new(point!(-0.2, 0.3, -2), vector!(0, 0, 1));
         let xs = t.local_intersect(r);
-        assert_eq!(xs[0].u, 0.45);
-        assert_eq!(xs[0].v, 0.25);
+        assert_abs_diff_eq!(xs[0].u, 0.45);
+        assert_abs_diff_eq!(xs[0].v, 0.25);
     }
 
     #[test]
     fn uses_u_and_v_to_interpolate_normal() {
         let t = default_smooth_triangle();
         let i = Intersection::new_with_uv(1.0, &t, 0.45, 0.25);
-        let n = t.normal_at(&point!(0, 0, 0), &i);
+        let n = t.normal_at_hit(&point!(0, 0, 0), &i);
         assert_abs_diff_eq!(n, vector!(-0.5547002, 0.8320504, 0.0));
     }
 
@@ -102,8 +137,19 @@ mod tests {
         let t = default_smooth_triangle();
         let i = Intersection::new_with_uv(1.0, &t, 0.45, 0.25);
         let r = Ray::new(point!(-0.2, 0.3, -2), vector!(0, 0, 1));
-        let xs = vec![i];
+        let xs = vec![i.clone()];
         let comps = precompute_values(r, &i, &xs);
         assert_abs_diff_eq!(comps.surface_normal, vector!(-0.5547002, 0.8320504, 0.0));
     }
+
+    #[test]
+    fn baking_transformation_keeps_world_normals() {
+        let mut t = default_smooth_triangle();
+        t.set_transformation(rotation_z(PI / 3.) * &scaling(1., 3., 1.));
+        let i = Intersection::new_with_uv(1.0, &t, 0.45, 0.25);
+        let before = t.normal_at_hit(&point!(0, 0, 0), &i);
+        assert!(t.bake_transform());
+        let i = Intersection::new_with_uv(1.0, &t, 0.45, 0.25);
+        assert_abs_diff_eq!(t.normal_at_hit(&point!(0, 0, 0), &i), before);
+    }
 }
@@ -68,7 +68,7 @@ impl Shape for Sphere {
             Intersection::new((-b + discriminant_sqrt) / two_a, self),
         ]
     }
-    fn local_norm_at(&self, object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, object_point: Tuple) -> Tuple {
         object_point - self.center
     }
 
@@ -83,7 +83,6 @@ impl Shape for Sphere {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::utils::dummy_intersection;
     use crate::transformations::scaling;
     use crate::transformations::translation;
 
@@ -113,31 +112,28 @@ mod tests {
     #[test]
     fn sphere_normal_on_x_axis() {
         let s = Sphere::new();
-        let n = s.local_norm_at(point!(1, 0, 0), &dummy_intersection(&s));
+        let n = s.local_norm_at(point!(1, 0, 0));
         assert_eq!(n, vector!(1, 0, 0));
     }
 
     #[test]
     fn sphere_normal_on_y_axis() {
         let s = Sphere::new();
-        let n = s.local_norm_at(point!(0, 1, 0), &dummy_intersection(&s));
+        let n = s.local_norm_at(point!(0, 1, 0));
         assert_eq!(n, vector!(0, 1, 0));
     }
 
     #[test]
     fn sphere_normal_on_z_axis() {
         let s = Sphere::new();
-        let n = s.local_norm_at(point!(0, 0, 1), &dummy_intersection(&s));
+        let n = s.local_norm_at(point!(0, 0, 1));
         assert_eq!(n, vector!(0, 0, 1));
     }
 
     #[test]
     fn sphere_normal_on_nonaxial_point() {
         let s = Sphere::new();
-        let n = s.local_norm_at(
-            point!(frac_1_sqrt_3(), frac_1_sqrt_3(), frac_1_sqrt_3()),
-            &dummy_intersection(&s),
-        );
+        let n = s.local_norm_at(point!(frac_1_sqrt_3(), frac_1_sqrt_3(), frac_1_sqrt_3()));
         assert_abs_diff_eq!(
             n,
             vector!(frac_1_sqrt_3(), frac_1_sqrt_3(), frac_1_sqrt_3())
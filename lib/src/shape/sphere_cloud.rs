@@ -0,0 +1,299 @@
+use crate::bounding_box::BoundingBox;
+use crate::fingerprint::{hash_f32, hash_tuple};
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::shape::base_shape::BaseShape;
+use crate::shape::shape::Shape;
+use crate::tuple::Tuple;
+use std::hash::Hasher;
+
+// leaves of the hierarchy hold at most this many spheres
+const MAX_LEAF_SIZE: usize = 4;
+
+// Many small spheres sharing one material, such as particles or the atoms of a molecule. The
+// spheres are stored as plain centers and radii instead of separate shapes, and are found through
+// a bounding volume hierarchy of their own. Hits carry the index of the sphere they hit in u.
+#[derive(Debug, Clone)]
+pub struct SphereCloud {
+    base: BaseShape,
+    // sorted so that each node of the hierarchy covers a contiguous range
+    centers: Vec<Tuple>,
+    radii: Vec<f32>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: BoundingBox,
+    // a leaf's spheres, or the indices of an inner node's two children
+    contents: NodeContents,
+}
+
+#[derive(Debug, Clone)]
+enum NodeContents {
+    Leaf { start: usize, end: usize },
+    Inner { left: usize, right: usize },
+}
+
+impl SphereCloud {
+    pub fn new(centers: Vec<Tuple>, radius: f32) -> Self {
+        let radii = vec![radius; centers.len()];
+        SphereCloud::with_radii(centers, radii)
+    }
+
+    pub fn with_radii(centers: Vec<Tuple>, radii: Vec<f32>) -> Self {
+        assert_eq!(
+            centers.len(),
+            radii.len(),
+            "A sphere cloud needs one radius per center"
+        );
+        let mut spheres: Vec<(Tuple, f32)> = centers.into_iter().zip(radii).collect();
+        let mut nodes = vec![];
+        if !spheres.is_empty() {
+            let count = spheres.len();
+            build_node(&mut spheres, 0, count, &mut nodes);
+        }
+        let (centers, radii) = spheres.into_iter().unzip();
+        SphereCloud {
+            base: BaseShape::new(),
+            centers,
+            radii,
+            nodes,
+        }
+    }
+
+    pub fn build(centers: Vec<Tuple>, radius: f32, transform: Matrix, material: Material) -> Self {
+        let mut s = SphereCloud::new(centers, radius);
+        s.set_transformation(transform);
+        s.set_material(material);
+        s
+    }
+
+    pub fn len(&self) -> usize {
+        self.centers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.centers.is_empty()
+    }
+
+    // the center and radius of the sphere with the given index (the u of its hits)
+    pub fn sphere(&self, index: usize) -> (Tuple, f32) {
+        (self.centers[index], self.radii[index])
+    }
+
+    fn intersect_sphere<'a>(
+        &'a self,
+        index: usize,
+        object_ray: &Ray,
+        intersections: &mut Vec<Intersection<'a>>,
+    ) {
+        let sphere_to_ray = object_ray.origin - self.centers[index];
+        let a = object_ray.direction.dot(object_ray.direction);
+        let b = 2.0 * object_ray.direction.dot(sphere_to_ray);
+        let c = sphere_to_ray.dot(sphere_to_ray) - self.radii[index].powi(2);
+        let discriminant = b.powi(2) - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return;
+        }
+        let two_a = 2.0 * a;
+        let discriminant_sqrt = discriminant.sqrt();
+        let u = index as f32;
+        intersections.push(Intersection::new_with_uv(
+            (-b - discriminant_sqrt) / two_a,
+            self,
+            u,
+            0.,
+        ));
+        intersections.push(Intersection::new_with_uv(
+            (-b + discriminant_sqrt) / two_a,
+            self,
+            u,
+            0.,
+        ));
+    }
+}
+
+fn sphere_bounds(&(center, radius): &(Tuple, f32)) -> BoundingBox {
+    let extent = vector!(radius, radius, radius);
+    BoundingBox::with_bounds(center - extent, center + extent)
+}
+
+// Adds a node for spheres[start..end] (and nodes for its descendants) and returns its index. The
+// spheres are split in half along the axis that their centers are most spread out on.
+fn build_node(
+    spheres: &mut [(Tuple, f32)],
+    start: usize,
+    end: usize,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let mut bounds = BoundingBox::empty();
+    let mut centers = BoundingBox::empty();
+    for sphere in &spheres[start..end] {
+        bounds.add_bounding_box(sphere_bounds(sphere));
+        centers.add_point(sphere.0);
+    }
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        contents: NodeContents::Leaf { start, end },
+    });
+    if end - start <= MAX_LEAF_SIZE {
+        return index;
+    }
+
+    let spread = centers.max - centers.min;
+    let axis_of = |p: &Tuple| {
+        if spread.x >= spread.y && spread.x >= spread.z {
+            p.x
+        } else if spread.y >= spread.z {
+            p.y
+        } else {
+            p.z
+        }
+    };
+    let middle = (start + end) / 2;
+    spheres[start..end].select_nth_unstable_by(middle - start, |a, b| {
+        axis_of(&a.0).partial_cmp(&axis_of(&b.0)).unwrap()
+    });
+    let left = build_node(spheres, start, middle, nodes);
+    let right = build_node(spheres, middle, end, nodes);
+    nodes[index].contents = NodeContents::Inner { left, right };
+    index
+}
+
+impl Shape for SphereCloud {
+    fn get_base(&self) -> &BaseShape {
+        &self.base
+    }
+    fn get_base_mut(&mut self) -> &mut BaseShape {
+        &mut self.base
+    }
+
+    fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
+        let mut intersections = vec![];
+        let mut to_visit = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(index) = to_visit.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.intersects(object_ray) {
+                continue;
+            }
+            match node.contents {
+                NodeContents::Leaf { start, end } => {
+                    for sphere in start..end {
+                        self.intersect_sphere(sphere, &object_ray, &mut intersections);
+                    }
+                }
+                NodeContents::Inner { left, right } => {
+                    to_visit.push(left);
+                    to_visit.push(right);
+                }
+            }
+        }
+        Intersection::sort(&mut intersections);
+        intersections
+    }
+
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
+        unreachable!("Use local_norm_at_hit; the hit's u records which of the spheres was hit")
+    }
+
+    fn local_norm_at_hit(&self, object_point: Tuple, hit: &Intersection) -> Tuple {
+        object_point - self.centers[hit.u as usize]
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.nodes
+            .first()
+            .map_or_else(BoundingBox::empty, |root| root.bounds)
+    }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        for (center, radius) in self.centers.iter().zip(&self.radii) {
+            hash_tuple(hasher, center);
+            hash_f32(hasher, *radius);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_intersects_spheres_in_cloud() {
+        let cloud = SphereCloud::with_radii(
+            vec![point!(0, 0, 0), point!(0, 0, 5), point!(3, 0, 0)],
+            vec![1., 0.5, 1.],
+        );
+        let xs = cloud.local_intersect(Ray::new(point!(0, 0, -5), vector!(0, 0, 1)));
+        let distances: Vec<f32> = xs.iter().map(|i| i.distance).collect();
+        assert_eq!(distances, vec![4., 6., 9.5, 10.5]);
+        let (center, radius) = cloud.sphere(xs[2].u as usize);
+        assert_eq!(center, point!(0, 0, 5));
+        assert_eq!(radius, 0.5);
+
+        let xs = cloud.local_intersect(Ray::new(point!(0, 2, -5), vector!(0, 0, 1)));
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn normal_points_away_from_hit_sphere() {
+        let cloud = SphereCloud::new(vec![point!(0, 0, 0), point!(3, 0, 0)], 1.);
+        let xs = cloud.local_intersect(Ray::new(point!(10, 0, 0), vector!(-1, 0, 0)));
+        assert_eq!(xs[0].distance, 6.);
+        let normal = cloud.local_norm_at_hit(point!(4, 0, 0), &xs[0]);
+        assert_eq!(normal, vector!(1, 0, 0));
+        let normal = cloud.local_norm_at_hit(point!(1, 0, 0), &xs[2]);
+        assert_eq!(normal, vector!(1, 0, 0));
+    }
+
+    #[test]
+    fn hierarchy_finds_same_hits_as_checking_every_sphere() {
+        let mut centers = vec![];
+        for i in 0..1000 {
+            let i = i as f32;
+            centers.push(point!(
+                (i * 0.618).fract() * 20.,
+                (i * 0.414).fract() * 20.,
+                (i * 0.732).fract() * 20.
+            ));
+        }
+        let cloud = SphereCloud::new(centers.clone(), 0.3);
+        assert!(cloud.nodes.len() > 1);
+        for (origin, target) in [
+            (point!(-5, 10, 10), centers[10]),
+            (point!(-5, -5, -5), centers[500]),
+            (point!(10, 25, 3), centers[999]),
+        ] {
+            let ray = Ray::new(origin, (target - origin).norm());
+            let mut expected = vec![];
+            for center in &centers {
+                let single = SphereCloud::new(vec![*center], 0.3);
+                expected.extend(single.local_intersect(ray).iter().map(|x| x.distance));
+            }
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let actual: Vec<f32> = cloud
+                .local_intersect(ray)
+                .iter()
+                .map(|x| x.distance)
+                .collect();
+            assert!(!actual.is_empty());
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn sphere_cloud_bounding_box() {
+        let cloud = SphereCloud::with_radii(vec![point!(0, 0, 0), point!(5, -2, 1)], vec![1., 0.5]);
+        let b = cloud.bounding_box();
+        assert_eq!(b.min, point!(-1, -2.5, -1));
+        assert_eq!(b.max, point!(5.5, 1, 1.5));
+    }
+}
@@ -4,13 +4,13 @@ use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
-use std::cell::RefCell;
 use std::fmt::Debug;
+use std::sync::Mutex;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct TestShape {
     pub base: BaseShape,
-    pub saved_ray: RefCell<Option<Ray>>,
+    saved_ray: Mutex<Option<Ray>>,
     pub divide_was_called_with_threshold: usize,
 }
 
@@ -18,10 +18,25 @@ impl TestShape {
     pub fn new() -> Self {
         TestShape {
             base: BaseShape::new(),
-            saved_ray: RefCell::new(None),
+            saved_ray: Mutex::new(None),
             divide_was_called_with_threshold: 0,
         }
     }
+
+    // the last ray passed to local_intersect
+    pub fn saved_ray(&self) -> Option<Ray> {
+        *self.saved_ray.lock().unwrap()
+    }
+}
+
+impl Clone for TestShape {
+    fn clone(&self) -> Self {
+        TestShape {
+            base: self.base.clone(),
+            saved_ray: Mutex::new(self.saved_ray()),
+            divide_was_called_with_threshold: self.divide_was_called_with_threshold,
+        }
+    }
 }
 
 impl Shape for TestShape {
@@ -33,10 +48,10 @@ impl Shape for TestShape {
     }
     fn local_intersect(&self, _object_ray: Ray) -> Vec<Intersection> {
         // save the incoming ray for a comparison test
-        self.saved_ray.borrow_mut().replace(_object_ray);
+        self.saved_ray.lock().unwrap().replace(_object_ray);
         vec![]
     }
-    fn local_norm_at(&self, _object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
         // return something that will let us test both the input and output calculations
         vector!(
             2.0 * _object_point.x,
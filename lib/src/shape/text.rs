@@ -0,0 +1,206 @@
+use crate::font::{Contour, Font, FontError};
+use crate::shape::group::GroupShape;
+use crate::shape::polygon::{merge_holes, triangulate};
+use crate::shape::triangle::Triangle;
+use crate::tuple::Tuple;
+
+// Builds solid 3D text out of a font's glyph outlines. Each character is filled in on the front (at
+// z = 0, facing -z) and the back (at z = depth), and the two are joined around the outline's edges.
+// The text starts at the origin with its baseline on the x axis and runs along +x; sizes are in ems,
+// and each new line starts one line height further down.
+pub struct Text3D<'a> {
+    font: &'a Font,
+    text: String,
+    depth: f32,
+}
+
+impl<'a> Text3D<'a> {
+    pub fn new(font: &'a Font, text: &str) -> Self {
+        Text3D {
+            font,
+            text: text.to_string(),
+            depth: 0.2,
+        }
+    }
+
+    pub fn with_depth(mut self, depth: f32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    // One child group of triangles for each character that has an outline
+    pub fn into_group(self) -> Result<GroupShape, FontError> {
+        let mut group = GroupShape::new();
+        let (mut x, mut y) = (0., 0.);
+        for c in self.text.chars() {
+            if c == '\n' {
+                x = 0.;
+                y -= self.font.line_height();
+                continue;
+            }
+            let contours: Vec<Contour> = self
+                .font
+                .glyph_contours(c)?
+                .into_iter()
+                .map(|contour| contour.into_iter().map(|(u, v)| (x + u, y + v)).collect())
+                .filter(|contour: &Contour| contour.len() >= 3)
+                .collect();
+            if !contours.is_empty() {
+                group.add_child(Box::new(extrude(&contours, self.depth)));
+            }
+            x += self.font.advance_width(c);
+        }
+        Ok(group)
+    }
+}
+
+// even-odd test for whether p is inside of the contour
+fn contains(contour: &[(f32, f32)], p: (f32, f32)) -> bool {
+    let mut inside = false;
+    for (i, &(x1, y1)) in contour.iter().enumerate() {
+        let (x2, y2) = contour[(i + 1) % contour.len()];
+        if (y1 > p.1) != (y2 > p.1) && p.0 < x1 + (p.1 - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn area(contour: &[(f32, f32)]) -> f32 {
+    let mut area = 0.;
+    for (i, &(x1, y1)) in contour.iter().enumerate() {
+        let (x2, y2) = contour[(i + 1) % contour.len()];
+        area += x1 * y2 - x2 * y1;
+    }
+    (area / 2.).abs()
+}
+
+// adds the triangle unless it has no area, flipping it if needed so its normal points along facing
+fn add_triangle(group: &mut GroupShape, p1: Tuple, p2: Tuple, p3: Tuple, facing: Option<Tuple>) {
+    // the same normal that Triangle computes
+    let normal = (p3 - p1).cross(p2 - p1);
+    if normal.magnitude() < 0.000_000_1 {
+        return;
+    }
+    let triangle = match facing {
+        Some(facing) if normal.dot(facing) < 0. => Triangle::new(p1, p3, p2),
+        _ => Triangle::new(p1, p2, p3),
+    };
+    group.add_child(Box::new(triangle));
+}
+
+// A solid from the contours of one glyph. Contours inside of an odd number of others are holes.
+fn extrude(contours: &[Contour], depth: f32) -> GroupShape {
+    let nesting: Vec<usize> = contours
+        .iter()
+        .enumerate()
+        .map(|(i, contour)| {
+            (0..contours.len())
+                .filter(|&j| j != i && contains(&contours[j], contour[0]))
+                .count()
+        })
+        .collect();
+    let is_hole = |i: usize| nesting[i] % 2 == 1;
+    let front = |&(x, y): &(f32, f32)| point!(x, y, 0);
+    let back = |&(x, y): &(f32, f32)| point!(x, y, depth);
+
+    let mut group = GroupShape::new();
+    for (i, outline) in contours.iter().enumerate() {
+        if is_hole(i) {
+            continue;
+        }
+        // each hole belongs to the smallest outline around it
+        let holes: Vec<Vec<Tuple>> = (0..contours.len())
+            .filter(|&h| is_hole(h) && contains(outline, contours[h][0]))
+            .filter(|&h| {
+                (0..contours.len()).all(|o| {
+                    o == i
+                        || is_hole(o)
+                        || !contains(&contours[o], contours[h][0])
+                        || area(&contours[o]) >= area(outline)
+                })
+            })
+            .map(|h| contours[h].iter().map(front).collect())
+            .collect();
+        let cap = merge_holes(&outline.iter().map(front).collect::<Vec<Tuple>>(), &holes);
+        for [a, b, c] in triangulate(&cap) {
+            let (p1, p2, p3) = (cap[a], cap[b], cap[c]);
+            add_triangle(&mut group, p1, p2, p3, Some(vector!(0, 0, -1)));
+            let shift = vector!(0, 0, depth);
+            add_triangle(
+                &mut group,
+                p1 + shift,
+                p2 + shift,
+                p3 + shift,
+                Some(vector!(0, 0, 1)),
+            );
+        }
+    }
+    for contour in contours {
+        for (i, corner) in contour.iter().enumerate() {
+            let next = &contour[(i + 1) % contour.len()];
+            add_triangle(&mut group, front(corner), front(next), back(next), None);
+            add_triangle(&mut group, front(corner), back(next), back(corner), None);
+        }
+    }
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::shape::shape::Shape;
+    use crate::test::utils::test_font_bytes;
+
+    fn hit_distance(group: &GroupShape, x: f32, y: f32) -> Option<f32> {
+        group
+            .intersect(Ray::new(point!(x, y, -5), vector!(0, 0, 1)))
+            .iter()
+            .map(|i| i.distance)
+            .fold(None, |closest: Option<f32>, d| {
+                Some(closest.map_or(d, |c| c.min(d)))
+            })
+    }
+
+    #[test]
+    fn extruded_glyph_with_hole() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        let group = Text3D::new(&font, "O")
+            .with_depth(0.5)
+            .into_group()
+            .unwrap();
+        assert_eq!(group.get_children().len(), 1);
+        let test_data = vec![
+            ("solid", 0.0625, 0.25, Some(5.)),
+            ("hole", 0.25, 0.25, None),
+            ("outside", 0.6, 0.25, None),
+        ];
+        for (name, x, y, expected) in test_data {
+            assert_eq!(hit_distance(&group, x, y), expected, "Case {}", name);
+        }
+        // the sides are there too
+        let side = group.intersect(Ray::new(point!(-1, 0.3, 0.2), vector!(1, 0, 0)));
+        assert_eq!(side.len(), 4);
+        let b = group.bounding_box();
+        assert_eq!(b.min, point!(0, 0, 0));
+        assert_eq!(b.max, point!(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn text_is_laid_out_along_lines() {
+        let font = Font::from_bytes(&test_font_bytes()).unwrap();
+        let group = Text3D::new(&font, "O O\nD").into_group().unwrap();
+        // the space has no outline
+        assert_eq!(group.get_children().len(), 3);
+        let test_data = vec![
+            ("first letter", 0.0625, 0.25, Some(5.)),
+            ("space", 0.875, 0.25, None),
+            ("after space", 1.0625, 0.25, Some(5.)),
+            ("second line", 0.4, -0.95, Some(5.)),
+        ];
+        for (name, x, y, expected) in test_data {
+            assert_eq!(hit_distance(&group, x, y), expected, "Case {}", name);
+        }
+    }
+}
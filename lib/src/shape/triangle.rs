@@ -1,9 +1,12 @@
 use crate::bounding_box::BoundingBox;
+use crate::fingerprint::hash_tuple;
 use crate::intersection::Intersection;
+use crate::matrix::identity_4x4;
 use crate::ray::Ray;
 use crate::shape::base_shape::BaseShape;
 use crate::shape::shape::Shape;
 use crate::tuple::Tuple;
+use std::hash::Hasher;
 
 #[derive(Debug, Clone)]
 pub struct Triangle {
@@ -14,6 +17,7 @@ pub struct Triangle {
     e1: Tuple,
     e2: Tuple,
     normal: Tuple,
+    cull_backfaces: bool,
 }
 
 impl Triangle {
@@ -29,10 +33,25 @@ impl Triangle {
             e1,
             e2,
             normal,
+            cull_backfaces: false,
         }
     }
 }
 
+impl Triangle {
+    // the triangle's points moved by its transformation, with a transformation of identity
+    pub(crate) fn baked(&self) -> Triangle {
+        let t = self.transformation();
+        let mut baked = Triangle::new(t * self.p1, t * self.p2, t * self.p3);
+        baked.base = self.base.clone();
+        baked.cull_backfaces = self.cull_backfaces;
+        baked
+            .base
+            .set_transformation_and_inverse(identity_4x4(), identity_4x4());
+        baked
+    }
+}
+
 impl Shape for Triangle {
     fn get_base(&self) -> &BaseShape {
         &self.base
@@ -42,40 +61,85 @@ impl Shape for Triangle {
         &mut self.base
     }
 
+    fn bake_transform(&mut self) -> bool {
+        *self = self.baked();
+        true
+    }
+
+    fn set_backface_culling(&mut self, cull: bool) {
+        self.cull_backfaces = cull;
+    }
+
+    // Watertight intersection (Woop, Benthin and Wald, "Watertight Ray/Triangle Intersection",
+    // 2013). Möller–Trumbore computes the barycentric coordinates from each triangle's own edges,
+    // so rounding can let rays slip between two triangles sharing an edge. Here the vertices are
+    // instead moved into a space where the ray starts at the origin and points along +z, and each
+    // edge test only depends on the two points of that edge; neighbors then agree exactly on
+    // which side of their shared edge a ray passes.
     fn local_intersect(&self, object_ray: Ray) -> Vec<Intersection> {
-        // Get a vector that's orthogonal to both the incoming ray and one of the edges
-        let dir_cross_e2 = object_ray.direction.cross(self.e2);
-        // Check the cosine between the other edge and this orthogonal vector
-        let determinant = self.e1.dot(dir_cross_e2);
-        // If the other edge is not even kind of orthogonal, then the ray is parallel
-        // to the triangle face and will miss the triangle
-        // TODO: should probably be a constant somewhere
-        if determinant.abs() < 0.0000001 {
-            return vec![];
+        let direction = to_array(object_ray.direction);
+        // use the largest axis of the direction as z, so that dividing by it is safe; swapping the
+        // other two when it is negative keeps the winding of the triangle
+        let kz = (0..3)
+            .max_by(|&a, &b| direction[a].abs().total_cmp(&direction[b].abs()))
+            .unwrap();
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        if direction[kz] < 0. {
+            std::mem::swap(&mut kx, &mut ky);
         }
+        // shear that points the ray along z
+        let shear_x = direction[kx] / direction[kz];
+        let shear_y = direction[ky] / direction[kz];
+        let shear_z = 1. / direction[kz];
 
-        // TODO: explain u and v
-        // Ray misses p1-p3 edge. TODO: explain math
-        let f = 1.0 / determinant;
-        let p1_to_origin = object_ray.origin - self.p1;
-        let u = f * p1_to_origin.dot(dir_cross_e2);
-        if u < 0.0 || u > 1.0 {
+        let [a, b, c] = [self.p1, self.p2, self.p3].map(|p| to_array(p - object_ray.origin));
+        let project = |p: [f32; 3]| (p[kx] - shear_x * p[kz], p[ky] - shear_y * p[kz]);
+        let (ax, ay) = project(a);
+        let (bx, by) = project(b);
+        let (cx, cy) = project(c);
+
+        // Each edge function is twice the area of the triangle formed by the edge and the ray,
+        // which is also the weight of the opposite vertex
+        let mut weights = [cx * by - cy * bx, ax * cy - ay * cx, bx * ay - by * ax];
+        // the ray passes exactly through an edge or vertex as far as f32 can tell, so check again
+        // with more precision to find out which side it's on
+        if weights.contains(&0.) {
+            let (ax, ay, bx, by, cx, cy) = (
+                ax as f64, ay as f64, bx as f64, by as f64, cx as f64, cy as f64,
+            );
+            weights = [
+                (cx * by - cy * bx) as f32,
+                (ax * cy - ay * cx) as f32,
+                (bx * ay - by * ax) as f32,
+            ];
+        }
+        // the ray misses if it is outside any edge; the sign of all of the weights depends on
+        // which side of the triangle the ray hits
+        if weights.iter().any(|&w| w < 0.) && weights.iter().any(|&w| w > 0.) {
             return vec![];
         }
-
-        // Ray misses p2-p3 and p1-p2 edges. TODO: explain math
-        let origin_cross_e1 = p1_to_origin.cross(self.e1);
-        let v = f * object_ray.direction.dot(origin_cross_e1);
-        if v < 0.0 || (u + v) > 1.0 {
+        let determinant: f32 = weights.iter().sum();
+        // the ray is parallel to the triangle
+        if determinant == 0. {
+            return vec![];
+        }
+        // The determinant has the same sign as the ray direction dotted with the normal, so it
+        // is positive when the ray hits the back of the triangle. The sign doesn't change when
+        // transforming to object space.
+        if self.cull_backfaces && object_ray.primary && determinant > 0. {
             return vec![];
         }
 
-        // Ray intersects the triangle. TODO: explain math
-        let distance = f * self.e2.dot(origin_cross_e1);
+        let scaled_distance =
+            shear_z * (weights[0] * a[kz] + weights[1] * b[kz] + weights[2] * c[kz]);
+        let distance = scaled_distance / determinant;
+        // u and v are the weights of p2 and p3, so that the hit is at p1 + u * e1 + v * e2
+        let u = weights[1] / determinant;
+        let v = weights[2] / determinant;
         vec![Intersection::new_with_uv(distance, self, u, v)]
     }
 
-    fn local_norm_at(&self, _object_point: Tuple, _hit: &Intersection) -> Tuple {
+    fn local_norm_at(&self, _object_point: Tuple) -> Tuple {
         // Normal is always the same, regardless of point on triangle
         self.normal
     }
@@ -87,12 +151,24 @@ impl Shape for Triangle {
         b.add_point(self.p3);
         b
     }
+
+    fn hash_geometry(&self, hasher: &mut dyn Hasher) {
+        hash_tuple(hasher, &self.p1);
+        hash_tuple(hasher, &self.p2);
+        hash_tuple(hasher, &self.p3);
+        hasher.write_u8(self.cull_backfaces as u8);
+    }
+}
+
+fn to_array(t: Tuple) -> [f32; 3] {
+    [t.x, t.y, t.z]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::utils::dummy_intersection;
+    use crate::transformations::scaling;
+    use crate::transformations::translation;
 
     fn default_triangle() -> Triangle {
         Triangle::new(point!(0, 1, 0), point!(-1, 0, 0), point!(1, 0, 0))
@@ -113,9 +189,9 @@ mod tests {
     fn triangle_normal() {
         let t = default_triangle();
 
-        let n1 = t.local_norm_at(point!(0, 0.5, 0), &dummy_intersection(&t));
-        let n2 = t.local_norm_at(point!(-0.5, 0.75, 0), &dummy_intersection(&t));
-        let n3 = t.local_norm_at(point!(0.5, 0.25, 0), &dummy_intersection(&t));
+        let n1 = t.local_norm_at(point!(0, 0.5, 0));
+        let n2 = t.local_norm_at(point!(-0.5, 0.75, 0));
+        let n3 = t.local_norm_at(point!(0.5, 0.25, 0));
 
         // t.normal should always be used for triangle's normal
         assert_eq!(n1, t.normal);
@@ -164,6 +240,37 @@ mod tests {
         assert_eq!(xs[0].distance, 2.0);
     }
 
+    #[test]
+    fn rays_dont_slip_between_neighbors() {
+        // two triangles sharing the edge from a to b, at an angle to every axis
+        let a = point!(-0.48726547, -0.018610358, 4.707196);
+        let b = point!(-0.1751172, 0.3054124, 4.658725);
+        let c = point!(-0.37452114, 0.41291958, 5.3588057);
+        let left = Triangle::new(a, b, c);
+        let right = Triangle::new(b, a, a + (b - c));
+        let origin = point!(0.4378265, 1.9774932, -20);
+        for i in 1..1000 {
+            let on_edge = a + (b - a) * (i as f32 / 1000.);
+            let r = Ray::new(origin, (on_edge - origin).norm());
+            let hits = left.local_intersect(r).len() + right.local_intersect(r).len();
+            assert!(hits >= 1, "Ray {} slipped through", i);
+        }
+    }
+
+    #[test]
+    fn culling_back_faces_for_camera_rays() {
+        let mut t = default_triangle();
+        t.set_transformation(scaling(1., 1., -1.));
+        t.set_backface_culling(true);
+        // the scaling flips the triangle to face +z in world space
+        let front = Ray::new(point!(0, 0.5, 2), vector!(0, 0, -1));
+        let back = Ray::new(point!(0, 0.5, -2), vector!(0, 0, 1));
+        assert_eq!(t.intersect(front.as_primary()).len(), 1);
+        assert!(t.intersect(back.as_primary()).is_empty());
+        // shadow and secondary rays still see both sides
+        assert_eq!(t.intersect(back).len(), 1);
+    }
+
     #[test]
     fn triangle_bounding_box() {
         let p1 = point!(-3, 7, 2);
@@ -174,4 +281,19 @@ mod tests {
         assert_eq!(b.min, point!(-3, -1, -4));
         assert_eq!(b.max, point!(6, 7, 2));
     }
+
+    #[test]
+    fn baking_transformation_into_triangle() {
+        let mut t = default_triangle();
+        t.set_transformation(translation(0., 0., 5.) * &scaling(2., 2., 2.));
+        let r = Ray::new(point!(0, 1, -2), vector!(0, 0, 1));
+        let before = t.intersect(r)[0].distance;
+        assert!(t.bake_transform());
+        assert_eq!(t.transformation(), &identity_4x4());
+        assert_eq!(t.p1, point!(0, 2, 5));
+        let after = t.intersect(r);
+        assert_eq!(after.len(), 1);
+        assert_abs_diff_eq!(after[0].distance, before, epsilon = 1e-5);
+        assert_abs_diff_eq!(t.normal_at(&point!(0, 1, 5)), vector!(0, 0, -1));
+    }
 }
@@ -0,0 +1,333 @@
+// Opt-in profiling. When enabled, records the time spent in each phase of loading and rendering
+// a scene and counts the intersection tests performed and the samples taken of each pixel. When disabled (the default), recording
+// costs an atomic load and a check of a thread-local flag (see count_on_thread).
+//
+// Times are exclusive: while a nested phase runs (e.g. a shadow ray cast while shading a
+// primary ray), the enclosing phase's clock is paused, so the phase times add up to the total.
+// Stats are collected per thread and merged into the global totals by flush_thread (called by
+// the renderer at the end of a render) or take.
+use enum_map::{Enum, EnumMap};
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, Enum, PartialEq)]
+pub enum Phase {
+    ObjParsing,
+    BvhBuild,
+    PrimaryRays,
+    ShadowRays,
+    SecondaryRays,
+}
+
+#[derive(Copy, Clone, Debug, Enum, PartialEq)]
+pub enum Counter {
+    RayPrimitiveTests,
+    RayAabbTests,
+    PixelSamples,
+    // shadow queries answered from, or added to, the shadow cache (see
+    // RenderSettings::shadow_cache_cell_size)
+    ShadowCacheHits,
+    ShadowCacheMisses,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub phase_times: EnumMap<Phase, Duration>,
+    pub counts: EnumMap<Counter, u64>,
+}
+
+impl RenderStats {
+    fn add(&mut self, other: &RenderStats) {
+        for (phase, time) in other.phase_times.iter() {
+            self.phase_times[phase] += *time;
+        }
+        for (counter, count) in other.counts.iter() {
+            self.counts[counter] += *count;
+        }
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.phase_times.values().sum()
+    }
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let total = self.total_time().as_secs_f64();
+        writeln!(f, "Render statistics:")?;
+        for (phase, time) in self.phase_times.iter() {
+            let percent = if total > 0. {
+                100. * time.as_secs_f64() / total
+            } else {
+                0.
+            };
+            writeln!(
+                f,
+                "  {:<16} {:>12.3?} ({:>5.1}%)",
+                format!("{:?}", phase),
+                time,
+                percent
+            )?;
+        }
+        for (counter, count) in self.counts.iter() {
+            writeln!(f, "  {:<18} {:>12}", format!("{:?}", counter), count)?;
+        }
+        Ok(())
+    }
+}
+
+// Phases currently being timed on this thread, innermost last, with the time each was last
+// started or resumed
+struct PhaseStack {
+    active: Vec<(Phase, Instant)>,
+    stats: RenderStats,
+}
+
+thread_local! {
+    static THREAD_STATS: RefCell<PhaseStack> = RefCell::new(PhaseStack {
+        active: vec![],
+        stats: RenderStats::default(),
+    });
+    // whether count_on_thread is counting on this thread
+    static COUNTING_ON_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+static TOTALS: Mutex<Option<RenderStats>> = Mutex::new(None);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn count(counter: Counter) {
+    count_n(counter, 1);
+}
+
+pub fn count_n(counter: Counter, n: u64) {
+    if is_enabled() || is_counting_on_thread() {
+        THREAD_STATS.with(|s| s.borrow_mut().stats.counts[counter] += n);
+    }
+}
+
+// Counts recorded so far on the current thread (and not yet flushed)
+pub fn thread_counts() -> EnumMap<Counter, u64> {
+    THREAD_STATS.with(|s| s.borrow().stats.counts)
+}
+
+// Run f and return what it counted on this thread, whether stats are enabled or not; e.g. to
+// count the intersection tests done for a single pixel. If stats are disabled, its counts are not
+// kept.
+pub fn count_on_thread<T>(f: impl FnOnce() -> T) -> (T, EnumMap<Counter, u64>) {
+    let keep = is_enabled() || is_counting_on_thread();
+    count_while(f, keep)
+}
+
+// Like count_on_thread, but the counts are never kept on this thread; for a helper thread to hand
+// them to the thread that it works for, which adds them with add_counts
+pub fn take_counts_on_thread<T>(f: impl FnOnce() -> T) -> (T, EnumMap<Counter, u64>) {
+    count_while(f, false)
+}
+
+fn count_while<T>(f: impl FnOnce() -> T, keep: bool) -> (T, EnumMap<Counter, u64>) {
+    let before = thread_counts();
+    let was_counting = COUNTING_ON_THREAD.with(|c| c.replace(true));
+    let result = f();
+    COUNTING_ON_THREAD.with(|c| c.set(was_counting));
+    let mut counts = thread_counts();
+    for (counter, count) in counts.iter_mut() {
+        *count -= before[counter];
+    }
+    if !keep {
+        THREAD_STATS.with(|s| s.borrow_mut().stats.counts = before);
+    }
+    (result, counts)
+}
+
+// whether this thread is inside count_on_thread, so that helper threads should count for it
+pub fn is_counting_on_thread() -> bool {
+    COUNTING_ON_THREAD.with(Cell::get)
+}
+
+// Count counts on this thread, as if they had been counted here
+pub fn add_counts(counts: &EnumMap<Counter, u64>) {
+    for (counter, count) in counts.iter() {
+        count_n(counter, *count);
+    }
+}
+
+// Start timing phase; timing stops when the returned guard is dropped
+pub fn time_phase(phase: Phase) -> PhaseTimer {
+    if !is_enabled() {
+        return PhaseTimer { active: false };
+    }
+    let now = Instant::now();
+    THREAD_STATS.with(|s| {
+        let mut s = s.borrow_mut();
+        // pause the enclosing phase
+        if let Some(&(outer, started)) = s.active.last() {
+            s.stats.phase_times[outer] += now - started;
+        }
+        s.active.push((phase, now));
+    });
+    PhaseTimer { active: true }
+}
+
+pub struct PhaseTimer {
+    active: bool,
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        let now = Instant::now();
+        THREAD_STATS.with(|s| {
+            let mut s = s.borrow_mut();
+            if let Some((phase, started)) = s.active.pop() {
+                s.stats.phase_times[phase] += now - started;
+            }
+            // resume the enclosing phase
+            if let Some(outer) = s.active.last_mut() {
+                outer.1 = now;
+            }
+        });
+    }
+}
+
+// Merge this thread's stats into the global totals
+pub fn flush_thread() {
+    let local = THREAD_STATS.with(|s| std::mem::take(&mut s.borrow_mut().stats));
+    TOTALS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(RenderStats::default)
+        .add(&local);
+}
+
+// Return the stats collected so far (including the current thread's) and reset them
+pub fn take() -> RenderStats {
+    flush_thread();
+    TOTALS.lock().unwrap().take().unwrap_or_default()
+}
+
+// Print and reset the collected stats, if collection is enabled
+pub fn print_summary() {
+    if is_enabled() {
+        eprint!("{}", take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    // The enabled flag is global, so these tests only inspect thread-local stats, which tests
+    // running in parallel on other threads cannot touch.
+    fn local_stats() -> RenderStats {
+        THREAD_STATS.with(|s| std::mem::take(&mut s.borrow_mut().stats))
+    }
+
+    // Enables stats until dropped, then restores the previous setting, even if the test fails
+    struct EnabledForTest {
+        was_enabled: bool,
+    }
+
+    impl EnabledForTest {
+        fn new() -> Self {
+            let was_enabled = is_enabled();
+            enable();
+            EnabledForTest { was_enabled }
+        }
+    }
+
+    impl Drop for EnabledForTest {
+        fn drop(&mut self) {
+            if !self.was_enabled {
+                disable();
+            }
+        }
+    }
+
+    #[test]
+    fn nested_phase_times_are_exclusive() {
+        let _enabled = EnabledForTest::new();
+        local_stats();
+        let start = Instant::now();
+        {
+            let _outer = time_phase(Phase::PrimaryRays);
+            sleep(Duration::from_millis(5));
+            {
+                let _inner = time_phase(Phase::ShadowRays);
+                sleep(Duration::from_millis(50));
+            }
+            count(Counter::RayPrimitiveTests);
+            count(Counter::RayPrimitiveTests);
+        }
+        let elapsed = start.elapsed();
+        let stats = local_stats();
+        assert!(stats.phase_times[Phase::ShadowRays] >= Duration::from_millis(50));
+        assert!(stats.phase_times[Phase::PrimaryRays] >= Duration::from_millis(5));
+        // counting the inner phase's time for the outer one as well would add up to more than the
+        // time that passed. Sleeps can overrun on a loaded machine, so there is no upper bound.
+        assert!(
+            stats.phase_times[Phase::PrimaryRays] + stats.phase_times[Phase::ShadowRays] <= elapsed,
+            "{:?}",
+            stats.phase_times
+        );
+        assert_eq!(stats.counts[Counter::RayPrimitiveTests], 2);
+    }
+
+    #[test]
+    fn count_on_thread_returns_its_own_counts() {
+        count(Counter::RayAabbTests);
+        let (result, counts) = count_on_thread(|| {
+            count_n(Counter::RayAabbTests, 3);
+            count(Counter::RayPrimitiveTests);
+            "done"
+        });
+        assert_eq!(result, "done");
+        assert_eq!(counts[Counter::RayAabbTests], 3);
+        assert_eq!(counts[Counter::RayPrimitiveTests], 1);
+        assert_eq!(counts[Counter::PixelSamples], 0);
+    }
+
+    #[test]
+    fn counts_taken_on_a_helper_thread_can_be_added() {
+        let (_, counts) = count_on_thread(|| {
+            let helper_counts = std::thread::spawn(|| {
+                let (_, counts) = take_counts_on_thread(|| count_n(Counter::RayAabbTests, 5));
+                assert_eq!(thread_counts()[Counter::RayAabbTests], 0);
+                counts
+            })
+            .join()
+            .unwrap();
+            add_counts(&helper_counts);
+        });
+        assert_eq!(counts[Counter::RayAabbTests], 5);
+    }
+
+    #[test]
+    fn summary_lists_phases_and_counts() {
+        let mut stats = RenderStats::default();
+        stats.counts[Counter::RayAabbTests] = 42;
+        stats.phase_times[Phase::BvhBuild] = Duration::from_millis(3);
+        let summary = stats.to_string();
+        assert!(summary.contains("BvhBuild"));
+        assert!(summary.contains("100.0%"));
+        assert!(summary.contains("42"));
+    }
+}
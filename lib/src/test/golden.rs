@@ -0,0 +1,219 @@
+// Golden-image regression tests: small canonical scenes are rendered and compared against
+// reference images in resources/test/golden, so that changes to shading can't silently change the
+// output. After an intentional change to the output, regenerate the references with
+// `UPDATE_GOLDEN=1 cargo test golden` and inspect the new images before committing them.
+use crate::camera::Camera;
+use crate::canvas::{canvas_from_ppm, Canvas};
+use crate::color::Color;
+use crate::constants::{glass, white};
+use crate::light::point_light::PointLight;
+use crate::material::Material;
+use crate::matrix::identity_4x4;
+use crate::obj_parser::parse_obj;
+use crate::pattern::checkers::Checkers;
+use crate::pattern::pattern::Pattern;
+use crate::render_settings::RenderSettings;
+use crate::shape::csg::{CSGOperator, CSG};
+use crate::shape::cube::Cube;
+use crate::shape::plane::Plane;
+use crate::shape::shape::Shape;
+use crate::shape::sphere::Sphere;
+use crate::transformations::{rotation_x, rotation_y, scaling, translation, view_transform};
+use crate::tuple::Tuple;
+use crate::world::World;
+use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const SIZE: u32 = 64;
+// maximum difference allowed in each color channel, on the 0-255 scale of the PPM files; allows
+// for floating point differences between platforms
+const CHANNEL_TOLERANCE: i32 = 2;
+
+fn camera(from: Tuple) -> Camera {
+    Camera::new(
+        SIZE,
+        SIZE,
+        PI / 3.,
+        view_transform(from, point!(0, 1, 0), vector!(0, 1, 0)),
+    )
+}
+
+fn checkered_floor(reflective: f32) -> Plane {
+    let mut checkers = Checkers::new(color!(0.9, 0.9, 0.9), color!(0.1, 0.1, 0.1));
+    checkers.set_transformation(scaling(0.5, 0.5, 0.5));
+    let material = Material::builder()
+        .pattern(Arc::new(checkers))
+        .reflective(reflective)
+        .specular(0.)
+        .build();
+    Plane::build(identity_4x4(), material)
+}
+
+fn world_with(objects: Vec<Box<dyn Shape>>) -> World {
+    let mut world = World::new();
+    world.light = Some(Box::new(PointLight::new(point!(-10, 10, -10), white())));
+    world.objects = objects;
+    world
+}
+
+fn reflection_scene() -> (World, Camera) {
+    let sphere = Sphere::build(
+        translation(0., 1., 0.),
+        Material::builder()
+            .color(color!(0.8, 0.2, 0.2))
+            .reflective(0.3)
+            .build(),
+    );
+    (
+        world_with(vec![Box::new(checkered_floor(0.5)), Box::new(sphere)]),
+        camera(point!(0, 2, -4)),
+    )
+}
+
+fn refraction_scene() -> (World, Camera) {
+    let mut wall = checkered_floor(0.);
+    wall.set_transformation(translation(0., 0., 3.) * rotation_x(PI / 2.));
+    let glass_sphere = Sphere::build(translation(0., 1., 0.), glass());
+    (
+        world_with(vec![
+            Box::new(checkered_floor(0.)),
+            Box::new(wall),
+            Box::new(glass_sphere),
+        ]),
+        camera(point!(0, 1.5, -4)),
+    )
+}
+
+fn csg_scene() -> (World, Camera) {
+    let cube = Cube::build(
+        identity_4x4(),
+        Material::builder().color(color!(0.2, 0.4, 0.8)).build(),
+    );
+    let sphere = Sphere::build(
+        scaling(1.3, 1.3, 1.3),
+        Material::builder().color(color!(0.9, 0.8, 0.2)).build(),
+    );
+    let mut csg = CSG::new(CSGOperator::Difference(), Box::new(cube), Box::new(sphere));
+    csg.set_transformation(translation(0., 1., 0.) * rotation_y(PI / 6.));
+    (
+        world_with(vec![Box::new(checkered_floor(0.)), Box::new(csg)]),
+        camera(point!(1, 3, -4)),
+    )
+}
+
+fn smooth_triangles_scene() -> (World, Camera) {
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "resources/test",
+        "smooth_octahedron.obj",
+    ]
+    .iter()
+    .collect();
+    let mut octahedron = parse_obj(File::open(&path).unwrap())
+        .unwrap()
+        .take_all_as_group()
+        .unwrap();
+    octahedron.set_material(Material::builder().color(color!(0.3, 0.8, 0.3)).build());
+    octahedron.set_transformation(translation(0., 1., 0.) * rotation_y(PI / 8.));
+    (
+        world_with(vec![Box::new(checkered_floor(0.)), Box::new(octahedron)]),
+        camera(point!(0, 2, -4)),
+    )
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    [
+        env!("CARGO_MANIFEST_DIR"),
+        "resources/test/golden",
+        &format!("{}.ppm", name),
+    ]
+    .iter()
+    .collect()
+}
+
+fn scale(value: f32) -> i32 {
+    (value * 255.).clamp(0., 255.) as i32
+}
+
+// Coordinates of the pixels that differ by more than tolerance in any channel
+fn differing_pixels(expected: &Canvas, actual: &Canvas, tolerance: i32) -> Vec<(usize, usize)> {
+    let mut differing = vec![];
+    for y in 0..expected.height {
+        for x in 0..expected.width {
+            let e = expected.pixel_at(x, y);
+            let a = actual.pixel_at(x, y);
+            if (scale(e.r) - scale(a.r)).abs() > tolerance
+                || (scale(e.g) - scale(a.g)).abs() > tolerance
+                || (scale(e.b) - scale(a.b)).abs() > tolerance
+            {
+                differing.push((x, y));
+            }
+        }
+    }
+    differing
+}
+
+fn check_against_reference(name: &str, (mut world, camera): (World, Camera)) {
+    let actual = camera.render(&mut world, RenderSettings::default());
+    let path = reference_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual.to_ppm()).unwrap();
+        return;
+    }
+    let expected = canvas_from_ppm(File::open(&path).unwrap_or_else(|e| {
+        panic!(
+            "Could not open {:?} ({}); generate it with UPDATE_GOLDEN=1",
+            path, e
+        )
+    }))
+    .unwrap();
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height)
+    );
+
+    let differing = differing_pixels(&expected, &actual, CHANNEL_TOLERANCE);
+    if !differing.is_empty() {
+        let actual_path = std::env::temp_dir().join(format!("{}.actual.ppm", name));
+        fs::write(&actual_path, actual.to_ppm()).unwrap();
+        panic!(
+            "{} pixels of {} differ from {:?}, first at {:?}; rendered image written to {:?}",
+            differing.len(),
+            name,
+            path,
+            differing[0],
+            actual_path
+        );
+    }
+}
+
+#[test]
+fn golden_reflection() {
+    check_against_reference("reflection", reflection_scene());
+}
+
+#[test]
+fn golden_refraction() {
+    check_against_reference("refraction", refraction_scene());
+}
+
+#[test]
+fn golden_csg() {
+    check_against_reference("csg", csg_scene());
+}
+
+#[test]
+fn golden_smooth_triangles() {
+    check_against_reference("smooth_triangles", smooth_triangles_scene());
+}
+
+#[test]
+fn differing_pixels_respects_tolerance() {
+    let expected = Canvas::new(2, 1);
+    let mut actual = Canvas::new(2, 1);
+    actual.write_pixel(0, 0, color!(2. / 255., 0, 0));
+    actual.write_pixel(1, 0, color!(0, 0, 4. / 255.));
+    assert_eq!(differing_pixels(&expected, &actual, 2), vec![(1, 0)]);
+}
@@ -1 +1,3 @@
+mod golden;
+pub mod strategies;
 pub mod utils;
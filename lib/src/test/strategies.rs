@@ -0,0 +1,56 @@
+// proptest generators for property-based tests of the math layer
+use crate::matrix::Matrix;
+use crate::transformations::{rotation_x, rotation_y, rotation_z, scaling, shearing, translation};
+use crate::tuple::Tuple;
+use proptest::prelude::*;
+use std::f32::consts::PI;
+
+pub fn coordinate() -> impl Strategy<Value = f32> {
+    -10f32..10.
+}
+
+pub fn point() -> impl Strategy<Value = Tuple> {
+    (coordinate(), coordinate(), coordinate()).prop_map(|(x, y, z)| point!(x, y, z))
+}
+
+// never (nearly) the zero vector
+pub fn nonzero_vector() -> impl Strategy<Value = Tuple> {
+    (coordinate(), coordinate(), coordinate())
+        .prop_filter("vector too short", |(x, y, z)| {
+            x.abs() + y.abs() + z.abs() > 0.1
+        })
+        .prop_map(|(x, y, z)| vector!(x, y, z))
+}
+
+// Arbitrary 4x4 matrix; not necessarily invertible
+pub fn matrix() -> impl Strategy<Value = Matrix> {
+    proptest::collection::vec(coordinate(), 16)
+        .prop_map(|values| Matrix::from_rows(values.chunks(4).map(|row| row.to_vec()).collect()))
+}
+
+// scale factor that is neither tiny nor huge, so the resulting transform is well-conditioned
+fn scale_factor() -> impl Strategy<Value = f32> {
+    (0.2f32..5., any::<bool>()).prop_map(|(s, negative)| if negative { -s } else { s })
+}
+
+// A single translation, scaling, rotation or (mild) shearing
+pub fn simple_transform() -> impl Strategy<Value = Matrix> {
+    prop_oneof![
+        (coordinate(), coordinate(), coordinate()).prop_map(|(x, y, z)| translation(x, y, z)),
+        (scale_factor(), scale_factor(), scale_factor()).prop_map(|(x, y, z)| scaling(x, y, z)),
+        (-PI..PI).prop_map(rotation_x),
+        (-PI..PI).prop_map(rotation_y),
+        (-PI..PI).prop_map(rotation_z),
+        proptest::collection::vec(-0.5f32..0.5, 6)
+            .prop_map(|s| shearing(s[0], s[1], s[2], s[3], s[4], s[5])),
+    ]
+}
+
+// Composition of a few simple transforms, like the ones used to place objects in a scene
+pub fn transform() -> impl Strategy<Value = Matrix> {
+    proptest::collection::vec(simple_transform(), 1..4).prop_map(|transforms| {
+        transforms
+            .into_iter()
+            .fold(crate::matrix::identity_4x4(), |acc, t| &t * &acc)
+    })
+}
@@ -1,24 +1,140 @@
-use crate::intersection::Intersection;
 use crate::shape::shape::Shape;
 use crate::shape::sphere::Sphere;
-use std::cell::RefCell;
-
-pub fn dummy_intersection(s: &dyn Shape) -> Intersection {
-    Intersection::new(1., s)
-}
+use std::sync::Mutex;
 
 pub fn any_shape() -> Box<dyn Shape> {
     Box::new(Sphere::new())
 }
 
 // "Jitter" referring to point sampling for area lights
-pub fn constant_jitter() -> Option<Box<dyn Fn() -> f32>> {
+pub fn constant_jitter() -> Option<Box<dyn Fn() -> f32 + Send + Sync>> {
     Some(Box::new(|| 0.5))
 }
 
-pub fn hardcoded_jitter(sequence: Vec<f32>) -> Option<Box<dyn Fn() -> f32>> {
-    let hardcoded_sequence = RefCell::new(sequence.into_iter().cycle());
+pub fn hardcoded_jitter(sequence: Vec<f32>) -> Option<Box<dyn Fn() -> f32 + Send + Sync>> {
+    let hardcoded_sequence = Mutex::new(sequence.into_iter().cycle());
     Some(Box::new(move || {
-        hardcoded_sequence.borrow_mut().next().unwrap()
+        hardcoded_sequence.lock().unwrap().next().unwrap()
     }))
 }
+
+// A tiny TrueType font with 1024 units per em. It has a square 'O' with a square hole, a 'D' made
+// of one curve, a compound '8' made of two O's stacked, and a space.
+pub fn test_font_bytes() -> Vec<u8> {
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_be_bytes().to_vec())
+            .collect()
+    }
+    // a simple glyph with all coordinates stored as 16-bit deltas
+    fn simple_glyph(contours: &[&[(i16, i16, bool)]]) -> Vec<u8> {
+        let mut ends = vec![];
+        let mut points = vec![];
+        for contour in contours {
+            points.extend_from_slice(contour);
+            ends.push(points.len() as u16 - 1);
+        }
+        let mut data = u16s(&[contours.len() as u16, 0, 0, 1024, 1024]);
+        data.extend(u16s(&ends));
+        // no instructions
+        data.extend(u16s(&[0]));
+        data.extend(points.iter().map(|p| p.2 as u8));
+        let mut deltas = |coordinate: fn(&(i16, i16, bool)) -> i16| {
+            let mut previous = 0;
+            for p in &points {
+                data.extend(u16s(&[(coordinate(p) - previous) as u16]));
+                previous = coordinate(p);
+            }
+        };
+        deltas(|p| p.0);
+        deltas(|p| p.1);
+        data
+    }
+
+    let square_with_hole = simple_glyph(&[
+        &[
+            (0, 0, true),
+            (0, 512, true),
+            (512, 512, true),
+            (512, 0, true),
+        ],
+        &[
+            (128, 128, true),
+            (384, 128, true),
+            (384, 384, true),
+            (128, 384, true),
+        ],
+    ]);
+    let curve = simple_glyph(&[&[(0, 0, true), (512, 0, false), (512, 512, true)]]);
+    // flags: words as arguments, arguments are offsets, more components follow
+    let mut stacked = u16s(&[0xFFFF, 0, 0, 512, 1024]);
+    stacked.extend(u16s(&[0x0023, 1, 0, 0]));
+    stacked.extend(u16s(&[0x0003, 1, 0, 512]));
+    // .notdef and space have no outline
+    let glyphs = vec![vec![], square_with_hole, vec![], curve, stacked];
+    let advances = [512, 768, 256, 768, 768];
+
+    let mut glyf = vec![];
+    let mut loca = vec![];
+    for glyph in &glyphs {
+        loca.extend((glyf.len() as u32).to_be_bytes().iter());
+        glyf.extend(glyph);
+    }
+    loca.extend((glyf.len() as u32).to_be_bytes().iter());
+
+    let mut head = vec![0; 54];
+    head[18..20].copy_from_slice(&1024u16.to_be_bytes());
+    // long loca offsets
+    head[50..52].copy_from_slice(&1u16.to_be_bytes());
+    let maxp = u16s(&[0, 0x5000, glyphs.len() as u16]);
+    let mut hhea = vec![0; 36];
+    hhea[4..6].copy_from_slice(&1024i16.to_be_bytes());
+    hhea[6..8].copy_from_slice(&(-256i16).to_be_bytes());
+    hhea[34..36].copy_from_slice(&(glyphs.len() as u16).to_be_bytes());
+    let hmtx: Vec<u8> = advances.iter().flat_map(|&a| u16s(&[a, 0])).collect();
+
+    // format 4 map from ' ', '8', 'D' and 'O' to their glyphs, with the required final segment
+    let codes: [(u16, u16); 5] = [(32, 2), (56, 4), (68, 3), (79, 1), (0xFFFF, 0)];
+    let mut cmap = u16s(&[0, 1, 3, 1, 0, 12]);
+    let segments = codes.len() as u16;
+    let mut format4 = u16s(&[4, 0, 0, 2 * segments, 0, 0, 0]);
+    format4.extend(u16s(&codes.iter().map(|c| c.0).collect::<Vec<u16>>()));
+    format4.extend(u16s(&[0]));
+    format4.extend(u16s(&codes.iter().map(|c| c.0).collect::<Vec<u16>>()));
+    format4.extend(u16s(
+        &codes
+            .iter()
+            .map(|&(code, glyph)| glyph.wrapping_sub(code))
+            .collect::<Vec<u16>>(),
+    ));
+    format4.extend(u16s(&vec![0; codes.len()]));
+    cmap.extend(format4);
+
+    let tables: Vec<(&str, Vec<u8>)> = vec![
+        ("cmap", cmap),
+        ("glyf", glyf),
+        ("head", head),
+        ("hhea", hhea),
+        ("hmtx", hmtx),
+        ("loca", loca),
+        ("maxp", maxp),
+    ];
+    let mut font = u16s(&[1, 0, tables.len() as u16, 0, 0, 0]);
+    let mut offset = 12 + 16 * tables.len();
+    let mut contents = vec![];
+    for (tag, table) in &tables {
+        font.extend(tag.as_bytes());
+        font.extend(u16s(&[0, 0]));
+        font.extend((offset as u32).to_be_bytes().iter());
+        font.extend((table.len() as u32).to_be_bytes().iter());
+        contents.extend(table);
+        // tables start on 4-byte boundaries
+        while contents.len() % 4 != 0 {
+            contents.push(0);
+        }
+        offset = 12 + 16 * tables.len() + contents.len();
+    }
+    font.extend(contents);
+    font
+}
@@ -52,6 +52,60 @@ pub fn shearing(x_y: f32, x_z: f32, y_x: f32, y_z: f32, z_x: f32, z_y: f32) -> M
     )
 }
 
+// Like shearing, but each value is the angle in radians by which the first axis leans towards
+// the second, e.g. x_y = PI / 4 moves x as much as y, which turns a square into a parallelogram
+// with a 45 degree slant.
+pub fn shearing_angles(x_y: f32, x_z: f32, y_x: f32, y_z: f32, z_x: f32, z_y: f32) -> Matrix {
+    shearing(
+        x_y.tan(),
+        x_z.tan(),
+        y_x.tan(),
+        y_z.tan(),
+        z_x.tan(),
+        z_y.tan(),
+    )
+}
+
+// mirror images across the planes that contain two of the axes
+pub fn reflection_xy() -> Matrix {
+    scaling(1., 1., -1.)
+}
+
+pub fn reflection_xz() -> Matrix {
+    scaling(1., -1., 1.)
+}
+
+pub fn reflection_yz() -> Matrix {
+    scaling(-1., 1., 1.)
+}
+
+// Object transform that moves an object from the origin to position and turns it so that its +z
+// axis points at target and its +y axis points as close to approximate_up as possible. The
+// counterpart of view_transform for things other than the camera, such as spotlights, arrows and
+// faces that should look at something. Does not mirror the object. If approximate_up points
+// along the line to target, any up is as close as any other, so the world axis furthest from that
+// line is used instead.
+pub fn orient_towards(position: Tuple, target: Tuple, approximate_up: Tuple) -> Matrix {
+    let forward = (target - position).norm();
+    let mut right = approximate_up.norm().cross(forward);
+    if right.magnitude() < 1e-4 {
+        let fallback_up = if forward.x.abs() < 0.5 {
+            vector!(1, 0, 0)
+        } else {
+            vector!(0, 1, 0)
+        };
+        right = fallback_up.cross(forward);
+    }
+    let right = right.norm();
+    let true_up = forward.cross(right);
+    matrix!(
+        [right.x, true_up.x, forward.x, position.x],
+        [right.y, true_up.y, forward.y, position.y],
+        [right.z, true_up.z, forward.z, position.z],
+        [0, 0, 0, 1]
+    )
+}
+
 // we use an approximate up so that the programmer doesn't have to do complex
 // calculations to figure out the correct input value
 pub fn view_transform(from: Tuple, to: Tuple, approximate_up: Tuple) -> Matrix {
@@ -201,6 +255,60 @@ mod tests {
         assert_eq!(transform * p, point!(2, 3, 7));
     }
 
+    #[test]
+    fn reflections_across_principal_planes() {
+        let p = point!(2, 3, 4);
+        assert_eq!(reflection_xy() * p, point!(2, 3, -4));
+        assert_eq!(reflection_xz() * p, point!(2, -3, 4));
+        assert_eq!(reflection_yz() * p, point!(-2, 3, 4));
+    }
+
+    #[test]
+    fn shearing_by_angles() {
+        let transform = shearing_angles(FRAC_PI_4, 0., 0., 0., 0., 0.);
+        assert_abs_diff_eq!(transform * point!(2, 3, 4), point!(5, 3, 4));
+        let transform = shearing_angles(0., 0., 0., 0., 0., -FRAC_PI_4);
+        assert_abs_diff_eq!(transform * point!(2, 3, 4), point!(2, 3, 1));
+        assert_eq!(shearing_angles(0., 0., 0., 0., 0., 0.), identity_4x4());
+    }
+
+    #[test]
+    fn orient_towards_default_orientation_is_identity() {
+        let t = orient_towards(point!(0, 0, 0), point!(0, 0, 5), vector!(0, 1, 0));
+        assert_eq!(t, identity_4x4());
+    }
+
+    #[test]
+    fn orient_towards_points_z_axis_at_target() {
+        let position = point!(1, 2, 3);
+        let target = point!(4, -2, 3);
+        let t = orient_towards(position, target, vector!(0, 1, 0));
+        assert_abs_diff_eq!(&t * point!(0, 0, 0), position);
+        assert_abs_diff_eq!(&t * point!(0, 0, 5), target);
+        assert_abs_diff_eq!(&t * vector!(0, 0, 1), vector!(0.6, -0.8, 0));
+        // up is tilted only as much as needed to stay perpendicular to the new z axis
+        assert_abs_diff_eq!(&t * vector!(0, 1, 0), vector!(0.8, 0.6, 0));
+        // a rotation, not a mirror image
+        assert_abs_diff_eq!(t.determinant(), 1., epsilon = 1e-5);
+    }
+
+    #[test]
+    fn orient_towards_target_along_up_falls_back_to_another_axis() {
+        // e.g. a spotlight aimed straight down with the usual up vector
+        type Case = (&'static str, Tuple, Tuple);
+        let test_data: Vec<Case> = vec![
+            ("down", point!(0, -5, 0), vector!(0, 1, 0)),
+            ("up", point!(0, 5, 0), vector!(0, 1, 0)),
+            ("along x", point!(5, 0, 0), vector!(-1, 0, 0)),
+        ];
+        for (name, target, up) in test_data {
+            println!("Case {}", name);
+            let t = orient_towards(point!(0, 0, 0), target, up);
+            assert_abs_diff_eq!(&t * point!(0, 0, 5), target);
+            assert_abs_diff_eq!(t.determinant(), 1., epsilon = 1e-5);
+        }
+    }
+
     #[test]
     fn transforms_applied_in_sequence() {
         let p = point!(1, 0, 1);
@@ -1,8 +1,10 @@
+use crate::matrix::Matrix;
 use approx::AbsDiffEq;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tuple {
     pub x: f32,
     pub y: f32,
@@ -13,13 +15,27 @@ pub struct Tuple {
 // TODO: implement approximate comparison via approx crate
 // TODO: allow changing datatypes to f64?
 impl Tuple {
+    // Validation is skipped in release builds unless the validate feature is enabled; use
+    // try_new to check the values regardless of build settings
     pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
-        debug_assert!(w == 1.0 || w == 0.0, "w must be 0 or 1; was {}", w);
-        debug_assert!(!x.is_nan(), "x cannot be NaN");
-        debug_assert!(!y.is_nan(), "x cannot be NaN");
-        debug_assert!(!z.is_nan(), "z cannot be NaN");
+        if cfg!(any(debug_assertions, feature = "validate")) {
+            if let Err(e) = Tuple::try_new(x, y, z, w) {
+                panic!("{}", e);
+            }
+        }
         Tuple { x, y, z, w }
     }
+    pub fn try_new(x: f32, y: f32, z: f32, w: f32) -> Result<Self, InvalidTupleError> {
+        if w != 1.0 && w != 0.0 {
+            return Err(InvalidTupleError::InvalidW(w));
+        }
+        for (name, value) in [("x", x), ("y", y), ("z", z)].iter() {
+            if value.is_nan() {
+                return Err(InvalidTupleError::NaN(name));
+            }
+        }
+        Ok(Tuple { x, y, z, w })
+    }
     pub fn is_vector(&self) -> bool {
         self.w == 0.0
     }
@@ -53,6 +69,10 @@ impl Tuple {
             w: 0.0,
         }
     }
+    // Multiply by the matrix in place, like Ray::transform_mut
+    pub fn transform_mut(&mut self, transform_matrix: &Matrix) {
+        *self = transform_matrix * *self;
+    }
 }
 
 impl Display for Tuple {
@@ -68,6 +88,25 @@ impl Display for Tuple {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InvalidTupleError {
+    // w must be 0 (vector) or 1 (point)
+    InvalidW(f32),
+    // name of the NaN component
+    NaN(&'static str),
+}
+
+impl Display for InvalidTupleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            InvalidTupleError::InvalidW(w) => write!(f, "w must be 0 or 1; was {}", w),
+            InvalidTupleError::NaN(name) => write!(f, "{} cannot be NaN", name),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTupleError {}
+
 // Use like this: point!(1,2,3)
 #[macro_export]
 macro_rules! point {
@@ -188,6 +227,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn try_new_rejects_invalid_values() {
+        let test_data = vec![
+            ("w = 2", 1., 2., 3., 2., InvalidTupleError::InvalidW(2.)),
+            ("x NaN", f32::NAN, 2., 3., 1., InvalidTupleError::NaN("x")),
+            ("y NaN", 1., f32::NAN, 3., 0., InvalidTupleError::NaN("y")),
+            ("z NaN", 1., 2., f32::NAN, 1., InvalidTupleError::NaN("z")),
+        ];
+        for (name, x, y, z, w, expected) in test_data {
+            println!("Case {}", name);
+            assert_eq!(Tuple::try_new(x, y, z, w), Err(expected));
+        }
+        assert_eq!(
+            Tuple::try_new(1., f32::INFINITY, 3., 1.),
+            Ok(point!(1., f32::INFINITY, 3.))
+        );
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    #[should_panic(expected = "y cannot be NaN")]
+    fn new_panics_on_nan_when_validating() {
+        Tuple::new(1., f32::NAN, 3., 1.);
+    }
+
     #[test]
     fn test_tuple_with_w_equal_1_is_point() {
         let tuple = Tuple {
@@ -399,4 +463,15 @@ mod tests {
         assert_eq!(x.cross(y), vector!(-1, 2, -1));
         assert_eq!(y.cross(x), vector!(1, -2, 1));
     }
+
+    #[test]
+    fn test_transform_in_place() {
+        let m = matrix!([1, 2, 3, 4], [2, 4, 4, 2], [8, 6, 4, 1], [0, 0, 0, 1]);
+        let mut p = point!(1, 2, 3);
+        p.transform_mut(&m);
+        assert_eq!(p, &m * point!(1, 2, 3));
+        let mut v = vector!(1, 2, 3);
+        v.transform_mut(&m);
+        assert_eq!(v, &m * vector!(1, 2, 3));
+    }
 }
@@ -1,34 +1,185 @@
+use crate::bounding_box::BoundingBox;
 use crate::color::Color;
 use crate::constants::white;
 use crate::constants::REFRACTION_VACCUM;
-use crate::intersection::Intersection;
-use crate::light::{light::Light, phong_lighting::phong_lighting, point_light::PointLight};
+use crate::constants::{SELF_INTERSECTION_AVOIDANCE_EPSILON, SELF_INTERSECTION_RELATIVE_EPSILON};
+use crate::intersection::{Intersection, Intersections};
+use crate::light::{
+    light::Light,
+    phong_lighting::{phong_lighting_colored, surface_color},
+    point_light::PointLight,
+};
 use crate::material::Material;
 use crate::matrix::identity_4x4;
+use crate::pattern::pattern::average_scale;
+use crate::pattern::uv::{SphericalMap, UVMapping, UVPattern};
 use crate::ray::Ray;
+use crate::render_settings::{RenderSettings, ShadingMode};
+use crate::sampler;
 use crate::shape::shape::Shape;
+use crate::shape::smooth_triangle::SmoothTriangle;
 use crate::shape::sphere::Sphere;
+use crate::shape::triangle::Triangle;
+use crate::stats::{self, Counter, Phase};
 use crate::transformations::scaling;
 use crate::tuple::Tuple;
 use linked_hash_set::LinkedHashSet;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::cmp::Ordering::Equal;
+use std::collections::HashMap;
 use std::f32;
+use std::hash::{Hash, Hasher};
+use std::thread;
+
+// the cell of the shading point and the bits of the light's position
+type ShadowCacheKey = ([i64; 3], [u32; 3]);
+
+thread_local! {
+    // answers to the shadow queries made on this thread since the cache was last cleared
+    static SHADOW_CACHE: RefCell<HashMap<ShadowCacheKey, bool>> = RefCell::new(HashMap::new());
+}
+
+// Forget the cached shadow queries of this thread (see RenderSettings::shadow_cache_cell_size).
+// The camera calls this before each row or tile, and must be called after changing the world.
+pub fn clear_shadow_cache() {
+    SHADOW_CACHE.with(|cache| cache.borrow_mut().clear());
+}
 
 // TODO: book said no light by default, but that seems weird. We always have a light, otherwise we can't see anything! Plus using Option complicates/makes dangerous everything.
 pub struct World {
     pub objects: Vec<Box<dyn Shape>>,
     pub light: Option<Box<dyn Light>>,
+    // number of threads used to intersect rays with the objects; only worthwhile for worlds with
+    // many root-level objects, since threads are started for each ray
+    pub intersect_threads: usize,
+    // seen by rays that miss every object, looked up by the ray's direction with a spherical
+    // mapping; e.g. a UVImage of an HDR panorama. Black when not set.
+    pub environment: Option<Box<dyn UVPattern>>,
+    // only the shading settings (shadows, soft shadow samples, the self intersection epsilon and
+    // the minimum contribution) are used by the world itself; Camera::render replaces these with
+    // its own
+    pub render_settings: RenderSettings,
+    // world space bounds of objects added, edited or removed through add, edit and remove since
+    // the last clear_changes; see Camera::rerender_dirty
+    pub changed_regions: Vec<BoundingBox>,
+}
+
+// Refers to an object added with World::add. Handles are based on the object's unique ID rather
+// than its position in objects, so they stay valid when other objects are added or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectHandle {
+    id: usize,
+}
+
+// How many more times a ray may be reflected, and how many more times it may be refracted. The
+// two are limited separately because glass needs many refractions to look right, while deep
+// reflections are rarely noticeable. A single number limits both equally.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemainingBounces {
+    pub reflections: i16,
+    pub refractions: i16,
+    // the fraction of the ray's color that reaches the camera: the product of the reflective and
+    // transparency values of every surface it bounced off of or passed through
+    pub contribution: f32,
+    // the number of reflections and refractions already traced
+    pub depth: i16,
+    // set once the ray has been split up by a dispersive material; the ray then stands for only
+    // this channel of the light, and is not split again
+    pub channel: Option<Channel>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+impl RemainingBounces {
+    pub fn new(reflections: i16, refractions: i16) -> Self {
+        RemainingBounces {
+            reflections,
+            refractions,
+            contribution: 1.,
+            depth: 0,
+            channel: None,
+        }
+    }
+
+    fn is_visible(&self, contribution: f32, min_contribution: f32) -> bool {
+        self.contribution * contribution >= min_contribution
+    }
+}
+
+impl From<i16> for RemainingBounces {
+    fn from(bounces: i16) -> Self {
+        RemainingBounces::new(bounces, bounces)
+    }
 }
 
+// each thread intersects at least this many objects, so that starting it is worth the cost
+const MIN_OBJECTS_PER_INTERSECT_THREAD: usize = 32;
+
 impl World {
     pub fn new() -> World {
         World {
             objects: vec![],
             light: Option::None,
+            intersect_threads: 1,
+            environment: None,
+            render_settings: RenderSettings::default(),
+            changed_regions: vec![],
         }
     }
 }
 
+// Declares a world in a single expression, e.g.
+// World::builder().light(PointLight::new(..)).add(Sphere::new()).add(Plane::new()).build()
+// Starts out like World::new: empty and without a light.
+pub struct WorldBuilder {
+    world: World,
+}
+
+impl WorldBuilder {
+    pub fn light(mut self, light: impl Light + 'static) -> Self {
+        self.world.light = Some(Box::new(light));
+        self
+    }
+
+    // named like World::add rather than being an operator
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, object: impl Shape + 'static) -> Self {
+        self.add_boxed(Box::new(object))
+    }
+
+    // for shapes that are already boxed, e.g. ones loaded from OBJ files
+    pub fn add_boxed(mut self, object: Box<dyn Shape>) -> Self {
+        self.world.objects.push(object);
+        self
+    }
+
+    // seen by rays that miss every object; see World::environment
+    pub fn background(mut self, environment: impl UVPattern + 'static) -> Self {
+        self.world.environment = Some(Box::new(environment));
+        self
+    }
+
+    pub fn render_settings(mut self, render_settings: RenderSettings) -> Self {
+        self.world.render_settings = render_settings;
+        self
+    }
+
+    pub fn intersect_threads(mut self, intersect_threads: usize) -> Self {
+        self.world.intersect_threads = intersect_threads;
+        self
+    }
+
+    pub fn build(self) -> World {
+        self.world
+    }
+}
+
 impl Default for World {
     fn default() -> Self {
         let m = Material::builder()
@@ -44,39 +195,201 @@ impl Default for World {
                 point!(-10.0, 10.0, -10.0),
                 white(),
             ))),
+            intersect_threads: 1,
+            environment: None,
+            render_settings: RenderSettings::default(),
+            changed_regions: vec![],
         }
     }
 }
 
 impl World {
-    pub fn intersect(&self, r: Ray) -> Vec<Intersection> {
-        let mut intersections: Vec<Intersection> = (&self.objects)
+    pub fn builder() -> WorldBuilder {
+        WorldBuilder {
+            world: World::new(),
+        }
+    }
+
+    pub fn add(&mut self, object: Box<dyn Shape>) -> ObjectHandle {
+        let handle = ObjectHandle {
+            id: object.get_unique_id(),
+        };
+        self.changed_regions
+            .push(object.parent_space_bounding_box());
+        self.objects.push(object);
+        handle
+    }
+
+    // Change an object in place, recording where it was and where it ends up as changed. Changes
+    // made through get_mut or objects directly are not tracked. False if the object is gone.
+    pub fn edit<F>(&mut self, handle: ObjectHandle, edit: F) -> bool
+    where
+        F: FnOnce(&mut dyn Shape),
+    {
+        match self
+            .objects
+            .iter_mut()
+            .find(|o| o.get_unique_id() == handle.id)
+        {
+            Some(object) => {
+                self.changed_regions
+                    .push(object.parent_space_bounding_box());
+                edit(object.as_mut());
+                self.changed_regions
+                    .push(object.parent_space_bounding_box());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, handle: ObjectHandle) -> Option<Box<dyn Shape>> {
+        let index = self
+            .objects
+            .iter()
+            .position(|o| o.get_unique_id() == handle.id)?;
+        let object = self.objects.remove(index);
+        self.changed_regions
+            .push(object.parent_space_bounding_box());
+        Some(object)
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.changed_regions.clear();
+    }
+
+    // None if the object has since been removed from objects
+    pub fn get(&self, handle: ObjectHandle) -> Option<&dyn Shape> {
+        self.objects
+            .iter()
+            .find(|o| o.get_unique_id() == handle.id)
+            .map(|o| o.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut dyn Shape> {
+        self.objects
+            .iter_mut()
+            .find(|o| o.get_unique_id() == handle.id)
+            .map(|o| o.as_mut() as &mut dyn Shape)
+    }
+
+    // Handles of the objects whose bounding boxes overlap bbox, which is in world space
+    pub fn objects_in(&self, bbox: BoundingBox) -> Vec<ObjectHandle> {
+        self.objects
+            .iter()
+            .filter(|o| o.parent_space_bounding_box().overlaps(bbox))
+            .map(|o| ObjectHandle {
+                id: o.get_unique_id(),
+            })
+            .collect()
+    }
+
+    // Handles of the objects that r hits, ordered by the distance to the nearest hit on each
+    pub fn objects_hit_by(&self, r: Ray) -> Vec<ObjectHandle> {
+        let mut hits: Vec<(f32, ObjectHandle)> = self
+            .objects
             .iter()
-            .map(|o| o.intersect(r))
-            .flatten()
+            .filter(|o| o.parent_space_bounding_box().intersects(r))
+            .filter_map(|o| {
+                Intersection::hit(&o.intersect(r)).map(|hit| {
+                    (
+                        hit.distance,
+                        ObjectHandle {
+                            id: o.get_unique_id(),
+                        },
+                    )
+                })
+            })
             .collect();
-        intersections.sort_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap_or(Equal));
-        intersections
+        hits.sort_by(|(d1, _), (d2, _)| d1.partial_cmp(d2).unwrap_or(Equal));
+        hits.into_iter().map(|(_, handle)| handle).collect()
+    }
+
+    pub fn intersect(&self, r: Ray) -> Intersections {
+        let threads = self
+            .intersect_threads
+            .min(self.objects.len() / MIN_OBJECTS_PER_INTERSECT_THREAD);
+        if threads > 1 {
+            Intersections::from_unsorted(self.intersect_in_parallel(r, threads))
+        } else {
+            let mut intersections = Intersections::new();
+            for o in &self.objects {
+                intersections.merge(o.intersect(r));
+            }
+            intersections
+        }
     }
 
-    pub fn shade_hit(&self, comps: PrecomputedValues, remaining_recursive_steps: i16) -> Color {
+    // Split the objects evenly between threads; the results are unsorted
+    fn intersect_in_parallel(&self, r: Ray, threads: usize) -> Vec<Intersection<'_>> {
+        let chunk_size = self.objects.len().div_ceil(threads);
+        // if this thread is counting the tests for one pixel's heatmap, the helper threads' tests
+        // count towards it too
+        let counting = stats::is_counting_on_thread();
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .objects
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let intersect_chunk = || -> Vec<Intersection> {
+                            chunk.iter().flat_map(|o| o.intersect(r)).collect()
+                        };
+                        let (intersections, counts) = if counting {
+                            let (intersections, counts) =
+                                stats::take_counts_on_thread(intersect_chunk);
+                            (intersections, Some(counts))
+                        } else {
+                            (intersect_chunk(), None)
+                        };
+                        if stats::is_enabled() {
+                            stats::flush_thread();
+                        }
+                        (intersections, counts)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| {
+                    let (intersections, counts) = h.join().expect("Intersection thread panicked");
+                    if let Some(counts) = counts {
+                        stats::add_counts(&counts);
+                    }
+                    intersections
+                })
+                .collect()
+        })
+    }
+
+    pub fn shade_hit(
+        &self,
+        comps: PrecomputedValues,
+        remaining_bounces: impl Into<RemainingBounces>,
+    ) -> Color {
+        let remaining_bounces = remaining_bounces.into();
         let light = self
             .light
             .as_ref()
             .expect("World light should be set")
             .as_ref();
-        let surface_color = phong_lighting(
-            comps.object,
-            comps.object.material(),
+        let surface_color = phong_lighting_colored(
+            surface_color(
+                comps.object,
+                &comps.material,
+                comps.pattern_point,
+                comps.pattern_footprint,
+            ),
+            &comps.material,
             light,
             comps.over_point,
             comps.eye_vector,
             comps.surface_normal,
             light.intensity_at(comps.over_point, self),
         );
-        let reflected_color = self.reflected_color(&comps, remaining_recursive_steps);
-        let refracted_color = self.refracted_color(&comps, remaining_recursive_steps);
-        let material = comps.object.material();
+        let reflected_color = self.reflected_color(&comps, remaining_bounces);
+        let refracted_color = self.refracted_color(&comps, remaining_bounces);
+        let material = &comps.material;
         if material.reflective > 0.0 && material.transparency > 0.0 {
             let reflectance = schlick_reflectance(&comps);
             surface_color + reflected_color * reflectance + refracted_color * (1.0 - reflectance)
@@ -85,25 +398,109 @@ impl World {
         }
     }
 
-    pub fn color_at(&self, r: Ray, remaining_recursive_steps: i16) -> Color {
+    pub fn color_at(&self, r: Ray, remaining_bounces: impl Into<RemainingBounces>) -> Color {
+        match self.render_settings.shading {
+            // the camera counts the intersection tests of the full shading for heatmaps
+            ShadingMode::Full | ShadingMode::Heatmap { .. } => {}
+            mode => return self.debug_color_at(r, mode),
+        }
         let intersections = self.intersect(r);
         if intersections.is_empty() {
-            color!(0, 0, 0)
+            self.environment_color(r.direction)
         } else {
-            match Intersection::hit(&intersections) {
+            match intersections.hit() {
                 Some(hit) => {
-                    let comps = precompute_values(r, hit, &intersections);
-                    self.shade_hit(comps, remaining_recursive_steps)
+                    let comps = precompute_values_with_epsilon(
+                        r,
+                        hit,
+                        &intersections,
+                        self.render_settings.self_intersection_epsilon,
+                        self.render_settings.self_intersection_relative_epsilon,
+                    );
+                    self.shade_hit(comps, remaining_bounces)
                 }
-                None => color!(0, 0, 0),
+                None => self.environment_color(r.direction),
             }
         }
     }
 
+    // the color that one of the debugging shading modes gives the surface hit by r
+    fn debug_color_at(&self, r: Ray, mode: ShadingMode) -> Color {
+        let intersections = self.intersect(r);
+        let hit = match intersections.hit() {
+            Some(hit) => hit,
+            None => return color!(0, 0, 0),
+        };
+        let point = r.position(hit.distance);
+        match mode {
+            ShadingMode::Full | ShadingMode::Heatmap { .. } => {
+                unreachable!("Full shading is done by color_at")
+            }
+            ShadingMode::Wireframe { edge_width } => {
+                let is_triangle = hit.object.downcast_ref::<Triangle>().is_some()
+                    || hit.object.downcast_ref::<SmoothTriangle>().is_some();
+                let nearest_edge = hit.u.min(hit.v).min(1. - hit.u - hit.v);
+                if is_triangle && nearest_edge < edge_width {
+                    white()
+                } else {
+                    let facing = hit.normal_at(&point).dot(r.direction.norm()).abs();
+                    white() * (0.2 + 0.5 * facing)
+                }
+            }
+            ShadingMode::Normals => {
+                let n = hit.normal_at(&point);
+                color!((n.x + 1.) / 2., (n.y + 1.) / 2., (n.z + 1.) / 2.)
+            }
+            ShadingMode::Depth { max_distance } => {
+                white() * (1. - hit.distance / max_distance).max(0.)
+            }
+        }
+    }
+
+    // the color of the environment infinitely far away in the given direction
+    pub fn environment_color(&self, direction: Tuple) -> Color {
+        match &self.environment {
+            Some(environment) => {
+                let (u, v) =
+                    SphericalMap.point_to_uv(point!(direction.x, direction.y, direction.z));
+                environment.color_at(u, v)
+            }
+            None => color!(0, 0, 0),
+        }
+    }
+
     // used only for point lights, where a shadow is a boolean instead of a number
     pub fn is_shadowed(&self, light_position: Tuple, point: Tuple) -> bool {
+        if !self.render_settings.shadows {
+            return false;
+        }
+        let cell_size = match self.render_settings.shadow_cache_cell_size {
+            Some(cell_size) => cell_size,
+            None => return self.cast_shadow_ray(light_position, point),
+        };
+        let cell = |v: f32| (v / cell_size).floor() as i64;
+        let key = (
+            [cell(point.x), cell(point.y), cell(point.z)],
+            [
+                light_position.x.to_bits(),
+                light_position.y.to_bits(),
+                light_position.z.to_bits(),
+            ],
+        );
+        if let Some(shadowed) = SHADOW_CACHE.with(|cache| cache.borrow().get(&key).copied()) {
+            stats::count(Counter::ShadowCacheHits);
+            return shadowed;
+        }
+        stats::count(Counter::ShadowCacheMisses);
+        let shadowed = self.cast_shadow_ray(light_position, point);
+        SHADOW_CACHE.with(|cache| cache.borrow_mut().insert(key, shadowed));
+        shadowed
+    }
+
+    fn cast_shadow_ray(&self, light_position: Tuple, point: Tuple) -> bool {
         // create a ray from a point to the light
         // if there's an intersection between the light and the point, then the point is in shadow
+        let _timer = stats::time_phase(Phase::ShadowRays);
         let light_to_point_vector = light_position - point;
         let distance = light_to_point_vector.magnitude();
         let direction = light_to_point_vector.norm();
@@ -111,9 +508,8 @@ impl World {
         let r = Ray::new(point, direction);
         let intersections = self.intersect(r);
 
-        let hit = Intersection::hit(&intersections);
-        match hit {
-            Some(i) => i.object.casts_shadow() && i.distance < distance,
+        match intersections.hit() {
+            Some(i) => i.casts_shadow() && i.distance < distance,
             None => false,
         }
     }
@@ -121,43 +517,125 @@ impl World {
     pub fn reflected_color(
         &self,
         comps: &PrecomputedValues,
-        remaining_recursive_steps: i16,
+        remaining_bounces: impl Into<RemainingBounces>,
     ) -> Color {
-        if comps.object.material().reflective == 0.0 || remaining_recursive_steps < 1 {
-            color!(0, 0, 0)
-        } else {
-            let reflected_ray = Ray::new(comps.over_point, comps.reflection_vector);
-            let c = self.color_at(reflected_ray, remaining_recursive_steps - 1);
-            c * comps.object.material().reflective
+        let remaining_bounces = remaining_bounces.into();
+        let reflective = comps.material.reflective;
+        if reflective == 0.0
+            || remaining_bounces.reflections < 1
+            || !remaining_bounces.is_visible(reflective, self.render_settings.min_contribution)
+        {
+            return color!(0, 0, 0);
         }
+        let weight = match self.russian_roulette(&remaining_bounces, reflective) {
+            Some(weight) => weight,
+            None => return color!(0, 0, 0),
+        };
+        let _timer = stats::time_phase(Phase::SecondaryRays);
+        let reflected_ray = Ray::new(comps.over_point, comps.reflection_vector)
+            .with_cone(comps.footprint, comps.spread);
+        let c = self.color_at(
+            reflected_ray,
+            RemainingBounces {
+                reflections: remaining_bounces.reflections - 1,
+                refractions: remaining_bounces.refractions,
+                contribution: remaining_bounces.contribution * reflective * weight,
+                depth: remaining_bounces.depth + 1,
+                channel: remaining_bounces.channel,
+            },
+        );
+        self.limit_secondary_brightness(c * reflective * weight)
     }
 
     pub fn refracted_color(
         &self,
         comps: &PrecomputedValues,
-        remaining_recursive_steps: i16,
+        remaining_bounces: impl Into<RemainingBounces>,
     ) -> Color {
-        if comps.object.material().transparency == 0.0 || remaining_recursive_steps == 0 {
-            // println!(
-            // "transparency: {}, remaining: {}",
-            // comps.object.material().transparency,
-            // remaining_recursive_steps
-            // );
+        let remaining_bounces = remaining_bounces.into();
+        let transparency = comps.material.transparency;
+        if transparency == 0.0
+            || remaining_bounces.refractions < 1
+            || !remaining_bounces.is_visible(transparency, self.render_settings.min_contribution)
+        {
             return color!(0, 0, 0);
         }
-        let refracted = comps.refracted_angle_values();
+        let weight = match self.russian_roulette(&remaining_bounces, transparency) {
+            Some(weight) => weight,
+            None => return color!(0, 0, 0),
+        };
+        let next_bounces = RemainingBounces {
+            reflections: remaining_bounces.reflections,
+            refractions: remaining_bounces.refractions - 1,
+            contribution: remaining_bounces.contribution * transparency * weight,
+            depth: remaining_bounces.depth + 1,
+            channel: remaining_bounces.channel,
+        };
+        let c = if comps.material.dispersion > 0. && remaining_bounces.channel.is_none() {
+            // each channel is bent by a different amount, so each needs its own ray
+            let channel_color = |channel| {
+                self.refracted_channel_color(
+                    comps,
+                    RemainingBounces {
+                        channel: Some(channel),
+                        ..next_bounces
+                    },
+                )
+            };
+            let red = channel_color(Channel::Red);
+            let green = channel_color(Channel::Green);
+            let blue = channel_color(Channel::Blue);
+            color!(red.r, green.g, blue.b)
+        } else {
+            self.refracted_channel_color(comps, next_bounces)
+        };
+        self.limit_secondary_brightness(c * transparency * weight)
+    }
+
+    // the color seen through the surface by light of remaining_bounces.channel (or by all light)
+    fn refracted_channel_color(
+        &self,
+        comps: &PrecomputedValues,
+        remaining_bounces: RemainingBounces,
+    ) -> Color {
+        let refracted = comps.refracted_angle_values(remaining_bounces.channel);
         if refracted.is_total_internal_reflection {
             // println!("Total internal refraction!");
             return color!(0, 0, 0);
-        } else {
-            // use trig formula to get cosine(refracted)
-            let cos_refracted = (1.0 - refracted.sin2).sqrt();
-            let direction_refracted = comps.surface_normal
-                * (refracted.n_ratio * refracted.cos_incoming - cos_refracted)
-                - (comps.eye_vector * refracted.n_ratio);
-            let ray_refracted = Ray::new(comps.under_point, direction_refracted);
-            self.color_at(ray_refracted, remaining_recursive_steps - 1)
-                * comps.object.material().transparency
+        }
+        let _timer = stats::time_phase(Phase::SecondaryRays);
+        // use trig formula to get cosine(refracted)
+        let cos_refracted = (1.0 - refracted.sin2).sqrt();
+        let direction_refracted = comps.surface_normal
+            * (refracted.n_ratio * refracted.cos_incoming - cos_refracted)
+            - (comps.eye_vector * refracted.n_ratio);
+        let ray_refracted = Ray::new(comps.under_point, direction_refracted)
+            .with_cone(comps.footprint, comps.spread);
+        self.color_at(ray_refracted, remaining_bounces)
+    }
+
+    fn limit_secondary_brightness(&self, color: Color) -> Color {
+        match self.render_settings.max_secondary_brightness {
+            Some(max) => color.limit_brightness(max),
+            None => color,
+        }
+    }
+
+    // Once a ray has bounced render_settings.russian_roulette_depth times, each further bounce is
+    // traced only with a probability equal to the contribution it would have, as decided by the
+    // sampler. Returns the weight that the bounce's color is multiplied by to make up for the
+    // bounces that are skipped, or None if this one is skipped.
+    fn russian_roulette(&self, bounces: &RemainingBounces, attenuation: f32) -> Option<f32> {
+        match self.render_settings.russian_roulette_depth {
+            Some(depth) if bounces.depth >= depth => {
+                let survival = (bounces.contribution * attenuation).min(1.);
+                if sampler::next_1d() < survival {
+                    Some(1. / survival)
+                } else {
+                    None
+                }
+            }
+            _ => Some(1.),
         }
     }
 }
@@ -165,6 +643,8 @@ impl World {
 pub struct PrecomputedValues<'a> {
     distance: f32,
     object: &'a dyn Shape,
+    // the object's material with any scalar patterns evaluated at point
+    material: Cow<'a, Material>,
     point: Tuple,
     eye_vector: Tuple,
     reflection_vector: Tuple,
@@ -179,6 +659,15 @@ pub struct PrecomputedValues<'a> {
     pub n1: f32,
     pub n2: f32,
     under_point: Tuple,
+
+    // width of the ray's cone at the hit, and how fast it widens; passed on to secondary rays
+    footprint: f32,
+    spread: f32,
+
+    // over_point and footprint in the space that object's transformation is relative to, where
+    // its patterns are evaluated
+    pattern_point: Tuple,
+    pattern_footprint: f32,
 }
 pub struct RefractedAngleValues {
     n_ratio: f32,
@@ -193,8 +682,9 @@ impl PrecomputedValues<'_> {
     // Snell's law states that sin(incoming) / sin(refracted) = refraction index of
     // material 2 / refraction index of material 1.
     // Implementing as a method instead of a field because it is not always needed.
-    fn refracted_angle_values(&self) -> RefractedAngleValues {
-        let n_ratio = self.n1 / self.n2;
+    fn refracted_angle_values(&self, channel: Option<Channel>) -> RefractedAngleValues {
+        let (n1, n2) = self.refractive_indices(channel);
+        let n_ratio = n1 / n2;
         let cos_incoming = self.eye_vector.dot(self.surface_normal);
         // sin^2(refracted angle) via trig identity
         let sin2 = n_ratio.powi(2) * (1.0 - cos_incoming.powi(2));
@@ -205,17 +695,52 @@ impl PrecomputedValues<'_> {
             is_total_internal_reflection: sin2 > 1.0,
         }
     }
-}
 
-const SELF_INTERSECTION_AVOIDANCE_EPSILON: f32 = f32::EPSILON * 10000.0;
+    // n1 and n2 for light of the given channel. The index of a dispersive material is lower for red
+    // and higher for blue than its refractive_index; the difference between the two is
+    // (refractive_index - 1) * dispersion, as with the F and C lines that define Abbe numbers.
+    fn refractive_indices(&self, channel: Option<Channel>) -> (f32, f32) {
+        let shift = match channel {
+            Some(Channel::Red) => -0.5,
+            Some(Channel::Blue) => 0.5,
+            Some(Channel::Green) | None => 0.,
+        };
+        let offset = (self.material.refractive_index - 1.) * self.material.dispersion * shift;
+        // the ray is leaving the hit object when it is inside of it, and entering it otherwise
+        if self.inside {
+            (self.n1 + offset, self.n2)
+        } else {
+            (self.n1, self.n2 + offset)
+        }
+    }
+}
 
 pub fn precompute_values<'a>(
     r: Ray,
     hit: &Intersection<'a>,
     intersections: &[Intersection<'a>],
+) -> PrecomputedValues<'a> {
+    precompute_values_with_epsilon(
+        r,
+        hit,
+        intersections,
+        SELF_INTERSECTION_AVOIDANCE_EPSILON,
+        SELF_INTERSECTION_RELATIVE_EPSILON,
+    )
+}
+
+// Like precompute_values, but with over_point and under_point offset from the surface by the
+// larger of self_intersection_epsilon and relative_epsilon times the largest coordinate of the
+// hit point or ray origin (see RenderSettings::self_intersection_epsilon)
+pub fn precompute_values_with_epsilon<'a>(
+    r: Ray,
+    hit: &Intersection<'a>,
+    intersections: &[Intersection<'a>],
+    self_intersection_epsilon: f32,
+    relative_epsilon: f32,
 ) -> PrecomputedValues<'a> {
     let point = r.position(hit.distance);
-    let mut surface_normal = hit.object.normal_at(&point, &hit);
+    let mut surface_normal = hit.normal_at(&point);
     let eye_vector = -r.direction;
     let reflection_vector = Ray::reflect(r.direction, surface_normal);
 
@@ -228,34 +753,37 @@ pub fn precompute_values<'a>(
         inside = false;
     }
 
-    let over_point = point + surface_normal * SELF_INTERSECTION_AVOIDANCE_EPSILON;
-    let under_point = point - surface_normal * SELF_INTERSECTION_AVOIDANCE_EPSILON;
+    let largest_coordinate = |t: Tuple| t.x.abs().max(t.y.abs()).max(t.z.abs());
+    let scale = largest_coordinate(point).max(largest_coordinate(r.origin));
+    let offset = self_intersection_epsilon.max(relative_epsilon * scale);
+    let over_point = point + surface_normal * offset;
+    let under_point = point - surface_normal * offset;
 
     // computing n1 and n2
     let mut n1 = f32::NAN;
     let mut n2 = f32::NAN;
 
     // objects containing the current hit, ordered outermost to innermost
-    let mut containing_objects: LinkedHashSet<&'a dyn Shape> = LinkedHashSet::new();
+    let mut containing_objects: LinkedHashSet<ContainingObject<'a>> = LinkedHashSet::new();
 
     // the book uses REFRACTION_VACCUM; should probably be REFRACTION_AIR (though the difference is small)
     let default_refraction_index = REFRACTION_VACCUM;
     for i in intersections {
         if i == hit {
             n1 = match containing_objects.back() {
-                Some(o) => o.material().refractive_index,
+                Some(o) => o.0.material().refractive_index,
                 None => default_refraction_index,
             };
         }
         // if the object is in containing_objects, then we are exiting it;
         // otherwise, we are entering it. Update accordingly.
-        if !containing_objects.remove(&i.object) {
-            containing_objects.insert(i.object);
+        if !containing_objects.remove(&ContainingObject(i.clone())) {
+            containing_objects.insert(ContainingObject(i.clone()));
         }
 
         if i == hit {
             n2 = match containing_objects.back() {
-                Some(o) => o.material().refractive_index,
+                Some(o) => o.0.material().refractive_index,
                 None => default_refraction_index,
             };
             break;
@@ -264,10 +792,19 @@ pub fn precompute_values<'a>(
     debug_assert!(!n1.is_nan());
     debug_assert!(!n2.is_nan());
 
+    let footprint = r.width_at(hit.distance);
+    let (pattern_point, pattern_footprint) = match &hit.world_to_parent {
+        Some(m) => (m.as_ref() * over_point, footprint * average_scale(m)),
+        None => (over_point, footprint),
+    };
+
     PrecomputedValues {
         // copy the intersection's properties, for convenience
         distance: hit.distance,
         object: hit.object,
+        material: hit
+            .material()
+            .at_point(hit.world_to_parent_point(&point), hit.object),
         // precompute some useful values
         point,
         eye_vector,
@@ -279,6 +816,31 @@ pub fn precompute_values<'a>(
 
         n1,
         n2,
+
+        footprint,
+        spread: r.spread,
+
+        pattern_point,
+        pattern_footprint,
+    }
+}
+
+// The object hit by an intersection, for tracking which objects a ray is inside of. Instances share
+// their prototype's shapes, so the instance that a shape was hit through is part of its identity.
+struct ContainingObject<'a>(Intersection<'a>);
+
+impl PartialEq for ContainingObject<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.object == other.0.object && self.0.instance == other.0.instance
+    }
+}
+
+impl Eq for ContainingObject<'_> {}
+
+impl Hash for ContainingObject<'_> {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.0.object.hash(hasher);
+        self.0.instance.map(|i| i.get_unique_id()).hash(hasher);
     }
 }
 
@@ -306,11 +868,17 @@ fn schlick_reflectance(comps: &PrecomputedValues) -> f32 {
 mod tests {
     use super::*;
     use crate::constants::black;
+    use crate::pattern::checkers::Checkers;
     use crate::pattern::pattern::TestPattern;
+    use crate::pattern::scalar::ScalarPattern;
+    use crate::pattern::uv::UVCheckers;
+    use crate::shape::group::GroupShape;
+    use crate::shape::instance::Instance;
     use crate::shape::plane::Plane;
     use crate::transformations::translation;
     use std::f32::consts::FRAC_1_SQRT_2;
     use std::f32::consts::SQRT_2;
+    use std::sync::Arc;
 
     #[test]
     fn create_blank_world() {
@@ -319,6 +887,28 @@ mod tests {
         assert!(w.light.is_none());
     }
 
+    #[test]
+    fn parallel_intersection_matches_serial() {
+        let mut w = World::new();
+        for i in 0..200 {
+            w.objects.push(Box::new(Sphere::build(
+                translation(0., 0., i as f32 * 3.),
+                Material::default(),
+            )));
+        }
+        let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
+        let serial: Vec<f32> = w.intersect(r).iter().map(|i| i.distance).collect();
+        w.intersect_threads = 4;
+        let parallel = w.intersect(r);
+        assert_eq!(parallel.len(), 400);
+        assert_eq!(
+            parallel.iter().map(|i| i.distance).collect::<Vec<f32>>(),
+            serial
+        );
+        // intersections still refer to the world's objects
+        assert!(w.objects[199].includes(parallel[399].object));
+    }
+
     #[test]
     fn intersect_world_with_ray() {
         let w = World::default();
@@ -336,7 +926,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
         let shape = Sphere::new();
         let i = Intersection::new(4.0, &shape);
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         assert_eq!(comps.distance, i.distance);
         assert_eq!(comps.point, point!(0, 0, -1));
         assert_eq!(comps.eye_vector, vector!(0, 0, -1));
@@ -348,7 +938,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
         let shape = Sphere::new();
         let i = Intersection::new(4.0, &shape);
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         assert!(!comps.inside);
     }
 
@@ -357,7 +947,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, 0), vector!(0, 0, 1));
         let shape = Sphere::new();
         let i = Intersection::new(1.0, &shape);
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         assert_eq!(comps.point, point!(0, 0, 1));
         assert_eq!(comps.eye_vector, vector!(0, 0, -1));
         assert_eq!(comps.inside, true);
@@ -373,7 +963,7 @@ mod tests {
         let shape = Plane::new();
         let r = Ray::new(point!(0, 1, -1), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
         let i = Intersection::new(SQRT_2, &shape);
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         assert_eq!(
             comps.reflection_vector,
             vector!(0, FRAC_1_SQRT_2, FRAC_1_SQRT_2)
@@ -449,6 +1039,28 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn find_n1_and_n2_in_overlapping_instances() {
+        let glass: Arc<dyn Shape> = Arc::new(glass_sphere());
+        let index = |refractive_index| {
+            let mut m = glass.material().clone();
+            m.refractive_index = refractive_index;
+            Some(m)
+        };
+        let a = Instance::new(Arc::clone(&glass), identity_4x4(), index(1.5));
+        let b = Instance::new(Arc::clone(&glass), translation(0., 0., 0.5), index(2.0));
+        let r = Ray::new(point!(0, 0, -4), vector!(0, 0, 1));
+        let mut intersections = a.intersect(r);
+        Intersection::add_sorted(&mut intersections, b.intersect(r));
+        // both instances hit the same sphere, but entering b does not mean leaving a
+        let expected = vec![(1.0, 1.5), (1.5, 2.0), (2.0, 2.0), (2.0, 1.0)];
+        for (i, (expected_n1, expected_n2)) in intersections.iter().zip(expected) {
+            let comps = precompute_values(r, i, &intersections);
+            assert_eq!((comps.n1, comps.n2), (expected_n1, expected_n2));
+        }
+    }
+
     #[test]
     fn under_point_is_offset_below_suface() {
         let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
@@ -458,7 +1070,7 @@ mod tests {
             sphere
         };
         let hit = Intersection::new(5.0, &shape);
-        let xs = vec![hit];
+        let xs = vec![hit.clone()];
         let comps = precompute_values(r, &hit, &xs);
         assert!(comps.under_point.z > SELF_INTERSECTION_AVOIDANCE_EPSILON / 2.0);
         assert!(comps.point.z < comps.under_point.z);
@@ -474,7 +1086,7 @@ mod tests {
 
         let r = Ray::new(point!(0, 0, 0), vector!(0, 0, 1));
         let i = Intersection::new(1.0, w.objects[1].as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let color = w.reflected_color(&comps, 1);
         assert_eq!(color, color!(0, 0, 0));
     }
@@ -488,9 +1100,84 @@ mod tests {
 
         let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
         let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let color = w.reflected_color(&comps, 1);
         assert_abs_diff_eq!(color, color!(0.190_521_97, 0.238_152_46, 0.142_891_48));
+        // only reflections count against the reflection limit
+        let color = w.reflected_color(&comps, RemainingBounces::new(1, 0));
+        assert_abs_diff_eq!(color, color!(0.190_521_97, 0.238_152_46, 0.142_891_48));
+        let color = w.reflected_color(&comps, RemainingBounces::new(0, 5));
+        assert_eq!(color, color!(0, 0, 0));
+    }
+
+    #[test]
+    fn reflections_below_minimum_contribution_are_not_traced() {
+        let mut w = World::default();
+        let m = Material::builder().reflective(0.5).build();
+        let plane = Box::new(Plane::build(translation(0.0, -1.0, 0.0), m));
+        w.objects.push(plane);
+
+        let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
+        let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
+        let comps = precompute_values(r, &i, std::slice::from_ref(&i));
+        let earlier_bounces = RemainingBounces {
+            contribution: 0.5,
+            ..RemainingBounces::from(5)
+        };
+        let test_data = vec![
+            ("below", 0.6, RemainingBounces::from(5), false),
+            ("equal", 0.5, RemainingBounces::from(5), true),
+            ("earlier bounces below", 0.3, earlier_bounces, false),
+            ("earlier bounces above", 0.2, earlier_bounces, true),
+        ];
+        for (name, min_contribution, remaining_bounces, traced) in test_data {
+            w.render_settings.min_contribution = min_contribution;
+            let color = w.reflected_color(&comps, remaining_bounces);
+            assert_eq!(color != color!(0, 0, 0), traced, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn russian_roulette_preserves_average_reflection() {
+        let mut w = World::default();
+        let m = Material::builder().reflective(0.5).build();
+        let plane = Box::new(Plane::build(translation(0.0, -1.0, 0.0), m));
+        w.objects.push(plane);
+
+        let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
+        let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
+        let comps = precompute_values(r, &i, std::slice::from_ref(&i));
+        let expected = w.reflected_color(&comps, 1);
+        w.render_settings.russian_roulette_depth = Some(0);
+        let samples = 4000;
+        let mut traced = 0;
+        let mut total = color!(0, 0, 0);
+        for _ in 0..samples {
+            let color = w.reflected_color(&comps, 1);
+            // traced half of the time, at twice the brightness
+            if color != color!(0, 0, 0) {
+                assert_abs_diff_eq!(color, expected * 2.);
+                traced += 1;
+            }
+            total += color;
+        }
+        assert!((traced as f32 / samples as f32 - 0.5).abs() < 0.05);
+        assert_abs_diff_eq!(total / samples as f32, expected, epsilon = 0.02);
+    }
+
+    #[test]
+    fn secondary_brightness_is_limited() {
+        let mut w = World::default();
+        let m = Material::builder().reflective(0.5).build();
+        let plane = Box::new(Plane::build(translation(0.0, -1.0, 0.0), m));
+        w.objects.push(plane);
+
+        let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
+        let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
+        let comps = precompute_values(r, &i, std::slice::from_ref(&i));
+        w.render_settings.max_secondary_brightness = Some(0.1);
+        let color = w.reflected_color(&comps, 1);
+        assert_abs_diff_eq!(color, color!(0.080_000_006, 0.1, 0.060_000_002));
     }
 
     #[test]
@@ -502,11 +1189,34 @@ mod tests {
 
         let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
         let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let color = w.shade_hit(comps, 1);
         assert_abs_diff_eq!(color, color!(0.876_910_8, 0.924_541_3, 0.829_280_3));
     }
 
+    #[test]
+    fn reflective_pattern_varies_reflection_over_surface() {
+        let mut w = World::default();
+        let tiles = ScalarPattern::new(Arc::new(Checkers::new(white(), black())), 0., 0.5);
+        let m = Material::builder().reflective_pattern(tiles).build();
+        let plane = Box::new(Plane::build(translation(0.0, -1.0, 0.0), m));
+        w.objects.push(plane);
+
+        let reflected_at = |x: f32| {
+            let r = Ray::new(point!(x, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
+            let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
+            let comps = precompute_values(r, &i, std::slice::from_ref(&i));
+            w.reflected_color(&comps, 1)
+        };
+        // glossy tile; same as a reflective material with reflective 0.5
+        assert_abs_diff_eq!(
+            reflected_at(0.),
+            color!(0.190_521_97, 0.238_152_46, 0.142_891_48)
+        );
+        // matte tile
+        assert_eq!(reflected_at(1.5), black());
+    }
+
     #[test]
     fn shade_hit_with_mutually_reflective_surfaces() {
         let mut w = World::new();
@@ -531,7 +1241,7 @@ mod tests {
 
         let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
         let i = Intersection::new(SQRT_2, w.objects.last().unwrap().as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let color = w.reflected_color(&comps, 0);
         assert_abs_diff_eq!(color, color!(0, 0, 0));
     }
@@ -542,7 +1252,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
         let shape = &w.objects[0];
         let i = Intersection::new(4.0, shape.as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let c = w.shade_hit(comps, 1);
         assert_abs_diff_eq!(c, color!(0.380_632_88, 0.475_791_04, 0.285_474_66))
     }
@@ -554,7 +1264,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, 0), vector!(0, 0, 1));
         let shape = &w.objects[1];
         let i = Intersection::new(0.5, shape.as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let c = w.shade_hit(comps, 1);
         assert_abs_diff_eq!(c, color!(0.904_599_5, 0.904_599_5, 0.904_599_5))
     }
@@ -567,6 +1277,78 @@ mod tests {
         assert_eq!(c, color!(0, 0, 0));
     }
 
+    #[test]
+    fn debug_shading_modes() {
+        let mut w = World::default();
+        let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
+        let test_data = vec![
+            ("Normals", ShadingMode::Normals, color!(0.5, 0.5, 0)),
+            (
+                "Depth",
+                ShadingMode::Depth { max_distance: 8. },
+                color!(0.5, 0.5, 0.5),
+            ),
+            (
+                "Depth beyond max_distance",
+                ShadingMode::Depth { max_distance: 2. },
+                color!(0, 0, 0),
+            ),
+            (
+                "Wireframe ignores spheres' u and v",
+                ShadingMode::Wireframe { edge_width: 0.05 },
+                color!(0.7, 0.7, 0.7),
+            ),
+        ];
+        for (name, mode, expected) in test_data {
+            println!("Case {}", name);
+            w.render_settings.shading = mode;
+            assert_abs_diff_eq!(w.color_at(r, 5), expected);
+            let miss = Ray::new(point!(0, 0, -5), vector!(0, 1, 0));
+            assert_eq!(w.color_at(miss, 5), color!(0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn wireframe_highlights_triangle_edges() {
+        let w = World::builder()
+            .add(Triangle::new(
+                point!(0, 1, 0),
+                point!(-1, 0, 0),
+                point!(1, 0, 0),
+            ))
+            .render_settings(RenderSettings {
+                shading: ShadingMode::Wireframe { edge_width: 0.05 },
+                ..RenderSettings::default()
+            })
+            .build();
+        let test_data = vec![
+            ("Near the bottom edge", point!(0, 0.02, -2), white()),
+            ("Near the left edge", point!(-0.45, 0.5, -2), white()),
+            ("Inside", point!(0, 0.4, -2), color!(0.7, 0.7, 0.7)),
+        ];
+        for (name, origin, expected) in test_data {
+            println!("Case {}", name);
+            let c = w.color_at(Ray::new(origin, vector!(0, 0, 1)), 5);
+            assert_abs_diff_eq!(c, expected);
+        }
+    }
+
+    #[test]
+    fn color_when_ray_misses_uses_environment() {
+        let mut w = World::default();
+        // values above 1, as in an HDR image, are kept
+        w.environment = Some(Box::new(UVCheckers::new(
+            1.,
+            2.,
+            color!(10, 10, 10),
+            color!(0, 0, 1),
+        )));
+        let below = Ray::new(point!(0, 0, -5), vector!(0, -0.5, -1));
+        assert_eq!(w.color_at(below, 1), color!(10, 10, 10));
+        let above = Ray::new(point!(0, 0, -5), vector!(0, 0.5, -1));
+        assert_eq!(w.color_at(above, 1), color!(0, 0, 1));
+    }
+
     #[test]
     fn color_when_ray_hits() {
         let w = World::default();
@@ -577,16 +1359,144 @@ mod tests {
 
     #[test]
     fn color_when_intersection_behind_ray() {
-        let mut w = World::default();
-        // TODO: can't take w.objects[x] and mutate it...
-        // outer
+        let mut w = World::new();
+        w.light = World::default().light;
         let m = Material::builder().ambient(1.).build();
-        w.objects[0].set_material(m.clone());
-        // inner
-        w.objects[1].set_material(m.clone());
+        let outer = w.add(Box::new(Sphere::build(identity_4x4(), m.clone())));
+        let inner = w.add(Box::new(Sphere::build(scaling(0.5, 0.5, 0.5), m)));
+        w.get_mut(outer).unwrap().set_material(
+            Material::builder()
+                .ambient(1.)
+                .color(color!(0, 1, 0))
+                .build(),
+        );
         let r = Ray::new(point!(0, 0, 0.75), vector!(0, 0, -1));
         let c = w.color_at(r, 1);
-        assert_eq!(c, w.objects[1].material().color);
+        assert_eq!(c, w.get(inner).unwrap().material().color);
+    }
+
+    #[test]
+    fn spatial_queries_return_handles() {
+        let mut w = World::new();
+        let left = w.add(Box::new(Sphere::build(
+            translation(-3., 0., 0.),
+            Material::default(),
+        )));
+        let middle = w.add(Box::new(Sphere::new()));
+        let right = w.add(Box::new(Sphere::build(
+            translation(3., 0., 0.),
+            Material::default(),
+        )));
+        let floor = w.add(Box::new(Plane::build(
+            translation(0., -1., 0.),
+            Material::default(),
+        )));
+
+        let test_data = vec![
+            (
+                "everything",
+                point!(-5, -5, -5),
+                point!(5, 5, 5),
+                vec![left, middle, right, floor],
+            ),
+            (
+                "right of middle",
+                point!(1.5, 0, 0),
+                point!(5, 5, 5),
+                vec![right],
+            ),
+            ("above floor", point!(-5, 1.5, -5), point!(5, 5, 5), vec![]),
+            (
+                "touching middle",
+                point!(1, -0.5, -0.5),
+                point!(1.5, 0.5, 0.5),
+                vec![middle],
+            ),
+        ];
+        for (name, min, max, expected) in test_data {
+            let found = w.objects_in(BoundingBox::with_bounds(min, max));
+            assert_eq!(found, expected, "Case {}", name);
+        }
+
+        let test_data = vec![
+            (
+                "across",
+                point!(10, 0, 0),
+                vector!(-1, 0, 0),
+                vec![right, middle, left],
+            ),
+            (
+                "down",
+                point!(3, 5, 0),
+                vector!(0, -1, 0),
+                vec![right, floor],
+            ),
+            ("behind", point!(-10, 0, 0), vector!(-1, 0, 0), vec![]),
+        ];
+        for (name, origin, direction, expected) in test_data {
+            let found = w.objects_hit_by(Ray::new(origin, direction));
+            assert_eq!(found, expected, "Case {}", name);
+        }
+    }
+
+    #[test]
+    fn handles_outlive_changes_to_other_objects() {
+        let mut w = World::new();
+        let a = w.add(Box::new(Sphere::new()));
+        let b = w.add(Box::new(Plane::new()));
+        w.get_mut(b)
+            .unwrap()
+            .set_transformation(translation(0., -1., 0.));
+        w.objects.remove(0);
+        let c = w.add(Box::new(Sphere::new()));
+        assert!(w.get(a).is_none());
+        assert_eq!(
+            w.get(b).unwrap().transformation(),
+            &translation(0., -1., 0.)
+        );
+        assert_ne!(b, c);
+        assert!(w.get(c).unwrap().includes(w.objects[1].as_ref()));
+    }
+
+    #[test]
+    fn builder_declares_whole_world() {
+        let checkers = UVCheckers::new(2., 2., color!(0, 0, 0), color!(1, 1, 1));
+        let w = World::builder()
+            .light(PointLight::new(point!(1, 2, 3), white()))
+            .add(Sphere::new())
+            .add_boxed(Box::new(Plane::new()))
+            .background(checkers)
+            .intersect_threads(2)
+            .build();
+        assert_eq!(w.objects.len(), 2);
+        assert!(w.objects[1].is::<Plane>());
+        assert_eq!(w.light.unwrap().position(), point!(1, 2, 3));
+        assert!(w.environment.is_some());
+        assert_eq!(w.intersect_threads, 2);
+        // objects are part of the world from the start rather than changes to it
+        assert!(w.changed_regions.is_empty());
+    }
+
+    #[test]
+    fn changes_are_tracked_by_bounds() {
+        let mut w = World::new();
+        let a = w.add(Box::new(Sphere::new()));
+        let unit = BoundingBox::with_bounds(point!(-1, -1, -1), point!(1, 1, 1));
+        assert_eq!(w.changed_regions, vec![unit]);
+
+        w.clear_changes();
+        assert!(w.edit(a, |s| s.set_transformation(translation(5., 0., 0.))));
+        assert_eq!(
+            w.changed_regions,
+            vec![unit, unit.transform(&translation(5., 0., 0.))]
+        );
+
+        w.clear_changes();
+        assert!(w.remove(a).is_some());
+        assert_eq!(w.changed_regions.len(), 1);
+        assert!(!w.edit(a, |_| {}));
+        assert!(w.remove(a).is_none());
+        assert!(w.objects.is_empty());
     }
 
     #[test]
@@ -609,6 +1519,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shadow_cache_reuses_answers_within_a_cell() {
+        let mut w = World::default();
+        w.render_settings.shadow_cache_cell_size = Some(100.);
+        let light_position = point!(-10, -10, -10);
+        clear_shadow_cache();
+        assert!(w.is_shadowed(light_position, point!(10, 10, 10)));
+        // not actually shadowed, but in the same cell
+        assert!(w.is_shadowed(light_position, point!(90, 10, 10)));
+        // a different light is a different query
+        assert!(!w.is_shadowed(point!(-10, -10, -11), point!(90, 10, 10)));
+        clear_shadow_cache();
+        assert!(!w.is_shadowed(light_position, point!(90, 10, 10)));
+        clear_shadow_cache();
+    }
+
+    #[test]
+    fn nothing_is_shadowed_when_shadows_are_disabled() {
+        let mut w = World::default();
+        w.render_settings.shadows = false;
+        assert!(!w.is_shadowed(point!(-10, -10, -10), point!(10, 10, 10)));
+    }
+
     #[test]
     fn point_lights_evaluate_light_intensity_at_point() {
         let w = World::default();
@@ -634,7 +1567,7 @@ mod tests {
         let r = Ray::new(point!(0, 0, -5), vector!(0, 0, 1));
         let shape = Sphere::build(translation(0.0, 0.0, 1.0), Material::default());
         let intersection = Intersection::new(5.0, &shape);
-        let comps = precompute_values(r, &intersection, &vec![intersection]);
+        let comps = precompute_values(r, &intersection, &vec![intersection.clone()]);
         // println!("{:?}", comps.point);
         // println!("{:?}", comps.over_point);
         assert!(comps.over_point.z < -SELF_INTERSECTION_AVOIDANCE_EPSILON / 2.0);
@@ -642,6 +1575,26 @@ mod tests {
         assert!(comps.point.z > comps.over_point.z);
     }
 
+    #[test]
+    fn offset_grows_with_distance_from_origin() {
+        let test_data = vec![
+            ("near origin", 0., SELF_INTERSECTION_AVOIDANCE_EPSILON),
+            ("far away", 1e5, 1.),
+        ];
+        for (name, x, expected_offset) in test_data {
+            let r = Ray::new(point!(x, 0, -5), vector!(0, 0, 1));
+            let shape = Sphere::build(translation(x, 0.0, 1.0), Material::default());
+            let intersection = Intersection::new(5.0, &shape);
+            let comps = precompute_values(r, &intersection, std::slice::from_ref(&intersection));
+            assert_abs_diff_eq!(
+                comps.point.z - comps.over_point.z,
+                expected_offset,
+                epsilon = expected_offset / 100.
+            );
+            assert!(comps.under_point.z > comps.point.z, "Case {}", name);
+        }
+    }
+
     #[test]
     fn shade_hit_for_intersection_in_shadow() {
         let mut w = World::new();
@@ -652,7 +1605,7 @@ mod tests {
         w.objects.push(Box::new(s2));
         let r = Ray::new(point!(0, 0, 5), vector!(0, 0, 1));
         let i = Intersection::new(4.0, w.objects[1].as_ref());
-        let comps = precompute_values(r, &i, &vec![i]);
+        let comps = precompute_values(r, &i, &vec![i.clone()]);
         let c = w.shade_hit(comps, 1);
         assert_eq!(c, color!(0.1, 0.1, 0.1));
     }
@@ -712,13 +1665,37 @@ mod tests {
         assert_abs_diff_eq!(c, black());
     }
 
+    #[test]
+    fn patterns_are_evaluated_in_each_shapes_own_space() {
+        // the pattern's color is the point it is evaluated at
+        let m = Material::builder()
+            .pattern(Arc::new(TestPattern::new()))
+            .ambient(1.)
+            .diffuse(0.)
+            .specular(0.)
+            .build();
+        let sphere = Sphere::build(scaling(2., 2., 2.), m);
+        let mut group = GroupShape::with_children(vec![Box::new(sphere.clone())]);
+        group.set_transformation(translation(5., 0., 0.));
+        let instance = Instance::new(Arc::new(sphere), translation(-5., 0., 0.), None);
+        let w = World {
+            objects: vec![Box::new(group), Box::new(instance)],
+            ..World::default()
+        };
+        for (name, x) in [("group", 5.), ("instance", -5.)] {
+            println!("Case {}", name);
+            let c = w.color_at(Ray::new(point!(x, 0, -10), vector!(0, 0, 1)), 0);
+            assert_abs_diff_eq!(c, color!(0, 0, -1), epsilon = 1e-3);
+        }
+    }
+
     #[test]
     fn refracted_color_with_refracted_ray() {
         let mut w = World::default();
         {
             let mut m = w.objects[0].material().clone();
             m.ambient = 1.0;
-            m.pattern = Some(Box::new(TestPattern::new()));
+            m.pattern = Some(Arc::new(TestPattern::new()));
             w.objects[0].set_material(m.clone());
         }
         {
@@ -741,6 +1718,49 @@ mod tests {
         let comps = precompute_values(r, &xs[2], &xs);
         let c = w.refracted_color(&comps, 5);
         assert_abs_diff_eq!(c, color!(0, 0.997_676_8, 0.047_521_036));
+        // only refractions count against the refraction limit
+        let c = w.refracted_color(&comps, RemainingBounces::new(0, 5));
+        assert_abs_diff_eq!(c, color!(0, 0.997_676_8, 0.047_521_036));
+        let c = w.refracted_color(&comps, RemainingBounces::new(5, 0));
+        assert_eq!(c, color!(0, 0, 0));
+    }
+
+    #[test]
+    fn dispersive_material_refracts_channels_separately() {
+        let mut w = World::default();
+        {
+            let mut m = w.objects[0].material().clone();
+            m.ambient = 1.0;
+            m.pattern = Some(Arc::new(TestPattern::new()));
+            w.objects[0].set_material(m.clone());
+        }
+        let r = Ray::new(point!(0, 0, 0.1), vector!(0, 1, 0));
+        let refracted = |w: &World, dispersion, remaining_bounces: RemainingBounces| {
+            let mut m = w.objects[1].material().clone();
+            m.transparency = 1.0;
+            m.refractive_index = 1.5;
+            m.dispersion = dispersion;
+            let shape_b = Sphere::build(w.objects[1].transformation().clone(), m);
+            let xs = vec![
+                Intersection::new(-0.489_9, &shape_b),
+                Intersection::new(0.489_9, &shape_b),
+            ];
+            let comps = precompute_values(r, &xs[1], &xs);
+            w.refracted_color(&comps, remaining_bounces)
+        };
+        let plain = refracted(&w, 0., RemainingBounces::from(5));
+        let dispersed = refracted(&w, 0.1, RemainingBounces::from(5));
+        // the pattern's blue is the z coordinate of the point that the ray reaches, which moves
+        // one way for blue light and the other way for red light
+        assert_abs_diff_eq!(dispersed.g, plain.g);
+        assert!(dispersed.b < plain.b - 0.001);
+        // rays already split up by an earlier surface are not split again
+        let red_ray = RemainingBounces {
+            channel: Some(Channel::Red),
+            ..RemainingBounces::from(5)
+        };
+        let red = refracted(&w, 0.1, red_ray);
+        assert!(red.b > plain.b + 0.001);
     }
 
     #[test]
@@ -753,7 +1773,7 @@ mod tests {
                 .build();
             Plane::build(translation(0.0, -1.0, 0.0), m)
         };
-        w.objects.push(Box::new(floor));
+        let floor = w.add(Box::new(floor));
         let ball = {
             let m = Material::builder()
                 .color(color!(1, 0, 0))
@@ -761,13 +1781,10 @@ mod tests {
                 .build();
             Sphere::build(translation(0.0, -3.5, -0.5), m)
         };
-        w.objects.push(Box::new(ball));
+        w.add(Box::new(ball));
         let r = Ray::new(point!(0, 0, -3), vector!(0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2));
         // intersection with floor
-        let xs = vec![Intersection::new(
-            SQRT_2,
-            w.objects[w.objects.len() - 2].as_ref(),
-        )];
+        let xs = vec![Intersection::new(SQRT_2, w.get(floor).unwrap())];
         let comps = precompute_values(r, &xs[0], &xs);
         let c = w.shade_hit(comps, 5);
 